@@ -1,6 +1,10 @@
 fn main() {
-    let config = slint_build::CompilerConfiguration::new().with_style("fluent-dark".into());
-    slint_build::compile_with_config("ui/main.slint", config).unwrap();
+    let config = || slint_build::CompilerConfiguration::new().with_style("fluent-dark".into());
+    // The passphrase prompt is its own window, shown before the main window is created.
+    // Slint generates one window per file, so it's compiled separately and included in
+    // encryption.rs. The main window is compiled last for include_modules!.
+    slint_build::compile_with_config("ui/passphrase.slint", config()).unwrap();
+    slint_build::compile_with_config("ui/main.slint", config()).unwrap();
 }
 
 /*
@@ -1,13 +1,39 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use async_std::fs;
+use serde::{Deserialize, Serialize};
+
+use crate::{proration::ProrationMethod, sales_tax::TaxJurisdiction};
 
 pub const TESTING_STATEMENT_PATH: &str = "./statements/";
 pub const TESTING_DATABASE_PATH: &str = "./";
+pub const TESTING_BACKUP_PATH: &str = "./backups/";
+pub const TESTING_TEMPLATE_PATH: &str = "./templates/";
+pub const TESTING_DOCUMENTS_PATH: &str = "./documents/";
+pub const SETTINGS_FILE: &str = "./hestia_settings.toml";
+pub const DATABASE_FILE: &str = "sqlite.db";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub paths: PathSettings,
+    pub backup: BackupSettings,
+    pub encryption: EncryptionSettings,
+    pub email: EmailSettings,
+    pub invoice: InvoiceSettings,
+    pub late_fees: LateFeeSettings,
+    pub tax: TaxSettings,
+    pub documents: DocumentSettings,
+}
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PathSettings {
     pub statements_path: String,
     pub database_path: String,
+    pub backup_path: String,
+    pub templates_path: String,
+    pub documents_path: String,
 }
 
 impl Default for PathSettings {
@@ -15,22 +41,245 @@ impl Default for PathSettings {
         PathSettings {
             statements_path: TESTING_STATEMENT_PATH.to_owned(),
             database_path: TESTING_DATABASE_PATH.to_owned(),
+            backup_path: TESTING_BACKUP_PATH.to_owned(),
+            templates_path: TESTING_TEMPLATE_PATH.to_owned(),
+            documents_path: TESTING_DOCUMENTS_PATH.to_owned(),
+        }
+    }
+}
+
+impl PathSettings {
+    pub fn database_file(&self) -> PathBuf {
+        Path::new(&self.database_path).join(DATABASE_FILE)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupSettings {
+    pub scheduled: bool,
+    pub check_interval_minutes: u64,
+    pub daily_retention: usize,
+    pub weekly_retention: usize,
+    pub monthly_retention: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            scheduled: true,
+            check_interval_minutes: 60,
+            daily_retention: 7,
+            weekly_retention: 4,
+            monthly_retention: 12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionSettings {
+    pub enabled: bool,
+    // Entered at startup and only ever kept in memory
+    #[serde(skip)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    Tls,
+}
+
+/// SMTP server and message templates for emailing statements. The subject and body
+/// templates can use {name}, {month}, {amount_due}, {property}, {company} and {invoice}.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: String,
+    pub password: String,
+    pub from_address: String,
+    pub subject_template: String,
+    pub body_template: String,
+    // Sends the HTML statement as the message, with the body template as the plain text part
+    pub html_statement: bool,
+    pub max_attempts: u32,
+    pub retry_interval_minutes: u64,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        EmailSettings {
+            enabled: false,
+            host: String::from("localhost"),
+            port: 587,
+            security: SmtpSecurity::StartTls,
+            username: String::new(),
+            password: String::new(),
+            from_address: String::new(),
+            subject_template: String::from("{company} statement {invoice} for {month}"),
+            body_template: String::from(
+                "Hello {name},\n\nAttached is your statement for {month} at {property}. \
+                 The amount due is ${amount_due}.\n\nThank you,\n{company}",
+            ),
+            html_statement: true,
+            max_attempts: 5,
+            retry_interval_minutes: 15,
+        }
+    }
+}
+
+/// How invoice numbers are built. The format can use {prefix}, {property} (the property
+/// id), {year} and {seq}. Numbers count up from 1 separately for every distinct value of the
+/// rest of the format, so the default restarts each year.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InvoiceSettings {
+    pub number_format: String,
+    pub prefix: String,
+    // Used in place of the prefix for credit memos, which are numbered separately
+    pub credit_memo_prefix: String,
+    pub sequence_digits: usize,
+    // Days after the end of the billing period that payment is due
+    pub due_days: u64,
+    // How charges are prorated for months the leaseholder only occupies part of
+    pub proration: ProrationMethod,
+}
+
+impl Default for InvoiceSettings {
+    fn default() -> Self {
+        InvoiceSettings {
+            number_format: String::from("{prefix}-{year}-{seq}"),
+            prefix: String::from("INV"),
+            credit_memo_prefix: String::from("CM"),
+            sequence_digits: 4,
+            due_days: 1,
+            proration: ProrationMethod::default(),
         }
     }
 }
 
-pub async fn initialize_data_paths() {
-    match Path::new(TESTING_STATEMENT_PATH).try_exists() {
+/// How often overdue statements are checked for late fees. The fees themselves are set on
+/// each lease.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LateFeeSettings {
+    pub scheduled: bool,
+    pub check_interval_minutes: u64,
+}
+
+impl Default for LateFeeSettings {
+    fn default() -> Self {
+        LateFeeSettings {
+            scheduled: true,
+            check_interval_minutes: 60,
+        }
+    }
+}
+
+/// Sales and rental taxes on charges. Each property picks one of the jurisdictions by name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaxSettings {
+    pub jurisdictions: Vec<TaxJurisdiction>,
+}
+
+impl TaxSettings {
+    pub fn jurisdiction(&self, name: &str) -> Option<&TaxJurisdiction> {
+        self.jurisdictions
+            .iter()
+            .find(|jurisdiction| jurisdiction.name == name)
+    }
+}
+
+/// How far ahead of an expiry date a stored document shows up in the reminders, and a
+/// tenant's insurance certificate is flagged as expiring.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocumentSettings {
+    pub reminder_days: i64,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        DocumentSettings { reminder_days: 30 }
+    }
+}
+
+/// Reads the settings file, writing out the defaults the first time so they can be edited.
+pub fn load_settings() -> AppSettings {
+    match std::fs::read_to_string(SETTINGS_FILE) {
+        Ok(contents) => match toml::from_str(&contents) {
+            Ok(settings) => settings,
+            Err(e) => {
+                println!("Failed to parse settings, using defaults: {}", e);
+                AppSettings::default()
+            }
+        },
+        Err(_) => {
+            let settings = AppSettings::default();
+            match save_settings(&settings) {
+                Ok(_) => println!("Created default settings file"),
+                Err(e) => println!("Failed to create settings file: {}", e),
+            }
+            settings
+        }
+    }
+}
+
+pub fn save_settings(settings: &AppSettings) -> std::io::Result<()> {
+    let contents = toml::to_string_pretty(settings)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    std::fs::write(SETTINGS_FILE, contents)
+}
+
+pub async fn initialize_data_paths(settings: &PathSettings) {
+    create_data_path(&settings.statements_path, "Statement").await;
+    create_data_path(&settings.backup_path, "Backup").await;
+    create_data_path(&settings.templates_path, "Template").await;
+    create_data_path(&settings.documents_path, "Document").await;
+}
+
+async fn create_data_path(path: &str, label: &str) {
+    match Path::new(path).try_exists() {
         Ok(o) => {
             if o {
-                println!("Statement path already created")
+                println!("{} path already created", label)
             } else {
-                match fs::create_dir(TESTING_STATEMENT_PATH).await {
-                    Ok(_) => println!("Successfully Created Statement Directory"),
-                    Err(e) => println!("Failed to create statement directory: {}", e),
+                match fs::create_dir_all(path).await {
+                    Ok(_) => println!("Successfully Created {} Directory", label),
+                    Err(e) => println!("Failed to create {} directory: {}", label, e),
                 }
             }
         }
-        Err(e) => println!("FAILED CHECKING STATEMENT PATH: {}", e),
+        Err(e) => println!("FAILED CHECKING {} PATH: {}", label.to_uppercase(), e),
+    }
+}
+
+/// Settings with every data path in a fresh directory under the system temp directory,
+/// for tests and the billing scenarios, which mustn't touch the user's data.
+pub fn scratch_settings(name: &str) -> AppSettings {
+    let dir = std::env::temp_dir().join(format!("hestia_{name}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = |sub: &str| {
+        let path = dir.join(sub);
+        std::fs::create_dir_all(&path).unwrap();
+        format!("{}/", path.to_string_lossy())
+    };
+    AppSettings {
+        paths: PathSettings {
+            statements_path: path("statements"),
+            database_path: path(""),
+            backup_path: path("backups"),
+            templates_path: path("templates"),
+            documents_path: path("documents"),
+        },
+        ..AppSettings::default()
     }
 }
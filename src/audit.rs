@@ -0,0 +1,280 @@
+use std::fmt;
+
+use chrono::{Local, NaiveDateTime};
+use serde_json::{Map, Number, Value};
+use sqlx::{sqlite::SqliteRow, Column, FromRow, Row, SqliteConnection, TypeInfo, ValueRef};
+
+use crate::{database::TIMESTAMP_FORMAT, AuditEntryInput};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityType {
+    Property,
+    Leaseholder,
+    Lease,
+    Expense,
+    Statement,
+    PropertyBill,
+    BillInstallment,
+    SalesReport,
+    MeterReading,
+    Document,
+    InsuranceCertificate,
+    Letter,
+    LateFee,
+    OutboxEmail,
+    MaintenanceRequest,
+}
+
+impl fmt::Display for EntityType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            EntityType::Property => String::from("Property"),
+            EntityType::Leaseholder => String::from("Leaseholder"),
+            EntityType::Lease => String::from("Lease"),
+            EntityType::Expense => String::from("Expense"),
+            EntityType::Statement => String::from("Statement"),
+            EntityType::PropertyBill => String::from("PropertyBill"),
+            EntityType::BillInstallment => String::from("BillInstallment"),
+            EntityType::SalesReport => String::from("SalesReport"),
+            EntityType::MeterReading => String::from("MeterReading"),
+            EntityType::Document => String::from("Document"),
+            EntityType::InsuranceCertificate => String::from("InsuranceCertificate"),
+            EntityType::Letter => String::from("Letter"),
+            EntityType::LateFee => String::from("LateFee"),
+            EntityType::OutboxEmail => String::from("OutboxEmail"),
+            EntityType::MaintenanceRequest => String::from("MaintenanceRequest"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl EntityType {
+    pub fn parse_string(entity_type: &str) -> Option<EntityType> {
+        match entity_type {
+            "Property" => Some(EntityType::Property),
+            "Leaseholder" => Some(EntityType::Leaseholder),
+            "Lease" => Some(EntityType::Lease),
+            "Expense" => Some(EntityType::Expense),
+            "Statement" => Some(EntityType::Statement),
+            "PropertyBill" => Some(EntityType::PropertyBill),
+            "BillInstallment" => Some(EntityType::BillInstallment),
+            "SalesReport" => Some(EntityType::SalesReport),
+            "MeterReading" => Some(EntityType::MeterReading),
+            "Document" => Some(EntityType::Document),
+            "InsuranceCertificate" => Some(EntityType::InsuranceCertificate),
+            "Letter" => Some(EntityType::Letter),
+            "LateFee" => Some(EntityType::LateFee),
+            "OutboxEmail" => Some(EntityType::OutboxEmail),
+            "MaintenanceRequest" => Some(EntityType::MaintenanceRequest),
+            _ => None,
+        }
+    }
+
+    fn table(&self) -> &'static str {
+        match self {
+            EntityType::Property => "properties",
+            EntityType::Leaseholder => "leaseholders",
+            EntityType::Lease => "leases",
+            EntityType::Expense => "expenses",
+            EntityType::Statement => "statements",
+            EntityType::PropertyBill => "property_bills",
+            EntityType::BillInstallment => "bill_installments",
+            EntityType::SalesReport => "sales_reports",
+            EntityType::MeterReading => "meter_readings",
+            EntityType::Document => "documents",
+            EntityType::InsuranceCertificate => "insurance_certificates",
+            EntityType::Letter => "letters",
+            EntityType::LateFee => "late_fees",
+            EntityType::OutboxEmail => "email_outbox",
+            EntityType::MaintenanceRequest => "maintenance_requests",
+        }
+    }
+
+    fn id_column(&self) -> &'static str {
+        match self {
+            EntityType::Property => "property_id",
+            EntityType::Leaseholder => "leaseholder_id",
+            EntityType::Lease => "lease_id",
+            EntityType::Expense => "expense_id",
+            EntityType::Statement => "statement_id",
+            EntityType::PropertyBill => "bill_id",
+            EntityType::BillInstallment => "installment_id",
+            EntityType::SalesReport => "sales_report_id",
+            EntityType::MeterReading => "meter_reading_id",
+            EntityType::Document => "document_id",
+            EntityType::InsuranceCertificate => "certificate_id",
+            EntityType::Letter => "letter_id",
+            EntityType::LateFee => "late_fee_id",
+            EntityType::OutboxEmail => "email_id",
+            EntityType::MaintenanceRequest => "request_id",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            AuditAction::Create => String::from("Create"),
+            AuditAction::Update => String::from("Update"),
+            AuditAction::Delete => String::from("Delete"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl AuditAction {
+    pub fn parse_string(action: &str) -> AuditAction {
+        match action {
+            "Create" => AuditAction::Create,
+            "Delete" => AuditAction::Delete,
+            _ => AuditAction::Update,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: u32,
+    pub entity_type: EntityType,
+    pub entity_id: u32,
+    pub action: AuditAction,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub timestamp: NaiveDateTime,
+    pub user: String,
+}
+
+impl AuditEntry {
+    pub fn convert_to_slint(&self) -> AuditEntryInput {
+        let copy = self.clone();
+        AuditEntryInput {
+            id: self.id as i32,
+            entity: format!("{} #{}", self.entity_type, self.entity_id).into(),
+            action: self.action.to_string().into(),
+            timestamp: self.timestamp.format(TIMESTAMP_FORMAT).to_string().into(),
+            user: copy.user.into(),
+            before: copy.before.unwrap_or_default().into(),
+            after: copy.after.unwrap_or_default().into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for AuditEntry {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let id: u32 = row.try_get("audit_id")?;
+        let entity_type: String = row.try_get("entity_type")?;
+        let entity_id: u32 = row.try_get("entity_id")?;
+        let action: String = row.try_get("action")?;
+        let before: Option<String> = row.try_get("before_snapshot")?;
+        let after: Option<String> = row.try_get("after_snapshot")?;
+        let timestamp: String = row.try_get("timestamp")?;
+        let user: String = row.try_get("user")?;
+
+        let entity_type = EntityType::parse_string(&entity_type).ok_or_else(|| {
+            sqlx::Error::Decode(format!("Unknown audit entity type: {entity_type}").into())
+        })?;
+        let timestamp = NaiveDateTime::parse_from_str(&timestamp, TIMESTAMP_FORMAT)
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+        Ok(AuditEntry {
+            id,
+            entity_type,
+            entity_id,
+            action: AuditAction::parse_string(&action),
+            before,
+            after,
+            timestamp,
+            user,
+        })
+    }
+}
+
+pub fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| String::from("unknown"))
+}
+
+/// Reads the current state of a record as a JSON object keyed by column name.
+/// Returns `None` when the record does not exist.
+pub async fn snapshot(
+    conn: &mut SqliteConnection,
+    entity_type: EntityType,
+    entity_id: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    let qry = format!(
+        "SELECT * FROM {} WHERE {} = ?",
+        entity_type.table(),
+        entity_type.id_column()
+    );
+    let row = sqlx::query(&qry)
+        .bind(entity_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(row.map(|r| row_to_json(&r).to_string()))
+}
+
+/// Appends an entry to the audit log. Must be called on the same transaction as the
+/// mutation it describes so that the log and the data can never disagree.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    entity_type: EntityType,
+    entity_id: i64,
+    action: AuditAction,
+    before: Option<String>,
+) -> Result<(), sqlx::Error> {
+    let after = match action {
+        AuditAction::Delete => None,
+        AuditAction::Create | AuditAction::Update => {
+            snapshot(&mut *conn, entity_type, entity_id).await?
+        }
+    };
+    sqlx::query(
+        "INSERT INTO audit_log (entity_type, entity_id, action, before_snapshot, after_snapshot, timestamp, user) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(entity_type.to_string())
+        .bind(entity_id)
+        .bind(action.to_string())
+        .bind(before)
+        .bind(after)
+        .bind(Local::now().naive_local().format(TIMESTAMP_FORMAT).to_string())
+        .bind(current_user())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+fn row_to_json(row: &SqliteRow) -> Value {
+    let mut object = Map::new();
+    for column in row.columns() {
+        let index = column.ordinal();
+        let value = match row.try_get_raw(index) {
+            Ok(raw) if raw.is_null() => Value::Null,
+            Ok(raw) => match raw.type_info().name() {
+                "INTEGER" => row
+                    .try_get::<i64, _>(index)
+                    .map(Value::from)
+                    .unwrap_or(Value::Null),
+                "REAL" => row
+                    .try_get::<f64, _>(index)
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null),
+                _ => row
+                    .try_get::<String, _>(index)
+                    .map(Value::from)
+                    .unwrap_or(Value::Null),
+            },
+            Err(_) => Value::Null,
+        };
+        object.insert(column.name().to_owned(), value);
+    }
+    Value::Object(object)
+}
@@ -0,0 +1,419 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, Local, NaiveDateTime};
+use slint::ComponentHandle;
+use sqlx::{ConnectOptions, Connection, Sqlite};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    app_settings::{AppSettings, PathSettings},
+    database, App, BackupInput, Backups,
+};
+
+const BACKUP_PREFIX: &str = "hestia_";
+const BACKUP_EXTENSION: &str = "db";
+const FILE_TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+const PENDING_RESTORE_SUFFIX: &str = ".restore-pending";
+const REQUIRED_TABLES: [&str; 5] = [
+    "properties",
+    "leaseholders",
+    "leases",
+    "expenses",
+    "statements",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupKind {
+    Daily,
+    Weekly,
+    Monthly,
+    Manual,
+    PreMigration,
+    PreRestore,
+}
+
+impl fmt::Display for BackupKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            BackupKind::Daily => String::from("daily"),
+            BackupKind::Weekly => String::from("weekly"),
+            BackupKind::Monthly => String::from("monthly"),
+            BackupKind::Manual => String::from("manual"),
+            BackupKind::PreMigration => String::from("pre-migration"),
+            BackupKind::PreRestore => String::from("pre-restore"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl BackupKind {
+    pub fn parse_string(kind: &str) -> Option<BackupKind> {
+        match kind {
+            "daily" => Some(BackupKind::Daily),
+            "weekly" => Some(BackupKind::Weekly),
+            "monthly" => Some(BackupKind::Monthly),
+            "manual" => Some(BackupKind::Manual),
+            "pre-migration" => Some(BackupKind::PreMigration),
+            "pre-restore" => Some(BackupKind::PreRestore),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    Database(sqlx::Error),
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupError::Database(e) => write!(f, "database error: {e}"),
+            BackupError::Io(e) => write!(f, "file error: {e}"),
+            BackupError::Invalid(reason) => write!(f, "invalid backup: {reason}"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(e: sqlx::Error) -> Self {
+        BackupError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupFile {
+    pub path: PathBuf,
+    pub kind: BackupKind,
+    pub created: NaiveDateTime,
+}
+
+impl BackupFile {
+    /// Backup files are named `hestia_<kind>_<timestamp>.db`.
+    fn from_path(path: PathBuf) -> Option<BackupFile> {
+        if path.extension()? != BACKUP_EXTENSION {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?.strip_prefix(BACKUP_PREFIX)?;
+        let (kind, timestamp) = stem.split_once('_')?;
+        let kind = BackupKind::parse_string(kind)?;
+        let created = NaiveDateTime::parse_from_str(timestamp, FILE_TIMESTAMP_FORMAT).ok()?;
+        Some(BackupFile {
+            path,
+            kind,
+            created,
+        })
+    }
+
+    pub fn convert_to_slint(&self) -> BackupInput {
+        BackupInput {
+            path: self.path.to_string_lossy().to_string().into(),
+            kind: self.kind.to_string().into(),
+            created: self.created.format("%Y-%m-%d %H:%M").to_string().into(),
+        }
+    }
+}
+
+/// Writes a consistent copy of the live database with `VACUUM INTO`, then checks the copy
+/// before rotating old backups out. An encrypted database is copied with its key, so the
+/// backup needs the same passphrase to open.
+pub async fn create_backup(
+    pool: &sqlx::Pool<Sqlite>,
+    settings: &AppSettings,
+    kind: BackupKind,
+) -> Result<PathBuf, BackupError> {
+    std::fs::create_dir_all(&settings.paths.backup_path)?;
+    let path = Path::new(&settings.paths.backup_path).join(format!(
+        "{}{}_{}.{}",
+        BACKUP_PREFIX,
+        kind,
+        Local::now().naive_local().format(FILE_TIMESTAMP_FORMAT),
+        BACKUP_EXTENSION
+    ));
+    println!("Creating {} backup at {}", kind, path.display());
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+
+    if let Err(e) = verify_backup(&path, settings.encryption.passphrase.as_deref()).await {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+    rotate_backups(settings)?;
+    Ok(path)
+}
+
+/// Checks that a file is an intact Hestia database before it is trusted as a backup or
+/// restored over the live database.
+pub async fn verify_backup(path: &Path, passphrase: Option<&str>) -> Result<(), BackupError> {
+    if !path.is_file() {
+        return Err(BackupError::Invalid(format!(
+            "{} does not exist",
+            path.display()
+        )));
+    }
+    let mut conn = database::connect_options(path, passphrase)
+        .read_only(true)
+        .connect()
+        .await?;
+
+    // An encrypted file opened with the wrong key looks like garbage to SQLite
+    let integrity: String = match sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_one(&mut conn)
+        .await
+    {
+        Ok(integrity) => integrity,
+        Err(_) => {
+            conn.close().await?;
+            return Err(BackupError::Invalid(format!(
+                "{} is not a database or is encrypted with a different passphrase",
+                path.display()
+            )));
+        }
+    };
+    if integrity != "ok" {
+        conn.close().await?;
+        return Err(BackupError::Invalid(format!(
+            "integrity check failed: {integrity}"
+        )));
+    }
+
+    let tables: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .fetch_all(&mut conn)
+            .await?;
+    conn.close().await?;
+    for required in REQUIRED_TABLES {
+        if !tables.iter().any(|t| t == required) {
+            return Err(BackupError::Invalid(format!("missing table {required}")));
+        }
+    }
+    Ok(())
+}
+
+/// Lists backups in the backup directory, newest first.
+pub fn list_backups(settings: &PathSettings) -> Vec<BackupFile> {
+    let mut backups: Vec<BackupFile> = match std::fs::read_dir(&settings.backup_path) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| BackupFile::from_path(e.path()))
+            .collect(),
+        Err(e) => {
+            println!("Failed to read backup directory: {}", e);
+            vec![]
+        }
+    };
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+    backups
+}
+
+/// Removes scheduled backups beyond each kind's retention count. Manual, pre-migration and
+/// pre-restore backups are never removed automatically.
+pub fn rotate_backups(settings: &AppSettings) -> Result<(), BackupError> {
+    let backups = list_backups(&settings.paths);
+    for (kind, retention) in [
+        (BackupKind::Daily, settings.backup.daily_retention),
+        (BackupKind::Weekly, settings.backup.weekly_retention),
+        (BackupKind::Monthly, settings.backup.monthly_retention),
+    ] {
+        for expired in backups.iter().filter(|b| b.kind == kind).skip(retention) {
+            println!("Removing expired backup {}", expired.path.display());
+            std::fs::remove_file(&expired.path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates whichever of the daily, weekly and monthly backups are due.
+pub async fn run_scheduled_backups(
+    pool: &sqlx::Pool<Sqlite>,
+    settings: &AppSettings,
+) -> Result<(), BackupError> {
+    let now = Local::now().naive_local();
+    let backups = list_backups(&settings.paths);
+    let latest = |kind: BackupKind| {
+        backups
+            .iter()
+            .filter(|b| b.kind == kind)
+            .map(|b| b.created)
+            .max()
+    };
+
+    if latest(BackupKind::Daily).is_none_or(|t| t.date() != now.date()) {
+        create_backup(pool, settings, BackupKind::Daily).await?;
+    }
+    if latest(BackupKind::Weekly).is_none_or(|t| (now - t).num_days() >= 7) {
+        create_backup(pool, settings, BackupKind::Weekly).await?;
+    }
+    if latest(BackupKind::Monthly)
+        .is_none_or(|t| t.year() != now.year() || t.month() != now.month())
+    {
+        create_backup(pool, settings, BackupKind::Monthly).await?;
+    }
+    Ok(())
+}
+
+fn pending_restore_file(settings: &PathSettings) -> PathBuf {
+    let mut path = settings.database_file().into_os_string();
+    path.push(PENDING_RESTORE_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// Validates a backup and stages it to replace the live database the next time Hestia
+/// starts, since the running app holds the database open.
+pub async fn stage_restore(backup: &Path, settings: &AppSettings) -> Result<(), BackupError> {
+    verify_backup(backup, settings.encryption.passphrase.as_deref()).await?;
+    std::fs::copy(backup, pending_restore_file(&settings.paths))?;
+    println!("Staged restore of {}", backup.display());
+    Ok(())
+}
+
+/// Replaces the live database with a staged restore, if there is one. Must run before the
+/// database pool is opened. The current database is backed up first.
+pub async fn apply_pending_restore(settings: &AppSettings) -> Result<bool, BackupError> {
+    let pending = pending_restore_file(&settings.paths);
+    if !pending.is_file() {
+        return Ok(false);
+    }
+    let passphrase = settings.encryption.passphrase.as_deref();
+    verify_backup(&pending, passphrase).await?;
+
+    let database_file = settings.paths.database_file();
+    if database_file.is_file() {
+        let pool =
+            sqlx::SqlitePool::connect_with(database::connect_options(&database_file, passphrase))
+                .await?;
+        let backup = create_backup(&pool, settings, BackupKind::PreRestore).await;
+        pool.close().await;
+        backup?;
+    }
+
+    remove_sidecar_files(&database_file);
+    std::fs::rename(&pending, &database_file)?;
+    println!("Restored database from staged backup");
+    Ok(true)
+}
+
+/// Removes the journal files SQLite keeps next to a database, so they are not applied to a
+/// database file that replaces it.
+pub fn remove_sidecar_files(database_file: &Path) {
+    for suffix in ["-journal", "-wal", "-shm"] {
+        let mut sidecar = database_file.to_path_buf().into_os_string();
+        sidecar.push(suffix);
+        let _ = std::fs::remove_file(PathBuf::from(sidecar));
+    }
+}
+
+pub enum BackupMessage {
+    BackupNow,
+    Restore(String),
+    Refresh,
+    Quit,
+}
+
+pub struct BackupWorker {
+    pub channel: UnboundedSender<BackupMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl BackupWorker {
+    pub fn new(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        settings: &AppSettings,
+        app: slint::Weak<App>,
+    ) -> Self {
+        println!("Create new Backup Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            let settings = settings.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(backup_worker_loop(new_pool, settings, app, r))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(BackupMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn backup_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    settings: AppSettings,
+    app: slint::Weak<App>,
+    mut r: UnboundedReceiver<BackupMessage>,
+) {
+    let mut schedule = tokio::time::interval(std::time::Duration::from_secs(
+        settings.backup.check_interval_minutes.max(1) * 60,
+    ));
+    loop {
+        let status = tokio::select! {
+            _ = schedule.tick() => {
+                if !settings.backup.scheduled {
+                    continue;
+                }
+                match run_scheduled_backups(&pool, &settings).await {
+                    Ok(_) => None,
+                    Err(e) => Some(format!("Scheduled backup failed: {e}")),
+                }
+            }
+            m = r.recv() => match m {
+                Some(BackupMessage::BackupNow) => {
+                    match create_backup(&pool, &settings, BackupKind::Manual).await {
+                        Ok(path) => Some(format!("Backed up to {}", path.display())),
+                        Err(e) => Some(format!("Backup failed: {e}")),
+                    }
+                }
+                Some(BackupMessage::Restore(path)) => {
+                    match stage_restore(Path::new(&path), &settings).await {
+                        Ok(_) => Some(String::from("Restore staged. Restart Hestia to finish restoring.")),
+                        Err(e) => Some(format!("Restore failed: {e}")),
+                    }
+                }
+                Some(BackupMessage::Refresh) => None,
+                Some(BackupMessage::Quit) | None => {
+                    println!("Quitting");
+                    break;
+                }
+            },
+        };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+
+        let backups: Vec<BackupInput> = list_backups(&settings.paths)
+            .iter()
+            .map(BackupFile::convert_to_slint)
+            .collect();
+        let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+            let backup_global = handle.global::<Backups>();
+            backup_global.set_backups(slint::ModelRc::new(slint::VecModel::from(backups)));
+            if let Some(status) = status {
+                backup_global.set_status(status.into());
+            }
+        });
+        match upgrade_res {
+            Ok(_) => (),
+            Err(e) => println!("Failed to upgrade ui: {e}"),
+        };
+    }
+}
@@ -0,0 +1,869 @@
+use chrono::{Datelike, NaiveDate};
+
+use crate::{
+    expenses::Expense,
+    lease::{ChargeCategory, StatementLine},
+    leaseholders::Leaseholder,
+};
+
+/// A limit on how much a CAM charge may grow each year. A cap with no category limits all
+/// the CAM categories together.
+#[derive(Debug, Clone, Copy)]
+pub struct CamCap {
+    pub category: Option<ChargeCategory>,
+    pub percent: f32,
+    // A cumulative cap compounds from the base year, so growth not used one year can be
+    // used later. A non-cumulative cap only allows `percent` over the year before.
+    pub cumulative: bool,
+}
+
+/// The CAM clauses of a commercial lease. They are applied to the building's expenses
+/// before the tenant's share is taken, in the order excluded categories, gross-up, caps,
+/// then the expense stop.
+#[derive(Debug, Clone, Default)]
+pub struct CamClauses {
+    // The tenant pays only the increase over this year's expenses. Caps also count from it.
+    pub base_year: Option<i32>,
+    // The tenant pays only the expenses over this annual amount
+    pub expense_stop: Option<f32>,
+    pub caps: Vec<CamCap>,
+    pub excluded: Vec<ChargeCategory>,
+    // The occupancy the variable expenses are grossed up to, e.g. 0.95
+    pub gross_up: Option<f32>,
+}
+
+// Categories that rise and fall with how much of the building is let
+const VARIABLE: [ChargeCategory; 4] = [
+    ChargeCategory::Electricity,
+    ChargeCategory::Gas,
+    ChargeCategory::GarbageRecycling,
+    ChargeCategory::WaterSewer,
+];
+
+fn parse_category(name: &str) -> Option<ChargeCategory> {
+    ChargeCategory::SHARED_EXPENSES
+        .into_iter()
+        .find(|category| category.to_string().eq_ignore_ascii_case(name.trim()))
+}
+
+impl CamClauses {
+    pub fn encode_to_database_string(&self) -> String {
+        let mut clauses = vec![];
+        if let Some(year) = self.base_year {
+            clauses.push(format!("Base Year {year}"));
+        }
+        if let Some(stop) = self.expense_stop {
+            clauses.push(format!("Expense Stop {stop}"));
+        }
+        for cap in &self.caps {
+            clauses.push(format!(
+                "Cap {} {} {}",
+                cap.category
+                    .map_or(String::from("All"), |category| category.to_string()),
+                cap.percent,
+                if cap.cumulative {
+                    "Cumulative"
+                } else {
+                    "Non-Cumulative"
+                }
+            ));
+        }
+        for category in &self.excluded {
+            clauses.push(format!("Exclude {category}"));
+        }
+        if let Some(occupancy) = self.gross_up {
+            clauses.push(format!("Gross Up {occupancy}"));
+        }
+        clauses.join("; ")
+    }
+
+    /// Reads back clauses written by `encode_to_database_string`, skipping any it does
+    /// not recognise.
+    pub fn decode_from_database_string(encoded: &str) -> CamClauses {
+        let mut clauses = CamClauses::default();
+        for clause in encoded.split(';').map(str::trim) {
+            if let Some(year) = clause.strip_prefix("Base Year ") {
+                clauses.base_year = year.trim().parse().ok();
+            } else if let Some(stop) = clause.strip_prefix("Expense Stop ") {
+                clauses.expense_stop = stop.trim().parse().ok();
+            } else if let Some(cap) = clause.strip_prefix("Cap ") {
+                // The category name may have spaces, the percent and kind never do
+                let mut words = cap.rsplitn(3, ' ');
+                let (Some(kind), Some(percent), Some(category)) =
+                    (words.next(), words.next(), words.next())
+                else {
+                    continue;
+                };
+                let category = match category {
+                    "All" => None,
+                    name => match parse_category(name) {
+                        Some(category) => Some(category),
+                        None => continue,
+                    },
+                };
+                if let Ok(percent) = percent.parse() {
+                    clauses.caps.push(CamCap {
+                        category,
+                        percent,
+                        cumulative: kind == "Cumulative",
+                    });
+                }
+            } else if let Some(category) = clause.strip_prefix("Exclude ") {
+                clauses.excluded.extend(parse_category(category));
+            } else if let Some(occupancy) = clause.strip_prefix("Gross Up ") {
+                clauses.gross_up = occupancy.trim().parse().ok();
+            }
+        }
+        clauses
+    }
+}
+
+/// The building's expenses and occupancy over the years, which base years, caps and
+/// gross-ups are measured against.
+#[derive(Debug, Clone, Default)]
+pub struct CamHistory {
+    pub expenses: Vec<Expense>,
+    pub num_units: u32,
+    // When each of the property's leaseholders moved in and out
+    pub tenancies: Vec<(NaiveDate, Option<NaiveDate>)>,
+}
+
+impl CamHistory {
+    pub fn new(expenses: Vec<Expense>, num_units: u32, leaseholders: &[Leaseholder]) -> Self {
+        CamHistory {
+            expenses,
+            num_units,
+            tenancies: leaseholders
+                .iter()
+                .map(|leaseholder| (leaseholder.move_in_date, leaseholder.move_out_date))
+                .collect(),
+        }
+    }
+
+    /// The building's expenses from `from` to `to` by CAM category.
+    pub fn totals(&self, from: NaiveDate, to: NaiveDate) -> Vec<(ChargeCategory, f32)> {
+        ChargeCategory::SHARED_EXPENSES
+            .into_iter()
+            .map(|category| {
+                let total = self
+                    .expenses
+                    .iter()
+                    .filter(|expense| expense.date >= from && expense.date <= to)
+                    .filter(|expense| {
+                        ChargeCategory::for_expense(&expense.expense_type) == category
+                    })
+                    .fold(0.0, |sum, expense| sum + expense.amount);
+                (category, total)
+            })
+            .collect()
+    }
+
+    /// The average fraction of units let from `from` to `to`, or None when the property
+    /// has no units on record.
+    pub fn occupancy(&self, from: NaiveDate, to: NaiveDate) -> Option<f32> {
+        if self.num_units == 0 || to < from {
+            return None;
+        }
+        let days = (to - from).num_days() + 1;
+        let occupied_days = self
+            .tenancies
+            .iter()
+            .map(|(move_in, move_out)| {
+                let start = from.max(*move_in);
+                let end = move_out.map_or(to, |move_out| to.min(move_out));
+                ((end - start).num_days() + 1).max(0)
+            })
+            .sum::<i64>();
+        Some((occupied_days as f32 / (days as f32 * self.num_units as f32)).min(1.0))
+    }
+}
+
+/// The building's CAM expenses after the lease's clauses, and how each clause changed them.
+#[derive(Debug, Clone)]
+pub struct CamAdjustment {
+    pub amounts: Vec<(ChargeCategory, f32)>,
+    pub trail: Vec<String>,
+}
+
+fn year_start(year: i32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, 1, 1).unwrap()
+}
+
+fn year_end(year: i32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, 12, 31).unwrap()
+}
+
+fn sum(amounts: &[(ChargeCategory, f32)]) -> f32 {
+    amounts.iter().fold(0.0, |sum, (_, amount)| sum + amount)
+}
+
+fn scale(amounts: &mut [(ChargeCategory, f32)], factor: f32) {
+    for (_, amount) in amounts {
+        *amount *= factor;
+    }
+}
+
+fn cap_name(cap: &CamCap) -> String {
+    cap.category
+        .map_or(String::from("CAM"), |category| category.to_string())
+}
+
+impl CamClauses {
+    /// Applies the clauses to the building's CAM expenses from `from` to `to`. Annual caps
+    /// and stops are scaled to the length of the period. `first_year` is where caps count
+    /// from when the lease has no base year.
+    pub fn apply(
+        &self,
+        history: &CamHistory,
+        from: NaiveDate,
+        to: NaiveDate,
+        first_year: i32,
+        amounts: &[(ChargeCategory, f32)],
+    ) -> CamAdjustment {
+        let mut trail = vec![];
+        let mut amounts = self.normalize(history, from, to, amounts, Some(&mut trail));
+        let year = from.year();
+        let year_days = (year_end(year) - year_start(year)).num_days() as f32 + 1.0;
+        let part_of_year = ((to - from).num_days() as f32 + 1.0) / year_days;
+
+        // Each category's own cap, then the caps on them all together
+        for cap in self.caps.iter().filter(|cap| cap.category.is_some()) {
+            let Some(allowed) = self.allowed(history, cap, year, first_year) else {
+                continue;
+            };
+            let allowed = allowed * part_of_year;
+            if let Some((_, amount)) = amounts
+                .iter_mut()
+                .find(|(category, _)| Some(*category) == cap.category)
+            {
+                if *amount > allowed {
+                    trail.push(self.cap_trail(cap, first_year, *amount, allowed));
+                    *amount = allowed;
+                }
+            }
+        }
+        for cap in self.caps.iter().filter(|cap| cap.category.is_none()) {
+            let Some(allowed) = self.allowed(history, cap, year, first_year) else {
+                continue;
+            };
+            let allowed = allowed * part_of_year;
+            let total = sum(&amounts);
+            if total > allowed {
+                trail.push(self.cap_trail(cap, first_year, total, allowed));
+                scale(&mut amounts, allowed / total);
+            }
+        }
+
+        if let Some((stop, source)) = self.stop(history) {
+            let stop = stop * part_of_year;
+            let total = sum(&amounts);
+            let recoverable = (total - stop).max(0.0);
+            trail.push(format!(
+                "{source} of ${:.2}: ${:.2} to ${:.2} recoverable",
+                stop, total, recoverable
+            ));
+            scale(
+                &mut amounts,
+                if total > 0.0 {
+                    recoverable / total
+                } else {
+                    0.0
+                },
+            );
+        }
+        CamAdjustment { amounts, trail }
+    }
+
+    /// Drops the excluded categories and grosses up the variable ones to the lease's
+    /// occupancy. Base years and caps are measured on normalized amounts too, so the
+    /// comparison is like for like.
+    fn normalize(
+        &self,
+        history: &CamHistory,
+        from: NaiveDate,
+        to: NaiveDate,
+        amounts: &[(ChargeCategory, f32)],
+        mut trail: Option<&mut Vec<String>>,
+    ) -> Vec<(ChargeCategory, f32)> {
+        let mut normalized = vec![];
+        for (category, amount) in amounts.iter().copied() {
+            if self.excluded.contains(&category) {
+                if let Some(trail) = trail.as_deref_mut() {
+                    trail.push(format!("{category} is excluded by the lease"));
+                }
+                continue;
+            }
+            normalized.push((category, amount));
+        }
+
+        let (Some(target), Some(occupancy)) = (self.gross_up, history.occupancy(from, to)) else {
+            return normalized;
+        };
+        if occupancy <= 0.0 || occupancy >= target {
+            return normalized;
+        }
+        for (category, amount) in normalized.iter_mut() {
+            if !VARIABLE.contains(category) || *amount <= 0.0 {
+                continue;
+            }
+            let grossed_up = *amount * target / occupancy;
+            if let Some(trail) = trail.as_deref_mut() {
+                trail.push(format!(
+                    "{} grossed up from {:.1}% to {:.1}% occupancy: ${:.2} to ${:.2}",
+                    category,
+                    occupancy * 100.0,
+                    target * 100.0,
+                    amount,
+                    grossed_up
+                ));
+            }
+            *amount = grossed_up;
+        }
+        normalized
+    }
+
+    /// A whole year's normalized expenses under the cap, all categories for an aggregate cap.
+    fn annual(&self, history: &CamHistory, cap: &CamCap, year: i32) -> f32 {
+        let (from, to) = (year_start(year), year_end(year));
+        let amounts = self.normalize(history, from, to, &history.totals(from, to), None);
+        match cap.category {
+            Some(category) => amounts
+                .iter()
+                .filter(|(c, _)| *c == category)
+                .fold(0.0, |sum, (_, amount)| sum + amount),
+            None => sum(&amounts),
+        }
+    }
+
+    /// The most the cap allows for `year`, or None in the base year itself and when the
+    /// base year has no expenses to grow from.
+    fn allowed(
+        &self,
+        history: &CamHistory,
+        cap: &CamCap,
+        year: i32,
+        first_year: i32,
+    ) -> Option<f32> {
+        let base = self.base_year.unwrap_or(first_year);
+        if year <= base {
+            return None;
+        }
+        let base_amount = self.annual(history, cap, base);
+        if base_amount <= 0.0 {
+            return None;
+        }
+        let growth = 1.0 + cap.percent;
+        if cap.cumulative {
+            return Some(base_amount * growth.powi(year - base));
+        }
+        let mut capped = base_amount;
+        for prior in base + 1..year {
+            capped = self.annual(history, cap, prior).min(capped * growth);
+        }
+        Some(capped * growth)
+    }
+
+    fn cap_trail(&self, cap: &CamCap, first_year: i32, amount: f32, allowed: f32) -> String {
+        format!(
+            "{} capped at {:.1}% a year, {} from {}: ${:.2} to ${:.2}",
+            cap_name(cap),
+            cap.percent * 100.0,
+            if cap.cumulative {
+                "cumulative"
+            } else {
+                "non-cumulative"
+            },
+            self.base_year.unwrap_or(first_year),
+            amount,
+            allowed
+        )
+    }
+
+    /// The annual expense stop, from the lease or the base year's expenses, and how it
+    /// is described on the statement.
+    fn stop(&self, history: &CamHistory) -> Option<(f32, String)> {
+        if let Some(stop) = self.expense_stop {
+            return Some((stop, String::from("Expense stop")));
+        }
+        let year = self.base_year?;
+        let (from, to) = (year_start(year), year_end(year));
+        let base = sum(&self.normalize(history, from, to, &history.totals(from, to), None));
+        Some((base, format!("Base year {year} expenses")))
+    }
+
+    /// Applies the clauses to a lease's CAM lines for `from` to `to`, leaving each line's
+    /// building expenses itemized and charging its share of what is recoverable. Lines for
+    /// excluded categories are removed. Returns the explanation for the statement.
+    pub fn adjust_lines(
+        &self,
+        history: &CamHistory,
+        from: NaiveDate,
+        to: NaiveDate,
+        first_year: i32,
+        lines: &mut Vec<StatementLine>,
+    ) -> Vec<String> {
+        let amounts: Vec<(ChargeCategory, f32)> = lines
+            .iter()
+            .filter(|line| line.category.is_shared_expense())
+            .map(|line| (line.category, line.base))
+            .collect();
+        let adjustment = self.apply(history, from, to, first_year, &amounts);
+        lines.retain(|line| !self.excluded.contains(&line.category));
+        for line in lines.iter_mut() {
+            if let Some((_, amount)) = adjustment
+                .amounts
+                .iter()
+                .find(|(category, _)| *category == line.category)
+            {
+                line.recover(*amount);
+            }
+        }
+        adjustment.trail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expenses::{ExpenseType, MaintenanceType, UtilitiesType},
+        statements::calculate_share,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn expense(expense_type: ExpenseType, year: i32, amount: f32) -> Expense {
+        Expense::new(0, 1, expense_type, amount, date(year, 6, 1), String::new())
+    }
+
+    fn landscaping(year: i32, amount: f32) -> Expense {
+        expense(
+            ExpenseType::Maintenance(MaintenanceType::Landscaping),
+            year,
+            amount,
+        )
+    }
+
+    fn electricity(year: i32, amount: f32) -> Expense {
+        expense(
+            ExpenseType::Utilities(UtilitiesType::Electricity),
+            year,
+            amount,
+        )
+    }
+
+    /// Landscaping of 10,000 in 2021 and 2022 and 11,000 in 2023, and electricity of
+    /// 4,000 each year.
+    fn history() -> CamHistory {
+        CamHistory {
+            expenses: vec![
+                landscaping(2021, 10000.0),
+                landscaping(2022, 10000.0),
+                landscaping(2023, 11000.0),
+                electricity(2021, 4000.0),
+                electricity(2022, 4000.0),
+                electricity(2023, 4000.0),
+            ],
+            ..CamHistory::default()
+        }
+    }
+
+    fn cap(category: Option<ChargeCategory>, cumulative: bool) -> CamCap {
+        CamCap {
+            category,
+            percent: 0.05,
+            cumulative,
+        }
+    }
+
+    /// The clauses applied to a whole year, with caps counting from 2021.
+    fn full_year(
+        clauses: &CamClauses,
+        history: &CamHistory,
+        year: i32,
+        amounts: &[(ChargeCategory, f32)],
+    ) -> CamAdjustment {
+        clauses.apply(history, year_start(year), year_end(year), 2021, amounts)
+    }
+
+    fn amount(adjustment: &CamAdjustment, category: ChargeCategory) -> f32 {
+        adjustment
+            .amounts
+            .iter()
+            .find(|(c, _)| *c == category)
+            .map(|(_, amount)| *amount)
+            .unwrap()
+    }
+
+    fn assert_amount(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "${actual:.2}, expected ${expected:.2}"
+        );
+    }
+
+    #[test]
+    fn clauses_round_trip_through_the_database_string() {
+        let clauses = CamClauses {
+            base_year: Some(2021),
+            expense_stop: Some(5000.0),
+            caps: vec![
+                cap(Some(ChargeCategory::GarbageRecycling), true),
+                cap(None, false),
+            ],
+            excluded: vec![ChargeCategory::Amenities],
+            gross_up: Some(0.95),
+        };
+        let encoded = clauses.encode_to_database_string();
+        assert_eq!(
+            encoded,
+            "Base Year 2021; Expense Stop 5000; Cap Garbage/Recycling 0.05 Cumulative; \
+             Cap All 0.05 Non-Cumulative; Exclude Amenities; Gross Up 0.95"
+        );
+        let decoded = CamClauses::decode_from_database_string(&encoded);
+        assert_eq!(decoded.encode_to_database_string(), encoded);
+        assert_eq!(
+            decoded.caps[0].category,
+            Some(ChargeCategory::GarbageRecycling)
+        );
+        assert!(decoded.caps[0].cumulative);
+        assert!(!decoded.caps[1].cumulative);
+
+        let unknown =
+            CamClauses::decode_from_database_string("Cap Parking 0.05 Cumulative; Nonsense");
+        assert!(unknown.caps.is_empty());
+        assert_eq!(unknown.encode_to_database_string(), "");
+    }
+
+    #[test]
+    fn cumulative_cap_carries_unused_growth_forward() {
+        let clauses = CamClauses {
+            caps: vec![cap(Some(ChargeCategory::Landscaping), true)],
+            ..CamClauses::default()
+        };
+        // 10,000 x 1.05 x 1.05, though 2022 didn't grow at all
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[
+                (ChargeCategory::Landscaping, 12000.0),
+                (ChargeCategory::Electricity, 4000.0),
+            ],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 11025.0);
+        assert_amount(amount(&adjustment, ChargeCategory::Electricity), 4000.0);
+        assert_eq!(
+            adjustment.trail,
+            vec!["Landscaping capped at 5.0% a year, cumulative from 2021: $12000.00 to $11025.00"]
+        );
+
+        // Under the cap nothing changes
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[(ChargeCategory::Landscaping, 11000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 11000.0);
+        assert!(adjustment.trail.is_empty());
+    }
+
+    #[test]
+    fn non_cumulative_cap_grows_from_the_year_before() {
+        let clauses = CamClauses {
+            caps: vec![cap(Some(ChargeCategory::Landscaping), false)],
+            ..CamClauses::default()
+        };
+        // 2022 stayed at 10,000, so 2023 may only be 5% over that
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[(ChargeCategory::Landscaping, 12000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 10500.0);
+        assert_eq!(
+            adjustment.trail,
+            vec!["Landscaping capped at 5.0% a year, non-cumulative from 2021: $12000.00 to $10500.00"]
+        );
+
+        // 2023 was itself capped at 10,500, so 2024 is measured from that, not the 11,000 spent
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2024,
+            &[(ChargeCategory::Landscaping, 12000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 11025.0);
+    }
+
+    #[test]
+    fn caps_do_not_apply_in_the_base_year() {
+        let clauses = CamClauses {
+            caps: vec![cap(Some(ChargeCategory::Landscaping), true)],
+            ..CamClauses::default()
+        };
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2021,
+            &[(ChargeCategory::Landscaping, 10000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 10000.0);
+        assert!(adjustment.trail.is_empty());
+
+        // Nor when the base year had nothing to grow from
+        let adjustment = full_year(
+            &clauses,
+            &CamHistory::default(),
+            2023,
+            &[(ChargeCategory::Landscaping, 12000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 12000.0);
+    }
+
+    #[test]
+    fn aggregate_cap_scales_every_category() {
+        let clauses = CamClauses {
+            caps: vec![cap(None, true)],
+            ..CamClauses::default()
+        };
+        // 14,000 in 2021, so 15,435 is allowed in 2023 of the 16,000 spent
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[
+                (ChargeCategory::Landscaping, 12000.0),
+                (ChargeCategory::Electricity, 4000.0),
+            ],
+        );
+        let factor = 15435.0 / 16000.0;
+        assert_amount(
+            amount(&adjustment, ChargeCategory::Landscaping),
+            12000.0 * factor,
+        );
+        assert_amount(
+            amount(&adjustment, ChargeCategory::Electricity),
+            4000.0 * factor,
+        );
+        assert_eq!(
+            adjustment.trail,
+            vec!["CAM capped at 5.0% a year, cumulative from 2021: $16000.00 to $15435.00"]
+        );
+    }
+
+    #[test]
+    fn caps_are_scaled_to_the_period() {
+        let clauses = CamClauses {
+            caps: vec![cap(Some(ChargeCategory::Landscaping), true)],
+            ..CamClauses::default()
+        };
+        let adjustment = clauses.apply(
+            &history(),
+            date(2023, 3, 1),
+            date(2023, 3, 31),
+            2021,
+            &[(ChargeCategory::Landscaping, 1500.0)],
+        );
+        assert_amount(
+            amount(&adjustment, ChargeCategory::Landscaping),
+            11025.0 * 31.0 / 365.0,
+        );
+    }
+
+    #[test]
+    fn base_year_stop_charges_only_the_increase() {
+        let clauses = CamClauses {
+            base_year: Some(2021),
+            ..CamClauses::default()
+        };
+        // 14,000 in the base year, 15,000 now
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[
+                (ChargeCategory::Landscaping, 11000.0),
+                (ChargeCategory::Electricity, 4000.0),
+            ],
+        );
+        assert_amount(
+            amount(&adjustment, ChargeCategory::Landscaping),
+            1000.0 * 11.0 / 15.0,
+        );
+        assert_amount(
+            amount(&adjustment, ChargeCategory::Electricity),
+            1000.0 * 4.0 / 15.0,
+        );
+        assert_eq!(
+            adjustment.trail,
+            vec!["Base year 2021 expenses of $14000.00: $15000.00 to $1000.00 recoverable"]
+        );
+
+        // Nothing is recoverable below the base year
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[(ChargeCategory::Landscaping, 9000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 0.0);
+    }
+
+    #[test]
+    fn base_year_caps_and_stop_together() {
+        let clauses = CamClauses {
+            base_year: Some(2021),
+            caps: vec![cap(Some(ChargeCategory::Landscaping), true)],
+            ..CamClauses::default()
+        };
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[
+                (ChargeCategory::Landscaping, 12000.0),
+                (ChargeCategory::Electricity, 4000.0),
+            ],
+        );
+        // Capped to 11,025 first, then 15,025 less the base year's 14,000
+        assert_amount(sum(&adjustment.amounts), 1025.0);
+        assert_eq!(adjustment.trail.len(), 2);
+        assert!(adjustment.trail[0].starts_with("Landscaping capped"));
+        assert!(adjustment.trail[1].starts_with("Base year 2021 expenses of $14000.00"));
+    }
+
+    #[test]
+    fn expense_stop_is_prorated_to_the_period() {
+        let clauses = CamClauses {
+            expense_stop: Some(3650.0),
+            ..CamClauses::default()
+        };
+        let adjustment = clauses.apply(
+            &CamHistory::default(),
+            date(2023, 4, 1),
+            date(2023, 4, 30),
+            2021,
+            &[(ChargeCategory::Landscaping, 500.0)],
+        );
+        // A stop of 3,650 a year is 300 for April
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 200.0);
+        assert_eq!(
+            adjustment.trail,
+            vec!["Expense stop of $300.00: $500.00 to $200.00 recoverable"]
+        );
+    }
+
+    #[test]
+    fn gross_up_applies_to_variable_expenses_below_the_occupancy() {
+        let tenancy = (date(2020, 1, 1), None);
+        let history = CamHistory {
+            num_units: 10,
+            tenancies: vec![tenancy; 5],
+            ..history()
+        };
+        let clauses = CamClauses {
+            gross_up: Some(0.95),
+            ..CamClauses::default()
+        };
+        let adjustment = full_year(
+            &clauses,
+            &history,
+            2023,
+            &[
+                (ChargeCategory::Electricity, 1000.0),
+                (ChargeCategory::Landscaping, 1000.0),
+            ],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Electricity), 1900.0);
+        assert_amount(amount(&adjustment, ChargeCategory::Landscaping), 1000.0);
+        assert_eq!(
+            adjustment.trail,
+            vec!["Electricity grossed up from 50.0% to 95.0% occupancy: $1000.00 to $1900.00"]
+        );
+
+        // Not when the building is already that full
+        let full = CamHistory {
+            tenancies: vec![tenancy; 10],
+            ..history
+        };
+        let adjustment = full_year(
+            &clauses,
+            &full,
+            2023,
+            &[(ChargeCategory::Electricity, 1000.0)],
+        );
+        assert_amount(amount(&adjustment, ChargeCategory::Electricity), 1000.0);
+        assert!(adjustment.trail.is_empty());
+    }
+
+    #[test]
+    fn excluded_categories_are_dropped() {
+        let clauses = CamClauses {
+            excluded: vec![ChargeCategory::Landscaping],
+            ..CamClauses::default()
+        };
+        let adjustment = full_year(
+            &clauses,
+            &history(),
+            2023,
+            &[
+                (ChargeCategory::Landscaping, 11000.0),
+                (ChargeCategory::Electricity, 4000.0),
+            ],
+        );
+        assert_eq!(
+            adjustment.amounts,
+            vec![(ChargeCategory::Electricity, 4000.0)]
+        );
+        assert_eq!(
+            adjustment.trail,
+            vec!["Landscaping is excluded by the lease"]
+        );
+    }
+
+    #[test]
+    fn adjusted_lines_charge_the_share_of_what_is_recoverable() {
+        let clauses = CamClauses {
+            caps: vec![cap(Some(ChargeCategory::Landscaping), false)],
+            excluded: vec![ChargeCategory::Electricity],
+            ..CamClauses::default()
+        };
+        let mut lines = vec![
+            StatementLine::flat(ChargeCategory::Rent, 1500.0),
+            StatementLine::expense_share(
+                ChargeCategory::Landscaping,
+                Some(0.3),
+                vec![landscaping(2023, 12000.0)],
+            ),
+            StatementLine::expense_share(
+                ChargeCategory::Electricity,
+                Some(0.3),
+                vec![electricity(2023, 4000.0)],
+            ),
+        ];
+        let trail = clauses.adjust_lines(
+            &history(),
+            year_start(2023),
+            year_end(2023),
+            2021,
+            &mut lines,
+        );
+        let categories: Vec<ChargeCategory> = lines.iter().map(|line| line.category).collect();
+        assert_eq!(
+            categories,
+            vec![ChargeCategory::Rent, ChargeCategory::Landscaping]
+        );
+        assert_amount(lines[0].amount, 1500.0);
+        // The building's expenses stay itemized, only the recoverable part is charged
+        assert_amount(lines[1].base, 12000.0);
+        assert_amount(lines[1].recoverable, 10500.0);
+        assert_amount(lines[1].amount, calculate_share(0.3, 10500.0));
+        assert_amount(lines[1].share_of(&landscaping(2023, 12000.0)), 3150.0);
+        assert_eq!(trail.len(), 2);
+    }
+}
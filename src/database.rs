@@ -1,29 +1,340 @@
-use chrono::NaiveDate;
-use sqlx::{migrate::MigrateDatabase, sqlite::SqliteQueryResult, FromRow, Sqlite, SqlitePool};
-use std::result::Result;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteQueryResult},
+    Connection, FromRow, Row, Sqlite, SqliteConnection, SqlitePool,
+};
+use std::{path::Path, result::Result};
 
 use crate::{
-    expenses::*, lease::Lease, leaseholders::Leaseholder, properties::Property,
-    statements::Statement,
+    app_settings::{AppSettings, InvoiceSettings},
+    audit::{self, AuditAction, AuditEntry, EntityType},
+    backup::{self, BackupKind},
+    documents::{DocumentOwner, StoredDocument},
+    email::{EmailStatus, OutboxEmail},
+    expenses::*,
+    insurance::InsuranceCertificate,
+    late_fees::LateFee,
+    lease::{Lease, RecurringCharge},
+    leaseholders::Leaseholder,
+    ledger::{self, LedgerEntry},
+    letters::SentLetter,
+    percentage_rent::SalesReport,
+    properties::Property,
+    property_bills::{Installment, PropertyBill},
+    statement_document::BillingPeriod,
+    statements::{StatementFilter, StatementRecord, StatementStatus},
+    utility_billing::MeterReading,
 };
 
-pub async fn initialize_database() -> sqlx::Pool<Sqlite> {
-    let db_url = String::from("sqlite://sqlite.db");
-    if !Sqlite::database_exists(&db_url).await.unwrap_or(false) {
-        Sqlite::create_database(&db_url).await.unwrap();
-        match create_schema(&db_url).await {
-            Ok(_) => println!("Database created successfully"),
-            Err(e) => panic!("{}", e),
-        }
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// Column changes to existing tables, applied in order on top of `create_schema`.
+// The index of the last applied migration + 1 is kept in PRAGMA user_version.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE properties ADD COLUMN archived_at TEXT null;
+    ALTER TABLE leaseholders ADD COLUMN archived_at TEXT null;",
+    "ALTER TABLE properties ADD COLUMN statement_template TEXT null;",
+    "ALTER TABLE email_outbox ADD COLUMN html_body TEXT null;",
+    "ALTER TABLE statements ADD COLUMN property_id INTEGER null;
+    ALTER TABLE statements ADD COLUMN invoice_number TEXT null;
+    ALTER TABLE statements ADD COLUMN period_start TEXT null;
+    ALTER TABLE statements ADD COLUMN period_end TEXT null;
+    ALTER TABLE statements ADD COLUMN issue_date TEXT null;
+    ALTER TABLE statements ADD COLUMN due_date TEXT null;
+    ALTER TABLE statements ADD COLUMN status TEXT NOT NULL DEFAULT 'draft';
+    ALTER TABLE statements ADD COLUMN pdf_hash TEXT null;
+    CREATE UNIQUE INDEX IF NOT EXISTS statements_invoice_number ON statements (invoice_number);",
+    // Issued statements are corrected by voiding, reissuing or crediting them, never by
+    // editing. Only the payment, status and void reason of an issued row may change.
+    "ALTER TABLE statements ADD COLUMN kind TEXT NOT NULL DEFAULT 'statement';
+    ALTER TABLE statements ADD COLUMN related_statement_id INTEGER null REFERENCES statements(statement_id);
+    ALTER TABLE statements ADD COLUMN note TEXT null;
+    ALTER TABLE statements ADD COLUMN void_reason TEXT null;
+    ALTER TABLE statements ADD COLUMN company_name TEXT null;
+    CREATE TRIGGER IF NOT EXISTS statements_issued_no_update BEFORE UPDATE ON statements
+    WHEN OLD.status != 'draft' AND (
+        NEW.leaseholder_id IS NOT OLD.leaseholder_id OR NEW.property_id IS NOT OLD.property_id
+        OR NEW.invoice_number IS NOT OLD.invoice_number OR NEW.kind IS NOT OLD.kind
+        OR NEW.period_start IS NOT OLD.period_start OR NEW.period_end IS NOT OLD.period_end
+        OR NEW.issue_date IS NOT OLD.issue_date OR NEW.due_date IS NOT OLD.due_date
+        OR NEW.amount_due IS NOT OLD.amount_due OR NEW.statement_path IS NOT OLD.statement_path
+        OR NEW.pdf_hash IS NOT OLD.pdf_hash OR NEW.related_statement_id IS NOT OLD.related_statement_id
+        OR NEW.note IS NOT OLD.note OR NEW.company_name IS NOT OLD.company_name)
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be edited, void or credit them instead');
+    END;
+    CREATE TRIGGER IF NOT EXISTS statements_void_is_final BEFORE UPDATE ON statements
+    WHEN OLD.status = 'void' AND NEW.status != 'void'
+    BEGIN
+        SELECT RAISE(ABORT, 'void statements cannot be reopened');
+    END;
+    CREATE TRIGGER IF NOT EXISTS statements_issued_no_delete BEFORE DELETE ON statements
+    WHEN OLD.status != 'draft'
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be deleted');
+    END;",
+    // late_fees is the part of amount_due carried over from late fees already on the ledger
+    "ALTER TABLE leases ADD COLUMN late_fee_rule TEXT null;
+    ALTER TABLE statements ADD COLUMN late_fees REAL NOT NULL DEFAULT 0;
+    DROP TRIGGER IF EXISTS statements_issued_no_update;
+    CREATE TRIGGER statements_issued_no_update BEFORE UPDATE ON statements
+    WHEN OLD.status != 'draft' AND (
+        NEW.leaseholder_id IS NOT OLD.leaseholder_id OR NEW.property_id IS NOT OLD.property_id
+        OR NEW.invoice_number IS NOT OLD.invoice_number OR NEW.kind IS NOT OLD.kind
+        OR NEW.period_start IS NOT OLD.period_start OR NEW.period_end IS NOT OLD.period_end
+        OR NEW.issue_date IS NOT OLD.issue_date OR NEW.due_date IS NOT OLD.due_date
+        OR NEW.amount_due IS NOT OLD.amount_due OR NEW.statement_path IS NOT OLD.statement_path
+        OR NEW.pdf_hash IS NOT OLD.pdf_hash OR NEW.related_statement_id IS NOT OLD.related_statement_id
+        OR NEW.note IS NOT OLD.note OR NEW.company_name IS NOT OLD.company_name
+        OR NEW.late_fees IS NOT OLD.late_fees)
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be edited, void or credit them instead');
+    END;",
+    // Leases keep their leaseholder so a replaced lease still bills the days before the change
+    "ALTER TABLE leases ADD COLUMN leaseholder_id INTEGER null;
+    UPDATE leases SET leaseholder_id =
+        (SELECT leaseholder_id FROM leaseholders WHERE leaseholders.lease_id = leases.lease_id);
+    ALTER TABLE leaseholders ADD COLUMN move_out_date TEXT null;",
+    // cam_billed is the part of amount_due from CAM charges, which the year end
+    // reconciliation compares with what the lease's CAM clauses allow
+    "ALTER TABLE leases ADD COLUMN cam_clauses TEXT null;
+    ALTER TABLE statements ADD COLUMN cam_billed REAL NOT NULL DEFAULT 0;
+    DROP TRIGGER IF EXISTS statements_issued_no_update;
+    CREATE TRIGGER statements_issued_no_update BEFORE UPDATE ON statements
+    WHEN OLD.status != 'draft' AND (
+        NEW.leaseholder_id IS NOT OLD.leaseholder_id OR NEW.property_id IS NOT OLD.property_id
+        OR NEW.invoice_number IS NOT OLD.invoice_number OR NEW.kind IS NOT OLD.kind
+        OR NEW.period_start IS NOT OLD.period_start OR NEW.period_end IS NOT OLD.period_end
+        OR NEW.issue_date IS NOT OLD.issue_date OR NEW.due_date IS NOT OLD.due_date
+        OR NEW.amount_due IS NOT OLD.amount_due OR NEW.statement_path IS NOT OLD.statement_path
+        OR NEW.pdf_hash IS NOT OLD.pdf_hash OR NEW.related_statement_id IS NOT OLD.related_statement_id
+        OR NEW.note IS NOT OLD.note OR NEW.company_name IS NOT OLD.company_name
+        OR NEW.late_fees IS NOT OLD.late_fees OR NEW.cam_billed IS NOT OLD.cam_billed)
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be edited, void or credit them instead');
+    END;",
+    // percentage_rent is the part of amount_due from percentage rent, which the year end
+    // true-up compares with what the year's sales come to
+    "ALTER TABLE leases ADD COLUMN percentage_rent TEXT null;
+    ALTER TABLE statements ADD COLUMN percentage_rent REAL NOT NULL DEFAULT 0;
+    DROP TRIGGER IF EXISTS statements_issued_no_update;
+    CREATE TRIGGER statements_issued_no_update BEFORE UPDATE ON statements
+    WHEN OLD.status != 'draft' AND (
+        NEW.leaseholder_id IS NOT OLD.leaseholder_id OR NEW.property_id IS NOT OLD.property_id
+        OR NEW.invoice_number IS NOT OLD.invoice_number OR NEW.kind IS NOT OLD.kind
+        OR NEW.period_start IS NOT OLD.period_start OR NEW.period_end IS NOT OLD.period_end
+        OR NEW.issue_date IS NOT OLD.issue_date OR NEW.due_date IS NOT OLD.due_date
+        OR NEW.amount_due IS NOT OLD.amount_due OR NEW.statement_path IS NOT OLD.statement_path
+        OR NEW.pdf_hash IS NOT OLD.pdf_hash OR NEW.related_statement_id IS NOT OLD.related_statement_id
+        OR NEW.note IS NOT OLD.note OR NEW.company_name IS NOT OLD.company_name
+        OR NEW.late_fees IS NOT OLD.late_fees OR NEW.cam_billed IS NOT OLD.cam_billed
+        OR NEW.percentage_rent IS NOT OLD.percentage_rent)
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be edited, void or credit them instead');
+    END;",
+    "ALTER TABLE leases ADD COLUMN recurring_charges TEXT null;",
+    // Statements keep the jurisdiction and tax they were issued with, for the remittance
+    // report, since a property's jurisdiction can change
+    "ALTER TABLE properties ADD COLUMN tax_jurisdiction TEXT null;
+    ALTER TABLE statements ADD COLUMN tax_jurisdiction TEXT null;
+    ALTER TABLE statements ADD COLUMN taxable_sales REAL NOT NULL DEFAULT 0;
+    ALTER TABLE statements ADD COLUMN sales_tax REAL NOT NULL DEFAULT 0;
+    DROP TRIGGER IF EXISTS statements_issued_no_update;
+    CREATE TRIGGER statements_issued_no_update BEFORE UPDATE ON statements
+    WHEN OLD.status != 'draft' AND (
+        NEW.leaseholder_id IS NOT OLD.leaseholder_id OR NEW.property_id IS NOT OLD.property_id
+        OR NEW.invoice_number IS NOT OLD.invoice_number OR NEW.kind IS NOT OLD.kind
+        OR NEW.period_start IS NOT OLD.period_start OR NEW.period_end IS NOT OLD.period_end
+        OR NEW.issue_date IS NOT OLD.issue_date OR NEW.due_date IS NOT OLD.due_date
+        OR NEW.amount_due IS NOT OLD.amount_due OR NEW.statement_path IS NOT OLD.statement_path
+        OR NEW.pdf_hash IS NOT OLD.pdf_hash OR NEW.related_statement_id IS NOT OLD.related_statement_id
+        OR NEW.note IS NOT OLD.note OR NEW.company_name IS NOT OLD.company_name
+        OR NEW.late_fees IS NOT OLD.late_fees OR NEW.cam_billed IS NOT OLD.cam_billed
+        OR NEW.percentage_rent IS NOT OLD.percentage_rent
+        OR NEW.tax_jurisdiction IS NOT OLD.tax_jurisdiction
+        OR NEW.taxable_sales IS NOT OLD.taxable_sales OR NEW.sales_tax IS NOT OLD.sales_tax)
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be edited, void or credit them instead');
+    END;",
+    // Occupants and square feet are what a ratio utility bill is divided by
+    "ALTER TABLE properties ADD COLUMN utility_billing TEXT null;
+    ALTER TABLE leaseholders ADD COLUMN occupants INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE leaseholders ADD COLUMN square_feet REAL NOT NULL DEFAULT 0;",
+    "ALTER TABLE leases ADD COLUMN insurance_requirement TEXT null;",
+    // Issued statements outlive their leaseholder, so removing a leaseholder no longer
+    // cascades to them. SQLite can't change a foreign key in place, so statements and
+    // late_fees are rebuilt with RESTRICT and the statements index and triggers recreated.
+    "CREATE TABLE statements_new (
+        statement_id        INTEGER PRIMARY KEY AUTOINCREMENT,
+        leaseholder_id      INTEGER,
+        amount_due          INTEGER,
+        amount_paid         INTEGER,
+        statement_path      TEXT,
+        property_id         INTEGER null,
+        invoice_number      TEXT null,
+        period_start        TEXT null,
+        period_end          TEXT null,
+        issue_date          TEXT null,
+        due_date            TEXT null,
+        status              TEXT NOT NULL DEFAULT 'draft',
+        pdf_hash            TEXT null,
+        kind                TEXT NOT NULL DEFAULT 'statement',
+        related_statement_id INTEGER null REFERENCES statements(statement_id),
+        note                TEXT null,
+        void_reason         TEXT null,
+        company_name        TEXT null,
+        late_fees           REAL NOT NULL DEFAULT 0,
+        cam_billed          REAL NOT NULL DEFAULT 0,
+        percentage_rent     REAL NOT NULL DEFAULT 0,
+        tax_jurisdiction    TEXT null,
+        taxable_sales       REAL NOT NULL DEFAULT 0,
+        sales_tax           REAL NOT NULL DEFAULT 0,
+        FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE RESTRICT
+    );
+    INSERT INTO statements_new (statement_id, leaseholder_id, amount_due, amount_paid,
+        statement_path, property_id, invoice_number, period_start, period_end, issue_date,
+        due_date, status, pdf_hash, kind, related_statement_id, note, void_reason, company_name,
+        late_fees, cam_billed, percentage_rent, tax_jurisdiction, taxable_sales, sales_tax)
+    SELECT statement_id, leaseholder_id, amount_due, amount_paid, statement_path, property_id,
+        invoice_number, period_start, period_end, issue_date, due_date, status, pdf_hash, kind,
+        related_statement_id, note, void_reason, company_name, late_fees, cam_billed,
+        percentage_rent, tax_jurisdiction, taxable_sales, sales_tax
+    FROM statements;
+    DROP TABLE statements;
+    ALTER TABLE statements_new RENAME TO statements;
+    CREATE UNIQUE INDEX statements_invoice_number ON statements (invoice_number);
+    CREATE TRIGGER statements_issued_no_update BEFORE UPDATE ON statements
+    WHEN OLD.status != 'draft' AND (
+        NEW.leaseholder_id IS NOT OLD.leaseholder_id OR NEW.property_id IS NOT OLD.property_id
+        OR NEW.invoice_number IS NOT OLD.invoice_number OR NEW.kind IS NOT OLD.kind
+        OR NEW.period_start IS NOT OLD.period_start OR NEW.period_end IS NOT OLD.period_end
+        OR NEW.issue_date IS NOT OLD.issue_date OR NEW.due_date IS NOT OLD.due_date
+        OR NEW.amount_due IS NOT OLD.amount_due OR NEW.statement_path IS NOT OLD.statement_path
+        OR NEW.pdf_hash IS NOT OLD.pdf_hash OR NEW.related_statement_id IS NOT OLD.related_statement_id
+        OR NEW.note IS NOT OLD.note OR NEW.company_name IS NOT OLD.company_name
+        OR NEW.late_fees IS NOT OLD.late_fees OR NEW.cam_billed IS NOT OLD.cam_billed
+        OR NEW.percentage_rent IS NOT OLD.percentage_rent
+        OR NEW.tax_jurisdiction IS NOT OLD.tax_jurisdiction
+        OR NEW.taxable_sales IS NOT OLD.taxable_sales OR NEW.sales_tax IS NOT OLD.sales_tax)
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be edited, void or credit them instead');
+    END;
+    CREATE TRIGGER statements_void_is_final BEFORE UPDATE ON statements
+    WHEN OLD.status = 'void' AND NEW.status != 'void'
+    BEGIN
+        SELECT RAISE(ABORT, 'void statements cannot be reopened');
+    END;
+    CREATE TRIGGER statements_issued_no_delete BEFORE DELETE ON statements
+    WHEN OLD.status != 'draft'
+    BEGIN
+        SELECT RAISE(ABORT, 'issued statements cannot be deleted');
+    END;
+    CREATE TABLE late_fees_new (
+        late_fee_id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        statement_id        INTEGER,
+        leaseholder_id      INTEGER,
+        assessed_on         TEXT,
+        days_late           INTEGER,
+        amount              REAL,
+        billed_statement_id INTEGER null,
+        waived_at           TEXT null,
+        FOREIGN KEY (statement_id) REFERENCES statements(statement_id) ON DELETE RESTRICT,
+        FOREIGN KEY (billed_statement_id) REFERENCES statements(statement_id) ON DELETE RESTRICT
+    );
+    INSERT INTO late_fees_new SELECT late_fee_id, statement_id, leaseholder_id, assessed_on,
+        days_late, amount, billed_statement_id, waived_at FROM late_fees;
+    DROP TABLE late_fees;
+    ALTER TABLE late_fees_new RENAME TO late_fees;",
+];
+
+pub async fn initialize_database(settings: &AppSettings) -> sqlx::Pool<Sqlite> {
+    let database_file = settings.paths.database_file();
+    if !database_file.is_file() {
+        println!("Creating database");
     } else {
         println!("Database already exists");
     }
+    let options = connect_options(&database_file, settings.encryption.passphrase.as_deref())
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await.unwrap();
+    // Every table is created with IF NOT EXISTS, so this also adds tables introduced
+    // after an existing database was first created.
+    match create_schema(&pool).await {
+        Ok(_) => println!("Database schema up to date"),
+        Err(e) => panic!("{}", e),
+    }
+
+    match run_migrations(&pool, settings).await {
+        Ok(_) => println!("Database migrations applied"),
+        Err(e) => panic!("{}", e),
+    }
+    pool
+}
+
+/// Connection options for a Hestia database file. With a passphrase the file is opened
+/// through SQLCipher, which has to be keyed before anything else is read.
+pub fn connect_options(path: &Path, passphrase: Option<&str>) -> SqliteConnectOptions {
+    let options = SqliteConnectOptions::new().filename(path);
+    match passphrase {
+        Some(passphrase) => options.pragma("key", quote_sql_string(passphrase)),
+        None => options,
+    }
+}
+
+pub fn quote_sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+pub async fn run_migrations(
+    pool: &sqlx::Pool<Sqlite>,
+    settings: &AppSettings,
+) -> Result<(), sqlx::Error> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+    if (version as usize) < MIGRATIONS.len() {
+        backup::create_backup(pool, settings, BackupKind::PreMigration)
+            .await
+            .map_err(|e| sqlx::Error::Protocol(format!("Pre-migration backup failed: {e}")))?;
+    }
+    // Migrations that rebuild a table run with foreign keys off, since dropping the old
+    // table would otherwise apply its ON DELETE actions to every row pointing at it. The
+    // keys are checked before each migration commits instead.
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+    let res = apply_migrations(&mut conn, version as usize).await;
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+    res
+}
 
-    SqlitePool::connect(&db_url).await.unwrap()
+async fn apply_migrations(conn: &mut SqliteConnection, version: usize) -> Result<(), sqlx::Error> {
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        println!("Applying database migration {}", index + 1);
+        let mut tx = conn.begin().await?;
+        sqlx::query(migration).execute(&mut *tx).await?;
+        let violations = sqlx::query("PRAGMA foreign_key_check")
+            .fetch_all(&mut *tx)
+            .await?;
+        if !violations.is_empty() {
+            return Err(sqlx::Error::Protocol(format!(
+                "Migration {} left {} rows with broken foreign keys",
+                index + 1,
+                violations.len()
+            )));
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", index + 1))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+    Ok(())
 }
 
-pub async fn create_schema(db_url: &str) -> Result<SqliteQueryResult, sqlx::Error> {
-    let pool = SqlitePool::connect(db_url).await?;
+pub async fn create_schema(pool: &sqlx::Pool<Sqlite>) -> Result<SqliteQueryResult, sqlx::Error> {
     let qry = "
     PRAGMA foreign_keys = ON;
     CREATE TABLE IF NOT EXISTS leases (
@@ -85,11 +396,151 @@ pub async fn create_schema(db_url: &str) -> Result<SqliteQueryResult, sqlx::Erro
         amount_due          INTEGER,
         amount_paid         INTEGER,
         statement_path      TEXT,
+        FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE RESTRICT
+    );
+    CREATE TABLE IF NOT EXISTS email_outbox (
+        email_id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        statement_id        INTEGER null,
+        leaseholder_id      INTEGER null,
+        recipient           TEXT,
+        subject             TEXT,
+        body                TEXT,
+        attachment_path     TEXT null,
+        status              TEXT,
+        attempts            INTEGER DEFAULT 0,
+        last_error          TEXT null,
+        created_at          TEXT,
+        next_attempt_at     TEXT,
+        sent_at             TEXT null,
+        FOREIGN KEY (statement_id) REFERENCES statements(statement_id) ON DELETE SET NULL,
+        FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE SET NULL
+    );
+    CREATE TABLE IF NOT EXISTS invoice_sequences (
+        sequence_key        TEXT PRIMARY KEY,
+        last_number         INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS ledger_entries (
+        entry_id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        leaseholder_id      INTEGER,
+        statement_id        INTEGER null,
+        entry_date          TEXT,
+        kind                TEXT,
+        description         TEXT,
+        amount              REAL,
+        created_at          TEXT
+    );
+    CREATE TRIGGER IF NOT EXISTS ledger_entries_no_update BEFORE UPDATE ON ledger_entries
+    BEGIN
+        SELECT RAISE(ABORT, 'ledger_entries is append-only');
+    END;
+    CREATE TRIGGER IF NOT EXISTS ledger_entries_no_delete BEFORE DELETE ON ledger_entries
+    BEGIN
+        SELECT RAISE(ABORT, 'ledger_entries is append-only');
+    END;
+    CREATE TABLE IF NOT EXISTS late_fees (
+        late_fee_id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        statement_id        INTEGER,
+        leaseholder_id      INTEGER,
+        assessed_on         TEXT,
+        days_late           INTEGER,
+        amount              REAL,
+        billed_statement_id INTEGER null,
+        waived_at           TEXT null,
+        FOREIGN KEY (statement_id) REFERENCES statements(statement_id) ON DELETE RESTRICT,
+        FOREIGN KEY (billed_statement_id) REFERENCES statements(statement_id) ON DELETE RESTRICT
+    );
+    CREATE TABLE IF NOT EXISTS property_bills (
+        bill_id             INTEGER PRIMARY KEY AUTOINCREMENT,
+        property_id         INTEGER,
+        kind                TEXT NOT NULL,
+        description         TEXT,
+        amount              REAL NOT NULL,
+        coverage_start      TEXT NOT NULL,
+        coverage_end        TEXT NOT NULL,
+        FOREIGN KEY (property_id) REFERENCES properties(property_id) ON DELETE CASCADE
+    );
+    CREATE TABLE IF NOT EXISTS bill_installments (
+        installment_id      INTEGER PRIMARY KEY AUTOINCREMENT,
+        bill_id             INTEGER NOT NULL,
+        due_date            TEXT NOT NULL,
+        amount              REAL NOT NULL,
+        paid_on             TEXT null,
+        FOREIGN KEY (bill_id) REFERENCES property_bills(bill_id) ON DELETE CASCADE
+    );
+    CREATE TABLE IF NOT EXISTS sales_reports (
+        sales_report_id     INTEGER PRIMARY KEY AUTOINCREMENT,
+        leaseholder_id      INTEGER NOT NULL,
+        period_start        TEXT NOT NULL,
+        period_end          TEXT NOT NULL,
+        gross_sales         REAL NOT NULL,
+        reported_on         TEXT NOT NULL,
+        FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE CASCADE
+    );
+    CREATE TABLE IF NOT EXISTS meter_readings (
+        meter_reading_id    INTEGER PRIMARY KEY AUTOINCREMENT,
+        leaseholder_id      INTEGER NOT NULL,
+        utility             TEXT NOT NULL,
+        period_start        TEXT NOT NULL,
+        period_end          TEXT NOT NULL,
+        start_reading       REAL NOT NULL,
+        end_reading         REAL NOT NULL,
+        recorded_on         TEXT NOT NULL,
+        FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE CASCADE
+    );
+    CREATE TABLE IF NOT EXISTS documents (
+        document_id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_type          TEXT NOT NULL,
+        owner_id            INTEGER NOT NULL,
+        kind                TEXT NOT NULL,
+        title               TEXT NOT NULL,
+        file_path           TEXT NOT NULL,
+        file_hash           TEXT NOT NULL,
+        effective_date      TEXT null,
+        expiry_date         TEXT null,
+        added_on            TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS insurance_certificates (
+        certificate_id      INTEGER PRIMARY KEY AUTOINCREMENT,
+        leaseholder_id      INTEGER NOT NULL,
+        carrier             TEXT NOT NULL,
+        policy_number       TEXT NOT NULL,
+        per_occurrence      REAL NOT NULL,
+        aggregate           REAL NOT NULL,
+        additional_insured  INTEGER NOT NULL,
+        effective_date      TEXT NOT NULL,
+        expiry_date         TEXT NOT NULL,
+        recorded_on         TEXT NOT NULL,
         FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE CASCADE
-    )";
-    let result = sqlx::query(qry).execute(&pool).await;
-    pool.close().await;
-    result
+    );
+    CREATE TABLE IF NOT EXISTS letters (
+        letter_id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        leaseholder_id      INTEGER NOT NULL,
+        template            TEXT NOT NULL,
+        subject             TEXT NOT NULL,
+        file_path           TEXT NOT NULL,
+        file_hash           TEXT NOT NULL,
+        sent_on             TEXT NOT NULL,
+        FOREIGN KEY (leaseholder_id) REFERENCES leaseholders(leaseholder_id) ON DELETE CASCADE
+    );
+    CREATE TABLE IF NOT EXISTS audit_log (
+        audit_id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        entity_type         TEXT,
+        entity_id           INTEGER,
+        action              TEXT,
+        before_snapshot     TEXT null,
+        after_snapshot      TEXT null,
+        timestamp           TEXT,
+        user                TEXT
+    );
+    CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'audit_log is append-only');
+    END;
+    CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+    BEGIN
+        SELECT RAISE(ABORT, 'audit_log is append-only');
+    END";
+    sqlx::query(qry).execute(pool).await
 }
 
 // -------------------------------------- ADD ---------------------------------------------
@@ -103,31 +554,54 @@ pub async fn add_maint_request(
         MaintenanceType::Repairs => String::from("Maintenance: Repairs"),
         MaintenanceType::Cleaning => String::from("Maintenance: Cleaning"),
         MaintenanceType::Landscaping => String::from("Maintenance: Landscaping"),
+        MaintenanceType::Amenities => String::from("Maintenance: Amenities"),
         MaintenanceType::Other => String::from("Maintenance: Other"),
     };
-    sqlx::query("INSERT INTO maintenance_requests (leaseholder_id, request_date, maintenance_type, description, status, completion_date) VALUES (?, ?, ?, ?, ?, ?)")
+    let mut tx = pool.begin().await?;
+    let request_id = sqlx::query("INSERT INTO maintenance_requests (leaseholder_id, request_date, maintenance_type, description, status, completion_date) VALUES (?, ?, ?, ?, ?, ?)")
         .bind(request.leaseholder_id)
         .bind(request.request_date.to_string())
         .bind(maint_type_str)
         .bind(&request.description)
         .bind(RequestStatus::Received.to_string())
-        .execute(pool)
-        .await?;
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::MaintenanceRequest,
+        request_id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(())
 }
 
 pub async fn add_expense(pool: &sqlx::Pool<Sqlite>, expense: &Expense) -> Result<(), sqlx::Error> {
     println!("Adding Expense");
     let expense_type_str = &expense.expense_type.to_string();
-    sqlx::query(
+    let mut tx = pool.begin().await?;
+    let expense_id = sqlx::query(
         "INSERT INTO expenses (property_id, expense_type, amount, date_incurred, description) VALUES (?, ?, ?, ?, ?)")
         .bind(expense.property_id)
         .bind(expense_type_str)
         .bind(expense.amount)
         .bind(expense.date.to_string())
         .bind(&expense.description)
-        .execute(pool)
-        .await?;
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::Expense,
+        expense_id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(())
 }
 
@@ -136,8 +610,9 @@ pub async fn add_property(
     property: &Property,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     println!("Adding Property");
+    let mut tx = pool.begin().await?;
     let x = sqlx::query(
-        "INSERT INTO properties (property_name, property_tax, business_insurance, address, city, state, zip_code, num_units) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+        "INSERT INTO properties (property_name, property_tax, business_insurance, address, city, state, zip_code, num_units, statement_template, tax_jurisdiction, utility_billing) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(&property.name)
         .bind(property.property_tax)
         .bind(property.business_insurance)
@@ -146,8 +621,20 @@ pub async fn add_property(
         .bind(&property.address.state)
         .bind(&property.address.zip_code)
         .bind(property.num_units)
-        .execute(pool)
+        .bind(&property.statement_template)
+        .bind(&property.tax_jurisdiction)
+        .bind(property.utility_billing.encode_to_database_string())
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Property,
+        x.last_insert_rowid(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(x)
 }
 
@@ -158,18 +645,32 @@ pub async fn add_leaseholders(
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     println!("Adding Leaseholder");
     let lease = &leaseholder.lease;
+    let mut tx = pool.begin().await?;
 
     let lease_id =
-        sqlx::query("INSERT INTO leases (start_date, end_date, fee_structure) VALUES (?, ?, ?)")
+        sqlx::query("INSERT INTO leases (start_date, end_date, fee_structure, late_fee_rule, cam_clauses, percentage_rent, recurring_charges, insurance_requirement) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
             .bind(lease.start_date.to_string())
             .bind(lease.end_date.to_string())
             .bind(leaseholder.lease.fee_structure.encode_to_database_string())
-            .execute(pool)
+            .bind(lease.late_fee.map(|rule| rule.encode_to_database_string()))
+            .bind(lease.cam.as_ref().map(|clauses| clauses.encode_to_database_string()))
+            .bind(lease.percentage_rent.map(|terms| terms.encode_to_database_string()))
+            .bind(RecurringCharge::encode_list(&lease.recurring_charges))
+            .bind(lease.insurance_requirement.map(|terms| terms.encode_to_database_string()))
+            .execute(&mut *tx)
             .await?
             .last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::Lease,
+        lease_id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
 
     let leaseholder_result = sqlx::query(
-        "INSERT INTO leaseholders (lease_id, property_id, name, address, city, state, zip_code, email, phone_number, move_in_date) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        "INSERT INTO leaseholders (lease_id, property_id, name, address, city, state, zip_code, email, phone_number, move_in_date, move_out_date, occupants, square_feet) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(lease_id)
         .bind(property_id)
         .bind(&leaseholder.contact_info.name)
@@ -180,33 +681,415 @@ pub async fn add_leaseholders(
         .bind(&leaseholder.contact_info.email)
         .bind(&leaseholder.contact_info.phone_number)
         .bind(&leaseholder.move_in_date.to_string())
-        .execute(pool)
+        .bind(leaseholder.move_out_date.map(|date| date.to_string()))
+        .bind(leaseholder.occupants)
+        .bind(leaseholder.square_feet)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE leases SET leaseholder_id = ? WHERE lease_id == ?")
+        .bind(leaseholder_result.last_insert_rowid())
+        .bind(lease_id)
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Leaseholder,
+        leaseholder_result.last_insert_rowid(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(leaseholder_result)
 }
 
-pub async fn add_statement(
+/// Replaces a leaseholder's lease, e.g. for a renewal or a change of terms. The old lease
+/// stays on record so statements for the month of the change bill both for their days.
+pub async fn add_lease(
     pool: &sqlx::Pool<Sqlite>,
-    statement: &Statement,
+    leaseholder_id: u32,
+    lease: &Lease,
+) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let lease_id = sqlx::query(
+        "INSERT INTO leases (leaseholder_id, start_date, end_date, fee_structure, payment_method, late_fee_rule, cam_clauses, percentage_rent, recurring_charges, insurance_requirement) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(leaseholder_id)
+        .bind(lease.start_date.to_string())
+        .bind(lease.end_date.to_string())
+        .bind(lease.fee_structure.encode_to_database_string())
+        .bind(&lease.payment_method)
+        .bind(lease.late_fee.map(|rule| rule.encode_to_database_string()))
+        .bind(lease.cam.as_ref().map(|clauses| clauses.encode_to_database_string()))
+        .bind(lease.percentage_rent.map(|terms| terms.encode_to_database_string()))
+        .bind(RecurringCharge::encode_list(&lease.recurring_charges))
+        .bind(lease.insurance_requirement.map(|terms| terms.encode_to_database_string()))
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::Lease,
+        lease_id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+
+    let before = audit::snapshot(&mut tx, EntityType::Leaseholder, leaseholder_id.into()).await?;
+    sqlx::query("UPDATE leaseholders SET lease_id = ? WHERE leaseholder_id == ?")
+        .bind(lease_id)
+        .bind(leaseholder_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Leaseholder,
+        leaseholder_id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(lease_id)
+}
+
+/// Adds the statement as a draft under the next number in its invoice sequence, filling in
+/// `record`'s id and invoice number. Runs in the caller's transaction, so the sequence only
+/// advances if the statement goes on to be issued.
+pub async fn add_statement(
+    conn: &mut SqliteConnection,
+    record: &mut StatementRecord,
+    settings: &InvoiceSettings,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     println!("Adding Statement");
+    let issue_date = record
+        .issue_date
+        .unwrap_or_else(|| Local::now().date_naive());
+    let sequence_key = settings.sequence_key(
+        record.kind,
+        record.property_id.unwrap_or_default(),
+        issue_date,
+    );
+
+    let sequence: i64 = sqlx::query_scalar(
+        "INSERT INTO invoice_sequences (sequence_key, last_number) VALUES (?, 1)
+        ON CONFLICT (sequence_key) DO UPDATE SET last_number = last_number + 1
+        RETURNING last_number",
+    )
+    .bind(&sequence_key)
+    .fetch_one(&mut *conn)
+    .await?;
+    let invoice_number = settings.invoice_number(&sequence_key, sequence);
     let x = sqlx::query(
-        "INSERT INTO statements (leaseholder_id, amount_due, amount_paid, statement_path) VALUES (?, ?, ?, ?)")
-        .bind(statement.leaseholder.id)
-        .bind(statement.total)
-        .bind(0)
-        .bind("test_statement")
-        .execute(pool)
+        "INSERT INTO statements (kind, leaseholder_id, property_id, invoice_number, period_start, period_end, issue_date, due_date, amount_due, amount_paid, status, statement_path, pdf_hash, related_statement_id, note, company_name, late_fees, cam_billed, percentage_rent, tax_jurisdiction, taxable_sales, sales_tax) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(record.kind.to_string())
+        .bind(record.leaseholder_id)
+        .bind(record.property_id)
+        .bind(&invoice_number)
+        .bind(record.period.map(|period| period.start.to_string()))
+        .bind(record.period.map(|period| period.end.to_string()))
+        .bind(issue_date.to_string())
+        .bind(record.due_date.map(|date| date.to_string()))
+        // Kept to the cent so it matches the total printed on the statement
+        .bind((record.amount_due as f64 * 100.0).round() / 100.0)
+        .bind(record.amount_paid)
+        .bind(record.status.to_string())
+        .bind(&record.pdf_path)
+        .bind(&record.pdf_hash)
+        .bind(record.related_statement_id)
+        .bind(&record.note)
+        .bind(&record.company_name)
+        .bind(record.late_fees)
+        .bind(record.cam_billed)
+        .bind(record.percentage_rent)
+        .bind(&record.tax_jurisdiction)
+        .bind(record.taxable_sales)
+        .bind(record.sales_tax)
+        .execute(&mut *conn)
+        .await?;
+    audit::record(
+        &mut *conn,
+        EntityType::Statement,
+        x.last_insert_rowid(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+
+    record.id = x.last_insert_rowid();
+    record.invoice_number = Some(invoice_number);
+    Ok(x)
+}
+
+pub async fn add_outbox_email(
+    pool: &sqlx::Pool<Sqlite>,
+    email: &OutboxEmail,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Queueing Email");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO email_outbox (statement_id, leaseholder_id, recipient, subject, body, html_body, attachment_path, status, attempts, created_at, next_attempt_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(email.statement_id)
+        .bind(email.leaseholder_id)
+        .bind(&email.recipient)
+        .bind(&email.subject)
+        .bind(&email.body)
+        .bind(&email.html_body)
+        .bind(&email.attachment_path)
+        .bind(email.status.to_string())
+        .bind(email.attempts)
+        .bind(email.created_at.format(TIMESTAMP_FORMAT).to_string())
+        .bind(email.next_attempt_at.format(TIMESTAMP_FORMAT).to_string())
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::OutboxEmail,
+        x.last_insert_rowid(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Records an assessed late fee and posts it to the leaseholder's ledger, filling in its id.
+pub async fn add_late_fee(
+    pool: &sqlx::Pool<Sqlite>,
+    fee: &mut LateFee,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Late Fee");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO late_fees (statement_id, leaseholder_id, assessed_on, days_late, amount) VALUES (?, ?, ?, ?, ?)")
+        .bind(fee.statement_id)
+        .bind(fee.leaseholder_id)
+        .bind(fee.assessed_on.to_string())
+        .bind(fee.days_late)
+        .bind(fee.amount)
+        .execute(&mut *tx)
+        .await?;
+    fee.id = x.last_insert_rowid();
+    ledger::post(&mut tx, &fee.ledger_entry()).await?;
+    audit::record(
+        &mut tx,
+        EntityType::LateFee,
+        fee.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Records a tax bill or insurance premium with its installments, filling in their ids.
+pub async fn add_property_bill(
+    pool: &sqlx::Pool<Sqlite>,
+    bill: &mut PropertyBill,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Property Bill");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO property_bills (property_id, kind, description, amount, coverage_start, coverage_end) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(bill.property_id)
+        .bind(bill.kind.to_string())
+        .bind(&bill.description)
+        .bind(bill.amount)
+        .bind(bill.coverage_start.to_string())
+        .bind(bill.coverage_end.to_string())
+        .execute(&mut *tx)
+        .await?;
+    bill.id = x.last_insert_rowid();
+    for installment in bill.installments.iter_mut() {
+        installment.bill_id = bill.id;
+        installment.id = sqlx::query(
+            "INSERT INTO bill_installments (bill_id, due_date, amount) VALUES (?, ?, ?)",
+        )
+        .bind(bill.id)
+        .bind(installment.due_date.to_string())
+        .bind(installment.amount)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    }
+    audit::record(
+        &mut tx,
+        EntityType::PropertyBill,
+        bill.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Records the gross sales a tenant reported for a period, filling in its id.
+pub async fn add_sales_report(
+    pool: &sqlx::Pool<Sqlite>,
+    report: &mut SalesReport,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Sales Report");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO sales_reports (leaseholder_id, period_start, period_end, gross_sales, reported_on) VALUES (?, ?, ?, ?, ?)")
+        .bind(report.leaseholder_id)
+        .bind(report.period.start.to_string())
+        .bind(report.period.end.to_string())
+        .bind(report.gross_sales)
+        .bind(report.reported_on.to_string())
+        .execute(&mut *tx)
+        .await?;
+    report.id = x.last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::SalesReport,
+        report.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Records a tenant's meter reading for a period, filling in its id.
+pub async fn add_meter_reading(
+    pool: &sqlx::Pool<Sqlite>,
+    reading: &mut MeterReading,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Meter Reading");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO meter_readings (leaseholder_id, utility, period_start, period_end, start_reading, end_reading, recorded_on) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(reading.leaseholder_id)
+        .bind(reading.utility.to_string())
+        .bind(reading.period.start.to_string())
+        .bind(reading.period.end.to_string())
+        .bind(reading.start_reading)
+        .bind(reading.end_reading)
+        .bind(reading.recorded_on.to_string())
+        .execute(&mut *tx)
+        .await?;
+    reading.id = x.last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::MeterReading,
+        reading.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Records a document already copied into the documents directory, filling in its id.
+pub async fn add_document(
+    pool: &sqlx::Pool<Sqlite>,
+    document: &mut StoredDocument,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Document");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO documents (owner_type, owner_id, kind, title, file_path, file_hash, effective_date, expiry_date, added_on) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(document.owner.owner_type())
+        .bind(document.owner.id())
+        .bind(document.kind.to_string())
+        .bind(&document.title)
+        .bind(&document.file_path)
+        .bind(&document.file_hash)
+        .bind(document.effective_date.map(|date| date.to_string()))
+        .bind(document.expiry_date.map(|date| date.to_string()))
+        .bind(document.added_on.to_string())
+        .execute(&mut *tx)
+        .await?;
+    document.id = x.last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::Document,
+        document.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Records a tenant's certificate of insurance, filling in its id.
+pub async fn add_insurance_certificate(
+    pool: &sqlx::Pool<Sqlite>,
+    certificate: &mut InsuranceCertificate,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Insurance Certificate");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO insurance_certificates (leaseholder_id, carrier, policy_number, per_occurrence, aggregate, additional_insured, effective_date, expiry_date, recorded_on) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(certificate.leaseholder_id)
+        .bind(&certificate.carrier)
+        .bind(&certificate.policy_number)
+        .bind(certificate.per_occurrence)
+        .bind(certificate.aggregate)
+        .bind(certificate.additional_insured)
+        .bind(certificate.effective_date.to_string())
+        .bind(certificate.expiry_date.to_string())
+        .bind(certificate.recorded_on.to_string())
+        .execute(&mut *tx)
         .await?;
+    certificate.id = x.last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::InsuranceCertificate,
+        certificate.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
 
+/// Logs a letter sent to a leaseholder, filling in its id.
+pub async fn add_letter(
+    pool: &sqlx::Pool<Sqlite>,
+    letter: &mut SentLetter,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Adding Letter");
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT INTO letters (leaseholder_id, template, subject, file_path, file_hash, sent_on) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(letter.leaseholder_id)
+        .bind(&letter.template)
+        .bind(&letter.subject)
+        .bind(&letter.file_path)
+        .bind(&letter.file_hash)
+        .bind(letter.sent_on.to_string())
+        .execute(&mut *tx)
+        .await?;
+    letter.id = x.last_insert_rowid();
+    audit::record(
+        &mut tx,
+        EntityType::Letter,
+        letter.id,
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(x)
 }
 
 // -------------------------------------- GET ---------------------------------------------
-pub async fn get_properties(pool: &sqlx::Pool<Sqlite>) -> Vec<Property> {
+/// Archived properties are only included when asked for, e.g. for reports and historical
+/// statements.
+pub async fn get_properties(pool: &sqlx::Pool<Sqlite>, include_archived: bool) -> Vec<Property> {
     let mut properties: Vec<Property> = vec![];
 
-    let property_rows = sqlx::query("SELECT * FROM properties")
+    let property_rows = sqlx::query("SELECT * FROM properties WHERE ? OR archived_at IS NULL")
+        .bind(include_archived)
         .fetch_all(pool)
         .await;
     for row in property_rows.unwrap() {
@@ -216,12 +1099,24 @@ pub async fn get_properties(pool: &sqlx::Pool<Sqlite>) -> Vec<Property> {
     properties
 }
 
-pub async fn get_leaseholders(pool: &sqlx::Pool<Sqlite>) -> Vec<Leaseholder> {
+/// Archived leaseholders are only included when asked for, e.g. for reports and historical
+/// statements.
+pub async fn get_leaseholders(
+    pool: &sqlx::Pool<Sqlite>,
+    include_archived: bool,
+) -> Vec<Leaseholder> {
     let mut leaseholders: Vec<Leaseholder> = vec![];
 
-    let leaseholder_rows = sqlx::query("SELECT * FROM leaseholders")
-        .fetch_all(pool)
-        .await;
+    let leaseholder_rows = sqlx::query(
+        "SELECT l.*, le.start_date, le.end_date, le.fee_structure, le.payment_method, le.late_fee_rule, le.cam_clauses,
+            le.percentage_rent, le.recurring_charges, le.insurance_requirement
+        FROM leaseholders l
+        LEFT JOIN leases le ON le.lease_id = l.lease_id
+        WHERE ? OR l.archived_at IS NULL",
+    )
+    .bind(include_archived)
+    .fetch_all(pool)
+    .await;
 
     for row in leaseholder_rows.unwrap() {
         let leaseholder = Leaseholder::from_row(&row);
@@ -230,6 +1125,165 @@ pub async fn get_leaseholders(pool: &sqlx::Pool<Sqlite>) -> Vec<Leaseholder> {
     leaseholders
 }
 
+/// The sales a tenant has reported, oldest period first.
+pub async fn get_sales_reports(pool: &sqlx::Pool<Sqlite>, leaseholder_id: u32) -> Vec<SalesReport> {
+    let report_rows = sqlx::query(
+        "SELECT * FROM sales_reports WHERE leaseholder_id == ? ORDER BY period_start, sales_report_id",
+    )
+    .bind(leaseholder_id)
+    .fetch_all(pool)
+    .await;
+
+    let mut reports: Vec<SalesReport> = vec![];
+    for row in report_rows.unwrap() {
+        reports.push(SalesReport::from_row(&row).unwrap());
+    }
+    reports
+}
+
+/// A tenant's meter readings, oldest period first.
+pub async fn get_meter_readings(
+    pool: &sqlx::Pool<Sqlite>,
+    leaseholder_id: u32,
+) -> Vec<MeterReading> {
+    let reading_rows = sqlx::query(
+        "SELECT * FROM meter_readings WHERE leaseholder_id == ? ORDER BY period_start, meter_reading_id",
+    )
+    .bind(leaseholder_id)
+    .fetch_all(pool)
+    .await;
+
+    let mut readings: Vec<MeterReading> = vec![];
+    for row in reading_rows.unwrap() {
+        readings.push(MeterReading::from_row(&row).unwrap());
+    }
+    readings
+}
+
+/// The meter readings of every tenant at a property, archived ones included.
+pub async fn get_property_meter_readings(
+    pool: &sqlx::Pool<Sqlite>,
+    property_id: u32,
+) -> Vec<MeterReading> {
+    let reading_rows = sqlx::query(
+        "SELECT m.* FROM meter_readings m
+        JOIN leaseholders l ON l.leaseholder_id = m.leaseholder_id
+        WHERE l.property_id == ? ORDER BY m.period_start, m.meter_reading_id",
+    )
+    .bind(property_id)
+    .fetch_all(pool)
+    .await;
+
+    let mut readings: Vec<MeterReading> = vec![];
+    for row in reading_rows.unwrap() {
+        readings.push(MeterReading::from_row(&row).unwrap());
+    }
+    readings
+}
+
+/// The letters sent to a leaseholder, the latest first.
+pub async fn get_letters(pool: &sqlx::Pool<Sqlite>, leaseholder_id: u32) -> Vec<SentLetter> {
+    let letter_rows = sqlx::query(
+        "SELECT * FROM letters WHERE leaseholder_id == ? ORDER BY sent_on DESC, letter_id DESC",
+    )
+    .bind(leaseholder_id)
+    .fetch_all(pool)
+    .await;
+
+    let mut letters: Vec<SentLetter> = vec![];
+    for row in letter_rows.unwrap() {
+        letters.push(SentLetter::from_row(&row).unwrap());
+    }
+    letters
+}
+
+/// A tenant's certificates of insurance, the latest expiry first.
+pub async fn get_insurance_certificates(
+    pool: &sqlx::Pool<Sqlite>,
+    leaseholder_id: u32,
+) -> Vec<InsuranceCertificate> {
+    let certificate_rows = sqlx::query(
+        "SELECT * FROM insurance_certificates WHERE leaseholder_id == ?
+        ORDER BY expiry_date DESC, certificate_id DESC",
+    )
+    .bind(leaseholder_id)
+    .fetch_all(pool)
+    .await;
+
+    let mut certificates: Vec<InsuranceCertificate> = vec![];
+    for row in certificate_rows.unwrap() {
+        certificates.push(InsuranceCertificate::from_row(&row).unwrap());
+    }
+    certificates
+}
+
+/// The documents attached to a record, oldest first. A leaseholder's list also has the
+/// documents attached to any of their leases.
+pub async fn get_documents(pool: &sqlx::Pool<Sqlite>, owner: DocumentOwner) -> Vec<StoredDocument> {
+    let document_rows = sqlx::query(
+        "SELECT * FROM documents
+        WHERE (owner_type == ? AND owner_id == ?)
+            OR (? == 'Leaseholder' AND owner_type == 'Lease'
+                AND owner_id IN (SELECT lease_id FROM leases WHERE leaseholder_id == ?))
+        ORDER BY added_on, document_id",
+    )
+    .bind(owner.owner_type())
+    .bind(owner.id())
+    .bind(owner.owner_type())
+    .bind(owner.id())
+    .fetch_all(pool)
+    .await;
+
+    let mut documents: Vec<StoredDocument> = vec![];
+    for row in document_rows.unwrap() {
+        documents.push(StoredDocument::from_row(&row).unwrap());
+    }
+    documents
+}
+
+/// Every document whose record is still active, with the name of the leaseholder or
+/// property it belongs to. Lease documents are named after the lease's leaseholder.
+pub async fn get_documents_with_owners(pool: &sqlx::Pool<Sqlite>) -> Vec<(StoredDocument, String)> {
+    let document_rows = sqlx::query(
+        "SELECT * FROM (
+            SELECT d.*, COALESCE(lh.name, ll.name, p.property_name) AS owner_name
+            FROM documents d
+            LEFT JOIN leaseholders lh ON d.owner_type = 'Leaseholder'
+                AND lh.leaseholder_id = d.owner_id AND lh.archived_at IS NULL
+            LEFT JOIN leases le ON d.owner_type = 'Lease' AND le.lease_id = d.owner_id
+            LEFT JOIN leaseholders ll ON ll.leaseholder_id = le.leaseholder_id
+                AND ll.archived_at IS NULL
+            LEFT JOIN properties p ON d.owner_type = 'Property'
+                AND p.property_id = d.owner_id AND p.archived_at IS NULL
+        ) WHERE owner_name IS NOT NULL
+        ORDER BY document_id",
+    )
+    .fetch_all(pool)
+    .await;
+
+    let mut documents: Vec<(StoredDocument, String)> = vec![];
+    for row in document_rows.unwrap() {
+        let owner_name: String = row.get("owner_name");
+        documents.push((StoredDocument::from_row(&row).unwrap(), owner_name));
+    }
+    documents
+}
+
+/// Every lease a leaseholder has had, oldest first.
+pub async fn get_leases(pool: &sqlx::Pool<Sqlite>, leaseholder_id: u32) -> Vec<Lease> {
+    let lease_rows =
+        sqlx::query("SELECT * FROM leases WHERE leaseholder_id == ? ORDER BY start_date, lease_id")
+            .bind(leaseholder_id)
+            .fetch_all(pool)
+            .await;
+
+    let mut leases: Vec<Lease> = vec![];
+    for row in lease_rows.unwrap() {
+        leases.push(Lease::from_row(&row).unwrap());
+    }
+    leases
+}
+
 pub async fn get_all_expenses(pool: &sqlx::Pool<Sqlite>) -> Vec<Expense> {
     let mut expenses: Vec<Expense> = vec![];
 
@@ -275,43 +1329,510 @@ pub async fn get_current_property_expenses(
     expenses
 }
 
-// -------------------------------------- UPDATE ---------------------------------------------
-
-pub async fn update_property(
+/// The property's expenses incurred within the billing period.
+pub async fn get_period_property_expenses(
     pool: &sqlx::Pool<Sqlite>,
-    property: &Property,
-) -> Result<SqliteQueryResult, sqlx::Error> {
-    let x = sqlx::query("UPDATE properties SET (property_name, property_tax, business_insurance, address, city, state, zip_code, num_units) = (?, ?, ?, ?, ?, ?, ?, ?) WHERE property_id == ?")
-        .bind(&property.name)
-        .bind(property.property_tax)
-        .bind(property.business_insurance)
-        .bind(&property.address.street_address)
-        .bind(&property.address.city)
-        .bind(&property.address.state)
-        .bind(&property.address.zip_code)
-        .bind(property.num_units)
-        .bind(property.id)
-        .execute(pool)
-        .await?;
-    Ok(x)
+    property_id: u32,
+    period: &BillingPeriod,
+) -> Vec<Expense> {
+    let mut expenses: Vec<Expense> = vec![];
+
+    let expense_rows = sqlx::query(
+        "SELECT * FROM expenses WHERE property_id = ? AND date_incurred BETWEEN ? AND ?",
+    )
+    .bind(property_id)
+    .bind(period.start.to_string())
+    .bind(period.end.to_string())
+    .fetch_all(pool)
+    .await;
+    for row in expense_rows.unwrap() {
+        let expense = Expense::from_row(&row);
+        expenses.push(expense.unwrap());
+    }
+    expenses
 }
 
-pub async fn update_expense(
+pub async fn get_audit_history(
     pool: &sqlx::Pool<Sqlite>,
-    expense: &Expense,
-) -> Result<SqliteQueryResult, sqlx::Error> {
-    let expense_type_str = &expense.expense_type.to_string();
+    entity_type: EntityType,
+    entity_id: u32,
+) -> Vec<AuditEntry> {
+    let mut entries: Vec<AuditEntry> = vec![];
 
-    let x = sqlx::query(
-        "UPDATE expenses SET (property_id, expense_type, amount, date_incurred, description) = (?, ?, ?, ?, ?) WHERE expense_id == ?")
-        .bind(expense.property_id)
-        .bind(expense_type_str)
-        .bind(expense.amount)
-        .bind(expense.date.to_string())
+    let audit_rows = sqlx::query(
+        "SELECT * FROM audit_log WHERE entity_type = ? AND entity_id = ? ORDER BY audit_id DESC",
+    )
+    .bind(entity_type.to_string())
+    .bind(entity_id)
+    .fetch_all(pool)
+    .await;
+    for row in audit_rows.unwrap() {
+        let entry = AuditEntry::from_row(&row);
+        entries.push(entry.unwrap());
+    }
+    entries
+}
+
+pub async fn get_outbox(pool: &sqlx::Pool<Sqlite>, limit: u32) -> Vec<OutboxEmail> {
+    let mut emails: Vec<OutboxEmail> = vec![];
+
+    let email_rows = sqlx::query("SELECT * FROM email_outbox ORDER BY email_id DESC LIMIT ?")
+        .bind(limit)
+        .fetch_all(pool)
+        .await;
+    for row in email_rows.unwrap() {
+        let email = OutboxEmail::from_row(&row);
+        emails.push(email.unwrap());
+    }
+    emails
+}
+
+/// Queued emails whose next attempt is due, oldest first.
+pub async fn get_due_emails(pool: &sqlx::Pool<Sqlite>) -> Vec<OutboxEmail> {
+    let mut emails: Vec<OutboxEmail> = vec![];
+
+    let email_rows = sqlx::query(
+        "SELECT * FROM email_outbox WHERE status = ? AND next_attempt_at <= ? ORDER BY email_id",
+    )
+    .bind(EmailStatus::Queued.to_string())
+    .bind(
+        Local::now()
+            .naive_local()
+            .format(TIMESTAMP_FORMAT)
+            .to_string(),
+    )
+    .fetch_all(pool)
+    .await;
+    for row in email_rows.unwrap() {
+        let email = OutboxEmail::from_row(&row);
+        emails.push(email.unwrap());
+    }
+    emails
+}
+
+// Statement records with the names and related invoice number they are listed with
+const STATEMENT_SELECT: &str = "SELECT s.statement_id, s.kind, s.invoice_number, s.leaseholder_id,
+        l.name AS leaseholder_name, s.property_id, p.property_name, s.period_start, s.period_end,
+        s.issue_date, s.due_date, CAST(s.amount_due AS REAL) AS amount_due,
+        CAST(s.amount_paid AS REAL) AS amount_paid, s.status, s.statement_path, s.pdf_hash,
+        s.related_statement_id, r.invoice_number AS related_invoice, s.note, s.void_reason,
+        s.company_name, CAST(s.late_fees AS REAL) AS late_fees,
+        CAST(s.cam_billed AS REAL) AS cam_billed,
+        CAST(s.percentage_rent AS REAL) AS percentage_rent, s.tax_jurisdiction,
+        CAST(s.taxable_sales AS REAL) AS taxable_sales, CAST(s.sales_tax AS REAL) AS sales_tax
+    FROM statements s
+    LEFT JOIN leaseholders l ON l.leaseholder_id = s.leaseholder_id
+    LEFT JOIN properties p ON p.property_id = s.property_id
+    LEFT JOIN statements r ON r.statement_id = s.related_statement_id";
+
+/// The statement registry, newest first, narrowed down by the filter.
+pub async fn get_statements(
+    pool: &sqlx::Pool<Sqlite>,
+    filter: &StatementFilter,
+) -> Vec<StatementRecord> {
+    let mut statements: Vec<StatementRecord> = vec![];
+
+    let qry = format!(
+        "{STATEMENT_SELECT}
+        WHERE (?1 IS NULL OR s.status = ?1)
+            AND (?2 = '' OR s.invoice_number LIKE ?3 OR l.name LIKE ?3 OR p.property_name LIKE ?3)
+            AND (?4 IS NULL OR s.issue_date >= ?4)
+            AND (?5 IS NULL OR s.issue_date <= ?5)
+        ORDER BY s.statement_id DESC"
+    );
+    let statement_rows = sqlx::query(&qry)
+        .bind(filter.status.map(|status| status.to_string()))
+        .bind(&filter.search)
+        .bind(format!("%{}%", filter.search))
+        .bind(filter.issued_from.map(|date| date.to_string()))
+        .bind(filter.issued_to.map(|date| date.to_string()))
+        .fetch_all(pool)
+        .await;
+    for row in statement_rows.unwrap() {
+        match StatementRecord::from_row(&row) {
+            Ok(statement) => statements.push(statement),
+            Err(e) => println!("Error parsing statement record: {}", e),
+        }
+    }
+    statements
+}
+
+pub async fn get_statement(
+    pool: &sqlx::Pool<Sqlite>,
+    statement_id: i64,
+) -> Option<StatementRecord> {
+    let qry = format!("{STATEMENT_SELECT} WHERE s.statement_id = ?");
+    let row = sqlx::query(&qry)
+        .bind(statement_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap()?;
+    match StatementRecord::from_row(&row) {
+        Ok(statement) => Some(statement),
+        Err(e) => {
+            println!("Error parsing statement record: {}", e);
+            None
+        }
+    }
+}
+
+/// How much the credit memos against a statement take off it, leaving out voided ones.
+pub async fn get_credited_amount(pool: &sqlx::Pool<Sqlite>, statement_id: i64) -> f32 {
+    let mut conn = pool.acquire().await.unwrap();
+    credited_amount(&mut conn, statement_id).await.unwrap()
+}
+
+async fn credited_amount(
+    conn: &mut SqliteConnection,
+    statement_id: i64,
+) -> Result<f32, sqlx::Error> {
+    let credited: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(-SUM(CAST(amount_due AS REAL)), 0.0) FROM statements
+        WHERE related_statement_id = ? AND kind = 'credit_memo' AND status != 'void'",
+    )
+    .bind(statement_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(credited as f32)
+}
+
+/// The CAM charges on the leaseholder's statements for periods in `year`, including
+/// earlier reconciliations, leaving out voided ones.
+pub async fn get_cam_billed(pool: &sqlx::Pool<Sqlite>, leaseholder_id: u32, year: i32) -> f32 {
+    let billed: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(CAST(cam_billed AS REAL)), 0.0) FROM statements
+        WHERE leaseholder_id = ? AND status != 'void' AND strftime('%Y', period_start) = ?",
+    )
+    .bind(leaseholder_id)
+    .bind(year.to_string())
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    billed as f32
+}
+
+/// The percentage rent charged for sales in `year`, leaving out voided statements. Sales
+/// are billed the month after their reporting period ends, so this is the statements for
+/// February to the next January, and the year's true-up, which covers the whole year.
+pub async fn get_percentage_rent_billed(
+    pool: &sqlx::Pool<Sqlite>,
+    leaseholder_id: u32,
+    year: i32,
+) -> f32 {
+    let billed: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(CAST(percentage_rent AS REAL)), 0.0) FROM statements
+        WHERE leaseholder_id = ?1 AND status != 'void'
+            AND (period_start BETWEEN ?2 AND ?3 OR (period_start = ?4 AND period_end = ?5))",
+    )
+    .bind(leaseholder_id)
+    .bind(format!("{year}-02-01"))
+    .bind(format!("{}-01-31", year + 1))
+    .bind(format!("{year}-01-01"))
+    .bind(format!("{year}-12-31"))
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    billed as f32
+}
+
+/// The late fees assessed so far on an overdue statement, leaving out waived ones.
+pub async fn get_assessed_late_fees(pool: &sqlx::Pool<Sqlite>, statement_id: i64) -> f32 {
+    let assessed: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(amount), 0.0) FROM late_fees WHERE statement_id = ? AND waived_at IS NULL",
+    )
+    .bind(statement_id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    assessed as f32
+}
+
+/// Late fees still to be put on one of the leaseholder's statements. Fees billed on the
+/// statement being replaced are included, so a reissue carries them over, but not fees for
+/// paying that statement late, which are waived when it is voided.
+pub async fn get_unbilled_late_fees(
+    pool: &sqlx::Pool<Sqlite>,
+    leaseholder_id: u32,
+    replacing: Option<i64>,
+) -> Vec<LateFee> {
+    let mut fees: Vec<LateFee> = vec![];
+
+    let fee_rows = sqlx::query(
+        "SELECT f.*, s.invoice_number FROM late_fees f
+        LEFT JOIN statements s ON s.statement_id = f.statement_id
+        WHERE f.leaseholder_id = ?1 AND f.waived_at IS NULL
+            AND (f.billed_statement_id IS NULL OR f.billed_statement_id = ?2)
+            AND (?2 IS NULL OR f.statement_id != ?2)
+        ORDER BY f.late_fee_id",
+    )
+    .bind(leaseholder_id)
+    .bind(replacing)
+    .fetch_all(pool)
+    .await;
+    for row in fee_rows.unwrap() {
+        match LateFee::from_row(&row) {
+            Ok(fee) => fees.push(fee),
+            Err(e) => println!("Error parsing late fee: {}", e),
+        }
+    }
+    fees
+}
+
+/// A property's tax bills and insurance premiums with their installments, oldest first.
+pub async fn get_property_bills(pool: &sqlx::Pool<Sqlite>, property_id: u32) -> Vec<PropertyBill> {
+    let bill_rows = sqlx::query(
+        "SELECT * FROM property_bills WHERE property_id == ? ORDER BY coverage_start, bill_id",
+    )
+    .bind(property_id)
+    .fetch_all(pool)
+    .await;
+    let installment_rows = sqlx::query(
+        "SELECT i.* FROM bill_installments i
+        JOIN property_bills b ON b.bill_id = i.bill_id
+        WHERE b.property_id == ?
+        ORDER BY i.due_date",
+    )
+    .bind(property_id)
+    .fetch_all(pool)
+    .await;
+
+    let mut bills: Vec<PropertyBill> = vec![];
+    for row in bill_rows.unwrap() {
+        bills.push(PropertyBill::from_row(&row).unwrap());
+    }
+    for row in installment_rows.unwrap() {
+        let installment = Installment::from_row(&row).unwrap();
+        if let Some(bill) = bills.iter_mut().find(|bill| bill.id == installment.bill_id) {
+            bill.installments.push(installment);
+        }
+    }
+    bills
+}
+
+/// A leaseholder's ledger, oldest first.
+pub async fn get_ledger(pool: &sqlx::Pool<Sqlite>, leaseholder_id: u32) -> Vec<LedgerEntry> {
+    let mut entries: Vec<LedgerEntry> = vec![];
+
+    let entry_rows =
+        sqlx::query("SELECT * FROM ledger_entries WHERE leaseholder_id = ? ORDER BY entry_id")
+            .bind(leaseholder_id)
+            .fetch_all(pool)
+            .await;
+    for row in entry_rows.unwrap() {
+        match LedgerEntry::from_row(&row) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => println!("Error parsing ledger entry: {}", e),
+        }
+    }
+    entries
+}
+
+// -------------------------------------- UPDATE ---------------------------------------------
+
+/// Records the written PDF against a numbered draft, marks it issued and posts it to the
+/// leaseholder's ledger. The late fees carried on it are marked billed.
+pub async fn mark_statement_issued(
+    conn: &mut SqliteConnection,
+    record: &StatementRecord,
+    late_fees: &[LateFee],
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let before = audit::snapshot(&mut *conn, EntityType::Statement, record.id).await?;
+    let x = sqlx::query(
+        "UPDATE statements SET (status, statement_path, pdf_hash) = (?, ?, ?) WHERE statement_id == ?",
+    )
+    .bind(StatementStatus::Issued.to_string())
+    .bind(&record.pdf_path)
+    .bind(&record.pdf_hash)
+    .bind(record.id)
+    .execute(&mut *conn)
+    .await?;
+    for fee in late_fees {
+        sqlx::query("UPDATE late_fees SET billed_statement_id = ? WHERE late_fee_id == ?")
+            .bind(record.id)
+            .bind(fee.id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    ledger::post(&mut *conn, &record.issue_entry()).await?;
+    audit::record(
+        &mut *conn,
+        EntityType::Statement,
+        record.id,
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    Ok(x)
+}
+
+/// Marks an issued statement paid in full and posts the payment. Credit memos against it
+/// have already come off the balance, so the payment is what's left after them.
+pub async fn mark_statement_paid(
+    pool: &sqlx::Pool<Sqlite>,
+    record: &StatementRecord,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Statement, record.id).await?;
+    let credited = credited_amount(&mut tx, record.id).await?;
+    let x = sqlx::query(
+        "UPDATE statements SET (status, amount_paid) = (?, amount_due - ?) WHERE statement_id == ? AND status == ?",
+    )
+    .bind(StatementStatus::Paid.to_string())
+    .bind(credited)
+    .bind(record.id)
+    .bind(StatementStatus::Issued.to_string())
+    .execute(&mut *tx)
+    .await?;
+    if x.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    ledger::post(&mut tx, &record.payment_entry(credited)).await?;
+    audit::record(
+        &mut tx,
+        EntityType::Statement,
+        record.id,
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Voids an issued statement and reverses it on the ledger. The row and its PDF are kept.
+pub async fn void_statement(
+    pool: &sqlx::Pool<Sqlite>,
+    record: &StatementRecord,
+    reason: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Statement, record.id).await?;
+    let x = sqlx::query(
+        "UPDATE statements SET (status, void_reason) = (?, ?) WHERE statement_id == ? AND status == ?",
+    )
+    .bind(StatementStatus::Void.to_string())
+    .bind(reason)
+    .bind(record.id)
+    .bind(StatementStatus::Issued.to_string())
+    .execute(&mut *tx)
+    .await?;
+    if x.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    ledger::post(&mut tx, &record.void_entry(reason)).await?;
+    // Fees billed on it go on the next statement instead, and fees for paying it late are
+    // waived along with it
+    sqlx::query("UPDATE late_fees SET billed_statement_id = NULL WHERE billed_statement_id == ?")
+        .bind(record.id)
+        .execute(&mut *tx)
+        .await?;
+    let waived: f64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(amount), 0.0) FROM late_fees WHERE statement_id == ? AND waived_at IS NULL",
+    )
+    .bind(record.id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if waived > 0.0 {
+        let waived_at = Local::now()
+            .naive_local()
+            .format(TIMESTAMP_FORMAT)
+            .to_string();
+        let fee_ids: Vec<i64> = sqlx::query_scalar(
+            "SELECT late_fee_id FROM late_fees WHERE statement_id == ? AND waived_at IS NULL",
+        )
+        .bind(record.id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for fee_id in fee_ids {
+            let before = audit::snapshot(&mut tx, EntityType::LateFee, fee_id).await?;
+            sqlx::query("UPDATE late_fees SET waived_at = ? WHERE late_fee_id == ?")
+                .bind(&waived_at)
+                .bind(fee_id)
+                .execute(&mut *tx)
+                .await?;
+            audit::record(
+                &mut tx,
+                EntityType::LateFee,
+                fee_id,
+                AuditAction::Update,
+                before,
+            )
+            .await?;
+        }
+        ledger::post(&mut tx, &record.waive_entry(waived as f32)).await?;
+    }
+    audit::record(
+        &mut tx,
+        EntityType::Statement,
+        record.id,
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn update_property(
+    pool: &sqlx::Pool<Sqlite>,
+    property: &Property,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Property, property.id.into()).await?;
+    let x = sqlx::query("UPDATE properties SET (property_name, property_tax, business_insurance, address, city, state, zip_code, num_units, statement_template, tax_jurisdiction, utility_billing) = (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) WHERE property_id == ?")
+        .bind(&property.name)
+        .bind(property.property_tax)
+        .bind(property.business_insurance)
+        .bind(&property.address.street_address)
+        .bind(&property.address.city)
+        .bind(&property.address.state)
+        .bind(&property.address.zip_code)
+        .bind(property.num_units)
+        .bind(&property.statement_template)
+        .bind(&property.tax_jurisdiction)
+        .bind(property.utility_billing.encode_to_database_string())
+        .bind(property.id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Property,
+        property.id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn update_expense(
+    pool: &sqlx::Pool<Sqlite>,
+    expense: &Expense,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let expense_type_str = &expense.expense_type.to_string();
+
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Expense, expense.id.into()).await?;
+    let x = sqlx::query(
+        "UPDATE expenses SET (property_id, expense_type, amount, date_incurred, description) = (?, ?, ?, ?, ?) WHERE expense_id == ?")
+        .bind(expense.property_id)
+        .bind(expense_type_str)
+        .bind(expense.amount)
+        .bind(expense.date.to_string())
         .bind(&expense.description)
         .bind(expense.id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Expense,
+        expense.id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(x)
 }
 
@@ -319,8 +1840,10 @@ pub async fn update_leaseholder(
     pool: &sqlx::Pool<Sqlite>,
     leaseholder: &Leaseholder,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Leaseholder, leaseholder.id.into()).await?;
     let x = sqlx::query(
-        "UPDATE leaseholders SET (lease_id, property_id, name, remittence_address, email, phone_number, move_in_date) = (?, ?, ?, ?, ?, ?, ?) WHERE leaseholder_id == ?"
+        "UPDATE leaseholders SET (lease_id, property_id, name, address, city, state, zip_code, email, phone_number, move_in_date, move_out_date, occupants, square_feet) = (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) WHERE leaseholder_id == ?"
     )
         .bind(leaseholder.lease.id)
         .bind(leaseholder.property_id)
@@ -332,9 +1855,21 @@ pub async fn update_leaseholder(
         .bind(&leaseholder.contact_info.email)
         .bind(&leaseholder.contact_info.phone_number)
         .bind(&leaseholder.move_in_date.to_string())
+        .bind(leaseholder.move_out_date.map(|date| date.to_string()))
+        .bind(leaseholder.occupants)
+        .bind(leaseholder.square_feet)
         .bind(leaseholder.id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Leaseholder,
+        leaseholder.id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(x)
 }
 
@@ -342,38 +1877,504 @@ pub async fn update_lease(
     pool: &sqlx::Pool<Sqlite>,
     new_lease: &Lease,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Lease, new_lease.id.into()).await?;
     let x = sqlx::query(
-        "UPDATE leases SET (start_date, end_date, fee_structure) = (?, ?, ?) WHERE lease_id == ?",
+        "UPDATE leases SET (start_date, end_date, fee_structure, late_fee_rule, cam_clauses, percentage_rent, recurring_charges, insurance_requirement) = (?, ?, ?, ?, ?, ?, ?, ?) WHERE lease_id == ?",
     )
     .bind(new_lease.start_date.to_string())
     .bind(new_lease.end_date.to_string())
     .bind(new_lease.fee_structure.encode_to_database_string())
-    .execute(pool)
+    .bind(new_lease.late_fee.map(|rule| rule.encode_to_database_string()))
+    .bind(new_lease.cam.as_ref().map(|clauses| clauses.encode_to_database_string()))
+    .bind(new_lease.percentage_rent.map(|terms| terms.encode_to_database_string()))
+    .bind(RecurringCharge::encode_list(&new_lease.recurring_charges))
+    .bind(new_lease.insurance_requirement.map(|terms| terms.encode_to_database_string()))
+    .bind(new_lease.id)
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Lease,
+        new_lease.id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn mark_email_sent(
+    pool: &sqlx::Pool<Sqlite>,
+    email: &OutboxEmail,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::OutboxEmail, email.id).await?;
+    let x = sqlx::query(
+        "UPDATE email_outbox SET status = ?, attempts = ?, last_error = NULL, sent_at = ? WHERE email_id = ?",
+    )
+    .bind(EmailStatus::Sent.to_string())
+    .bind(email.attempts + 1)
+    .bind(Local::now().naive_local().format(TIMESTAMP_FORMAT).to_string())
+    .bind(email.id)
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut tx,
+        EntityType::OutboxEmail,
+        email.id,
+        AuditAction::Update,
+        before,
+    )
     .await?;
+    tx.commit().await?;
     Ok(x)
 }
 
+/// Records a failed delivery attempt. The email stays queued for `next_attempt` unless
+/// `status` gives up on it.
+pub async fn record_email_failure(
+    pool: &sqlx::Pool<Sqlite>,
+    email: &OutboxEmail,
+    status: EmailStatus,
+    error: &str,
+    next_attempt: NaiveDateTime,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::OutboxEmail, email.id).await?;
+    let x = sqlx::query(
+        "UPDATE email_outbox SET status = ?, attempts = ?, last_error = ?, next_attempt_at = ? WHERE email_id = ?",
+    )
+    .bind(status.to_string())
+    .bind(email.attempts + 1)
+    .bind(error)
+    .bind(next_attempt.format(TIMESTAMP_FORMAT).to_string())
+    .bind(email.id)
+    .execute(&mut *tx)
+    .await?;
+    audit::record(
+        &mut tx,
+        EntityType::OutboxEmail,
+        email.id,
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+/// Puts a failed email back in the queue for an immediate retry with a fresh set of attempts.
+pub async fn requeue_email(
+    pool: &sqlx::Pool<Sqlite>,
+    email_id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::OutboxEmail, email_id).await?;
+    let x = sqlx::query(
+        "UPDATE email_outbox SET status = ?, attempts = 0, next_attempt_at = ? WHERE email_id = ? AND status = ?",
+    )
+    .bind(EmailStatus::Queued.to_string())
+    .bind(Local::now().naive_local().format(TIMESTAMP_FORMAT).to_string())
+    .bind(email_id)
+    .bind(EmailStatus::Failed.to_string())
+    .execute(&mut *tx)
+    .await?;
+    // Only emails that had failed are requeued
+    if x.rows_affected() > 0 {
+        audit::record(
+            &mut tx,
+            EntityType::OutboxEmail,
+            email_id,
+            AuditAction::Update,
+            before,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn mark_installment_paid(
+    pool: &sqlx::Pool<Sqlite>,
+    installment_id: i64,
+    paid_on: NaiveDate,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::BillInstallment, installment_id).await?;
+    let x = sqlx::query(
+        "UPDATE bill_installments SET paid_on = ? WHERE installment_id == ? AND paid_on IS NULL",
+    )
+    .bind(paid_on.to_string())
+    .bind(installment_id)
+    .execute(&mut *tx)
+    .await?;
+    if x.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    audit::record(
+        &mut tx,
+        EntityType::BillInstallment,
+        installment_id,
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+// -------------------------------------- ARCHIVE ---------------------------------------------
+// Archived records are hidden from the default lists but keep their statements and history.
+
+pub async fn set_property_archived(
+    pool: &sqlx::Pool<Sqlite>,
+    property: &Property,
+    archived: bool,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!(
+        "Setting archived = {archived} on Property with id: {}",
+        property.id
+    );
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Property, property.id.into()).await?;
+    let x = sqlx::query("UPDATE properties SET archived_at = ? WHERE property_id == ?")
+        .bind(archived_timestamp(archived))
+        .bind(property.id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Property,
+        property.id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn set_leaseholder_archived(
+    pool: &sqlx::Pool<Sqlite>,
+    lessee: &Leaseholder,
+    archived: bool,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!(
+        "Setting archived = {archived} on Leaseholder with id: {}",
+        lessee.id
+    );
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Leaseholder, lessee.id.into()).await?;
+    let x = sqlx::query("UPDATE leaseholders SET archived_at = ? WHERE leaseholder_id == ?")
+        .bind(archived_timestamp(archived))
+        .bind(lessee.id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Leaseholder,
+        lessee.id.into(),
+        AuditAction::Update,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+fn archived_timestamp(archived: bool) -> Option<String> {
+    match archived {
+        true => Some(
+            Local::now()
+                .naive_local()
+                .format(TIMESTAMP_FORMAT)
+                .to_string(),
+        ),
+        false => None,
+    }
+}
+
 // -------------------------------------- REMOVE ---------------------------------------------
+// Removing a record purges it for good; prefer archiving from the UI.
 pub async fn remove_expense(
     pool: &sqlx::Pool<Sqlite>,
     expense: &Expense,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     println!("Removing Expense with id: {}", expense.id);
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Expense, expense.id.into()).await?;
     let x = sqlx::query("DELETE FROM expenses WHERE expense_id == ?")
         .bind(expense.id)
-        .execute(pool)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Expense,
+        expense.id.into(),
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+pub async fn remove_property_bill(
+    pool: &sqlx::Pool<Sqlite>,
+    bill_id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Removing Property Bill with id: {}", bill_id);
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::PropertyBill, bill_id).await?;
+    sqlx::query("DELETE FROM bill_installments WHERE bill_id == ?")
+        .bind(bill_id)
+        .execute(&mut *tx)
+        .await?;
+    let x = sqlx::query("DELETE FROM property_bills WHERE bill_id == ?")
+        .bind(bill_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::PropertyBill,
+        bill_id,
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+pub async fn remove_sales_report(
+    pool: &sqlx::Pool<Sqlite>,
+    sales_report_id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Removing Sales Report with id: {}", sales_report_id);
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::SalesReport, sales_report_id).await?;
+    let x = sqlx::query("DELETE FROM sales_reports WHERE sales_report_id == ?")
+        .bind(sales_report_id)
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::SalesReport,
+        sales_report_id,
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(x)
 }
+pub async fn remove_meter_reading(
+    pool: &sqlx::Pool<Sqlite>,
+    meter_reading_id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Removing Meter Reading with id: {}", meter_reading_id);
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::MeterReading, meter_reading_id).await?;
+    let x = sqlx::query("DELETE FROM meter_readings WHERE meter_reading_id == ?")
+        .bind(meter_reading_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::MeterReading,
+        meter_reading_id,
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+pub async fn remove_insurance_certificate(
+    pool: &sqlx::Pool<Sqlite>,
+    certificate_id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Removing Insurance Certificate with id: {}", certificate_id);
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::InsuranceCertificate, certificate_id).await?;
+    let x = sqlx::query("DELETE FROM insurance_certificates WHERE certificate_id == ?")
+        .bind(certificate_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::InsuranceCertificate,
+        certificate_id,
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+/// Only the record goes, the stored file stays in the documents directory.
+pub async fn remove_document(
+    pool: &sqlx::Pool<Sqlite>,
+    document_id: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Removing Document with id: {}", document_id);
+    let mut tx = pool.begin().await?;
+    let before = audit::snapshot(&mut tx, EntityType::Document, document_id).await?;
+    let x = sqlx::query("DELETE FROM documents WHERE document_id == ?")
+        .bind(document_id)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Document,
+        document_id,
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+// Records a purge would take with it: (table, what one is called, which rows count).
+const LEASEHOLDER_RECORDS: &[(&str, &str, &str)] = &[
+    ("statements", "issued statement", " AND status != 'draft'"),
+    ("meter_readings", "meter reading", ""),
+    ("sales_reports", "sales report", ""),
+    ("insurance_certificates", "insurance certificate", ""),
+    ("letters", "letter", ""),
+];
+const PROPERTY_RECORDS: &[(&str, &str, &str)] = &[
+    ("expenses", "expense", ""),
+    ("property_bills", "property bill", ""),
+];
+
+/// Why a property or leaseholder can't be purged, if it can't. Purging is for records
+/// entered by mistake: issued statements are kept for good, and undoing a purge only
+/// brings back the property and its leaseholders, so anything with records of its own
+/// stays archived instead.
+pub async fn purge_blocker(
+    conn: &mut SqliteConnection,
+    entity_type: EntityType,
+    entity_id: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    let (name_qry, lessee_filter, records) = match entity_type {
+        EntityType::Property => (
+            "SELECT property_name FROM properties WHERE property_id == ?",
+            "leaseholder_id IN (SELECT leaseholder_id FROM leaseholders WHERE property_id == ?)",
+            PROPERTY_RECORDS,
+        ),
+        EntityType::Leaseholder => (
+            "SELECT name FROM leaseholders WHERE leaseholder_id == ?",
+            "leaseholder_id == ?",
+            &[][..],
+        ),
+        _ => return Ok(None),
+    };
+    let name: Option<String> = sqlx::query_scalar(name_qry)
+        .bind(entity_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let owned = records
+        .iter()
+        .map(|(table, label, filter)| (*table, *label, "property_id == ?", *filter))
+        .chain(
+            LEASEHOLDER_RECORDS
+                .iter()
+                .map(|(table, label, filter)| (*table, *label, lessee_filter, *filter)),
+        );
+    let mut kept = vec![];
+    for (table, label, owner, filter) in owned {
+        let count: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {table} WHERE {owner}{filter}"
+        ))
+        .bind(entity_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        if count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            kept.push(format!("{count} {label}{plural}"));
+        }
+    }
+    if kept.is_empty() {
+        return Ok(None);
+    }
+    let (has, them) = match entity_type {
+        EntityType::Property => ("it and its leaseholders have", "it"),
+        _ => ("they have", "them"),
+    };
+    Ok(Some(format!(
+        "{} can't be purged, {has} {}. Leave {them} archived instead.",
+        name.unwrap_or_default(),
+        kept.join(", ")
+    )))
+}
+
+// Draft statements were never sent, so they go with their leaseholder. Late fees due to be
+// billed on one are left to be billed on the next statement.
+async fn remove_draft_statements(
+    conn: &mut SqliteConnection,
+    lessee_id: u32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE late_fees SET billed_statement_id = NULL WHERE billed_statement_id IN
+            (SELECT statement_id FROM statements WHERE leaseholder_id == ? AND status == 'draft')",
+    )
+    .bind(lessee_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("DELETE FROM statements WHERE leaseholder_id == ? AND status == 'draft'")
+        .bind(lessee_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
 pub async fn remove_property(
     pool: &sqlx::Pool<Sqlite>,
     property: &Property,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     println!("Removing Property with id: {}", property.id);
+    let mut tx = pool.begin().await?;
+    if let Some(reason) = purge_blocker(&mut tx, EntityType::Property, property.id.into()).await? {
+        return Err(sqlx::Error::Protocol(reason));
+    }
+    // Leaseholders are removed explicitly rather than through ON DELETE CASCADE so that
+    // each of them gets its own audit entry and can be restored individually.
+    let lessee_ids: Vec<u32> =
+        sqlx::query_scalar("SELECT leaseholder_id FROM leaseholders WHERE property_id == ?")
+            .bind(property.id)
+            .fetch_all(&mut *tx)
+            .await?;
+    for lessee_id in lessee_ids {
+        let before = audit::snapshot(&mut tx, EntityType::Leaseholder, lessee_id.into()).await?;
+        remove_draft_statements(&mut tx, lessee_id).await?;
+        sqlx::query("DELETE FROM leaseholders WHERE leaseholder_id == ?")
+            .bind(lessee_id)
+            .execute(&mut *tx)
+            .await?;
+        audit::record(
+            &mut tx,
+            EntityType::Leaseholder,
+            lessee_id.into(),
+            AuditAction::Delete,
+            before,
+        )
+        .await?;
+    }
+    let before = audit::snapshot(&mut tx, EntityType::Property, property.id.into()).await?;
     let x = sqlx::query("DELETE FROM properties WHERE property_id == ?")
         .bind(property.id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Property,
+        property.id.into(),
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(x)
 }
 pub async fn remove_leaseholder(
@@ -381,10 +2382,149 @@ pub async fn remove_leaseholder(
     lessee: &Leaseholder,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     println!("Removing Leaseholder with id: {}", lessee.id);
+    let mut tx = pool.begin().await?;
+    if let Some(reason) = purge_blocker(&mut tx, EntityType::Leaseholder, lessee.id.into()).await? {
+        return Err(sqlx::Error::Protocol(reason));
+    }
+    let before = audit::snapshot(&mut tx, EntityType::Leaseholder, lessee.id.into()).await?;
+    remove_draft_statements(&mut tx, lessee.id).await?;
     let x = sqlx::query("DELETE FROM leaseholders WHERE leaseholder_id == ?")
         .bind(lessee.id)
-        .execute(pool)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Leaseholder,
+        lessee.id.into(),
+        AuditAction::Delete,
+        before,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+// -------------------------------------- RESTORE ---------------------------------------------
+// Restores re-insert a previously removed record under its original id, used by undo/redo.
+
+pub async fn restore_expense(
+    pool: &sqlx::Pool<Sqlite>,
+    expense: &Expense,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Restoring Expense with id: {}", expense.id);
+    let expense_type_str = &expense.expense_type.to_string();
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT OR REPLACE INTO expenses (expense_id, property_id, expense_type, amount, date_incurred, description) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(expense.id)
+        .bind(expense.property_id)
+        .bind(expense_type_str)
+        .bind(expense.amount)
+        .bind(expense.date.to_string())
+        .bind(&expense.description)
+        .execute(&mut *tx)
+        .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Expense,
+        expense.id.into(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn restore_property(
+    pool: &sqlx::Pool<Sqlite>,
+    property: &Property,
+    leaseholders: &[Leaseholder],
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Restoring Property with id: {}", property.id);
+    let mut tx = pool.begin().await?;
+    let x = sqlx::query(
+        "INSERT OR REPLACE INTO properties (property_id, property_name, property_tax, business_insurance, address, city, state, zip_code, num_units, statement_template, tax_jurisdiction, utility_billing, archived_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(property.id)
+        .bind(&property.name)
+        .bind(property.property_tax)
+        .bind(property.business_insurance)
+        .bind(&property.address.street_address)
+        .bind(&property.address.city)
+        .bind(&property.address.state)
+        .bind(&property.address.zip_code)
+        .bind(property.num_units)
+        .bind(&property.statement_template)
+        .bind(&property.tax_jurisdiction)
+        .bind(property.utility_billing.encode_to_database_string())
+        .bind(
+            property
+                .archived_at
+                .map(|t| t.format(TIMESTAMP_FORMAT).to_string()),
+        )
+        .execute(&mut *tx)
         .await?;
+    audit::record(
+        &mut tx,
+        EntityType::Property,
+        property.id.into(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
+    for leaseholder in leaseholders {
+        insert_leaseholder_with_id(&mut tx, leaseholder).await?;
+    }
+    tx.commit().await?;
+    Ok(x)
+}
+
+pub async fn restore_leaseholder(
+    pool: &sqlx::Pool<Sqlite>,
+    leaseholder: &Leaseholder,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let x = insert_leaseholder_with_id(&mut tx, leaseholder).await?;
+    tx.commit().await?;
+    Ok(x)
+}
+
+async fn insert_leaseholder_with_id(
+    conn: &mut SqliteConnection,
+    leaseholder: &Leaseholder,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    println!("Restoring Leaseholder with id: {}", leaseholder.id);
+    let x = sqlx::query(
+        "INSERT OR REPLACE INTO leaseholders (leaseholder_id, lease_id, property_id, name, address, city, state, zip_code, email, phone_number, move_in_date, move_out_date, occupants, square_feet, archived_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(leaseholder.id)
+        .bind(leaseholder.lease.id)
+        .bind(leaseholder.property_id)
+        .bind(&leaseholder.contact_info.name)
+        .bind(&leaseholder.contact_info.remittence_address.street_address)
+        .bind(&leaseholder.contact_info.remittence_address.city)
+        .bind(&leaseholder.contact_info.remittence_address.state)
+        .bind(&leaseholder.contact_info.remittence_address.zip_code)
+        .bind(&leaseholder.contact_info.email)
+        .bind(&leaseholder.contact_info.phone_number)
+        .bind(leaseholder.move_in_date.to_string())
+        .bind(leaseholder.move_out_date.map(|date| date.to_string()))
+        .bind(leaseholder.occupants)
+        .bind(leaseholder.square_feet)
+        .bind(
+            leaseholder
+                .archived_at
+                .map(|t| t.format(TIMESTAMP_FORMAT).to_string()),
+        )
+        .execute(&mut *conn)
+        .await?;
+    audit::record(
+        &mut *conn,
+        EntityType::Leaseholder,
+        leaseholder.id.into(),
+        AuditAction::Create,
+        None,
+    )
+    .await?;
     Ok(x)
 }
 
@@ -407,6 +2547,20 @@ pub async fn get_max_expense_id(pool: &sqlx::Pool<Sqlite>) -> u32 {
         }
     }
 }
+pub async fn get_max_statement_id(pool: &sqlx::Pool<Sqlite>) -> u32 {
+    let res: Result<Option<i64>, sqlx::Error> =
+        sqlx::query_scalar("SELECT MAX(statement_id) FROM statements;")
+            .fetch_one(pool)
+            .await;
+    match res {
+        Ok(id) => id.map_or(0, |id| id as u32 + 1),
+        Err(e) => {
+            println!("Error getting max statement id: {}", e);
+            0
+        }
+    }
+}
+
 pub async fn get_max_property_id(pool: &sqlx::Pool<Sqlite>) -> u32 {
     let res = sqlx::query("SELECT * FROM properties ORDER BY property_id DESC LIMIT 1;")
         .fetch_one(pool)
@@ -444,3 +2598,218 @@ pub async fn get_max_leaseholder_id(pool: &sqlx::Pool<Sqlite>) -> u32 {
         }
     }
 }
+
+/// A migrated database in its own scratch directory, for tests.
+#[cfg(test)]
+pub async fn scratch_database(name: &str) -> (sqlx::Pool<Sqlite>, AppSettings) {
+    let settings = crate::app_settings::scratch_settings(name);
+    let pool = initialize_database(&settings).await;
+    (pool, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn seed(pool: &sqlx::Pool<Sqlite>) {
+        sqlx::query(
+            "INSERT INTO properties (property_id, property_name) VALUES (1, 'Elm Court');
+            INSERT INTO leaseholders (leaseholder_id, property_id, name, move_in_date)
+                VALUES (1, 1, 'Ada', '2024-01-01'), (2, 1, 'Bea', '2024-01-01');
+            INSERT INTO statements (statement_id, leaseholder_id, amount_due, status, invoice_number)
+                VALUES (1, 1, 100, 'issued', 'INV-1'), (2, 2, 50, 'draft', null);
+            INSERT INTO late_fees (statement_id, leaseholder_id, assessed_on, days_late, amount, billed_statement_id)
+                VALUES (1, 1, '2024-02-10', 10, 25.0, 2);
+            INSERT INTO email_outbox (statement_id, leaseholder_id, recipient, status)
+                VALUES (1, 1, 'ada@example.com', 'sent');",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn leaseholder(pool: &sqlx::Pool<Sqlite>, id: u32) -> Leaseholder {
+        get_leaseholders(pool, true)
+            .await
+            .into_iter()
+            .find(|l| l.id == id)
+            .unwrap()
+    }
+
+    #[async_std::test]
+    async fn leaseholders_with_issued_statements_are_not_purged() {
+        let (pool, _) = scratch_database("purge_issued").await;
+        seed(&pool).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        let reason = purge_blocker(&mut conn, EntityType::Leaseholder, 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            reason,
+            "Ada can't be purged, they have 1 issued statement. Leave them archived instead."
+        );
+        assert!(purge_blocker(&mut conn, EntityType::Property, 1)
+            .await
+            .unwrap()
+            .is_some());
+        drop(conn);
+
+        let err = remove_leaseholder(&pool, &leaseholder(&pool, 1).await)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("1 issued statement"), "{err}");
+        // The schema refuses as well, rather than cascading the statements away
+        assert!(
+            sqlx::query("DELETE FROM leaseholders WHERE leaseholder_id == 1")
+                .execute(&pool)
+                .await
+                .is_err()
+        );
+        let statements: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM statements")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(statements, 2);
+    }
+
+    #[async_std::test]
+    async fn purging_removes_draft_statements() {
+        let (pool, _) = scratch_database("purge_draft").await;
+        seed(&pool).await;
+
+        remove_leaseholder(&pool, &leaseholder(&pool, 2).await)
+            .await
+            .unwrap();
+        let drafts: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM statements WHERE status == 'draft'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(drafts, 0);
+        let billed: Option<i64> = sqlx::query_scalar("SELECT billed_statement_id FROM late_fees")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(billed, None);
+    }
+
+    #[async_std::test]
+    async fn properties_with_records_are_not_purged() {
+        let (pool, _) = scratch_database("purge_records").await;
+        sqlx::query(
+            "INSERT INTO properties (property_id, property_name) VALUES (2, 'Oak Row');
+            INSERT INTO leaseholders (leaseholder_id, property_id, name, move_in_date)
+                VALUES (3, 2, 'Cy', '2024-01-01');
+            INSERT INTO expenses (property_id, expense_type, amount, date_incurred)
+                VALUES (2, 'Repairs', 80.0, '2024-03-01');
+            INSERT INTO meter_readings (leaseholder_id, utility, period_start, period_end,
+                start_reading, end_reading, recorded_on)
+            VALUES (3, 'Water', '2024-01-01', '2024-01-31', 0, 10, '2024-02-01'),
+                (3, 'Water', '2024-02-01', '2024-02-29', 10, 25, '2024-03-01');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut conn = pool.acquire().await.unwrap();
+        let reason = purge_blocker(&mut conn, EntityType::Property, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            reason.as_deref(),
+            Some("Oak Row can't be purged, it and its leaseholders have 1 expense, 2 meter readings. Leave it archived instead.")
+        );
+        let reason = purge_blocker(&mut conn, EntityType::Leaseholder, 3)
+            .await
+            .unwrap();
+        assert_eq!(
+            reason.as_deref(),
+            Some("Cy can't be purged, they have 2 meter readings. Leave them archived instead.")
+        );
+    }
+
+    #[async_std::test]
+    async fn purged_property_is_restored_with_its_leaseholders() {
+        let (pool, _) = scratch_database("purge_restore").await;
+        sqlx::query(
+            "INSERT INTO properties (property_id, property_name, property_tax, business_insurance,
+                address, city, state, zip_code, num_units)
+            VALUES (3, 'Ash Lane', '0', '0', '1 Ash Lane', 'Reno', 'NV', '89501', 1);
+            INSERT INTO leases (lease_id) VALUES (4);
+            INSERT INTO leaseholders (leaseholder_id, lease_id, property_id, name, move_in_date)
+                VALUES (4, 4, 3, 'Di', '2024-01-01');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let property = get_properties(&pool, true)
+            .await
+            .into_iter()
+            .find(|p| p.id == 3)
+            .unwrap();
+        let lessee = leaseholder(&pool, 4).await;
+
+        remove_property(&pool, &property).await.unwrap();
+        assert!(get_leaseholders(&pool, true).await.is_empty());
+        restore_property(&pool, &property, &[lessee]).await.unwrap();
+
+        let restored = leaseholder(&pool, 4).await;
+        assert_eq!(
+            (restored.contact_info.name.as_str(), restored.property_id),
+            ("Di", 3)
+        );
+    }
+
+    #[async_std::test]
+    async fn rebuilding_statements_keeps_rows_and_references() {
+        let (pool, settings) = scratch_database("rebuild_statements").await;
+        seed(&pool).await;
+
+        // Run the table rebuild again over existing rows. Its backup would be named the
+        // same as the one taken when the database was created a moment ago.
+        std::fs::remove_dir_all(&settings.paths.backup_path).unwrap();
+        sqlx::query(&format!("PRAGMA user_version = {}", MIGRATIONS.len() - 1))
+            .execute(&pool)
+            .await
+            .unwrap();
+        run_migrations(&pool, &settings).await.unwrap();
+
+        let outbox: Option<i64> = sqlx::query_scalar("SELECT statement_id FROM email_outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(outbox, Some(1));
+        let fee: (i64, Option<i64>) =
+            sqlx::query_as("SELECT statement_id, billed_statement_id FROM late_fees")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(fee, (1, Some(2)));
+        // The triggers and invoice number index came back with the table
+        assert!(
+            sqlx::query("UPDATE statements SET amount_due = 1 WHERE statement_id == 1")
+                .execute(&pool)
+                .await
+                .is_err()
+        );
+        assert!(
+            sqlx::query("DELETE FROM statements WHERE statement_id == 1")
+                .execute(&pool)
+                .await
+                .is_err()
+        );
+        assert!(sqlx::query(
+            "INSERT INTO statements (leaseholder_id, status, invoice_number) VALUES (2, 'issued', 'INV-1')"
+        )
+        .execute(&pool)
+        .await
+        .is_err());
+        let foreign_keys: i64 = sqlx::query_scalar("PRAGMA foreign_keys")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(foreign_keys, 1);
+    }
+}
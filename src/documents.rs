@@ -0,0 +1,544 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDate};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    app_settings::{AppSettings, DocumentSettings, PathSettings},
+    database::{add_document, get_documents, get_documents_with_owners, remove_document},
+    statements::hash_file,
+    App, DocumentInput, DocumentReminderInput, Documents,
+};
+
+/// What a stored document is, e.g. for finding a tenant's latest insurance certificate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentKind {
+    SignedLease,
+    Amendment,
+    InsuranceCertificate,
+    W9,
+    Other,
+}
+
+impl fmt::Display for DocumentKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            DocumentKind::SignedLease => String::from("Signed Lease"),
+            DocumentKind::Amendment => String::from("Amendment"),
+            DocumentKind::InsuranceCertificate => String::from("Insurance Certificate"),
+            DocumentKind::W9 => String::from("W-9"),
+            DocumentKind::Other => String::from("Other"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl DocumentKind {
+    pub fn parse_string(kind: &str) -> Option<DocumentKind> {
+        match kind {
+            "Signed Lease" => Some(DocumentKind::SignedLease),
+            "Amendment" => Some(DocumentKind::Amendment),
+            "Insurance Certificate" => Some(DocumentKind::InsuranceCertificate),
+            "W-9" => Some(DocumentKind::W9),
+            "Other" => Some(DocumentKind::Other),
+            _ => None,
+        }
+    }
+}
+
+/// The record a document is attached to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentOwner {
+    Lease(u32),
+    Leaseholder(u32),
+    Property(u32),
+}
+
+impl fmt::Display for DocumentOwner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.owner_type(), self.id())
+    }
+}
+
+impl DocumentOwner {
+    pub fn parse_string(owner_type: &str, id: u32) -> Option<DocumentOwner> {
+        match owner_type {
+            "Lease" => Some(DocumentOwner::Lease(id)),
+            "Leaseholder" => Some(DocumentOwner::Leaseholder(id)),
+            "Property" => Some(DocumentOwner::Property(id)),
+            _ => None,
+        }
+    }
+
+    pub fn owner_type(&self) -> &'static str {
+        match self {
+            DocumentOwner::Lease(_) => "Lease",
+            DocumentOwner::Leaseholder(_) => "Leaseholder",
+            DocumentOwner::Property(_) => "Property",
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            DocumentOwner::Lease(id)
+            | DocumentOwner::Leaseholder(id)
+            | DocumentOwner::Property(id) => *id,
+        }
+    }
+}
+
+/// A file copied into the documents directory, with the hash it had when it was attached.
+#[derive(Debug, Clone)]
+pub struct StoredDocument {
+    pub id: i64,
+    pub owner: DocumentOwner,
+    pub kind: DocumentKind,
+    pub title: String,
+    pub file_path: String,
+    pub file_hash: String,
+    pub effective_date: Option<NaiveDate>,
+    // None for documents that don't lapse, e.g. a W-9
+    pub expiry_date: Option<NaiveDate>,
+    pub added_on: NaiveDate,
+}
+
+impl StoredDocument {
+    /// Whether the stored file is still there and unchanged since it was attached.
+    pub fn check(&self) -> &'static str {
+        match hash_file(Path::new(&self.file_path)) {
+            Ok(hash) if hash == self.file_hash => "on file",
+            Ok(_) => "changed since attached",
+            Err(_) => "missing",
+        }
+    }
+
+    pub fn convert_to_slint(&self) -> DocumentInput {
+        let date = |date: Option<NaiveDate>| date.map(|date| date.to_string()).unwrap_or_default();
+        DocumentInput {
+            id: self.id as i32,
+            owner: self.owner.owner_type().into(),
+            kind: self.kind.to_string().into(),
+            title: self.title.clone().into(),
+            effective_date: date(self.effective_date).into(),
+            expiry_date: date(self.expiry_date).into(),
+            file_path: self.file_path.clone().into(),
+            // Enough of the hash to tell copies apart at a glance
+            file_hash: self.file_hash.chars().take(12).collect::<String>().into(),
+            status: self.check().into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for StoredDocument {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let date = |column: &str| -> Result<Option<NaiveDate>, sqlx::Error> {
+            let date: Option<String> = row.try_get(column)?;
+            date.map(|date| {
+                NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+            })
+            .transpose()
+        };
+        let owner_type: String = row.try_get("owner_type")?;
+        let owner_id: u32 = row.try_get("owner_id")?;
+        let kind: String = row.try_get("kind")?;
+
+        Ok(StoredDocument {
+            id: row.try_get("document_id")?,
+            owner: DocumentOwner::parse_string(&owner_type, owner_id).ok_or_else(|| {
+                sqlx::Error::Decode(format!("Unknown document owner: {owner_type}").into())
+            })?,
+            kind: DocumentKind::parse_string(&kind).unwrap_or(DocumentKind::Other),
+            title: row.try_get("title")?,
+            file_path: row.try_get("file_path")?,
+            file_hash: row.try_get("file_hash")?,
+            effective_date: date("effective_date")?,
+            expiry_date: date("expiry_date")?,
+            added_on: date("added_on")?.unwrap_or_default(),
+        })
+    }
+}
+
+/// A document as entered in the Documents panel, checked by `parse`.
+#[derive(Debug, Clone)]
+pub struct NewDocument {
+    pub kind: String,
+    pub title: String,
+    // Where the file is now, it is copied into the documents directory
+    pub source: String,
+    pub effective_date: String,
+    pub expiry_date: String,
+}
+
+impl NewDocument {
+    pub fn parse(&self, owner: DocumentOwner) -> Result<(StoredDocument, PathBuf), String> {
+        let date = |text: &str| match text.trim() {
+            "" => Ok(None),
+            text => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| format!("Invalid date: {text}, use YYYY-MM-DD")),
+        };
+        let kind = DocumentKind::parse_string(&self.kind)
+            .ok_or_else(|| format!("Unknown document type: {}", self.kind))?;
+        let source = PathBuf::from(self.source.trim());
+        if !source.is_file() {
+            return Err(format!("No file at {}", source.display()));
+        }
+        let effective_date = date(&self.effective_date)?;
+        let expiry_date = date(&self.expiry_date)?;
+        if let (Some(effective), Some(expiry)) = (effective_date, expiry_date) {
+            if expiry < effective {
+                return Err(String::from(
+                    "The document has to expire after it takes effect",
+                ));
+            }
+        }
+        let title = match self.title.trim() {
+            "" => source
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| kind.to_string()),
+            title => title.to_owned(),
+        };
+        let document = StoredDocument {
+            id: 0,
+            owner,
+            kind,
+            title,
+            file_path: String::new(),
+            file_hash: String::new(),
+            effective_date,
+            expiry_date,
+            added_on: Local::now().date_naive(),
+        };
+        Ok((document, source))
+    }
+}
+
+/// Copies the file into the owner's folder in the documents directory and records it.
+/// The stored name starts with the file's hash, so attaching another version never
+/// overwrites the one before it.
+pub async fn attach_document(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    paths: &PathSettings,
+    owner: DocumentOwner,
+    new_document: &NewDocument,
+) -> Result<StoredDocument, String> {
+    let (mut document, source) = new_document.parse(owner)?;
+    let hash =
+        hash_file(&source).map_err(|e| format!("Failed to read {}: {e}", source.display()))?;
+    if let Some(attached) = get_documents(pool, owner)
+        .await
+        .into_iter()
+        .find(|attached| attached.owner == owner && attached.file_hash == hash)
+    {
+        return Err(format!(
+            "That file is already attached as {}",
+            attached.title
+        ));
+    }
+    let folder = Path::new(&paths.documents_path).join(format!(
+        "{}_{}",
+        owner.owner_type().to_lowercase(),
+        owner.id()
+    ));
+    let file_name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let destination = folder.join(format!("{}_{}", &hash[..12], file_name));
+    std::fs::create_dir_all(&folder)
+        .and_then(|_| std::fs::copy(&source, &destination))
+        .map_err(|e| format!("Failed to store {}: {e}", source.display()))?;
+    document.file_path = destination.to_string_lossy().into_owned();
+    document.file_hash = hash;
+    add_document(pool, &mut document)
+        .await
+        .map_err(|e| format!("Failed to record document: {e}"))?;
+    Ok(document)
+}
+
+/// A document that has expired or expires within the reminder window.
+#[derive(Debug, Clone)]
+pub struct Reminder {
+    pub document: StoredDocument,
+    // The leaseholder or property the document is for
+    pub owner_name: String,
+    // Negative once it has expired
+    pub days_left: i64,
+}
+
+impl Reminder {
+    pub fn describe(&self) -> String {
+        let when = match self.days_left {
+            days if days < 0 => format!("expired {} days ago", -days),
+            0 => String::from("expires today"),
+            days => format!("expires in {days} days"),
+        };
+        format!(
+            "{} for {} ({}) {}",
+            self.document.kind, self.owner_name, self.document.title, when
+        )
+    }
+
+    pub fn convert_to_slint(&self) -> DocumentReminderInput {
+        DocumentReminderInput {
+            document_id: self.document.id as i32,
+            text: self.describe().into(),
+            expired: self.days_left < 0,
+        }
+    }
+}
+
+/// The documents that expire within `settings.reminder_days` of `today`, or already have,
+/// soonest first. A document is left out once a newer one of the same type for the same
+/// record runs longer, e.g. last year's insurance certificate after the renewal comes in,
+/// and so are documents for archived or deleted records.
+pub async fn reminders(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &DocumentSettings,
+    today: NaiveDate,
+) -> Vec<Reminder> {
+    let documents = get_documents_with_owners(pool).await;
+    let mut reminders: Vec<Reminder> = documents
+        .iter()
+        .filter_map(|(document, owner_name)| {
+            let expiry = document.expiry_date?;
+            let superseded = documents.iter().any(|(other, _)| {
+                other.owner == document.owner
+                    && other.kind == document.kind
+                    && other
+                        .expiry_date
+                        .is_some_and(|other_expiry| other_expiry > expiry)
+            });
+            let days_left = (expiry - today).num_days();
+            match !superseded && days_left <= settings.reminder_days {
+                true => Some(Reminder {
+                    document: document.clone(),
+                    owner_name: owner_name.clone(),
+                    days_left,
+                }),
+                false => None,
+            }
+        })
+        .collect();
+    reminders.sort_by_key(|reminder| reminder.days_left);
+    reminders
+}
+
+pub enum DocumentMessage {
+    Show(DocumentOwner),
+    Add(DocumentOwner, NewDocument),
+    Remove(i64),
+    Refresh,
+    Quit,
+}
+
+pub struct DocumentWorker {
+    pub channel: UnboundedSender<DocumentMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl DocumentWorker {
+    pub fn new(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        settings: &AppSettings,
+        app: slint::Weak<App>,
+    ) -> Self {
+        println!("Create new Document Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            let settings = settings.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(document_worker_loop(new_pool, settings, r, app))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(DocumentMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn document_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    settings: AppSettings,
+    mut r: UnboundedReceiver<DocumentMessage>,
+    app: slint::Weak<App>,
+) {
+    let mut owner: Option<DocumentOwner> = None;
+    loop {
+        let status = match r.recv().await {
+            Some(DocumentMessage::Show(shown)) => {
+                owner = Some(shown);
+                None
+            }
+            Some(DocumentMessage::Add(attach_to, new_document)) => {
+                // Lease documents are listed with the leaseholder they were attached from
+                if !matches!(attach_to, DocumentOwner::Lease(_)) {
+                    owner = Some(attach_to);
+                }
+                Some(
+                    match attach_document(&pool, &settings.paths, attach_to, &new_document).await {
+                        Ok(document) => format!("Attached {}: {}", document.kind, document.title),
+                        Err(e) => e,
+                    },
+                )
+            }
+            Some(DocumentMessage::Remove(id)) => Some(match remove_document(&pool, id).await {
+                Ok(_) => {
+                    String::from("Removed document, its file is kept in the documents directory")
+                }
+                Err(e) => format!("Failed to remove document: {e}"),
+            }),
+            Some(DocumentMessage::Refresh) => None,
+            Some(DocumentMessage::Quit) | None => break,
+        };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+        show_documents(&pool, &settings, &app, owner, status).await;
+    }
+}
+
+async fn show_documents(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    app: &slint::Weak<App>,
+    owner: Option<DocumentOwner>,
+    status: Option<String>,
+) {
+    let documents = match owner {
+        Some(owner) => get_documents(pool, owner).await,
+        None => vec![],
+    };
+    let reminders = reminders(pool, &settings.documents, Local::now().date_naive()).await;
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let documents: Vec<DocumentInput> = documents
+            .iter()
+            .map(StoredDocument::convert_to_slint)
+            .collect();
+        let reminders: Vec<DocumentReminderInput> =
+            reminders.iter().map(Reminder::convert_to_slint).collect();
+        let registry = handle.global::<Documents>();
+        if let Some(owner) = owner {
+            registry.set_owner_type(owner.owner_type().into());
+            registry.set_owner_id(owner.id() as i32);
+        }
+        registry.set_documents(ModelRc::new(VecModel::from(documents)));
+        registry.set_reminders(ModelRc::new(VecModel::from(reminders)));
+        if let Some(status) = status {
+            registry.set_status(status.into());
+        }
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::tests::billed;
+
+    /// A certificate file to attach, written into the scratch directory.
+    fn certificate(settings: &AppSettings, name: &str, expiry_date: &str) -> NewDocument {
+        let source = Path::new(&settings.paths.database_path).join(name);
+        std::fs::write(&source, format!("Certificate of insurance, {name}")).unwrap();
+        NewDocument {
+            kind: String::from("Insurance Certificate"),
+            title: String::new(),
+            source: source.to_string_lossy().into_owned(),
+            effective_date: String::new(),
+            expiry_date: expiry_date.to_owned(),
+        }
+    }
+
+    #[async_std::test]
+    async fn attached_documents_are_checked_against_their_hash() {
+        let (pool, settings, statement, _, _) = billed("documents_attach").await;
+        let owner = DocumentOwner::Leaseholder(statement.leaseholder.id);
+        let new_document = certificate(&settings, "coi_2024.pdf", "2024-12-31");
+
+        let document = attach_document(&pool, &settings.paths, owner, &new_document)
+            .await
+            .unwrap();
+        assert_eq!(document.title, "coi_2024.pdf");
+        assert!(document
+            .file_path
+            .starts_with(&settings.paths.documents_path));
+        assert_eq!(document.check(), "on file");
+        assert_eq!(
+            attach_document(&pool, &settings.paths, owner, &new_document)
+                .await
+                .unwrap_err(),
+            "That file is already attached as coi_2024.pdf"
+        );
+
+        std::fs::write(&document.file_path, "edited").unwrap();
+        assert_eq!(document.check(), "changed since attached");
+        std::fs::remove_file(&document.file_path).unwrap();
+        assert_eq!(document.check(), "missing");
+    }
+
+    #[test]
+    fn documents_have_to_expire_after_they_take_effect() {
+        let settings = crate::app_settings::scratch_settings("documents_dates");
+        let mut new_document = certificate(&settings, "coi.pdf", "2024-01-01");
+        new_document.effective_date = String::from("2024-06-01");
+        let owner = DocumentOwner::Leaseholder(1);
+        assert_eq!(
+            new_document.parse(owner).unwrap_err(),
+            "The document has to expire after it takes effect"
+        );
+        new_document.expiry_date = String::from("06/01/2025");
+        assert_eq!(
+            new_document.parse(owner).unwrap_err(),
+            "Invalid date: 06/01/2025, use YYYY-MM-DD"
+        );
+    }
+
+    #[async_std::test]
+    async fn renewed_documents_stop_reminding() {
+        let (pool, settings, statement, property, _) = billed("documents_reminders").await;
+        let tenant = DocumentOwner::Leaseholder(statement.leaseholder.id);
+        for (name, expiry_date) in [
+            ("coi_2023.pdf", "2024-06-30"),
+            ("coi_2024.pdf", "2025-06-30"),
+        ] {
+            let new_document = certificate(&settings, name, expiry_date);
+            attach_document(&pool, &settings.paths, tenant, &new_document)
+                .await
+                .unwrap();
+        }
+        let building = certificate(&settings, "building.pdf", "2024-06-10");
+        attach_document(
+            &pool,
+            &settings.paths,
+            DocumentOwner::Property(property.id),
+            &building,
+        )
+        .await
+        .unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2024, 6, 20).unwrap();
+        let reminders = reminders(&pool, &DocumentSettings::default(), today).await;
+        let reminders: Vec<String> = reminders.iter().map(Reminder::describe).collect();
+        assert_eq!(
+            reminders,
+            ["Insurance Certificate for Birch Court (building.pdf) expired 10 days ago"]
+        );
+    }
+}
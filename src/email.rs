@@ -0,0 +1,707 @@
+use std::{fmt, path::Path};
+
+use chrono::{Local, NaiveDateTime};
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+use slint::ComponentHandle;
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    app_settings::{AppSettings, EmailSettings, SmtpSecurity},
+    database::{
+        add_outbox_email, get_due_emails, get_outbox, mark_email_sent, record_email_failure,
+        requeue_email, TIMESTAMP_FORMAT,
+    },
+    html_formatting::render_html,
+    statements::IssuedStatement,
+    App, Outbox, OutboxEmailInput,
+};
+
+const OUTBOX_DISPLAY_LIMIT: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailStatus {
+    Queued,
+    Sent,
+    Failed,
+}
+
+impl fmt::Display for EmailStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            EmailStatus::Queued => String::from("queued"),
+            EmailStatus::Sent => String::from("sent"),
+            EmailStatus::Failed => String::from("failed"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl EmailStatus {
+    pub fn parse_string(status: &str) -> Option<EmailStatus> {
+        match status {
+            "queued" => Some(EmailStatus::Queued),
+            "sent" => Some(EmailStatus::Sent),
+            "failed" => Some(EmailStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EmailError {
+    Smtp(lettre::transport::smtp::Error),
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmailError::Smtp(e) => write!(f, "smtp error: {e}"),
+            EmailError::Io(e) => write!(f, "file error: {e}"),
+            EmailError::Invalid(reason) => write!(f, "invalid email: {reason}"),
+        }
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for EmailError {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        EmailError::Smtp(e)
+    }
+}
+
+impl From<std::io::Error> for EmailError {
+    fn from(e: std::io::Error) -> Self {
+        EmailError::Io(e)
+    }
+}
+
+impl EmailError {
+    /// Errors that will fail the same way on every attempt, like a rejected address.
+    fn is_permanent(&self) -> bool {
+        match self {
+            EmailError::Smtp(e) => e.is_permanent(),
+            EmailError::Io(_) | EmailError::Invalid(_) => true,
+        }
+    }
+}
+
+/// A message waiting in, or already delivered from, the `email_outbox` table.
+#[derive(Debug, Clone)]
+pub struct OutboxEmail {
+    pub id: i64,
+    pub statement_id: Option<i64>,
+    pub leaseholder_id: Option<u32>,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    // Sent as the HTML alternative to `body` when set
+    pub html_body: Option<String>,
+    pub attachment_path: Option<String>,
+    pub status: EmailStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub next_attempt_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+}
+
+impl OutboxEmail {
+    pub fn new(
+        statement_id: Option<i64>,
+        leaseholder_id: Option<u32>,
+        recipient: String,
+        subject: String,
+        body: String,
+        html_body: Option<String>,
+        attachment_path: Option<String>,
+    ) -> OutboxEmail {
+        let now = Local::now().naive_local();
+        OutboxEmail {
+            id: 0,
+            statement_id,
+            leaseholder_id,
+            recipient,
+            subject,
+            body,
+            html_body,
+            attachment_path,
+            status: EmailStatus::Queued,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            next_attempt_at: now,
+            sent_at: None,
+        }
+    }
+
+    pub fn convert_to_slint(&self) -> OutboxEmailInput {
+        let copy = self.clone();
+        let updated = self.sent_at.unwrap_or(match self.status {
+            EmailStatus::Queued if self.attempts > 0 => self.next_attempt_at,
+            _ => self.created_at,
+        });
+        OutboxEmailInput {
+            id: self.id as i32,
+            recipient: copy.recipient.into(),
+            subject: copy.subject.into(),
+            status: self.status.to_string().into(),
+            attempts: self.attempts as i32,
+            last_error: copy.last_error.unwrap_or_default().into(),
+            updated: updated.format("%Y-%m-%d %H:%M").to_string().into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for OutboxEmail {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let status: String = row.try_get("status")?;
+        let created_at: String = row.try_get("created_at")?;
+        let next_attempt_at: String = row.try_get("next_attempt_at")?;
+        let sent_at: Option<String> = row.try_get("sent_at")?;
+
+        let status = EmailStatus::parse_string(&status)
+            .ok_or_else(|| sqlx::Error::Decode(format!("Unknown email status: {status}").into()))?;
+        let parse = |timestamp: &str| {
+            NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+
+        Ok(OutboxEmail {
+            id: row.try_get("email_id")?,
+            statement_id: row.try_get("statement_id")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            recipient: row.try_get("recipient")?,
+            subject: row.try_get("subject")?,
+            body: row.try_get("body")?,
+            html_body: row.try_get("html_body")?,
+            attachment_path: row.try_get("attachment_path")?,
+            status,
+            attempts: row.try_get("attempts")?,
+            last_error: row.try_get("last_error")?,
+            created_at: parse(&created_at)?,
+            next_attempt_at: parse(&next_attempt_at)?,
+            sent_at: sent_at.as_deref().map(parse).transpose()?,
+        })
+    }
+}
+
+/// Replaces each `{field}` in the template with its value. Unknown fields are left as is.
+pub fn fill_template(template: &str, fields: &[(&str, String)]) -> String {
+    fields
+        .iter()
+        .fold(template.to_owned(), |text, (field, value)| {
+            text.replace(&format!("{{{field}}}"), value)
+        })
+}
+
+/// Queues the statement PDF to be emailed to the leaseholder, with the statement itself as
+/// the HTML body unless that is turned off. Nothing is queued while email delivery is turned
+/// off in the settings.
+pub async fn queue_statement_email(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &EmailSettings,
+    issued: &IssuedStatement,
+) -> Result<Option<i64>, sqlx::Error> {
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let document = &issued.document;
+    let fields = [
+        ("name", document.recipient.name.clone()),
+        ("month", document.date.format("%B %Y").to_string()),
+        ("amount_due", format!("{:.2}", document.total_due)),
+        ("property", document.property.clone()),
+        ("company", document.company.clone()),
+        (
+            "invoice",
+            document.invoice_number.clone().unwrap_or_default(),
+        ),
+    ];
+    let body = fill_template(&settings.body_template, &fields);
+    let html_body = settings
+        .html_statement
+        .then(|| render_html(document, &issued.template, Some(&body)));
+    let email = OutboxEmail::new(
+        Some(issued.record.id),
+        Some(issued.record.leaseholder_id),
+        document.recipient.email.clone(),
+        fill_template(&settings.subject_template, &fields),
+        body,
+        html_body,
+        Some(issued.files.pdf.to_string_lossy().to_string()),
+    );
+    let result = add_outbox_email(pool, &email).await?;
+    Ok(Some(result.last_insert_rowid()))
+}
+
+fn build_message(settings: &EmailSettings, email: &OutboxEmail) -> Result<Message, EmailError> {
+    let from: Mailbox = settings
+        .from_address
+        .parse()
+        .map_err(|e| EmailError::Invalid(format!("from address {}: {e}", settings.from_address)))?;
+    let to: Mailbox = email
+        .recipient
+        .parse()
+        .map_err(|e| EmailError::Invalid(format!("recipient {}: {e}", email.recipient)))?;
+    let builder = Message::builder().from(from).to(to).subject(&email.subject);
+    let body = email
+        .html_body
+        .as_ref()
+        .map(|html| MultiPart::alternative_plain_html(email.body.clone(), html.clone()));
+
+    let message = match &email.attachment_path {
+        Some(path) => {
+            let path = Path::new(path);
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| String::from("statement.pdf"));
+            let attachment = Attachment::new(file_name).body(
+                std::fs::read(path)?,
+                ContentType::parse("application/pdf").unwrap(),
+            );
+            let mixed = match body {
+                Some(body) => MultiPart::mixed().multipart(body),
+                None => MultiPart::mixed().singlepart(SinglePart::plain(email.body.clone())),
+            };
+            builder.multipart(mixed.singlepart(attachment))
+        }
+        None => match body {
+            Some(body) => builder.multipart(body),
+            None => builder.body(email.body.clone()),
+        },
+    };
+    message.map_err(|e| EmailError::Invalid(e.to_string()))
+}
+
+fn build_transport(settings: &EmailSettings) -> Result<SmtpTransport, EmailError> {
+    let builder = match settings.security {
+        SmtpSecurity::None => SmtpTransport::builder_dangerous(&settings.host),
+        SmtpSecurity::StartTls => SmtpTransport::starttls_relay(&settings.host)?,
+        SmtpSecurity::Tls => SmtpTransport::relay(&settings.host)?,
+    };
+    let builder = builder.port(settings.port);
+    let builder = if settings.username.is_empty() {
+        builder
+    } else {
+        builder.credentials(Credentials::new(
+            settings.username.clone(),
+            settings.password.clone(),
+        ))
+    };
+    Ok(builder.build())
+}
+
+pub fn send_email(settings: &EmailSettings, email: &OutboxEmail) -> Result<(), EmailError> {
+    let message = build_message(settings, email)?;
+    build_transport(settings)?.send(&message)?;
+    Ok(())
+}
+
+/// Tries every due email once. Failures wait out an interval that doubles with each
+/// attempt, and are marked failed once they run out of attempts or can never succeed.
+pub async fn process_outbox(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &EmailSettings,
+) -> Result<(usize, usize), sqlx::Error> {
+    let (mut sent, mut failed) = (0, 0);
+    for email in get_due_emails(pool).await {
+        let result = async_std::task::spawn_blocking({
+            let settings = settings.clone();
+            let email = email.clone();
+            move || send_email(&settings, &email)
+        })
+        .await;
+
+        match result {
+            Ok(_) => {
+                println!("Sent email {} to {}", email.id, email.recipient);
+                mark_email_sent(pool, &email).await?;
+                sent += 1;
+            }
+            Err(e) => {
+                println!("Failed to send email {}: {e}", email.id);
+                let attempts = email.attempts + 1;
+                let status = if e.is_permanent() || attempts >= settings.max_attempts {
+                    failed += 1;
+                    EmailStatus::Failed
+                } else {
+                    EmailStatus::Queued
+                };
+                let backoff = settings.retry_interval_minutes.max(1) << (attempts - 1).min(10);
+                let next_attempt = Local::now().naive_local()
+                    + chrono::Duration::try_minutes(backoff as i64).unwrap_or_default();
+                record_email_failure(pool, &email, status, &e.to_string(), next_attempt).await?;
+            }
+        }
+    }
+    Ok((sent, failed))
+}
+
+pub enum EmailMessage {
+    SendNow,
+    Retry(i64),
+    Refresh,
+    Quit,
+}
+
+pub struct EmailWorker {
+    pub channel: UnboundedSender<EmailMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl EmailWorker {
+    pub fn new(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        settings: &AppSettings,
+        app: slint::Weak<App>,
+    ) -> Self {
+        println!("Create new Email Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            let settings = settings.email.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(email_worker_loop(new_pool, settings, app, r))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(EmailMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn email_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    settings: EmailSettings,
+    app: slint::Weak<App>,
+    mut r: UnboundedReceiver<EmailMessage>,
+) {
+    let mut schedule = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        let send = tokio::select! {
+            _ = schedule.tick() => true,
+            m = r.recv() => match m {
+                Some(EmailMessage::SendNow) => true,
+                Some(EmailMessage::Retry(id)) => {
+                    match requeue_email(&pool, id).await {
+                        Ok(_) => println!("Requeued email {id}"),
+                        Err(e) => println!("Failed to requeue email {id}: {e}"),
+                    }
+                    true
+                }
+                Some(EmailMessage::Refresh) => false,
+                Some(EmailMessage::Quit) | None => {
+                    println!("Quitting");
+                    break;
+                }
+            },
+        };
+        let status = if !settings.enabled {
+            Some(String::from("Email delivery is turned off in the settings"))
+        } else if send {
+            match process_outbox(&pool, &settings).await {
+                Ok((0, 0)) => None,
+                Ok((sent, failed)) => Some(format!("Sent {sent} emails, {failed} failed")),
+                Err(e) => Some(format!("Sending emails failed: {e}")),
+            }
+        } else {
+            None
+        };
+
+        let emails: Vec<OutboxEmailInput> = get_outbox(&pool, OUTBOX_DISPLAY_LIMIT)
+            .await
+            .iter()
+            .map(OutboxEmail::convert_to_slint)
+            .collect();
+        let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+            let outbox = handle.global::<Outbox>();
+            outbox.set_emails(slint::ModelRc::new(slint::VecModel::from(emails)));
+            if let Some(status) = status {
+                outbox.set_status(status.into());
+            }
+        });
+        match upgrade_res {
+            Ok(_) => (),
+            Err(e) => println!("Failed to upgrade ui: {e}"),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use super::*;
+    use crate::{
+        audit::{AuditAction, EntityType},
+        database::{get_audit_history, scratch_database},
+    };
+
+    /// A local SMTP server that accepts everything except one command, which it answers
+    /// with the given reply instead. Every connection and accepted message is counted.
+    struct SmtpStandIn {
+        port: u16,
+        connections: Arc<AtomicUsize>,
+        messages: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SmtpStandIn {
+        fn start(reject: Option<(&'static str, &'static str)>) -> SmtpStandIn {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let messages = Arc::new(Mutex::new(vec![]));
+            std::thread::spawn({
+                let connections = connections.clone();
+                let messages = messages.clone();
+                move || {
+                    for stream in listener.incoming() {
+                        let Ok(mut stream) = stream else { break };
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        let mut reader = BufReader::new(stream.try_clone().unwrap());
+                        let _ = serve(&mut reader, &mut stream, reject, &messages);
+                    }
+                }
+            });
+            SmtpStandIn {
+                port,
+                connections,
+                messages,
+            }
+        }
+
+        fn connections(&self) -> usize {
+            self.connections.load(Ordering::SeqCst)
+        }
+
+        fn settings(&self) -> EmailSettings {
+            EmailSettings {
+                enabled: true,
+                host: String::from("127.0.0.1"),
+                port: self.port,
+                security: SmtpSecurity::None,
+                from_address: String::from("Hestia <office@example.com>"),
+                max_attempts: 3,
+                retry_interval_minutes: 15,
+                ..EmailSettings::default()
+            }
+        }
+    }
+
+    fn serve(
+        reader: &mut impl BufRead,
+        stream: &mut impl Write,
+        reject: Option<(&str, &str)>,
+        messages: &Mutex<Vec<String>>,
+    ) -> std::io::Result<()> {
+        stream.write_all(b"220 localhost ESMTP\r\n")?;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let command = line.get(..4).unwrap_or_default().to_ascii_uppercase();
+            let reply = match reject {
+                Some((rejected, reply)) if rejected == command => reply,
+                _ => match command.as_str() {
+                    "EHLO" | "HELO" => "250 localhost",
+                    "DATA" => {
+                        stream.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")?;
+                        let mut message = String::new();
+                        loop {
+                            line.clear();
+                            if reader.read_line(&mut line)? == 0 || line == ".\r\n" {
+                                break;
+                            }
+                            message.push_str(&line);
+                        }
+                        messages.lock().unwrap().push(message);
+                        "250 OK queued"
+                    }
+                    "QUIT" => {
+                        stream.write_all(b"221 Bye\r\n")?;
+                        return Ok(());
+                    }
+                    _ => "250 OK",
+                },
+            };
+            stream.write_all(format!("{reply}\r\n").as_bytes())?;
+        }
+    }
+
+    async fn queue_email(pool: &sqlx::Pool<sqlx::Sqlite>) -> i64 {
+        let email = OutboxEmail::new(
+            None,
+            None,
+            String::from("rosa@example.com"),
+            String::from("Statement for March 2024"),
+            String::from("Hello Rosa, your statement is attached."),
+            None,
+            None,
+        );
+        add_outbox_email(pool, &email)
+            .await
+            .unwrap()
+            .last_insert_rowid()
+    }
+
+    async fn outbox_email(pool: &sqlx::Pool<sqlx::Sqlite>, id: i64) -> OutboxEmail {
+        get_outbox(pool, 10)
+            .await
+            .into_iter()
+            .find(|email| email.id == id)
+            .unwrap()
+    }
+
+    async fn make_due(pool: &sqlx::Pool<sqlx::Sqlite>, id: i64) {
+        sqlx::query(
+            "UPDATE email_outbox SET next_attempt_at = '2000-01-01 00:00:00' WHERE email_id = ?",
+        )
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    fn assert_retry_in(email: &OutboxEmail, minutes: i64) {
+        let wait = email.next_attempt_at - Local::now().naive_local();
+        assert!(
+            (minutes - 1..=minutes).contains(&wait.num_minutes()),
+            "next attempt in {} minutes, expected {minutes}",
+            wait.num_minutes()
+        );
+    }
+
+    /// The audited changes to the email, oldest first.
+    async fn audit_actions(pool: &sqlx::Pool<sqlx::Sqlite>, id: i64) -> Vec<AuditAction> {
+        let mut history = get_audit_history(pool, EntityType::OutboxEmail, id as u32).await;
+        history.reverse();
+        history.into_iter().map(|entry| entry.action).collect()
+    }
+
+    #[async_std::test]
+    async fn sends_queued_email() {
+        let (pool, _) = scratch_database("email_send").await;
+        let server = SmtpStandIn::start(None);
+        let id = queue_email(&pool).await;
+
+        assert_eq!(
+            process_outbox(&pool, &server.settings()).await.unwrap(),
+            (1, 0)
+        );
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Sent);
+        assert_eq!(email.attempts, 1);
+        assert!(email.sent_at.is_some());
+        assert!(email.last_error.is_none());
+        let messages = server.messages.lock().unwrap().clone();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: Statement for March 2024"));
+        assert!(messages[0].contains("Hello Rosa, your statement is attached."));
+
+        // Sent emails aren't tried again
+        assert_eq!(
+            process_outbox(&pool, &server.settings()).await.unwrap(),
+            (0, 0)
+        );
+        assert_eq!(server.connections(), 1);
+        assert_eq!(
+            audit_actions(&pool, id).await,
+            [AuditAction::Create, AuditAction::Update]
+        );
+    }
+
+    #[async_std::test]
+    async fn retries_transient_failures_with_backoff() {
+        let (pool, _) = scratch_database("email_retry").await;
+        let server = SmtpStandIn::start(Some(("MAIL", "451 4.3.0 Try again later")));
+        let settings = server.settings();
+        let id = queue_email(&pool).await;
+
+        assert_eq!(process_outbox(&pool, &settings).await.unwrap(), (0, 0));
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Queued);
+        assert_eq!(email.attempts, 1);
+        assert!(email.last_error.unwrap().contains("Try again later"));
+        assert_retry_in(&outbox_email(&pool, id).await, 15);
+
+        // Not due again until the backoff has passed
+        assert_eq!(process_outbox(&pool, &settings).await.unwrap(), (0, 0));
+        assert_eq!(server.connections(), 1);
+
+        make_due(&pool, id).await;
+        assert_eq!(process_outbox(&pool, &settings).await.unwrap(), (0, 0));
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Queued);
+        assert_eq!(email.attempts, 2);
+        assert_retry_in(&email, 30);
+
+        // The last allowed attempt gives up on it
+        make_due(&pool, id).await;
+        assert_eq!(process_outbox(&pool, &settings).await.unwrap(), (0, 1));
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Failed);
+        assert_eq!(email.attempts, 3);
+        assert_eq!(server.connections(), 3);
+        assert!(server.messages.lock().unwrap().is_empty());
+
+        // A manual retry starts over and goes through once the server accepts it
+        requeue_email(&pool, id).await.unwrap();
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Queued);
+        assert_eq!(email.attempts, 0);
+        let working = SmtpStandIn::start(None);
+        assert_eq!(
+            process_outbox(&pool, &working.settings()).await.unwrap(),
+            (1, 0)
+        );
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Sent);
+        assert_eq!(email.attempts, 1);
+
+        // Queued, three failures, the requeue and the send are all in the audit log, but a
+        // requeue of an email that hadn't failed changes nothing
+        requeue_email(&pool, id).await.unwrap();
+        let mut expected = vec![AuditAction::Create];
+        expected.extend([AuditAction::Update; 5]);
+        assert_eq!(audit_actions(&pool, id).await, expected);
+    }
+
+    #[async_std::test]
+    async fn gives_up_on_permanent_failures() {
+        let (pool, _) = scratch_database("email_reject").await;
+        let server = SmtpStandIn::start(Some(("RCPT", "550 5.1.1 No such user")));
+        let id = queue_email(&pool).await;
+
+        assert_eq!(
+            process_outbox(&pool, &server.settings()).await.unwrap(),
+            (0, 1)
+        );
+        let email = outbox_email(&pool, id).await;
+        assert_eq!(email.status, EmailStatus::Failed);
+        assert_eq!(email.attempts, 1);
+        assert!(email.last_error.unwrap().contains("No such user"));
+    }
+}
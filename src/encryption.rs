@@ -0,0 +1,369 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use slint::ComponentHandle;
+use sqlx::{ConnectOptions, Connection};
+
+use crate::{
+    app_settings::AppSettings,
+    backup::{self, BackupError},
+    database,
+};
+
+mod passphrase_ui {
+    // Generated by build.rs, which also emits the std-widgets types the dialog doesn't use
+    #![allow(dead_code)]
+    include!(concat!(env!("OUT_DIR"), "/passphrase.rs"));
+}
+use passphrase_ui::PassphraseDialog;
+
+// Read instead of prompting, for scheduled or scripted runs of the CLI commands
+pub const PASSPHRASE_ENV: &str = "HESTIA_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "HESTIA_NEW_PASSPHRASE";
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+const EXPORT_SUFFIX: &str = ".export";
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    Database(sqlx::Error),
+    Io(std::io::Error),
+    Backup(BackupError),
+    Passphrase(String),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncryptionError::Database(e) => write!(f, "database error: {e}"),
+            EncryptionError::Io(e) => write!(f, "file error: {e}"),
+            EncryptionError::Backup(e) => write!(f, "{e}"),
+            EncryptionError::Passphrase(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<sqlx::Error> for EncryptionError {
+    fn from(e: sqlx::Error) -> Self {
+        EncryptionError::Database(e)
+    }
+}
+
+impl From<std::io::Error> for EncryptionError {
+    fn from(e: std::io::Error) -> Self {
+        EncryptionError::Io(e)
+    }
+}
+
+impl From<BackupError> for EncryptionError {
+    fn from(e: BackupError) -> Self {
+        EncryptionError::Backup(e)
+    }
+}
+
+/// A plain SQLite file always starts with the same 16 byte header, SQLCipher encrypts it
+/// along with the rest of the file.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    match std::fs::File::open(path).and_then(|mut file| file.read_exact(&mut header)) {
+        Ok(_) => &header != SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+/// Checks that the passphrase opens the database by reading its schema.
+pub async fn check_passphrase(
+    path: &Path,
+    passphrase: Option<&str>,
+) -> Result<(), EncryptionError> {
+    let mut conn = database::connect_options(path, passphrase)
+        .read_only(true)
+        .connect()
+        .await?;
+    let result = sqlx::query_scalar::<_, i64>("SELECT count(*) FROM sqlite_master")
+        .fetch_one(&mut conn)
+        .await;
+    conn.close().await?;
+    match result {
+        Ok(_) => Ok(()),
+        Err(_) => Err(EncryptionError::Passphrase(
+            "Incorrect passphrase".to_owned(),
+        )),
+    }
+}
+
+/// Brings the database file in line with the encryption setting and keeps the passphrase in
+/// `settings` for every connection opened afterwards. An existing plain database is
+/// encrypted when encryption is turned on, and decrypted again when it is turned off.
+/// Must run before the database pool is opened.
+pub async fn unlock_database(settings: &mut AppSettings) -> Result<(), EncryptionError> {
+    let database_file = settings.paths.database_file();
+    let encrypted = is_encrypted(&database_file);
+    if !encrypted && !settings.encryption.enabled {
+        return Ok(());
+    }
+
+    if encrypted {
+        let passphrase = ask_existing_passphrase(&database_file).await?;
+        if settings.encryption.enabled {
+            settings.encryption.passphrase = Some(passphrase);
+        } else {
+            export_database(settings, Some(&passphrase), None).await?;
+            println!("Decrypted database");
+        }
+    } else {
+        let passphrase = ask_new_passphrase(
+            PASSPHRASE_ENV,
+            "Database encryption is turned on. Choose a passphrase to encrypt the database with. \
+             It cannot be recovered if it is lost.",
+        )?;
+        if database_file.is_file() {
+            export_database(settings, None, Some(&passphrase)).await?;
+            println!("Encrypted database");
+            println!(
+                "Backups in {} made before encryption are not encrypted",
+                settings.paths.backup_path
+            );
+        }
+        settings.encryption.passphrase = Some(passphrase);
+    }
+    Ok(())
+}
+
+/// Re-encrypts the database with a new passphrase. `settings` must already hold the current
+/// passphrase. Backups made before the change keep the passphrase they were made with.
+pub async fn change_passphrase(settings: &mut AppSettings) -> Result<(), EncryptionError> {
+    let current =
+        settings.encryption.passphrase.clone().ok_or_else(|| {
+            EncryptionError::Passphrase("The database is not encrypted".to_owned())
+        })?;
+    let passphrase = ask_new_passphrase(
+        NEW_PASSPHRASE_ENV,
+        "Choose a new passphrase for the database.",
+    )?;
+    export_database(settings, Some(&current), Some(&passphrase)).await?;
+    settings.encryption.passphrase = Some(passphrase);
+    Ok(())
+}
+
+/// Copies the database into a new file keyed with `to` (no key writes a plain database),
+/// checks the copy, then swaps it in for the live file.
+async fn export_database(
+    settings: &AppSettings,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(), EncryptionError> {
+    let database_file = settings.paths.database_file();
+    let mut export_file = database_file.clone().into_os_string();
+    export_file.push(EXPORT_SUFFIX);
+    let export_file = PathBuf::from(export_file);
+    if export_file.exists() {
+        std::fs::remove_file(&export_file)?;
+    }
+
+    // The attached database is opened with the same flags, so it needs permission to create
+    let mut conn = database::connect_options(&database_file, from)
+        .create_if_missing(true)
+        .connect()
+        .await?;
+    // sqlcipher_export copies the schema and rows but not the header fields
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(&mut conn)
+        .await?;
+    sqlx::query("ATTACH DATABASE ? AS export KEY ?")
+        .bind(export_file.to_string_lossy().to_string())
+        .bind(to.unwrap_or(""))
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("SELECT sqlcipher_export('export')")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query(&format!("PRAGMA export.user_version = {version}"))
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("DETACH DATABASE export")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    if let Err(e) = verify_export(&export_file, to).await {
+        let _ = std::fs::remove_file(&export_file);
+        return Err(e);
+    }
+    backup::remove_sidecar_files(&database_file);
+    std::fs::rename(&export_file, &database_file)?;
+    Ok(())
+}
+
+/// An encrypted copy has to open with its key and must not open without it.
+async fn verify_export(path: &Path, passphrase: Option<&str>) -> Result<(), EncryptionError> {
+    backup::verify_backup(path, passphrase).await?;
+    if passphrase.is_some() && (!is_encrypted(path) || check_passphrase(path, None).await.is_ok()) {
+        return Err(EncryptionError::Passphrase(
+            "Encrypted copy of the database is readable without the passphrase".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+async fn ask_existing_passphrase(database_file: &Path) -> Result<String, EncryptionError> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        check_passphrase(database_file, Some(&passphrase)).await?;
+        return Ok(passphrase);
+    }
+    let mut error = String::new();
+    loop {
+        let passphrase = prompt_passphrase(
+            "The database is encrypted. Enter the passphrase to unlock it.",
+            false,
+            &error,
+        )?;
+        match check_passphrase(database_file, Some(&passphrase)).await {
+            Ok(_) => return Ok(passphrase),
+            Err(e) => error = e.to_string(),
+        }
+    }
+}
+
+fn ask_new_passphrase(env: &str, message: &str) -> Result<String, EncryptionError> {
+    match std::env::var(env) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(passphrase),
+        _ => prompt_passphrase(message, true, ""),
+    }
+}
+
+/// Shows the passphrase window and waits for it to close.
+fn prompt_passphrase(message: &str, confirm: bool, error: &str) -> Result<String, EncryptionError> {
+    let dialog = PassphraseDialog::new().map_err(|e| {
+        EncryptionError::Passphrase(format!("Could not show passphrase prompt: {e}"))
+    })?;
+    dialog.set_message(message.into());
+    dialog.set_confirm(confirm);
+    dialog.set_error(error.into());
+
+    let entered: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+    let submitted = entered.clone();
+    let weak_dialog = dialog.as_weak();
+    dialog.on_submit(move |passphrase| {
+        *submitted.borrow_mut() = Some(passphrase.to_string());
+        let _ = weak_dialog.unwrap().hide();
+    });
+    let weak_dialog = dialog.as_weak();
+    dialog.on_cancel(move || {
+        let _ = weak_dialog.unwrap().hide();
+    });
+    dialog.run().map_err(|e| {
+        EncryptionError::Passphrase(format!("Could not show passphrase prompt: {e}"))
+    })?;
+
+    let passphrase = entered.borrow_mut().take();
+    passphrase.ok_or_else(|| EncryptionError::Passphrase("No passphrase entered".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_settings::scratch_settings;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+    const SECRET: &str = "Rosa Delgado, 1475.00";
+
+    /// Creates the app's schema, so the copy passes the backup checks, plus a table holding
+    /// a value that must not show up in the encrypted file.
+    async fn write_secret(path: &Path, passphrase: Option<&str>) {
+        let options = database::connect_options(path, passphrase).create_if_missing(true);
+        let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+        database::create_schema(&pool).await.unwrap();
+        sqlx::query("CREATE TABLE tenants (name TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO tenants (name) VALUES (?)")
+            .bind(SECRET)
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+    }
+
+    async fn read_secret(path: &Path, passphrase: Option<&str>) -> Result<String, sqlx::Error> {
+        let mut conn = database::connect_options(path, passphrase)
+            .read_only(true)
+            .connect()
+            .await?;
+        let name = sqlx::query_scalar("SELECT name FROM tenants")
+            .fetch_one(&mut conn)
+            .await;
+        conn.close().await?;
+        name
+    }
+
+    fn assert_unreadable_on_disk(path: &Path) {
+        let contents = std::fs::read(path).unwrap();
+        assert!(!contents.starts_with(SQLITE_HEADER));
+        assert!(!contents
+            .windows(SECRET.len())
+            .any(|window| window == SECRET.as_bytes()));
+    }
+
+    #[async_std::test]
+    async fn keyed_database_cannot_be_read_without_its_key() {
+        let settings = scratch_settings("keyed");
+        let database_file = settings.paths.database_file();
+        write_secret(&database_file, Some(PASSPHRASE)).await;
+
+        assert!(is_encrypted(&database_file));
+        assert_unreadable_on_disk(&database_file);
+        assert!(check_passphrase(&database_file, None).await.is_err());
+        assert!(check_passphrase(&database_file, Some("wrong passphrase"))
+            .await
+            .is_err());
+        assert!(read_secret(&database_file, None).await.is_err());
+        assert!(read_secret(&database_file, Some("wrong passphrase"))
+            .await
+            .is_err());
+        check_passphrase(&database_file, Some(PASSPHRASE))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_secret(&database_file, Some(PASSPHRASE)).await.unwrap(),
+            SECRET
+        );
+
+        let _ = std::fs::remove_dir_all(&settings.paths.database_path);
+    }
+
+    #[async_std::test]
+    async fn encrypting_and_decrypting_an_existing_database() {
+        let settings = scratch_settings("export");
+        let database_file = settings.paths.database_file();
+        write_secret(&database_file, None).await;
+        assert!(!is_encrypted(&database_file));
+
+        export_database(&settings, None, Some(PASSPHRASE))
+            .await
+            .unwrap();
+        assert!(is_encrypted(&database_file));
+        assert_unreadable_on_disk(&database_file);
+        assert!(read_secret(&database_file, None).await.is_err());
+        assert!(read_secret(&database_file, Some("wrong passphrase"))
+            .await
+            .is_err());
+        assert_eq!(
+            read_secret(&database_file, Some(PASSPHRASE)).await.unwrap(),
+            SECRET
+        );
+
+        export_database(&settings, Some(PASSPHRASE), None)
+            .await
+            .unwrap();
+        assert!(!is_encrypted(&database_file));
+        assert_eq!(read_secret(&database_file, None).await.unwrap(), SECRET);
+
+        let _ = std::fs::remove_dir_all(&settings.paths.database_path);
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use crate::{
-    database::{add_expense, remove_expense, update_expense},
+    database::{add_expense, remove_expense, restore_expense, update_expense},
     ExpenseInput,
 };
 use chrono::NaiveDate;
@@ -20,6 +20,7 @@ pub enum MaintenanceType {
     Repairs,
     Cleaning,
     Landscaping,
+    Amenities,
     Other,
 }
 
@@ -39,6 +40,7 @@ impl fmt::Display for ExpenseType {
                 MaintenanceType::Repairs => String::from("Maintenance: Repairs"),
                 MaintenanceType::Cleaning => String::from("Maintenance: Cleaning"),
                 MaintenanceType::Landscaping => String::from("Maintenance: Landscaping"),
+                MaintenanceType::Amenities => String::from("Maintenance: Amenities"),
                 MaintenanceType::Other => String::from("Maintenance: Other"),
             },
             ExpenseType::Utilities(utilities_type) => match utilities_type {
@@ -61,6 +63,7 @@ impl ExpenseType {
                 "Repairs" => ExpenseType::Maintenance(MaintenanceType::Repairs),
                 "Cleaning" => ExpenseType::Maintenance(MaintenanceType::Cleaning),
                 "Landscaping" => ExpenseType::Maintenance(MaintenanceType::Landscaping),
+                "Amenities" => ExpenseType::Maintenance(MaintenanceType::Amenities),
                 _ => ExpenseType::Maintenance(MaintenanceType::Other),
             },
             "Utilities" => match subtype {
@@ -83,6 +86,9 @@ impl ExpenseType {
                 MaintenanceType::Landscaping => {
                     (String::from("Maintenance"), String::from("Landscaping"))
                 }
+                MaintenanceType::Amenities => {
+                    (String::from("Maintenance"), String::from("Amenities"))
+                }
                 MaintenanceType::Other => (String::from("Maintenance"), String::from("Other")),
             },
             ExpenseType::Utilities(utilities_type) => match utilities_type {
@@ -217,6 +223,7 @@ pub enum ExpenseMessage {
     ExpenseCreated(ExpenseInput),
     ExpenseUpdate(ExpenseInput),
     ExpenseDelete(ExpenseInput),
+    ExpenseRestore(ExpenseInput),
     Quit,
 }
 
@@ -278,6 +285,13 @@ async fn expense_worker_loop(
                         Err(e) => println!("Failed to remove expense via slint: {e}"),
                     }
                 }
+                ExpenseMessage::ExpenseRestore(restore) => {
+                    let converted_expense = Expense::convert_from_slint(restore);
+                    match restore_expense(&pool, &converted_expense).await {
+                        Ok(_) => println!("Successfully restored expense via slint"),
+                        Err(e) => println!("Failed to restore expense via slint: {e}"),
+                    }
+                }
                 ExpenseMessage::Quit => {
                     println!("Quitting");
                     continue;
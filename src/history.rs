@@ -0,0 +1,238 @@
+use slint::{Model, VecModel};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    expenses::ExpenseMessage, leaseholders::LeaseholderMessage, properties::PropertyMessage, App,
+    ExpenseInput, LeaseholderInput, PropertyInput,
+};
+
+const HISTORY_LIMIT: usize = 100;
+
+/// A single reversible edit made from the UI. `before` is the record as it was before the
+/// edit and `after` is the record as it is after, so a create has no `before` and a delete
+/// has no `after`. Applying the inverse of a command undoes it.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Expense {
+        before: Option<ExpenseInput>,
+        after: Option<ExpenseInput>,
+    },
+    Property {
+        before: Option<PropertyInput>,
+        after: Option<PropertyInput>,
+        // Leaseholders removed along with the property, restored with it on undo
+        cascaded: Vec<LeaseholderInput>,
+    },
+    Leaseholder {
+        before: Option<LeaseholderInput>,
+        after: Option<LeaseholderInput>,
+    },
+}
+
+impl Command {
+    pub fn inverse(&self) -> Command {
+        match self.clone() {
+            Command::Expense { before, after } => Command::Expense {
+                before: after,
+                after: before,
+            },
+            Command::Property {
+                before,
+                after,
+                cascaded,
+            } => Command::Property {
+                before: after,
+                after: before,
+                cascaded,
+            },
+            Command::Leaseholder { before, after } => Command::Leaseholder {
+                before: after,
+                after: before,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CommandHistory {
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+}
+
+impl CommandHistory {
+    pub fn push(&mut self, command: Command) {
+        if self.undo_stack.len() == HISTORY_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    /// Returns the command that reverts the most recent edit.
+    pub fn undo(&mut self) -> Option<Command> {
+        let command = self.undo_stack.pop()?;
+        let inverse = command.inverse();
+        self.redo_stack.push(command);
+        Some(inverse)
+    }
+
+    /// Returns the most recently undone edit so it can be applied again.
+    pub fn redo(&mut self) -> Option<Command> {
+        let command = self.redo_stack.pop()?;
+        self.undo_stack.push(command.clone());
+        Some(command)
+    }
+}
+
+/// Replaces the row `matches` finds, or adds it when the model no longer has it, e.g. after
+/// a worker reloaded the list or a filter hid the row between the edit and its undo.
+fn upsert_row<T: Clone + 'static>(model: &VecModel<T>, row: T, matches: impl Fn(&T) -> bool) {
+    match model.iter().position(|r| matches(&r)) {
+        Some(index) => model.set_row_data(index, row),
+        None => model.push(row),
+    }
+}
+
+/// Removes the row `matches` finds, if the model still has it.
+fn remove_row<T: Clone + 'static>(model: &VecModel<T>, matches: impl Fn(&T) -> bool) {
+    if let Some(index) = model.iter().position(|r| matches(&r)) {
+        model.remove(index);
+    }
+}
+
+#[derive(Clone)]
+pub struct WorkerChannels {
+    pub expense: UnboundedSender<ExpenseMessage>,
+    pub property: UnboundedSender<PropertyMessage>,
+    pub lessee: UnboundedSender<LeaseholderMessage>,
+}
+
+/// Applies a command to the UI models and forwards it to the matching worker.
+/// `replay` is set when the command comes from undo/redo, in which case records are
+/// recreated with their original ids instead of being inserted as new rows.
+pub fn apply_command(handle: &App, command: &Command, channels: &WorkerChannels, replay: bool) {
+    match command {
+        Command::Expense { before, after } => {
+            let prev_expense = handle.get_expenses();
+            let expenses = prev_expense
+                .as_any()
+                .downcast_ref::<VecModel<ExpenseInput>>()
+                .expect("Expenses failed to downcast");
+            let message = match (before, after) {
+                (Some(_), Some(a)) => {
+                    upsert_row(expenses, a.clone(), |r| r.id == a.id);
+                    ExpenseMessage::ExpenseUpdate(a.clone())
+                }
+                (None, Some(a)) => {
+                    upsert_row(expenses, a.clone(), |r| r.id == a.id);
+                    if a.id >= handle.get_potential_expense_id() {
+                        handle.set_potential_expense_id(a.id + 1);
+                    }
+                    if replay {
+                        ExpenseMessage::ExpenseRestore(a.clone())
+                    } else {
+                        ExpenseMessage::ExpenseCreated(a.clone())
+                    }
+                }
+                (Some(b), None) => {
+                    remove_row(expenses, |r| r.id == b.id);
+                    ExpenseMessage::ExpenseDelete(b.clone())
+                }
+                (None, None) => return,
+            };
+            match channels.expense.send(message) {
+                Ok(_) => println!("expense successfully sent"),
+                Err(_e) => println!("expense send failed"),
+            }
+        }
+        Command::Property {
+            before,
+            after,
+            cascaded,
+        } => {
+            let prev_property = handle.get_properties();
+            let properties = prev_property
+                .as_any()
+                .downcast_ref::<VecModel<PropertyInput>>()
+                .expect("Properties failed to downcast");
+            let prev_lessees = handle.get_lessees();
+            let lessees = prev_lessees
+                .as_any()
+                .downcast_ref::<VecModel<LeaseholderInput>>()
+                .expect("Leaseholders failed to downcast");
+            let message = match (before, after) {
+                (Some(b), Some(a)) => {
+                    upsert_row(properties, a.clone(), |r| r.id == a.id);
+                    match (b.archived, a.archived) {
+                        (false, true) => PropertyMessage::PropertyArchive(a.clone()),
+                        (true, false) => PropertyMessage::PropertyUnarchive(a.clone()),
+                        _ => PropertyMessage::PropertyUpdate(a.clone()),
+                    }
+                }
+                (None, Some(a)) => {
+                    upsert_row(properties, a.clone(), |r| r.id == a.id);
+                    if a.id >= handle.get_potential_prop_id() {
+                        handle.set_potential_prop_id(a.id + 1);
+                    }
+                    for lessee in cascaded {
+                        upsert_row(lessees, lessee.clone(), |r| r.id == lessee.id);
+                    }
+                    if replay {
+                        PropertyMessage::PropertyRestore(a.clone(), cascaded.clone())
+                    } else {
+                        PropertyMessage::PropertyCreated(a.clone())
+                    }
+                }
+                (Some(b), None) => {
+                    remove_row(properties, |r| r.id == b.id);
+                    for lessee in cascaded {
+                        remove_row(lessees, |r| r.id == lessee.id);
+                    }
+                    PropertyMessage::PropertyRemove(b.clone())
+                }
+                (None, None) => return,
+            };
+            match channels.property.send(message) {
+                Ok(_) => println!("property successfully sent"),
+                Err(_e) => println!("property send failed"),
+            };
+        }
+        Command::Leaseholder { before, after } => {
+            let prev_lessees = handle.get_lessees();
+            let lessees = prev_lessees
+                .as_any()
+                .downcast_ref::<VecModel<LeaseholderInput>>()
+                .expect("Leaseholders failed to downcast");
+            let message = match (before, after) {
+                (Some(b), Some(a)) => {
+                    upsert_row(lessees, a.clone(), |r| r.id == a.id);
+                    match (b.archived, a.archived) {
+                        (false, true) => LeaseholderMessage::LeaseholderArchive(a.clone()),
+                        (true, false) => LeaseholderMessage::LeaseholderUnarchive(a.clone()),
+                        _ => LeaseholderMessage::LeaseholderUpdate(a.clone()),
+                    }
+                }
+                (None, Some(a)) => {
+                    upsert_row(lessees, a.clone(), |r| r.id == a.id);
+                    if a.id >= handle.get_potential_lessee_id() {
+                        handle.set_potential_lessee_id(a.id + 1);
+                    }
+                    if replay {
+                        LeaseholderMessage::LeaseholderRestore(a.clone())
+                    } else {
+                        LeaseholderMessage::LeaseholderCreated(a.clone())
+                    }
+                }
+                (Some(b), None) => {
+                    remove_row(lessees, |r| r.id == b.id);
+                    LeaseholderMessage::LeaseholderDelete(b.clone())
+                }
+                (None, None) => return,
+            };
+            match channels.lessee.send(message) {
+                Ok(_) => println!("Leaseholder successfully sent"),
+                Err(_e) => println!("Leaseholder send failed"),
+            };
+        }
+    }
+}
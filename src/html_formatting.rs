@@ -0,0 +1,185 @@
+use std::{fmt::Write, fs::OpenOptions, io::Write as _, path::PathBuf};
+
+use crate::{
+    app_settings::PathSettings, statement_document::StatementDocument,
+    statement_template::StatementTemplate,
+};
+
+// Inline so the file displays the same when opened on its own or read in a mail client
+const STYLE: &str = "
+body { font-family: Helvetica, Arial, sans-serif; color: #222; margin: 0; padding: 16px; }
+.statement { max-width: 640px; margin: 0 auto; }
+h1 { font-size: 1.4em; margin: 0 0 4px; }
+h2 { font-size: 1.1em; font-weight: normal; color: #555; margin: 0 0 16px; }
+.parties { display: flex; flex-wrap: wrap; gap: 16px; justify-content: space-between; }
+.parties div { min-width: 200px; }
+table { width: 100%; border-collapse: collapse; margin: 16px 0; }
+th, td { padding: 6px 4px; border-bottom: 1px solid #ddd; text-align: left; }
+td.amount, th.amount { text-align: right; white-space: nowrap; }
+tr.total td { font-weight: bold; border-top: 2px solid #222; border-bottom: none; }
+.remit { margin-top: 24px; }
+.message { margin: 0 0 16px; }
+.note { margin: 16px 0 0; font-style: italic; }
+";
+
+fn escape_html(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+/// Renders the statement as a single HTML page with its styles inline. The layout narrows
+/// to fit phone screens. `message` is shown above the statement, for the email body.
+pub fn render_html(
+    document: &StatementDocument,
+    template: &StatementTemplate,
+    message: Option<&str>,
+) -> String {
+    let recipient = &document.recipient;
+    let balances = &document.balances;
+    let e = escape_html;
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{} - {}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<div class=\"statement\">\n",
+        e(document.title(template)),
+        e(&recipient.name)
+    );
+    if let Some(message) = message {
+        for paragraph in message.split("\n\n") {
+            let _ = writeln!(
+                html,
+                "<p class=\"message\">{}</p>",
+                e(paragraph).replace('\n', "<br>")
+            );
+        }
+        html.push_str("<hr>\n");
+    }
+    let _ = writeln!(html, "<h1>{}</h1>", e(&document.company));
+    let _ = writeln!(
+        html,
+        "<h2>{} for {}</h2>",
+        e(document.title(template)),
+        document.period.start.format("%B %Y")
+    );
+    let _ = writeln!(
+        html,
+        "<div class=\"parties\">\n<div><strong>{}</strong><br>{}<br>{}<br>{}</div>\n\
+         <div>{}Date: {}<br>{}Property: {}<br>Period: {}</div>\n</div>",
+        e(&recipient.name),
+        e(&recipient.address.street_address),
+        e(&recipient.city_line()),
+        e(&recipient.email),
+        document
+            .invoice_number
+            .as_ref()
+            .map(|number| format!("Invoice: {}<br>", e(number)))
+            .unwrap_or_default(),
+        document.date,
+        if document.is_credit_memo() {
+            String::new()
+        } else {
+            format!("Due: {}<br>", document.due_date)
+        },
+        e(&document.property),
+        document.period.range()
+    );
+
+    if let Some(note) = &document.note {
+        let _ = writeln!(html, "<p class=\"note\">{}</p>", e(note));
+    }
+
+    if !document.is_credit_memo() {
+        html.push_str("<table>\n");
+        for (label, amount) in [
+            ("Balance Forward", balances.forward),
+            ("Payment Received", balances.payments),
+            ("Outstanding Balance", balances.outstanding),
+        ] {
+            let _ = writeln!(
+                html,
+                "<tr><td>{label}</td><td class=\"amount\">{amount:.2}</td></tr>"
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    let _ = writeln!(
+        html,
+        "<table>\n<tr><th>{}</th><th class=\"amount\">{}</th></tr>",
+        e(&template.charge_heading),
+        e(&template.amount_heading)
+    );
+    for line in &document.lines {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"amount\">{}</td></tr>",
+            e(&line.label()),
+            line.amount_text()
+        );
+    }
+    let _ = writeln!(
+        html,
+        "<tr class=\"total\"><td>{}</td><td class=\"amount\">${:.2}</td></tr>\n</table>",
+        document.total_label(),
+        document.total_due
+    );
+    if !document.adjustments.is_empty() {
+        let _ = writeln!(
+            html,
+            "<p><strong>{}</strong></p>\n<ul>",
+            e(&template.adjustments_heading)
+        );
+        for adjustment in &document.adjustments {
+            let _ = writeln!(html, "<li>{}</li>", e(adjustment));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    let _ = writeln!(
+        html,
+        "<p>{}</p>\n<div class=\"remit\"><strong>{}</strong><br>{}<br>{}</div>\n<p>{}</p>",
+        [
+            document.payment_terms(template),
+            document.late_fee_terms(template),
+        ]
+        .iter()
+        .filter(|terms| !terms.is_empty())
+        .map(|terms| e(terms))
+        .collect::<Vec<_>>()
+        .join("<br>"),
+        e(&template.remit_heading),
+        e(&recipient.address.street_address),
+        e(&recipient.city_line()),
+        e(&template.footer)
+    );
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+/// Writes the statement as an HTML file next to its PDF.
+pub fn write_html(
+    document: &StatementDocument,
+    template: &StatementTemplate,
+    settings: &PathSettings,
+) -> std::io::Result<PathBuf> {
+    let path = document.file_path(settings, "html");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(render_html(document, template, None).as_bytes())?;
+    Ok(path)
+}
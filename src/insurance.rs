@@ -0,0 +1,581 @@
+use chrono::{Local, NaiveDate};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    app_settings::{AppSettings, DocumentSettings},
+    database::{
+        add_insurance_certificate, get_insurance_certificates, get_leaseholders,
+        remove_insurance_certificate,
+    },
+    lease::encoded_term,
+    leaseholders::Leaseholder,
+    App, CertificateInput, ComplianceInput, Insurance,
+};
+
+/// The liability coverage a lease requires the tenant to carry, usually with the landlord
+/// named as additional insured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InsuranceRequirement {
+    pub per_occurrence: f32,
+    pub aggregate: f32,
+    pub additional_insured: bool,
+}
+
+impl InsuranceRequirement {
+    pub fn encode_to_database_string(&self) -> String {
+        let mut encoded = format!(
+            "Occurrence {}, Aggregate {}",
+            self.per_occurrence, self.aggregate
+        );
+        if self.additional_insured {
+            encoded.push_str(", Additional Insured");
+        }
+        encoded
+    }
+
+    pub fn decode_from_database_string(encoded: &str) -> Option<InsuranceRequirement> {
+        Some(InsuranceRequirement {
+            per_occurrence: encoded_term(encoded, "Occurrence")?,
+            aggregate: encoded_term(encoded, "Aggregate")?,
+            additional_insured: encoded.contains("Additional Insured"),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "${:.0} per occurrence, ${:.0} aggregate{}",
+            self.per_occurrence,
+            self.aggregate,
+            match self.additional_insured {
+                true => ", naming us as additional insured",
+                false => "",
+            }
+        )
+    }
+
+    /// How a certificate falls short of the requirement, empty when it meets it.
+    pub fn shortfalls(&self, certificate: &InsuranceCertificate) -> Vec<String> {
+        let mut shortfalls = vec![];
+        if certificate.per_occurrence < self.per_occurrence {
+            shortfalls.push(format!(
+                "${:.0} per occurrence of ${:.0} required",
+                certificate.per_occurrence, self.per_occurrence
+            ));
+        }
+        if certificate.aggregate < self.aggregate {
+            shortfalls.push(format!(
+                "${:.0} aggregate of ${:.0} required",
+                certificate.aggregate, self.aggregate
+            ));
+        }
+        if self.additional_insured && !certificate.additional_insured {
+            shortfalls.push(String::from("not named as additional insured"));
+        }
+        shortfalls
+    }
+}
+
+/// A tenant's certificate of insurance, as the carrier issued it.
+#[derive(Debug, Clone)]
+pub struct InsuranceCertificate {
+    pub id: i64,
+    pub leaseholder_id: u32,
+    pub carrier: String,
+    pub policy_number: String,
+    pub per_occurrence: f32,
+    pub aggregate: f32,
+    pub additional_insured: bool,
+    pub effective_date: NaiveDate,
+    pub expiry_date: NaiveDate,
+    pub recorded_on: NaiveDate,
+}
+
+impl InsuranceCertificate {
+    pub fn in_force(&self, date: NaiveDate) -> bool {
+        self.effective_date <= date && date <= self.expiry_date
+    }
+
+    pub fn convert_to_slint(&self) -> CertificateInput {
+        CertificateInput {
+            id: self.id as i32,
+            carrier: self.carrier.clone().into(),
+            policy_number: self.policy_number.clone().into(),
+            coverage: format!(
+                "${:.0} / ${:.0}{}",
+                self.per_occurrence,
+                self.aggregate,
+                match self.additional_insured {
+                    true => ", additional insured",
+                    false => "",
+                }
+            )
+            .into(),
+            term: format!("{} to {}", self.effective_date, self.expiry_date).into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for InsuranceCertificate {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let date = |column: &str| -> Result<NaiveDate, sqlx::Error> {
+            let date: String = row.try_get(column)?;
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+
+        Ok(InsuranceCertificate {
+            id: row.try_get("certificate_id")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            carrier: row.try_get("carrier")?,
+            policy_number: row.try_get("policy_number")?,
+            per_occurrence: row.try_get::<f64, _>("per_occurrence")? as f32,
+            aggregate: row.try_get::<f64, _>("aggregate")? as f32,
+            additional_insured: row.try_get("additional_insured")?,
+            effective_date: date("effective_date")?,
+            expiry_date: date("expiry_date")?,
+            recorded_on: date("recorded_on")?,
+        })
+    }
+}
+
+/// A certificate as typed into the Leaseholders tab, checked by `parse`.
+#[derive(Debug, Clone)]
+pub struct NewCertificate {
+    pub carrier: String,
+    pub policy_number: String,
+    pub per_occurrence: String,
+    pub aggregate: String,
+    pub additional_insured: bool,
+    pub effective_date: String,
+    pub expiry_date: String,
+}
+
+impl NewCertificate {
+    pub fn parse(&self, leaseholder_id: u32) -> Result<InsuranceCertificate, String> {
+        let date = |text: &str| {
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date: {text}, use YYYY-MM-DD"))
+        };
+        let amount = |text: &str, label: &str| {
+            text.trim()
+                .trim_start_matches('$')
+                .replace(',', "")
+                .parse::<f32>()
+                .ok()
+                .filter(|amount| *amount >= 0.0)
+                .ok_or_else(|| format!("Invalid {label}: {text}"))
+        };
+        let carrier = self.carrier.trim();
+        if carrier.is_empty() {
+            return Err(String::from("The certificate needs the insurance carrier"));
+        }
+        let effective_date = date(&self.effective_date)?;
+        let expiry_date = date(&self.expiry_date)?;
+        if expiry_date < effective_date {
+            return Err(String::from(
+                "The policy has to expire after it takes effect",
+            ));
+        }
+        Ok(InsuranceCertificate {
+            id: 0,
+            leaseholder_id,
+            carrier: carrier.to_owned(),
+            policy_number: self.policy_number.trim().to_owned(),
+            per_occurrence: amount(&self.per_occurrence, "per occurrence coverage")?,
+            aggregate: amount(&self.aggregate, "aggregate coverage")?,
+            additional_insured: self.additional_insured,
+            effective_date,
+            expiry_date,
+            recorded_on: Local::now().date_naive(),
+        })
+    }
+}
+
+/// Where a tenant's coverage stands against what their lease requires.
+#[derive(Debug, Clone)]
+pub enum Compliance {
+    Compliant(InsuranceCertificate),
+    // Meets the requirement but runs out within the warning window, with no renewal on file
+    Expiring(InsuranceCertificate, i64),
+    Insufficient(InsuranceCertificate, Vec<String>),
+    // With the last certificate that lapsed, if there was one
+    Missing(Option<InsuranceCertificate>),
+}
+
+impl Compliance {
+    /// Judges the certificates in force on `today`, preferring one that meets the
+    /// requirement. A certificate about to expire is fine once a renewal that meets the
+    /// requirement and picks up where it leaves off is on file.
+    pub fn evaluate(
+        requirement: &InsuranceRequirement,
+        certificates: &[InsuranceCertificate],
+        today: NaiveDate,
+        warning_days: i64,
+    ) -> Compliance {
+        let sufficient =
+            |certificate: &InsuranceCertificate| requirement.shortfalls(certificate).is_empty();
+        let in_force: Vec<&InsuranceCertificate> = certificates
+            .iter()
+            .filter(|certificate| certificate.in_force(today))
+            .collect();
+        let Some(best) = in_force
+            .iter()
+            .filter(|certificate| sufficient(certificate))
+            .max_by_key(|certificate| certificate.expiry_date)
+        else {
+            return match in_force
+                .iter()
+                .max_by_key(|certificate| certificate.expiry_date)
+            {
+                Some(certificate) => Compliance::Insufficient(
+                    (*certificate).clone(),
+                    requirement.shortfalls(certificate),
+                ),
+                None => Compliance::Missing(
+                    certificates
+                        .iter()
+                        .filter(|certificate| certificate.expiry_date < today)
+                        .max_by_key(|certificate| certificate.expiry_date)
+                        .cloned(),
+                ),
+            };
+        };
+        let days_left = (best.expiry_date - today).num_days();
+        let renewed = certificates.iter().any(|renewal| {
+            renewal.effective_date <= best.expiry_date.succ_opt().unwrap_or(best.expiry_date)
+                && renewal.expiry_date > best.expiry_date
+                && sufficient(renewal)
+        });
+        match days_left <= warning_days && !renewed {
+            true => Compliance::Expiring((*best).clone(), days_left),
+            false => Compliance::Compliant((*best).clone()),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Compliance::Compliant(_) => "Compliant",
+            Compliance::Expiring(_, _) => "Expiring",
+            Compliance::Insufficient(_, _) => "Insufficient",
+            Compliance::Missing(_) => "Missing",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Compliance::Compliant(certificate) => format!(
+                "{} policy {} in force until {}",
+                certificate.carrier, certificate.policy_number, certificate.expiry_date
+            ),
+            Compliance::Expiring(certificate, days_left) => format!(
+                "{} policy {} expires {}, in {} days, with no renewal on file",
+                certificate.carrier, certificate.policy_number, certificate.expiry_date, days_left
+            ),
+            Compliance::Insufficient(certificate, shortfalls) => format!(
+                "{} policy {}: {}",
+                certificate.carrier,
+                certificate.policy_number,
+                shortfalls.join(", ")
+            ),
+            Compliance::Missing(Some(lapsed)) => format!(
+                "No certificate in force, {} policy {} expired {}",
+                lapsed.carrier, lapsed.policy_number, lapsed.expiry_date
+            ),
+            Compliance::Missing(None) => String::from("No certificate of insurance on file"),
+        }
+    }
+}
+
+/// A tenant whose current lease requires insurance, and where their coverage stands.
+#[derive(Debug, Clone)]
+pub struct ComplianceEntry {
+    pub leaseholder_id: u32,
+    pub name: String,
+    pub requirement: InsuranceRequirement,
+    pub compliance: Compliance,
+}
+
+impl ComplianceEntry {
+    pub fn convert_to_slint(&self) -> ComplianceInput {
+        ComplianceInput {
+            leaseholder_id: self.leaseholder_id as i32,
+            name: self.name.clone().into(),
+            status: self.compliance.label().into(),
+            detail: self.compliance.describe().into(),
+            compliant: matches!(self.compliance, Compliance::Compliant(_)),
+        }
+    }
+}
+
+/// Every active tenant whose current lease requires insurance, the ones needing attention
+/// first. Certificates count as expiring within the document reminder window.
+pub async fn compliance_report(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &DocumentSettings,
+    today: NaiveDate,
+) -> Vec<ComplianceEntry> {
+    let mut entries: Vec<ComplianceEntry> = vec![];
+    for leaseholder in get_leaseholders(pool, false).await {
+        let Some(requirement) = leaseholder.lease.insurance_requirement else {
+            continue;
+        };
+        if leaseholder
+            .move_out_date
+            .is_some_and(|move_out| move_out < today)
+        {
+            continue;
+        }
+        let certificates = get_insurance_certificates(pool, leaseholder.id).await;
+        entries.push(ComplianceEntry {
+            leaseholder_id: leaseholder.id,
+            name: leaseholder.contact_info.name.clone(),
+            requirement,
+            compliance: Compliance::evaluate(
+                &requirement,
+                &certificates,
+                today,
+                settings.reminder_days,
+            ),
+        });
+    }
+    entries.sort_by_key(|entry| match entry.compliance {
+        Compliance::Missing(_) => 0,
+        Compliance::Insufficient(_, _) => 1,
+        Compliance::Expiring(_, _) => 2,
+        Compliance::Compliant(_) => 3,
+    });
+    entries
+}
+
+pub enum InsuranceMessage {
+    Show(u32),
+    Add(u32, NewCertificate),
+    Remove(i64),
+    Refresh,
+    Quit,
+}
+
+pub struct InsuranceWorker {
+    pub channel: UnboundedSender<InsuranceMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl InsuranceWorker {
+    pub fn new(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        settings: &AppSettings,
+        app: slint::Weak<App>,
+    ) -> Self {
+        println!("Create new Insurance Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            let settings = settings.documents.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(insurance_worker_loop(new_pool, settings, r, app))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(InsuranceMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn insurance_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    settings: DocumentSettings,
+    mut r: UnboundedReceiver<InsuranceMessage>,
+    app: slint::Weak<App>,
+) {
+    let mut leaseholder_id: Option<u32> = None;
+    loop {
+        let status = match r.recv().await {
+            Some(InsuranceMessage::Show(id)) => {
+                leaseholder_id = Some(id);
+                None
+            }
+            Some(InsuranceMessage::Add(id, new_certificate)) => {
+                leaseholder_id = Some(id);
+                Some(match new_certificate.parse(id) {
+                    Ok(mut certificate) => {
+                        match add_insurance_certificate(&pool, &mut certificate).await {
+                            Ok(_) => format!(
+                                "Recorded {} policy {} until {}",
+                                certificate.carrier,
+                                certificate.policy_number,
+                                certificate.expiry_date
+                            ),
+                            Err(e) => format!("Failed to record certificate: {e}"),
+                        }
+                    }
+                    Err(e) => e,
+                })
+            }
+            Some(InsuranceMessage::Remove(id)) => {
+                Some(match remove_insurance_certificate(&pool, id).await {
+                    Ok(_) => String::from("Removed certificate"),
+                    Err(e) => format!("Failed to remove certificate: {e}"),
+                })
+            }
+            Some(InsuranceMessage::Refresh) => None,
+            Some(InsuranceMessage::Quit) | None => break,
+        };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+        show_insurance(&pool, &settings, &app, leaseholder_id, status).await;
+    }
+}
+
+async fn show_insurance(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &DocumentSettings,
+    app: &slint::Weak<App>,
+    leaseholder_id: Option<u32>,
+    status: Option<String>,
+) {
+    let today = Local::now().date_naive();
+    let (certificates, requirement) = match leaseholder_id {
+        Some(id) => {
+            let requirement = get_leaseholders(pool, true)
+                .await
+                .into_iter()
+                .find(|leaseholder| leaseholder.id == id)
+                .as_ref()
+                .and_then(required_coverage);
+            (get_insurance_certificates(pool, id).await, requirement)
+        }
+        None => (vec![], None),
+    };
+    let report = compliance_report(pool, settings, today).await;
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let certificates: Vec<CertificateInput> = certificates
+            .iter()
+            .map(InsuranceCertificate::convert_to_slint)
+            .collect();
+        let report: Vec<ComplianceInput> = report
+            .iter()
+            .map(ComplianceEntry::convert_to_slint)
+            .collect();
+        let registry = handle.global::<Insurance>();
+        registry.set_certificates(ModelRc::new(VecModel::from(certificates)));
+        registry.set_requirement(
+            requirement
+                .unwrap_or_else(|| String::from("The current lease requires no insurance."))
+                .into(),
+        );
+        registry.set_compliance(ModelRc::new(VecModel::from(report)));
+        registry.set_status(status.unwrap_or_default().into());
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+fn required_coverage(leaseholder: &Leaseholder) -> Option<String> {
+    leaseholder
+        .lease
+        .insurance_requirement
+        .map(|requirement| format!("The current lease requires {}.", requirement.describe()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn requirement() -> InsuranceRequirement {
+        InsuranceRequirement {
+            per_occurrence: 1_000_000.0,
+            aggregate: 2_000_000.0,
+            additional_insured: true,
+        }
+    }
+
+    fn certificate(
+        policy_number: &str,
+        per_occurrence: f32,
+        effective_date: NaiveDate,
+        expiry_date: NaiveDate,
+    ) -> InsuranceCertificate {
+        InsuranceCertificate {
+            id: 0,
+            leaseholder_id: 1,
+            carrier: String::from("Cascade Mutual"),
+            policy_number: policy_number.to_owned(),
+            per_occurrence,
+            aggregate: 2_000_000.0,
+            additional_insured: true,
+            effective_date,
+            expiry_date,
+            recorded_on: effective_date,
+        }
+    }
+
+    #[test]
+    fn certificates_under_the_minimum_are_insufficient() {
+        let under = certificate("CM-1", 500_000.0, date(1, 1), date(12, 31));
+        let compliance = Compliance::evaluate(&requirement(), &[under], date(6, 1), 30);
+        let Compliance::Insufficient(_, shortfalls) = &compliance else {
+            panic!("expected insufficient, got {compliance:?}");
+        };
+        assert_eq!(shortfalls, &["$500000 per occurrence of $1000000 required"]);
+
+        let mut unnamed = certificate("CM-2", 1_000_000.0, date(1, 1), date(12, 31));
+        unnamed.additional_insured = false;
+        assert_eq!(
+            requirement().shortfalls(&unnamed),
+            ["not named as additional insured"]
+        );
+    }
+
+    #[test]
+    fn a_sufficient_certificate_is_preferred_over_an_insufficient_one() {
+        let certificates = [
+            certificate("CM-1", 500_000.0, date(1, 1), date(12, 31)),
+            certificate("CM-2", 1_000_000.0, date(1, 1), date(9, 30)),
+        ];
+        let compliance = Compliance::evaluate(&requirement(), &certificates, date(6, 1), 30);
+        assert_eq!(compliance.label(), "Compliant");
+    }
+
+    #[test]
+    fn expiring_certificates_are_fine_once_renewed() {
+        let current = certificate("CM-1", 1_000_000.0, date(1, 1), date(6, 30));
+        let compliance = Compliance::evaluate(
+            &requirement(),
+            std::slice::from_ref(&current),
+            date(6, 20),
+            30,
+        );
+        assert!(matches!(compliance, Compliance::Expiring(_, 10)));
+
+        let renewal = certificate("CM-2", 1_000_000.0, date(7, 1), date(12, 31));
+        let compliance = Compliance::evaluate(&requirement(), &[current, renewal], date(6, 20), 30);
+        assert_eq!(compliance.label(), "Compliant");
+    }
+
+    #[test]
+    fn lapsed_certificates_leave_the_tenant_missing_coverage() {
+        let lapsed = certificate("CM-1", 1_000_000.0, date(1, 1), date(3, 31));
+        let compliance = Compliance::evaluate(&requirement(), &[lapsed], date(6, 1), 30);
+        assert_eq!(
+            compliance.describe(),
+            "No certificate in force, Cascade Mutual policy CM-1 expired 2024-03-31"
+        );
+        let compliance = Compliance::evaluate(&requirement(), &[], date(6, 1), 30);
+        assert!(matches!(compliance, Compliance::Missing(None)));
+    }
+}
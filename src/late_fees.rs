@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+
+use crate::{
+    database::{
+        add_late_fee, get_assessed_late_fees, get_credited_amount, get_leaseholders, get_statements,
+    },
+    ledger::{LedgerEntry, LedgerKind},
+    statements::{StatementFilter, StatementKind, StatementRecord, StatementStatus},
+};
+
+/// A late fee assessed on an overdue statement. Fees accrue as separate rows, each posted
+/// to the ledger when assessed and billed on the leaseholder's next statement.
+#[derive(Debug, Clone)]
+pub struct LateFee {
+    pub id: i64,
+    pub statement_id: i64,
+    // The invoice number of the overdue statement
+    pub invoice_number: String,
+    pub leaseholder_id: u32,
+    pub assessed_on: NaiveDate,
+    pub days_late: i64,
+    pub amount: f32,
+}
+
+impl LateFee {
+    pub fn ledger_entry(&self) -> LedgerEntry {
+        let mut entry = LedgerEntry::new(
+            self.leaseholder_id,
+            Some(self.statement_id),
+            LedgerKind::LateFee,
+            format!(
+                "Late fee on invoice {}, {} days late",
+                self.invoice_number, self.days_late
+            ),
+            self.amount,
+        );
+        entry.date = self.assessed_on;
+        entry
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for LateFee {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let assessed_on: String = row.try_get("assessed_on")?;
+        let assessed_on = NaiveDate::parse_from_str(&assessed_on, "%Y-%m-%d")
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+        Ok(LateFee {
+            id: row.try_get("late_fee_id")?,
+            statement_id: row.try_get("statement_id")?,
+            invoice_number: row
+                .try_get::<Option<String>, _>("invoice_number")?
+                .unwrap_or_default(),
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            assessed_on,
+            days_late: row.try_get("days_late")?,
+            amount: row.try_get::<f64, _>("amount")? as f32,
+        })
+    }
+}
+
+/// Brings the late fees on every overdue statement up to what its lease's rule allows as
+/// of `today`. Only the difference from what was already assessed is added, so running it
+/// again the same day assesses nothing.
+pub async fn assess_late_fees(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    today: NaiveDate,
+) -> Result<Vec<LateFee>, sqlx::Error> {
+    let rules: HashMap<u32, _> = get_leaseholders(pool, true)
+        .await
+        .into_iter()
+        .filter_map(|leaseholder| Some((leaseholder.id, leaseholder.lease.late_fee?)))
+        .collect();
+    let filter = StatementFilter {
+        status: Some(StatementStatus::Issued),
+        ..Default::default()
+    };
+
+    let mut assessed = vec![];
+    for statement in get_statements(pool, &filter).await {
+        let (Some(rule), Some(due_date)) =
+            (rules.get(&statement.leaseholder_id), statement.due_date)
+        else {
+            continue;
+        };
+        if statement.kind != StatementKind::Statement || due_date >= today {
+            continue;
+        }
+        let days_late = (today - due_date).num_days();
+        let outstanding = outstanding(pool, &statement).await;
+        let owed = rule.fee(outstanding, days_late);
+        let amount =
+            ((owed - get_assessed_late_fees(pool, statement.id).await) * 100.0).round() / 100.0;
+        if amount < 0.01 {
+            continue;
+        }
+        let mut fee = LateFee {
+            id: 0,
+            statement_id: statement.id,
+            invoice_number: statement.invoice_number.clone().unwrap_or_default(),
+            leaseholder_id: statement.leaseholder_id,
+            assessed_on: today,
+            days_late,
+            amount,
+        };
+        add_late_fee(pool, &mut fee).await?;
+        assessed.push(fee);
+    }
+    Ok(assessed)
+}
+
+async fn outstanding(pool: &sqlx::Pool<sqlx::Sqlite>, statement: &StatementRecord) -> f32 {
+    statement.amount_due - statement.amount_paid - get_credited_amount(pool, statement.id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Days;
+
+    use super::*;
+    use crate::{
+        audit::{AuditAction, EntityType},
+        database::{get_audit_history, get_statement, mark_statement_paid},
+        lease::{FeeStructure, LateFeeRule, Lease, Rent},
+        statements::{
+            credit, issue_statement,
+            tests::{billed_on, statement_on},
+            void,
+        },
+    };
+
+    /// A lease at 1200 a month charging 50 plus 5 a day once five days late, up to 100.
+    fn late_fee_lease() -> Lease {
+        lease_with(LateFeeRule {
+            grace_days: 5,
+            flat_fee: 50.0,
+            percent: 0.0,
+            daily_fee: 5.0,
+            cap: Some(100.0),
+        })
+    }
+
+    fn lease_with(rule: LateFeeRule) -> Lease {
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        let mut lease = Lease::new(
+            date(1, 1),
+            date(12, 31),
+            FeeStructure::Gross(Rent { base_rent: 1200.0 }),
+            "Check".to_string(),
+        );
+        lease.late_fee = Some(rule);
+        lease
+    }
+
+    #[async_std::test]
+    async fn assessing_again_only_adds_what_has_accrued_since() {
+        let (pool, settings, statement, property, company) =
+            billed_on("late_fee_accrual", statement_on(late_fee_lease())).await;
+        let issued = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .unwrap();
+        let due_date = issued.record.due_date.unwrap();
+        let amounts = |fees: Vec<LateFee>| fees.iter().map(|fee| fee.amount).collect::<Vec<_>>();
+
+        // Nothing within the grace period
+        let assessed = assess_late_fees(&pool, due_date + Days::new(5)).await;
+        assert!(assessed.unwrap().is_empty());
+
+        let on = due_date + Days::new(7);
+        assert_eq!(amounts(assess_late_fees(&pool, on).await.unwrap()), [60.0]);
+        assert!(assess_late_fees(&pool, on).await.unwrap().is_empty());
+
+        let on = due_date + Days::new(9);
+        assert_eq!(amounts(assess_late_fees(&pool, on).await.unwrap()), [10.0]);
+        assert_eq!(
+            amounts(
+                assess_late_fees(&pool, due_date + Days::new(30))
+                    .await
+                    .unwrap()
+            ),
+            [30.0]
+        );
+        let assessed = assess_late_fees(&pool, due_date + Days::new(60)).await;
+        assert!(assessed.unwrap().is_empty());
+        assert_eq!(get_assessed_late_fees(&pool, issued.record.id).await, 100.0);
+    }
+
+    #[async_std::test]
+    async fn fees_are_charged_on_what_is_left_after_credits() {
+        let rule = LateFeeRule {
+            grace_days: 0,
+            flat_fee: 0.0,
+            percent: 0.1,
+            daily_fee: 0.0,
+            cap: None,
+        };
+        let (pool, settings, statement, property, company) =
+            billed_on("late_fee_credit", statement_on(lease_with(rule))).await;
+        let issued = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .unwrap();
+        credit(
+            &pool,
+            &settings,
+            issued.record.id,
+            200.0,
+            "Parking lot closed",
+        )
+        .await
+        .unwrap();
+        let record = get_statement(&pool, issued.record.id).await.unwrap();
+        assert_eq!(outstanding(&pool, &record).await, record.amount_due - 200.0);
+
+        let late = issued.record.due_date.unwrap() + Days::new(1);
+        let assessed = assess_late_fees(&pool, late).await.unwrap();
+        assert_eq!(assessed.len(), 1);
+        assert_eq!(assessed[0].amount, (record.amount_due - 200.0) * 0.1);
+    }
+
+    #[async_std::test]
+    async fn paid_statements_are_not_charged() {
+        let (pool, settings, statement, property, company) =
+            billed_on("late_fee_paid", statement_on(late_fee_lease())).await;
+        let issued = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .unwrap();
+        mark_statement_paid(&pool, &issued.record).await.unwrap();
+
+        let late = issued.record.due_date.unwrap() + Days::new(30);
+        assert!(assess_late_fees(&pool, late).await.unwrap().is_empty());
+    }
+
+    #[async_std::test]
+    async fn late_fees_are_audited_when_assessed_and_waived() {
+        let (pool, settings, statement, property, company) =
+            billed_on("late_fee_audit", statement_on(late_fee_lease())).await;
+        let issued = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .unwrap();
+        let due_date = issued.record.due_date.unwrap();
+
+        let assessed = assess_late_fees(&pool, due_date + Days::new(7))
+            .await
+            .unwrap();
+        assert_eq!(assessed.len(), 1);
+        let fee_id = assessed[0].id as u32;
+        let history = get_audit_history(&pool, EntityType::LateFee, fee_id).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].action, AuditAction::Create);
+        assert!(history[0].after.is_some());
+
+        void(&pool, issued.record.id, "Billed the wrong unit")
+            .await
+            .unwrap();
+        let history = get_audit_history(&pool, EntityType::LateFee, fee_id).await;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].action, AuditAction::Update);
+    }
+}
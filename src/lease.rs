@@ -1,6 +1,12 @@
-use chrono::NaiveDate;
+use std::fmt;
 
-use crate::{expenses::*, statements::calculate_share};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+
+use crate::{
+    cam::CamClauses, expenses::*, insurance::InsuranceRequirement, percentage_rent::PercentageRent,
+    proration::Occupancy, statement_document::BillingPeriod, statements::calculate_share,
+};
 
 #[derive(Debug, Clone)]
 pub enum FeeStructure {
@@ -37,108 +43,346 @@ impl FeeStructure {
         }
     }
 
-    pub fn display_amounts_due(
+    /// Reads back a fee structure written by `encode_to_database_string`.
+    pub fn decode_from_database_string(encoded: &str) -> Option<FeeStructure> {
+        let (kind, terms) = encoded.split_once(':')?;
+        let term = |name: &str| encoded_term(terms, name);
+        let rent = Rent {
+            base_rent: term("Base Rent")?,
+        };
+        let tax_rate = || {
+            Some(PropertyTaxRate {
+                property_tax: term("Property Tax Rate")?,
+            })
+        };
+        let insurance_rate = || {
+            Some(InsuranceRate {
+                building_insurance: term("Insurance Rate")?,
+            })
+        };
+        match kind {
+            "Gross" => Some(FeeStructure::Gross(rent)),
+            "Single Net" => Some(FeeStructure::SingleNet(rent, tax_rate()?)),
+            "Double Net" => Some(FeeStructure::DoubleNet(
+                rent,
+                tax_rate()?,
+                insurance_rate()?,
+            )),
+            "Triple Net" => Some(FeeStructure::TripleNet(
+                rent,
+                tax_rate()?,
+                insurance_rate()?,
+                CAMRates {
+                    electicity: term("electicity")?,
+                    // Gas was passed through in full before leases had a share of it
+                    gas: term("gas").unwrap_or(1.0),
+                    recycling: term("recycling")?,
+                    garbage: term("garbage")?,
+                    water: term("water")?,
+                    landscaping: term("landscaping")?,
+                    amenities: term("amenities")?,
+                    misc: term("misc")?,
+                },
+            )),
+            _ => None,
+        }
+    }
+
+    /// The monthly base rent.
+    pub fn base_rent(&self) -> f32 {
+        match self {
+            FeeStructure::Gross(rent)
+            | FeeStructure::SingleNet(rent, ..)
+            | FeeStructure::DoubleNet(rent, ..)
+            | FeeStructure::TripleNet(rent, ..) => rent.base_rent,
+        }
+    }
+
+    /// Whether the lease passes the building's utilities through to the tenant.
+    pub fn bills_utilities(&self) -> bool {
+        matches!(self, FeeStructure::TripleNet(..))
+    }
+
+    /// Breaks the tenant's charges for the month into statement lines. Building expenses are
+    /// grouped by category and kept on their line so they can be itemized. Categories with no
+    /// expenses this month are left off.
+    pub fn statement_lines(
         &self,
-        totals: Vec<Expense>,
+        expenses: Vec<Expense>,
         prop_tax: f32,
         bus_insurance: f32,
-    ) -> Vec<String> {
-        let mut lines: Vec<String> = vec![];
-        let property_tax_total: f32 = prop_tax;
-        let insurance_total: f32 = bus_insurance;
-        let mut elect_total: f32 = 0.0;
-        let mut garb_recycl_total: f32 = 0.0;
-        let mut water_total: f32 = 0.0;
-        let mut gas_total: f32 = 0.0;
-        let mut landscaping_total: f32 = 0.0;
-        let mut misc_total: f32 = 0.0;
-
-        let mut total: f32 = 0.0;
-
-        for expense in totals {
-            match expense.expense_type {
-                ExpenseType::Maintenance(maintenance_type) => match maintenance_type {
-                    MaintenanceType::Repairs => misc_total += expense.amount,
-                    MaintenanceType::Cleaning => misc_total += expense.amount,
-                    MaintenanceType::Landscaping => landscaping_total += expense.amount,
-                    MaintenanceType::Other => misc_total += expense.amount,
-                },
-                ExpenseType::Utilities(utilities_type) => match utilities_type {
-                    UtilitiesType::Water => water_total += expense.amount,
-                    UtilitiesType::Electricity => elect_total += expense.amount,
-                    UtilitiesType::Garbage => garb_recycl_total += expense.amount,
-                    UtilitiesType::Gas => gas_total += expense.amount,
-                    UtilitiesType::Other => misc_total += expense.amount,
-                },
-                ExpenseType::Other => misc_total += expense.amount,
-            }
-        }
+    ) -> Vec<StatementLine> {
+        let expenses_for = |category: ChargeCategory| -> Vec<Expense> {
+            expenses
+                .iter()
+                .filter(|expense| ChargeCategory::for_expense(&expense.expense_type) == category)
+                .cloned()
+                .collect()
+        };
 
         match *self {
-            FeeStructure::Gross(r) => lines.push(format!("Rent: ${:.2}", r.base_rent)),
-            FeeStructure::SingleNet(r, t) => {
-                let tax_due = calculate_share(t.property_tax, property_tax_total);
-                total += r.base_rent + tax_due;
-                lines.push(format!("Total Due: ${:.2}", total));
-                lines.push("Rent:".to_owned());
-                lines.push(format!("${:.2}", r.base_rent));
-                lines.push(format!("Property Tax ({:.1}%):", t.property_tax * 100.0));
-                lines.push(format!("${:.2}", tax_due));
-            }
-            FeeStructure::DoubleNet(r, t, i) => {
-                let tax_due = calculate_share(t.property_tax, property_tax_total);
-                let insurance_due = calculate_share(i.building_insurance, insurance_total);
-                total += r.base_rent + tax_due + insurance_due;
-                lines.push(format!("Total Due: ${:.2}", total));
-                lines.push("Rent:".to_owned());
-                lines.push(format!("${:.2}", r.base_rent));
-                lines.push(format!("Property Tax ({:.1}%):", t.property_tax * 100.0));
-                lines.push(format!("${:.2}", tax_due));
-                lines.push(format!("Insurance ({:.1}%):", i.building_insurance * 100.0));
-                lines.push(format!("${:.2}", insurance_due));
-            }
-            FeeStructure::TripleNet(r, t, i, c) => {
-                let tax_due = calculate_share(t.property_tax, property_tax_total);
-                let insurance_due = calculate_share(i.building_insurance, insurance_total);
-                let elec_due = calculate_share(c.electicity, elect_total);
-                let garb_recycl_due = calculate_share(c.garbage + c.recycling, garb_recycl_total);
-                let water_sewer_due = calculate_share(c.water, water_total);
-                let landscaping_due = calculate_share(c.landscaping, landscaping_total);
-                let misc_due = calculate_share(c.misc, misc_total);
-                total += r.base_rent
-                    + tax_due
-                    + insurance_due
-                    + elec_due
-                    + gas_total
-                    + garb_recycl_due
-                    + water_sewer_due
-                    + landscaping_due
-                    + misc_due;
-                lines.push(format!("Total Due: ${:.2}", total));
-                lines.push("Rent:".to_owned());
-                lines.push(format!("${:.2}", r.base_rent));
-                lines.push(format!("Property Tax ({:.1}%):", t.property_tax * 100.0));
-                lines.push(format!("${:.2}", tax_due));
-                lines.push(format!("Insurance ({:.1}%):", i.building_insurance * 100.0));
-                lines.push(format!("${:.2}", insurance_due));
-                lines.push(format!("Electricity ({:.1}%):", c.electicity * 100.0));
-                lines.push(format!("${:.2}", elec_due));
-                lines.push(format!(
-                    "Garbage/Recycling ({:.1}% / {:.1}%):",
-                    c.garbage * 100.0,
-                    c.recycling * 100.0
-                ));
-                lines.push(format!("${:.2}", garb_recycl_due));
-                lines.push(format!("Water/Sewer ({:.1}%):", c.water * 100.0));
-                lines.push(format!("${:.2}", water_sewer_due));
-                lines.push(format!("Landscaping ({:.1}%):", c.landscaping * 100.0));
-                lines.push(format!("${:.2}", landscaping_due));
-                lines.push(format!("Miscellaneous ({:.1}%):", c.misc * 100.0));
-                lines.push(format!("${:.2}", misc_due));
-            }
+            FeeStructure::Gross(r) => vec![StatementLine::flat(ChargeCategory::Rent, r.base_rent)],
+            FeeStructure::SingleNet(r, t) => vec![
+                StatementLine::flat(ChargeCategory::Rent, r.base_rent),
+                StatementLine::share(ChargeCategory::PropertyTax, t.property_tax, prop_tax),
+            ],
+            FeeStructure::DoubleNet(r, t, i) => vec![
+                StatementLine::flat(ChargeCategory::Rent, r.base_rent),
+                StatementLine::share(ChargeCategory::PropertyTax, t.property_tax, prop_tax),
+                StatementLine::share(
+                    ChargeCategory::Insurance,
+                    i.building_insurance,
+                    bus_insurance,
+                ),
+            ],
+            FeeStructure::TripleNet(r, t, i, c) => vec![
+                StatementLine::flat(ChargeCategory::Rent, r.base_rent),
+                StatementLine::share(ChargeCategory::PropertyTax, t.property_tax, prop_tax),
+                StatementLine::share(
+                    ChargeCategory::Insurance,
+                    i.building_insurance,
+                    bus_insurance,
+                ),
+            ]
+            .into_iter()
+            .chain(ChargeCategory::SHARED_EXPENSES.into_iter().map(|category| {
+                StatementLine::expense_share(category, c.rate(category), expenses_for(category))
+            }))
+            .filter(|line| !line.category.is_shared_expense() || !line.expenses.is_empty())
+            .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChargeCategory {
+    Rent,
+    PropertyTax,
+    Insurance,
+    Electricity,
+    Gas,
+    GarbageRecycling,
+    WaterSewer,
+    Landscaping,
+    // Upkeep of shared facilities, e.g. a pool, gym or lounge
+    Amenities,
+    Miscellaneous,
+    // Taken off an earlier statement by a credit memo
+    Credit,
+    // Assessed on an earlier statement that was paid late
+    LateFee,
+    // Shows how a partial month was prorated, and is never charged itself
+    Proration,
+    // The year end true-up of CAM charges under the lease's CAM clauses
+    CamReconciliation,
+    // A share of a retail tenant's sales over the breakpoint
+    PercentageRent,
+    // The year end true-up of percentage rent against the year's sales
+    PercentageRentTrueUp,
+    // One of the lease's named recurring charges, e.g. a parking stall
+    Recurring,
+    // Tax on the taxable lines, under the property's tax jurisdiction
+    SalesTax,
+}
+
+impl fmt::Display for ChargeCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            ChargeCategory::Rent => String::from("Rent"),
+            ChargeCategory::PropertyTax => String::from("Property Tax"),
+            ChargeCategory::Insurance => String::from("Insurance"),
+            ChargeCategory::Electricity => String::from("Electricity"),
+            ChargeCategory::Gas => String::from("Gas"),
+            ChargeCategory::GarbageRecycling => String::from("Garbage/Recycling"),
+            ChargeCategory::WaterSewer => String::from("Water/Sewer"),
+            ChargeCategory::Landscaping => String::from("Landscaping"),
+            ChargeCategory::Amenities => String::from("Amenities"),
+            ChargeCategory::Miscellaneous => String::from("Miscellaneous"),
+            ChargeCategory::Credit => String::from("Credit"),
+            ChargeCategory::LateFee => String::from("Late Fee"),
+            ChargeCategory::Proration => String::from("Prorated"),
+            ChargeCategory::CamReconciliation => String::from("CAM Reconciliation"),
+            ChargeCategory::PercentageRent => String::from("Percentage Rent"),
+            ChargeCategory::PercentageRentTrueUp => String::from("Percentage Rent True-Up"),
+            ChargeCategory::Recurring => String::from("Recurring Charge"),
+            ChargeCategory::SalesTax => String::from("Sales Tax"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl ChargeCategory {
+    /// The lines made up from building expenses, in the order they are billed. Each has a
+    /// rate in `CAMRates::rate`.
+    pub const SHARED_EXPENSES: [ChargeCategory; 7] = [
+        ChargeCategory::Electricity,
+        ChargeCategory::Gas,
+        ChargeCategory::GarbageRecycling,
+        ChargeCategory::WaterSewer,
+        ChargeCategory::Landscaping,
+        ChargeCategory::Amenities,
+        ChargeCategory::Miscellaneous,
+    ];
+
+    /// Whether the line is made up from building expenses rather than a fixed amount.
+    pub fn is_shared_expense(&self) -> bool {
+        ChargeCategory::SHARED_EXPENSES.contains(self)
+    }
+
+    /// The CAM line a building expense is billed under.
+    pub fn for_expense(expense_type: &ExpenseType) -> ChargeCategory {
+        match expense_type {
+            ExpenseType::Maintenance(MaintenanceType::Landscaping) => ChargeCategory::Landscaping,
+            ExpenseType::Maintenance(MaintenanceType::Amenities) => ChargeCategory::Amenities,
+            ExpenseType::Maintenance(_) => ChargeCategory::Miscellaneous,
+            ExpenseType::Utilities(utilities_type) => match utilities_type {
+                UtilitiesType::Water => ChargeCategory::WaterSewer,
+                UtilitiesType::Electricity => ChargeCategory::Electricity,
+                UtilitiesType::Garbage => ChargeCategory::GarbageRecycling,
+                UtilitiesType::Gas => ChargeCategory::Gas,
+                UtilitiesType::Other => ChargeCategory::Miscellaneous,
+            },
+            ExpenseType::Other => ChargeCategory::Miscellaneous,
+        }
+    }
+}
+
+/// One charge on a statement: the tenant's share (`rate`) of a building cost (`base`).
+#[derive(Debug, Clone)]
+pub struct StatementLine {
+    pub category: ChargeCategory,
+    // None when the base is charged in full
+    pub rate: Option<f32>,
+    pub base: f32,
+    pub amount: f32,
+    // The building expenses that add up to `base`, empty for rent, tax and insurance
+    pub expenses: Vec<Expense>,
+    // Shown after the category when the line has no rate, e.g. the invoice a fee is for
+    pub detail: Option<String>,
+    // The fraction of the month billed, below 1 when the line is prorated
+    pub factor: f32,
+    // The part of `base` the lease's CAM clauses let be passed on, the whole base otherwise
+    pub recoverable: f32,
+    // Set on recurring charges the lease marks taxable
+    pub taxable: bool,
+    // Set on utilities billed by the property's meter or ratio billing, not the lease
+    pub utility_billed: bool,
+}
+
+impl StatementLine {
+    pub fn flat(category: ChargeCategory, amount: f32) -> StatementLine {
+        StatementLine {
+            category,
+            rate: None,
+            base: amount,
+            amount,
+            expenses: vec![],
+            detail: None,
+            factor: 1.0,
+            recoverable: amount,
+            taxable: false,
+            utility_billed: false,
+        }
+    }
+
+    pub fn share(category: ChargeCategory, rate: f32, base: f32) -> StatementLine {
+        StatementLine {
+            category,
+            rate: Some(rate),
+            base,
+            amount: (calculate_share(rate, base) * 100.0).round() / 100.0,
+            expenses: vec![],
+            detail: None,
+            factor: 1.0,
+            recoverable: base,
+            taxable: false,
+            utility_billed: false,
+        }
+    }
+
+    pub fn expense_share(
+        category: ChargeCategory,
+        rate: Option<f32>,
+        expenses: Vec<Expense>,
+    ) -> StatementLine {
+        let base = expenses
+            .iter()
+            .fold(0.0, |sum, expense| sum + expense.amount);
+        StatementLine {
+            category,
+            rate,
+            base,
+            amount: (rate.map_or(base, |rate| calculate_share(rate, base)) * 100.0).round() / 100.0,
+            expenses,
+            detail: None,
+            factor: 1.0,
+            recoverable: base,
+            taxable: false,
+            utility_billed: false,
+        }
+    }
+
+    /// A line showing the proration math for part of the month.
+    pub fn proration(occupancy: &Occupancy) -> StatementLine {
+        let mut line = StatementLine::flat(ChargeCategory::Proration, 0.0);
+        line.detail = Some(occupancy.describe());
+        line
+    }
+
+    /// Charges the share of `recoverable` rather than of the whole base. Must be done before
+    /// the line is prorated.
+    pub fn recover(&mut self, recoverable: f32) {
+        self.recoverable = recoverable;
+        let amount = self
+            .rate
+            .map_or(recoverable, |rate| calculate_share(rate, recoverable));
+        self.amount = (amount * 100.0).round() / 100.0;
+    }
+
+    /// Charges only `factor` of the line, for a month that is only partly occupied.
+    pub fn prorate(mut self, factor: f32) -> StatementLine {
+        self.factor = factor;
+        self.amount = (self.amount * factor * 100.0).round() / 100.0;
+        self
+    }
+
+    pub fn label(&self) -> String {
+        match (self.rate, &self.detail) {
+            // Recurring charges go by their own name
+            (None, Some(detail)) if self.category == ChargeCategory::Recurring => detail.clone(),
+            (Some(rate), _) => format!("{} ({:.1}%)", self.category, rate * 100.0),
+            (None, Some(detail)) => format!("{} ({})", self.category, detail),
+            (None, None) => self.category.to_string(),
+        }
+    }
+
+    /// The tenant's part of one of the expenses on this line.
+    pub fn share_of(&self, expense: &Expense) -> f32 {
+        let recovered = if self.base > 0.0 {
+            self.recoverable / self.base
+        } else {
+            1.0
         };
-        lines
+        self.rate
+            .map_or(expense.amount, |rate| calculate_share(rate, expense.amount))
+            * self.factor
+            * recovered
     }
+
+    /// The amount column for the line, left blank for lines that only explain the others.
+    pub fn amount_text(&self) -> String {
+        match self.category {
+            ChargeCategory::Proration => String::new(),
+            _ => format!("${:.2}", self.amount),
+        }
+    }
+}
+
+/// The sum of the lines, each already rounded to the cent, so the total printed always
+/// adds up to the lines printed above it.
+pub fn total_due(lines: &[StatementLine]) -> f32 {
+    let total = lines.iter().fold(0.0, |sum, line| sum + line.amount);
+    (total * 100.0).round() / 100.0
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -159,6 +403,7 @@ pub struct InsuranceRate {
 #[derive(Debug, Clone, Copy)]
 pub struct CAMRates {
     pub electicity: f32,
+    pub gas: f32,
     pub recycling: f32,
     pub garbage: f32,
     pub water: f32,
@@ -171,6 +416,7 @@ impl Default for CAMRates {
     fn default() -> CAMRates {
         CAMRates {
             electicity: 0.4,
+            gas: 0.3,
             recycling: 0.3,
             garbage: 0.3,
             water: 0.3,
@@ -181,6 +427,238 @@ impl Default for CAMRates {
     }
 }
 
+impl CAMRates {
+    /// The tenant's share of a category of building expenses, or None for a line that
+    /// isn't made up from building expenses. Every category is listed so a new one has to
+    /// be given a rule before it can be billed.
+    pub fn rate(&self, category: ChargeCategory) -> Option<f32> {
+        match category {
+            ChargeCategory::Electricity => Some(self.electicity),
+            ChargeCategory::Gas => Some(self.gas),
+            ChargeCategory::GarbageRecycling => Some(self.garbage + self.recycling),
+            ChargeCategory::WaterSewer => Some(self.water),
+            ChargeCategory::Landscaping => Some(self.landscaping),
+            ChargeCategory::Amenities => Some(self.amenities),
+            ChargeCategory::Miscellaneous => Some(self.misc),
+            ChargeCategory::Rent
+            | ChargeCategory::PropertyTax
+            | ChargeCategory::Insurance
+            | ChargeCategory::Credit
+            | ChargeCategory::LateFee
+            | ChargeCategory::Proration
+            | ChargeCategory::CamReconciliation
+            | ChargeCategory::PercentageRent
+            | ChargeCategory::PercentageRentTrueUp
+            | ChargeCategory::Recurring
+            | ChargeCategory::SalesTax => None,
+        }
+    }
+}
+
+/// The number written after `name` in an encoded database string.
+pub fn encoded_term(terms: &str, name: &str) -> Option<f32> {
+    let start = terms.find(name)? + name.len();
+    terms[start..]
+        .trim_start_matches(':')
+        .split([',', ' ', '}'])
+        .find(|value| !value.is_empty())?
+        .parse()
+        .ok()
+}
+
+/// What a tenant is charged for paying a statement late. Nothing is charged until
+/// `grace_days` after the due date, then the flat fee and percentage of what is still
+/// outstanding, plus `daily_fee` for each day after the grace period, up to the cap.
+#[derive(Debug, Clone, Copy)]
+pub struct LateFeeRule {
+    pub grace_days: u32,
+    pub flat_fee: f32,
+    pub percent: f32,
+    pub daily_fee: f32,
+    pub cap: Option<f32>,
+}
+
+impl LateFeeRule {
+    pub fn encode_to_database_string(&self) -> String {
+        let mut encoded = format!(
+            "Grace Days {}, Flat Fee {}, Percent {}, Daily Fee {}",
+            self.grace_days, self.flat_fee, self.percent, self.daily_fee
+        );
+        if let Some(cap) = self.cap {
+            encoded.push_str(&format!(", Cap {cap}"));
+        }
+        encoded
+    }
+
+    pub fn decode_from_database_string(encoded: &str) -> Option<LateFeeRule> {
+        Some(LateFeeRule {
+            grace_days: encoded_term(encoded, "Grace Days")? as u32,
+            flat_fee: encoded_term(encoded, "Flat Fee")?,
+            percent: encoded_term(encoded, "Percent")?,
+            daily_fee: encoded_term(encoded, "Daily Fee")?,
+            cap: encoded_term(encoded, "Cap"),
+        })
+    }
+
+    /// The last day a payment is on time for a statement due on `due_date`.
+    pub fn late_after(&self, due_date: NaiveDate) -> NaiveDate {
+        due_date + Days::new(self.grace_days.into())
+    }
+
+    /// The whole late fee owed on `outstanding` when it is `days_late` days past due.
+    pub fn fee(&self, outstanding: f32, days_late: i64) -> f32 {
+        let accrued_days = days_late - i64::from(self.grace_days);
+        if accrued_days <= 0 || outstanding <= 0.0 {
+            return 0.0;
+        }
+        let fee = self.flat_fee + self.percent * outstanding + self.daily_fee * accrued_days as f32;
+        let fee = self.cap.map_or(fee, |cap| fee.min(cap));
+        (fee * 100.0).round() / 100.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChargeFrequency {
+    Monthly,
+    Quarterly,
+    Annual,
+}
+
+impl fmt::Display for ChargeFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            ChargeFrequency::Monthly => "Monthly",
+            ChargeFrequency::Quarterly => "Quarterly",
+            ChargeFrequency::Annual => "Annual",
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl ChargeFrequency {
+    pub fn parse_string(frequency: &str) -> Option<ChargeFrequency> {
+        match frequency {
+            "Monthly" => Some(ChargeFrequency::Monthly),
+            "Quarterly" => Some(ChargeFrequency::Quarterly),
+            "Annual" => Some(ChargeFrequency::Annual),
+            _ => None,
+        }
+    }
+
+    pub fn months(&self) -> u32 {
+        match self {
+            ChargeFrequency::Monthly => 1,
+            ChargeFrequency::Quarterly => 3,
+            ChargeFrequency::Annual => 12,
+        }
+    }
+}
+
+/// A named charge billed on top of rent while it runs, e.g. a parking stall, a storage
+/// locker or pet rent.
+#[derive(Debug, Clone)]
+pub struct RecurringCharge {
+    pub name: String,
+    pub amount: f32,
+    pub frequency: ChargeFrequency,
+    pub start_date: NaiveDate,
+    // Runs until the lease ends when None
+    pub end_date: Option<NaiveDate>,
+    pub taxable: bool,
+}
+
+impl RecurringCharge {
+    /// e.g. "Parking Stall 4: 75 Monthly, From 2024-01-01, To 2024-12-31, Taxable", with
+    /// the lease's charges separated by semicolons. None when the lease has no charges.
+    pub fn encode_list(charges: &[RecurringCharge]) -> Option<String> {
+        if charges.is_empty() {
+            return None;
+        }
+        let encoded: Vec<String> = charges
+            .iter()
+            .map(|charge| {
+                let mut encoded = format!(
+                    "{}: {} {}, From {}",
+                    charge.name, charge.amount, charge.frequency, charge.start_date
+                );
+                if let Some(end_date) = charge.end_date {
+                    encoded.push_str(&format!(", To {end_date}"));
+                }
+                if charge.taxable {
+                    encoded.push_str(", Taxable");
+                }
+                encoded
+            })
+            .collect();
+        Some(encoded.join("; "))
+    }
+
+    /// Reads back charges written by `encode_list`, skipping any it can't make sense of.
+    pub fn decode_list(encoded: &str) -> Vec<RecurringCharge> {
+        let date = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok();
+        encoded
+            .split(';')
+            .filter_map(|charge| {
+                let (name, terms) = charge.trim().rsplit_once(": ")?;
+                let mut terms = terms.split(", ");
+                let (amount, frequency) = terms.next()?.split_once(' ')?;
+                let mut charge = RecurringCharge {
+                    name: name.to_owned(),
+                    amount: amount.parse().ok()?,
+                    frequency: ChargeFrequency::parse_string(frequency)?,
+                    start_date: date(terms.next()?.strip_prefix("From ")?)?,
+                    end_date: None,
+                    taxable: false,
+                };
+                for term in terms {
+                    match term.strip_prefix("To ") {
+                        Some(end_date) => charge.end_date = Some(date(end_date)?),
+                        None => charge.taxable |= term == "Taxable",
+                    }
+                }
+                Some(charge)
+            })
+            .collect()
+    }
+
+    /// The line for the charge in the part of `period` a lease covers, if it is billed
+    /// then. A monthly charge is billed every month it runs and prorated like rent. A
+    /// quarterly or annual charge falls due every three or twelve months from its start
+    /// date, and is billed in full in that month if the lease covers the day it falls due.
+    pub fn statement_line(
+        &self,
+        period: &BillingPeriod,
+        occupancy: &Occupancy,
+    ) -> Option<StatementLine> {
+        let end_date = self.end_date.unwrap_or(NaiveDate::MAX);
+        let (billed, factor) = match self.frequency {
+            ChargeFrequency::Monthly => (
+                self.start_date <= occupancy.to && occupancy.from <= end_date,
+                occupancy.factor,
+            ),
+            frequency => {
+                let months = (period.start.year() - self.start_date.year()) * 12
+                    + period.start.month() as i32
+                    - self.start_date.month() as i32;
+                let due_date = u32::try_from(months)
+                    .ok()
+                    .filter(|months| months % frequency.months() == 0)
+                    .and_then(|months| self.start_date.checked_add_months(Months::new(months)));
+                let billed = due_date.is_some_and(|due_date| {
+                    occupancy.from <= due_date && due_date <= occupancy.to && due_date <= end_date
+                });
+                (billed, 1.0)
+            }
+        };
+        billed.then(|| {
+            let mut line = StatementLine::flat(ChargeCategory::Recurring, self.amount);
+            line.detail = Some(self.name.clone());
+            line.taxable = self.taxable;
+            line.prorate(factor)
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Lease {
     pub id: u32,
@@ -188,6 +666,15 @@ pub struct Lease {
     pub end_date: NaiveDate,
     pub fee_structure: FeeStructure,
     pub payment_method: String,
+    pub late_fee: Option<LateFeeRule>,
+    // Caps, stops and gross-ups on the CAM charges of a commercial lease
+    pub cam: Option<CamClauses>,
+    // Rent on a retail tenant's sales over a breakpoint, on top of base rent
+    pub percentage_rent: Option<PercentageRent>,
+    // Parking, storage, pet rent and the like, billed as their own lines
+    pub recurring_charges: Vec<RecurringCharge>,
+    // The liability coverage the tenant has to show a certificate for
+    pub insurance_requirement: Option<InsuranceRequirement>,
 }
 
 impl Lease {
@@ -203,6 +690,50 @@ impl Lease {
             end_date,
             fee_structure,
             payment_method,
+            late_fee: None,
+            cam: None,
+            percentage_rent: None,
+            recurring_charges: vec![],
+            insurance_requirement: None,
         }
     }
 }
+
+impl<'r> FromRow<'r, SqliteRow> for Lease {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let date = |column: &str| -> Result<NaiveDate, sqlx::Error> {
+            let date: String = row.try_get(column)?;
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+        let fee_structure: String = row.try_get("fee_structure")?;
+
+        Ok(Lease {
+            id: row.try_get("lease_id")?,
+            start_date: date("start_date")?,
+            end_date: date("end_date")?,
+            fee_structure: FeeStructure::decode_from_database_string(&fee_structure).ok_or_else(
+                || sqlx::Error::Decode(format!("Unknown fee structure: {fee_structure}").into()),
+            )?,
+            payment_method: row
+                .try_get::<Option<String>, _>("payment_method")?
+                .unwrap_or_else(|| "Check".to_string()),
+            late_fee: row
+                .try_get::<Option<String>, _>("late_fee_rule")?
+                .and_then(|rule| LateFeeRule::decode_from_database_string(&rule)),
+            cam: row
+                .try_get::<Option<String>, _>("cam_clauses")?
+                .map(|clauses| CamClauses::decode_from_database_string(&clauses)),
+            percentage_rent: row
+                .try_get::<Option<String>, _>("percentage_rent")?
+                .and_then(|terms| PercentageRent::decode_from_database_string(&terms)),
+            recurring_charges: row
+                .try_get::<Option<String>, _>("recurring_charges")?
+                .map(|charges| RecurringCharge::decode_list(&charges))
+                .unwrap_or_default(),
+            insurance_requirement: row
+                .try_get::<Option<String>, _>("insurance_requirement")?
+                .and_then(|terms| InsuranceRequirement::decode_from_database_string(&terms)),
+        })
+    }
+}
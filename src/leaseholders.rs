@@ -1,10 +1,19 @@
-use chrono::{Datelike, NaiveDate};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime};
 use sqlx::{sqlite::SqliteRow, FromRow, Row};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 use crate::{
-    database::{add_leaseholders, remove_leaseholder, update_leaseholder},
-    lease::{self, CAMRates, FeeStructure, InsuranceRate, Lease, PropertyTaxRate, Rent},
+    cam::CamClauses,
+    database::{
+        add_leaseholders, remove_leaseholder, restore_leaseholder, set_leaseholder_archived,
+        update_leaseholder,
+    },
+    insurance::InsuranceRequirement,
+    lease::{
+        self, CAMRates, FeeStructure, InsuranceRate, LateFeeRule, Lease, PropertyTaxRate,
+        RecurringCharge, Rent,
+    },
+    percentage_rent::PercentageRent,
     properties::Address,
     LeaseholderInput,
 };
@@ -49,6 +58,11 @@ pub struct Leaseholder {
     pub property_id: u32,
     pub contact_info: ContactInformation,
     pub move_in_date: NaiveDate,
+    pub move_out_date: Option<NaiveDate>,
+    // What the unit's share of a ratio utility bill is worked out from
+    pub occupants: u32,
+    pub square_feet: f32,
+    pub archived_at: Option<NaiveDateTime>,
 }
 
 impl Leaseholder {
@@ -65,6 +79,10 @@ impl Leaseholder {
             property_id,
             contact_info,
             move_in_date,
+            move_out_date: None,
+            occupants: 1,
+            square_feet: 0.0,
+            archived_at: None,
         }
     }
     pub fn convert_to_slint(&self) -> LeaseholderInput {
@@ -78,14 +96,26 @@ impl Leaseholder {
             city: copy.contact_info.remittence_address.city.into(),
             email: copy.contact_info.email.into(),
             move_in_date: copy.move_in_date.to_string().into(),
+            move_out_date: copy
+                .move_out_date
+                .map(|date| date.to_string())
+                .unwrap_or_default()
+                .into(),
             phone_number: copy.contact_info.phone_number.into(),
             state: copy.contact_info.remittence_address.state.into(),
             street_address: copy.contact_info.remittence_address.street_address.into(),
             zip_code: copy.contact_info.remittence_address.zip_code.into(),
+            occupants: self.occupants.to_string().into(),
+            square_feet: match self.square_feet {
+                square_feet if square_feet > 0.0 => square_feet.to_string().into(),
+                _ => Default::default(),
+            },
+            archived: self.archived_at.is_some(),
         }
     }
 
     pub fn convert_from_slint(lessee: LeaseholderInput) -> Leaseholder {
+        let date = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok();
         Leaseholder {
             id: lessee.id as u32,
             lease: Lease {
@@ -101,6 +131,11 @@ impl Leaseholder {
                     },
                     CAMRates::default(),
                 ),
+                late_fee: None,
+                cam: None,
+                percentage_rent: None,
+                recurring_charges: vec![],
+                insurance_requirement: None,
             },
             property_id: lessee.property_id as u32,
             contact_info: ContactInformation {
@@ -114,7 +149,20 @@ impl Leaseholder {
                 email: lessee.email.into(),
                 phone_number: lessee.phone_number.into(),
             },
-            move_in_date: NaiveDate::from_ymd_opt(2022, 3, 3).unwrap(),
+            move_in_date: date(&lessee.move_in_date)
+                .unwrap_or_else(|| NaiveDate::from_ymd_opt(2022, 3, 3).unwrap()),
+            move_out_date: date(&lessee.move_out_date),
+            occupants: lessee.occupants.trim().parse::<u32>().unwrap_or(1),
+            square_feet: lessee
+                .square_feet
+                .trim()
+                .replace(',', "")
+                .parse::<f32>()
+                .unwrap_or_default(),
+            archived_at: match lessee.archived {
+                true => Some(Local::now().naive_local()),
+                false => None,
+            },
         }
     }
 }
@@ -132,18 +180,50 @@ impl<'r> FromRow<'r, SqliteRow> for Leaseholder {
         let email: String = row.try_get("email")?;
         let phone_number: String = row.try_get("phone_number")?;
         let move_in_date: String = row.try_get("move_in_date")?;
+        let move_out_date: Option<String> = row.try_get("move_out_date")?;
+        let archived_string: Option<String> = row.try_get("archived_at")?;
+        let occupants: u32 = row.try_get("occupants")?;
+        let square_feet: f64 = row.try_get("square_feet")?;
 
         let naive_date = NaiveDate::parse_from_str(move_in_date.as_str(), "%Y-%m-%d")
             .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        let archived_at = match archived_string {
+            Some(s) => Some(
+                NaiveDateTime::parse_from_str(&s, crate::database::TIMESTAMP_FORMAT)
+                    .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            ),
+            None => None,
+        };
+
+        // The lease columns are only there when the leases table is joined in
+        let lease_column = |column: &str| row.try_get::<Option<String>, _>(column).ok().flatten();
+        let lease_date = |column: &str| {
+            lease_column(column).and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+        };
 
         Ok(Leaseholder {
             id,
             lease: Lease {
                 id: lease_id,
-                start_date: naive_date,
-                end_date: NaiveDate::from_ymd_opt(2024, 3, 3).unwrap(),
-                fee_structure: FeeStructure::Gross(Rent { base_rent: 1700.0 }),
-                payment_method: "Check".to_string(),
+                start_date: lease_date("start_date").unwrap_or(naive_date),
+                end_date: lease_date("end_date")
+                    .unwrap_or_else(|| NaiveDate::from_ymd_opt(2024, 3, 3).unwrap()),
+                fee_structure: lease_column("fee_structure")
+                    .and_then(|fees| FeeStructure::decode_from_database_string(&fees))
+                    .unwrap_or(FeeStructure::Gross(Rent { base_rent: 1700.0 })),
+                payment_method: lease_column("payment_method")
+                    .unwrap_or_else(|| "Check".to_string()),
+                late_fee: lease_column("late_fee_rule")
+                    .and_then(|rule| LateFeeRule::decode_from_database_string(&rule)),
+                cam: lease_column("cam_clauses")
+                    .map(|clauses| CamClauses::decode_from_database_string(&clauses)),
+                percentage_rent: lease_column("percentage_rent")
+                    .and_then(|terms| PercentageRent::decode_from_database_string(&terms)),
+                recurring_charges: lease_column("recurring_charges")
+                    .map(|charges| RecurringCharge::decode_list(&charges))
+                    .unwrap_or_default(),
+                insurance_requirement: lease_column("insurance_requirement")
+                    .and_then(|terms| InsuranceRequirement::decode_from_database_string(&terms)),
             },
             property_id,
             contact_info: ContactInformation {
@@ -158,6 +238,11 @@ impl<'r> FromRow<'r, SqliteRow> for Leaseholder {
                 phone_number,
             },
             move_in_date: naive_date,
+            move_out_date: move_out_date
+                .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+            occupants,
+            square_feet: square_feet as f32,
+            archived_at,
         })
     }
 }
@@ -204,6 +289,9 @@ pub enum LeaseholderMessage {
     LeaseholderCreated(LeaseholderInput),
     LeaseholderUpdate(LeaseholderInput),
     LeaseholderDelete(LeaseholderInput),
+    LeaseholderRestore(LeaseholderInput),
+    LeaseholderArchive(LeaseholderInput),
+    LeaseholderUnarchive(LeaseholderInput),
     Quit,
 }
 
@@ -271,6 +359,27 @@ pub async fn leaseholder_worker_loop(
                         Err(e) => println!("Failed to remove leaseholder via slint: {e}"),
                     }
                 }
+                LeaseholderMessage::LeaseholderRestore(restore) => {
+                    let converted_leaseholder = Leaseholder::convert_from_slint(restore);
+                    match restore_leaseholder(&pool, &converted_leaseholder).await {
+                        Ok(_) => println!("Successfully restored leaseholder via slint"),
+                        Err(e) => println!("Failed to restore leaseholder via slint: {e}"),
+                    }
+                }
+                LeaseholderMessage::LeaseholderArchive(archive) => {
+                    let converted_leaseholder = Leaseholder::convert_from_slint(archive);
+                    match set_leaseholder_archived(&pool, &converted_leaseholder, true).await {
+                        Ok(_) => println!("Successfully archived leaseholder via slint"),
+                        Err(e) => println!("Failed to archive leaseholder via slint: {e}"),
+                    }
+                }
+                LeaseholderMessage::LeaseholderUnarchive(unarchive) => {
+                    let converted_leaseholder = Leaseholder::convert_from_slint(unarchive);
+                    match set_leaseholder_archived(&pool, &converted_leaseholder, false).await {
+                        Ok(_) => println!("Successfully unarchived leaseholder via slint"),
+                        Err(e) => println!("Failed to unarchive leaseholder via slint: {e}"),
+                    }
+                }
                 LeaseholderMessage::Quit => {
                     println!("Quitting");
                     continue;
@@ -0,0 +1,135 @@
+use chrono::{Local, NaiveDate};
+use sqlx::{sqlite::SqliteRow, FromRow, Row, SqliteConnection};
+use std::fmt;
+
+use crate::{database::TIMESTAMP_FORMAT, LedgerEntryInput};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedgerKind {
+    Charge,
+    Credit,
+    Payment,
+    Void,
+    LateFee,
+}
+
+impl fmt::Display for LedgerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            LedgerKind::Charge => String::from("charge"),
+            LedgerKind::Credit => String::from("credit"),
+            LedgerKind::Payment => String::from("payment"),
+            LedgerKind::Void => String::from("void"),
+            LedgerKind::LateFee => String::from("late_fee"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl LedgerKind {
+    pub fn parse_string(kind: &str) -> Option<LedgerKind> {
+        match kind {
+            "charge" => Some(LedgerKind::Charge),
+            "credit" => Some(LedgerKind::Credit),
+            "payment" => Some(LedgerKind::Payment),
+            "void" => Some(LedgerKind::Void),
+            "late_fee" => Some(LedgerKind::LateFee),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a leaseholder's account. Charges are positive and everything that reduces
+/// what they owe is negative, so the balance is the sum of the amounts.
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub leaseholder_id: u32,
+    pub statement_id: Option<i64>,
+    pub date: NaiveDate,
+    pub kind: LedgerKind,
+    pub description: String,
+    pub amount: f32,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        leaseholder_id: u32,
+        statement_id: Option<i64>,
+        kind: LedgerKind,
+        description: String,
+        amount: f32,
+    ) -> LedgerEntry {
+        LedgerEntry {
+            id: 0,
+            leaseholder_id,
+            statement_id,
+            date: Local::now().date_naive(),
+            kind,
+            description,
+            amount,
+        }
+    }
+
+    pub fn convert_to_slint(&self, balance: f32) -> LedgerEntryInput {
+        LedgerEntryInput {
+            id: self.id as i32,
+            date: self.date.to_string().into(),
+            kind: self.kind.to_string().into(),
+            description: self.description.clone().into(),
+            amount: format!("{:.2}", self.amount).into(),
+            balance: format!("{:.2}", balance).into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for LedgerEntry {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let kind: String = row.try_get("kind")?;
+        let kind = LedgerKind::parse_string(&kind).ok_or_else(|| {
+            sqlx::Error::Decode(format!("Unknown ledger entry kind: {kind}").into())
+        })?;
+        let date: String = row.try_get("entry_date")?;
+        let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+        Ok(LedgerEntry {
+            id: row.try_get("entry_id")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            statement_id: row.try_get("statement_id")?,
+            date,
+            kind,
+            description: row.try_get("description")?,
+            amount: row.try_get::<f64, _>("amount")? as f32,
+        })
+    }
+}
+
+/// Appends an entry to the ledger. Like the audit log it is append-only, so mistakes are
+/// fixed with another entry, and it must be posted on the transaction that caused it.
+pub async fn post(conn: &mut SqliteConnection, entry: &LedgerEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO ledger_entries (leaseholder_id, statement_id, entry_date, kind, description, amount, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(entry.leaseholder_id)
+        .bind(entry.statement_id)
+        .bind(entry.date.to_string())
+        .bind(entry.kind.to_string())
+        .bind(&entry.description)
+        .bind(entry.amount)
+        .bind(Local::now().naive_local().format(TIMESTAMP_FORMAT).to_string())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Running balances alongside each entry, oldest first.
+pub fn with_balances(entries: &[LedgerEntry]) -> Vec<(LedgerEntry, f32)> {
+    let mut balance = 0.0;
+    entries
+        .iter()
+        .map(|entry| {
+            balance += entry.amount;
+            (entry.clone(), balance)
+        })
+        .collect()
+}
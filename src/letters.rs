@@ -0,0 +1,592 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    app_settings::{AppSettings, PathSettings},
+    database::{add_letter, get_leaseholders, get_ledger, get_letters, get_properties},
+    email::fill_template,
+    leaseholders::Leaseholder,
+    pdf_formatting::write_letter_pdf,
+    properties::Property,
+    statement_document::Recipient,
+    statement_template::load_template,
+    statements::{hash_file, latest_company},
+    App, LetterInput, Letters,
+};
+
+const LETTER_FOLDER: &str = "letters";
+const TEMPLATE_EXTENSION: &str = "toml";
+
+/// A notice or letter, read from `<name>.toml` in the letters folder of the templates
+/// directory. The subject and body can use the merge fields listed in `merge_fields`, and
+/// any other `{field}` has to be given a value when the letter is generated. Paragraphs in
+/// the body are separated by blank lines. The page layout, fonts and logo come from the
+/// property's statement template.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LetterTemplate {
+    pub subject: String,
+    pub body: String,
+    pub closing: String,
+}
+
+impl Default for LetterTemplate {
+    fn default() -> Self {
+        LetterTemplate {
+            subject: String::new(),
+            body: String::from("Dear {tenant_name},\n\n"),
+            closing: String::from("Sincerely,"),
+        }
+    }
+}
+
+/// The letters written out the first time, as a starting point to edit.
+fn built_in_templates() -> Vec<(&'static str, LetterTemplate)> {
+    vec![
+        (
+            "rent_increase",
+            LetterTemplate {
+                subject: String::from("Notice of Rent Increase at {property_name}"),
+                body: String::from(
+                    "Dear {tenant_name},\n\n\
+                     This letter is notice that the monthly rent for {property_address} will \
+                     increase from ${base_rent} to ${new_rent}, starting {effective_date}.\n\n\
+                     All other terms of your lease stay the same. Please continue to pay by \
+                     {payment_method}. Contact us at any time with questions.",
+                ),
+                ..LetterTemplate::default()
+            },
+        ),
+        (
+            "lease_renewal",
+            LetterTemplate {
+                subject: String::from("Lease Renewal Offer for {property_name}"),
+                body: String::from(
+                    "Dear {tenant_name},\n\n\
+                     Your lease at {property_address} ends on {lease_end}. We would like to \
+                     offer you a renewal for {renewal_term} at a monthly rent of \
+                     ${renewal_rent}.\n\n\
+                     Please let us know whether you would like to renew by {respond_by}.",
+                ),
+                ..LetterTemplate::default()
+            },
+        ),
+        (
+            "late_notice",
+            LetterTemplate {
+                subject: String::from("Past Due Notice for {property_name}"),
+                body: String::from(
+                    "Dear {tenant_name},\n\n\
+                     Our records show an outstanding balance of ${balance_due} on your account \
+                     for {property_address}.\n\n\
+                     Please pay the balance as soon as possible to avoid further late fees. If \
+                     you have already sent payment, please disregard this notice.",
+                ),
+                ..LetterTemplate::default()
+            },
+        ),
+        (
+            "move_out",
+            LetterTemplate {
+                subject: String::from("Move-Out Instructions for {property_address}"),
+                body: String::from(
+                    "Dear {tenant_name},\n\n\
+                     We have you moving out on {move_out_date}. Please return all keys to the \
+                     office by that day and leave the unit broom clean, with all personal \
+                     belongings removed.\n\n\
+                     Your security deposit will be returned to the address we have on file, \
+                     {tenant_address}, less any charges for damage. Let us know if you will be \
+                     using a different address.",
+                ),
+                ..LetterTemplate::default()
+            },
+        ),
+    ]
+}
+
+fn letter_folder(settings: &PathSettings) -> PathBuf {
+    Path::new(&settings.templates_path).join(LETTER_FOLDER)
+}
+
+/// Writes out the built in letters the first time, leaving any that were edited or removed.
+pub fn initialize_letter_templates(settings: &PathSettings) {
+    let folder = letter_folder(settings);
+    if folder.exists() {
+        return;
+    }
+    if let Err(e) = std::fs::create_dir_all(&folder) {
+        println!("Failed to create letter template directory: {}", e);
+        return;
+    }
+    for (name, template) in built_in_templates() {
+        let result = toml::to_string_pretty(&template)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            .and_then(|contents| {
+                std::fs::write(
+                    folder.join(format!("{name}.{TEMPLATE_EXTENSION}")),
+                    contents,
+                )
+            });
+        match result {
+            Ok(_) => println!("Created {name} letter template"),
+            Err(e) => println!("Failed to create {name} letter template: {}", e),
+        }
+    }
+}
+
+/// The names of the letter templates in the templates directory, alphabetically.
+pub fn letter_templates(settings: &PathSettings) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(letter_folder(settings))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == TEMPLATE_EXTENSION)
+                })
+                .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+pub fn load_letter_template(settings: &PathSettings, name: &str) -> Result<LetterTemplate, String> {
+    let path = letter_folder(settings).join(format!("{name}.{TEMPLATE_EXTENSION}"));
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read letter template {}: {e}", path.display()))?;
+    toml::from_str(&contents)
+        .map_err(|e| format!("Failed to parse letter template {}: {e}", path.display()))
+}
+
+/// The merge fields every letter can use, from the leaseholder, their lease and property.
+/// A field the records have no value for, like the move out date of a tenant who hasn't
+/// given notice, is left out so a letter using it is refused.
+pub fn merge_fields(
+    leaseholder: &Leaseholder,
+    property: &Property,
+    company: &str,
+    balance_due: f32,
+    date: NaiveDate,
+) -> Vec<(&'static str, String)> {
+    let contact_info = &leaseholder.contact_info;
+    let recipient = Recipient::for_leaseholder(leaseholder);
+    let lease = &leaseholder.lease;
+    let date_text = |date: NaiveDate| date.format("%B %-d, %Y").to_string();
+    let mut fields = vec![
+        ("date", date_text(date)),
+        ("company", company.to_owned()),
+        ("tenant_name", contact_info.name.clone()),
+        ("tenant_email", contact_info.email.clone()),
+        ("tenant_phone", contact_info.phone_number.clone()),
+        ("tenant_address", recipient.address_line()),
+        ("move_in_date", date_text(leaseholder.move_in_date)),
+        ("lease_start", date_text(lease.start_date)),
+        ("lease_end", date_text(lease.end_date)),
+        (
+            "base_rent",
+            format!("{:.2}", lease.fee_structure.base_rent()),
+        ),
+        ("payment_method", lease.payment_method.clone()),
+        ("property_name", property.name.clone()),
+        (
+            "property_address",
+            format!(
+                "{}, {}, {} {}",
+                property.address.street_address,
+                property.address.city,
+                property.address.state,
+                property.address.zip_code
+            ),
+        ),
+        ("balance_due", format!("{:.2}", balance_due)),
+    ];
+    if let Some(move_out_date) = leaseholder.move_out_date {
+        fields.push(("move_out_date", date_text(move_out_date)));
+    }
+    fields
+}
+
+/// Reads the extra fields typed in for one letter, e.g. `new_rent=1850; effective_date=May 1`.
+pub fn parse_extra_fields(extra: &str) -> Result<Vec<(String, String)>, String> {
+    extra
+        .split(';')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| match field.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_owned(), value.trim().to_owned()))
+            }
+            _ => Err(format!("Invalid field: {field}, use name=value")),
+        })
+        .collect()
+}
+
+/// The `{field}` placeholders still in the text.
+fn unfilled_fields(text: &str) -> Vec<String> {
+    let mut unfilled: Vec<String> = vec![];
+    for (start, _) in text.match_indices('{') {
+        let Some(length) = text[start + 1..].find('}') else {
+            continue;
+        };
+        let name = &text[start + 1..start + 1 + length];
+        let field = format!("{{{name}}}");
+        if !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !unfilled.contains(&field)
+        {
+            unfilled.push(field);
+        }
+    }
+    unfilled
+}
+
+/// A letter with its merge fields filled in, ready to print.
+#[derive(Debug, Clone)]
+pub struct Letter {
+    pub company: String,
+    pub date: NaiveDate,
+    pub recipient: Recipient,
+    pub subject: String,
+    pub paragraphs: Vec<String>,
+    pub closing: String,
+}
+
+impl Letter {
+    /// Fills in the template, refusing when a field is left without a value.
+    pub fn fill(
+        template: &LetterTemplate,
+        fields: &[(&str, String)],
+        leaseholder: &Leaseholder,
+        company: &str,
+        date: NaiveDate,
+    ) -> Result<Letter, String> {
+        let subject = fill_template(&template.subject, fields);
+        let body = fill_template(&template.body, fields);
+        let closing = fill_template(&template.closing, fields);
+        let mut unfilled = unfilled_fields(&subject);
+        for field in unfilled_fields(&body)
+            .into_iter()
+            .chain(unfilled_fields(&closing))
+        {
+            if !unfilled.contains(&field) {
+                unfilled.push(field);
+            }
+        }
+        if !unfilled.is_empty() {
+            return Err(format!("No value for {}", unfilled.join(", ")));
+        }
+        Ok(Letter {
+            company: company.to_owned(),
+            date,
+            recipient: Recipient::for_leaseholder(leaseholder),
+            subject,
+            paragraphs: body
+                .split("\n\n")
+                .map(|paragraph| paragraph.trim().replace('\n', " "))
+                .filter(|paragraph| !paragraph.is_empty())
+                .collect(),
+            closing,
+        })
+    }
+}
+
+/// A letter that was generated and sent to a leaseholder, with the hash of its PDF.
+#[derive(Debug, Clone)]
+pub struct SentLetter {
+    pub id: i64,
+    pub leaseholder_id: u32,
+    pub template: String,
+    pub subject: String,
+    pub file_path: String,
+    pub file_hash: String,
+    pub sent_on: NaiveDate,
+}
+
+impl SentLetter {
+    pub fn convert_to_slint(&self) -> LetterInput {
+        let status = match hash_file(Path::new(&self.file_path)) {
+            Ok(hash) if hash == self.file_hash => "on file",
+            Ok(_) => "changed since sent",
+            Err(_) => "missing",
+        };
+        LetterInput {
+            id: self.id as i32,
+            template: self.template.clone().into(),
+            subject: self.subject.clone().into(),
+            sent_on: self.sent_on.to_string().into(),
+            file_path: self.file_path.clone().into(),
+            status: status.into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for SentLetter {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let sent_on: String = row.try_get("sent_on")?;
+
+        Ok(SentLetter {
+            id: row.try_get("letter_id")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            template: row.try_get("template")?,
+            subject: row.try_get("subject")?,
+            file_path: row.try_get("file_path")?,
+            file_hash: row.try_get("file_hash")?,
+            sent_on: NaiveDate::parse_from_str(&sent_on, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        })
+    }
+}
+
+/// Fills in the named template for the leaseholder, writes the PDF into their folder in the
+/// documents directory and logs it as sent today.
+pub async fn generate_letter(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    leaseholder_id: u32,
+    template_name: &str,
+    extra_fields: &str,
+) -> Result<SentLetter, String> {
+    let template = load_letter_template(&settings.paths, template_name)?;
+    let extra_fields = parse_extra_fields(extra_fields)?;
+    let leaseholder = get_leaseholders(pool, true)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.id == leaseholder_id)
+        .ok_or_else(|| format!("No leaseholder with id {leaseholder_id}"))?;
+    let property = get_properties(pool, true)
+        .await
+        .into_iter()
+        .find(|property| property.id == leaseholder.property_id)
+        .ok_or_else(|| format!("No property for {}", leaseholder.contact_info.name))?;
+    // Letters go out under the company on the tenant's statements, or the property's name
+    // before their first statement
+    let company = match latest_company(pool, leaseholder.id).await {
+        company if company.is_empty() => property.name.clone(),
+        company => company,
+    };
+    let balance_due = get_ledger(pool, leaseholder.id)
+        .await
+        .iter()
+        .fold(0.0, |balance, entry| balance + entry.amount);
+    let today = Local::now().date_naive();
+
+    // Fields typed in for this letter take the place of the ones from the records
+    let mut fields: Vec<(&str, String)> =
+        merge_fields(&leaseholder, &property, &company, balance_due, today)
+            .into_iter()
+            .filter(|(name, _)| {
+                !extra_fields
+                    .iter()
+                    .any(|(extra, _)| extra.as_str() == *name)
+            })
+            .collect();
+    fields.extend(
+        extra_fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone())),
+    );
+    let letter = Letter::fill(&template, &fields, &leaseholder, &company, today)?;
+
+    let folder = Path::new(&settings.paths.documents_path)
+        .join(format!("leaseholder_{}", leaseholder.id))
+        .join(LETTER_FOLDER);
+    std::fs::create_dir_all(&folder)
+        .map_err(|e| format!("Failed to create {}: {e}", folder.display()))?;
+    // Letters are never overwritten, a second one the same day gets a number
+    let path = (1..)
+        .map(|count| match count {
+            1 => folder.join(format!("{today}_{template_name}.pdf")),
+            count => folder.join(format!("{today}_{template_name}_{count}.pdf")),
+        })
+        .find(|path| !path.exists())
+        .unwrap();
+    let layout = load_template(&settings.paths, property.statement_template.as_deref());
+    write_letter_pdf(&letter, &layout, &settings.paths, &path)
+        .map_err(|e| format!("Failed to write letter: {e}"))?;
+    let mut sent = SentLetter {
+        id: 0,
+        leaseholder_id: leaseholder.id,
+        template: template_name.to_owned(),
+        subject: letter.subject,
+        file_path: path.to_string_lossy().into_owned(),
+        file_hash: hash_file(&path).map_err(|e| format!("Failed to read letter: {e}"))?,
+        sent_on: today,
+    };
+    add_letter(pool, &mut sent)
+        .await
+        .map_err(|e| format!("Failed to log letter: {e}"))?;
+    Ok(sent)
+}
+
+pub enum LetterMessage {
+    Show(u32),
+    Generate(u32, String, String),
+    Quit,
+}
+
+pub struct LetterWorker {
+    pub channel: UnboundedSender<LetterMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl LetterWorker {
+    pub fn new(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        settings: &AppSettings,
+        app: slint::Weak<App>,
+    ) -> Self {
+        println!("Create new Letter Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            let settings = settings.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(letter_worker_loop(new_pool, settings, r, app))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(LetterMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn letter_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    settings: AppSettings,
+    mut r: UnboundedReceiver<LetterMessage>,
+    app: slint::Weak<App>,
+) {
+    loop {
+        let (leaseholder_id, status) = match r.recv().await {
+            Some(LetterMessage::Show(id)) => (id, None),
+            Some(LetterMessage::Generate(id, template, extra_fields)) => {
+                let status =
+                    match generate_letter(&pool, &settings, id, &template, &extra_fields).await {
+                        Ok(letter) => format!("Wrote {} to {}", letter.subject, letter.file_path),
+                        Err(e) => e,
+                    };
+                println!("{status}");
+                (id, Some(status))
+            }
+            Some(LetterMessage::Quit) | None => break,
+        };
+        show_letters(&pool, &settings.paths, &app, leaseholder_id, status).await;
+    }
+}
+
+async fn show_letters(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &PathSettings,
+    app: &slint::Weak<App>,
+    leaseholder_id: u32,
+    status: Option<String>,
+) {
+    let letters = get_letters(pool, leaseholder_id).await;
+    let templates = letter_templates(settings);
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let letters: Vec<LetterInput> = letters.iter().map(SentLetter::convert_to_slint).collect();
+        let templates: Vec<slint::SharedString> =
+            templates.into_iter().map(|name| name.into()).collect();
+        let registry = handle.global::<Letters>();
+        registry.set_letters(ModelRc::new(VecModel::from(letters)));
+        registry.set_templates(ModelRc::new(VecModel::from(templates)));
+        registry.set_status(status.unwrap_or_default().into());
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::tests::{billed, statement};
+
+    #[test]
+    fn extra_fields_need_a_name_and_value() {
+        assert_eq!(
+            parse_extra_fields(" new_rent = 1850; effective_date=May 1, 2024; ").unwrap(),
+            [
+                (String::from("new_rent"), String::from("1850")),
+                (String::from("effective_date"), String::from("May 1, 2024")),
+            ]
+        );
+        assert_eq!(
+            parse_extra_fields("new_rent 1850").unwrap_err(),
+            "Invalid field: new_rent 1850, use name=value"
+        );
+        assert!(parse_extra_fields("=1850").is_err());
+    }
+
+    #[test]
+    fn letters_are_refused_with_fields_left_unfilled() {
+        let (statement, property) = statement();
+        let tenant = &statement.leaseholder;
+        let date = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+        let fields = merge_fields(tenant, &property, "Birch Holdings", 0.0, date);
+        let (_, move_out) = built_in_templates()
+            .into_iter()
+            .find(|(name, _)| *name == "move_out")
+            .unwrap();
+
+        // No notice was given, so there is no move out date to fill in
+        let err = Letter::fill(&move_out, &fields, tenant, "Birch Holdings", date).unwrap_err();
+        assert_eq!(err, "No value for {move_out_date}");
+
+        let mut given_notice = tenant.clone();
+        given_notice.move_out_date = NaiveDate::from_ymd_opt(2024, 5, 31);
+        let fields = merge_fields(&given_notice, &property, "Birch Holdings", 0.0, date);
+        let letter =
+            Letter::fill(&move_out, &fields, &given_notice, "Birch Holdings", date).unwrap();
+        assert_eq!(
+            letter.subject,
+            "Move-Out Instructions for 12 Birch Way, Tacoma, WA 98402"
+        );
+        assert_eq!(letter.paragraphs.len(), 3);
+        assert!(letter.paragraphs[1].starts_with("We have you moving out on May 31, 2024."));
+    }
+
+    #[async_std::test]
+    async fn generated_letters_are_logged_with_their_hash() {
+        let (pool, settings, statement, _, _) = billed("letters").await;
+        initialize_letter_templates(&settings.paths);
+        let tenant = statement.leaseholder.id;
+
+        let err = generate_letter(&pool, &settings, tenant, "rent_increase", "")
+            .await
+            .unwrap_err();
+        assert_eq!(err, "No value for {new_rent}, {effective_date}");
+
+        let extra = "new_rent=1300; effective_date=May 1, 2024";
+        let first = generate_letter(&pool, &settings, tenant, "rent_increase", extra)
+            .await
+            .unwrap();
+        assert_eq!(first.subject, "Notice of Rent Increase at Birch Court");
+        assert_eq!(
+            first.file_hash,
+            hash_file(Path::new(&first.file_path)).unwrap()
+        );
+
+        // A second letter the same day is written alongside the first
+        let second = generate_letter(&pool, &settings, tenant, "rent_increase", extra)
+            .await
+            .unwrap();
+        assert_ne!(first.file_path, second.file_path);
+        assert!(second.file_path.ends_with("_rent_increase_2.pdf"));
+        assert_eq!(get_letters(&pool, tenant).await.len(), 2);
+    }
+}
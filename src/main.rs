@@ -1,29 +1,71 @@
 //#![windows_subsystem = "windows"]
 
 mod generated_code {
+    // The item tree Slint generates for the UI is one large const array
+    #![allow(clippy::large_const_arrays)]
     slint::include_modules!();
 }
 
+use chrono::{Local, NaiveDate};
 pub use generated_code::*;
-use slint::Model;
+use slint::{Model, ModelRc, VecModel};
 use sqlx::Sqlite;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 mod app_settings;
+mod audit;
+mod backup;
+mod cam;
 mod database;
+mod documents;
+mod email;
+mod encryption;
 mod expenses;
+mod history;
+mod html_formatting;
+mod insurance;
+mod late_fees;
 mod lease;
 mod leaseholders;
+mod ledger;
+mod letters;
 mod pdf_formatting;
+mod percentage_rent;
 mod properties;
+mod property_bills;
+mod proration;
+mod sales_tax;
 mod slint_conversion;
+mod statement_document;
+mod statement_template;
 mod statements;
 mod testing;
+mod text_formatting;
+mod utility_billing;
 
 #[async_std::main]
 async fn main() {
     //println!("{:?}", std::env::current_exe());
-    app_settings::initialize_data_paths().await;
-    let instances = database::initialize_database().await;
+    let mut settings = app_settings::load_settings();
+    app_settings::initialize_data_paths(&settings.paths).await;
+    statement_template::initialize_default_template(&settings.paths);
+    letters::initialize_letter_templates(&settings.paths);
+    if let Err(e) = encryption::unlock_database(&mut settings).await {
+        println!("Could not open the database: {e}");
+        return;
+    }
+    if run_cli_command(&mut settings).await {
+        return;
+    }
+    match backup::apply_pending_restore(&settings).await {
+        Ok(true) => println!("Successfully restored database from backup"),
+        Ok(false) => (),
+        Err(e) => println!("Failed to restore database from backup: {e}"),
+    }
+    let instances = database::initialize_database(&settings).await;
 
     testing::activate_test_mode(true, &instances).await;
     let app = App::new().unwrap();
@@ -54,14 +96,41 @@ async fn main() {
     let expense_worker = expenses::ExpenseWorker::new(&worker_instances);
     let property_worker = properties::PropertyWorker::new(&worker_instances);
     let lessee_worker = leaseholders::LeaseholderWorker::new(&worker_instances);
-    let statement_worker = statements::StatementWorker::new(&worker_instances);
+    let statement_worker =
+        statements::StatementWorker::new(&worker_instances, &settings, app.as_weak());
+    let backup_worker = backup::BackupWorker::new(&worker_instances, &settings, app.as_weak());
+    let email_worker = email::EmailWorker::new(&worker_instances, &settings, app.as_weak());
+    let bill_worker = property_bills::BillWorker::new(&worker_instances, app.as_weak());
+    let sales_worker = percentage_rent::SalesWorker::new(&worker_instances, app.as_weak());
+    let meter_worker = utility_billing::MeterWorker::new(&worker_instances, app.as_weak());
+    let document_worker =
+        documents::DocumentWorker::new(&worker_instances, &settings, app.as_weak());
+    let insurance_worker =
+        insurance::InsuranceWorker::new(&worker_instances, &settings, app.as_weak());
+    let letter_worker = letters::LetterWorker::new(&worker_instances, &settings, app.as_weak());
+    // Fills in the expiry reminders and insurance compliance on the home tab
+    let _ = document_worker
+        .channel
+        .send(documents::DocumentMessage::Refresh);
+    let _ = insurance_worker
+        .channel
+        .send(insurance::InsuranceMessage::Refresh);
 
     intialize_slint_callbacks(
         &app,
+        &instances,
         &expense_worker,
         &property_worker,
         &lessee_worker,
         &statement_worker,
+        &backup_worker,
+        &email_worker,
+        &bill_worker,
+        &sales_worker,
+        &meter_worker,
+        &document_worker,
+        &insurance_worker,
+        &letter_worker,
     );
 
     app.run().unwrap();
@@ -71,6 +140,144 @@ async fn main() {
     let _property_result = property_worker.join();
     let _lessee_result = lessee_worker.join();
     let _statement_result = statement_worker.join();
+    let _backup_result = backup_worker.join();
+    let _email_result = email_worker.join();
+    let _bill_result = bill_worker.join();
+    let _sales_result = sales_worker.join();
+    let _meter_result = meter_worker.join();
+    let _document_result = document_worker.join();
+    let _insurance_result = insurance_worker.join();
+    let _letter_result = letter_worker.join();
+}
+
+/// Handles `hestia backup` and `hestia restore <file>` without starting the UI.
+/// Returns whether a command was given.
+async fn run_cli_command(settings: &mut app_settings::AppSettings) -> bool {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => false,
+        Some("backup") => {
+            let pool = database::initialize_database(settings).await;
+            match backup::create_backup(&pool, settings, backup::BackupKind::Manual).await {
+                Ok(path) => println!("Backed up database to {}", path.display()),
+                Err(e) => println!("Backup failed: {e}"),
+            }
+            pool.close().await;
+            true
+        }
+        Some("restore") => {
+            match args.get(1) {
+                Some(file) => match backup::stage_restore(Path::new(file), settings).await {
+                    Ok(_) => match backup::apply_pending_restore(settings).await {
+                        Ok(_) => println!("Restored database from {file}"),
+                        Err(e) => println!("Restore failed: {e}"),
+                    },
+                    Err(e) => println!("Restore failed: {e}"),
+                },
+                None => println!("Usage: hestia restore <backup file>"),
+            }
+            true
+        }
+        Some("send") => {
+            let pool = database::initialize_database(settings).await;
+            match email::process_outbox(&pool, &settings.email).await {
+                Ok((sent, failed)) => println!("Sent {sent} emails, {failed} failed"),
+                Err(e) => println!("Sending emails failed: {e}"),
+            }
+            pool.close().await;
+            true
+        }
+        Some("tax-report") => {
+            let date = |index: usize| {
+                args.get(index)
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            };
+            let pool = database::initialize_database(settings).await;
+            let report = sales_tax::tax_report(&pool, date(1), date(2)).await;
+            println!("{}", sales_tax::describe_report(&report, date(1), date(2)));
+            pool.close().await;
+            true
+        }
+        Some("reminders") => {
+            if let Some(days) = args.get(1).and_then(|days| days.parse().ok()) {
+                settings.documents.reminder_days = days;
+            }
+            let pool = database::initialize_database(settings).await;
+            let reminders =
+                documents::reminders(&pool, &settings.documents, Local::now().date_naive()).await;
+            if reminders.is_empty() {
+                println!(
+                    "No documents expire in the next {} days",
+                    settings.documents.reminder_days
+                );
+            }
+            for reminder in reminders {
+                println!("{}", reminder.describe());
+            }
+            pool.close().await;
+            true
+        }
+        Some("compliance") => {
+            let pool = database::initialize_database(settings).await;
+            let report =
+                insurance::compliance_report(&pool, &settings.documents, Local::now().date_naive())
+                    .await;
+            if report.is_empty() {
+                println!("No current lease requires insurance");
+            }
+            for entry in report {
+                println!(
+                    "{}: {}, {} (requires {})",
+                    entry.name,
+                    entry.compliance.label(),
+                    entry.compliance.describe(),
+                    entry.requirement.describe()
+                );
+            }
+            pool.close().await;
+            true
+        }
+        Some("letter") => {
+            let (Some(leaseholder_id), Some(template)) =
+                (args.get(1).and_then(|id| id.parse().ok()), args.get(2))
+            else {
+                println!("Usage: hestia letter <leaseholder id> <template> [field=value ...]");
+                println!(
+                    "Templates: {}",
+                    letters::letter_templates(&settings.paths).join(", ")
+                );
+                return true;
+            };
+            let extra_fields = args[3..].join(";");
+            let pool = database::initialize_database(settings).await;
+            match letters::generate_letter(&pool, settings, leaseholder_id, template, &extra_fields)
+                .await
+            {
+                Ok(letter) => println!("Wrote {} to {}", letter.subject, letter.file_path),
+                Err(e) => println!("Letter failed: {e}"),
+            }
+            pool.close().await;
+            true
+        }
+        Some("scenarios") => {
+            testing::run_scenarios().await;
+            true
+        }
+        Some("passphrase") => {
+            match encryption::change_passphrase(settings).await {
+                Ok(_) => println!("Changed database passphrase"),
+                Err(e) => println!("Passphrase change failed: {e}"),
+            }
+            true
+        }
+        Some(other) => {
+            println!("Unknown command: {other}");
+            println!(
+                "Usage: hestia [backup | restore <backup file> | send | tax-report [from] [to] | reminders [days] | compliance | letter <leaseholder id> <template> [field=value ...] | scenarios | passphrase]"
+            );
+            true
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -86,69 +293,534 @@ async fn get_ids(pool: &sqlx::Pool<Sqlite>) -> ValidIds {
         expense_id: database::get_max_expense_id(pool).await,
         property_id: database::get_max_property_id(pool).await,
         leaseholder_id: database::get_max_leaseholder_id(pool).await,
-        statement_id: 0,
+        statement_id: database::get_max_statement_id(pool).await,
     };
     println!("Created ID Struct: {:#?}", ids);
     ids
 }
 
+#[allow(clippy::too_many_arguments)]
 fn intialize_slint_callbacks(
     app: &App,
+    pool: &sqlx::Pool<Sqlite>,
     expense_worker: &expenses::ExpenseWorker,
     property_worker: &properties::PropertyWorker,
     lessee_worker: &leaseholders::LeaseholderWorker,
     statement_worker: &statements::StatementWorker,
+    backup_worker: &backup::BackupWorker,
+    email_worker: &email::EmailWorker,
+    bill_worker: &property_bills::BillWorker,
+    sales_worker: &percentage_rent::SalesWorker,
+    meter_worker: &utility_billing::MeterWorker,
+    document_worker: &documents::DocumentWorker,
+    insurance_worker: &insurance::InsuranceWorker,
+    letter_worker: &letters::LetterWorker,
 ) {
     let weak_app = app.as_weak();
 
+    app.global::<Backups>().on_backup_now({
+        let backup_channel = backup_worker.channel.clone();
+        move || match backup_channel.send(backup::BackupMessage::BackupNow) {
+            Ok(_) => println!("backup request successfully sent"),
+            Err(_e) => println!("backup request send failed"),
+        }
+    });
+    app.global::<Backups>().on_restore({
+        let backup_channel = backup_worker.channel.clone();
+        move |path| match backup_channel.send(backup::BackupMessage::Restore(path.into())) {
+            Ok(_) => println!("restore request successfully sent"),
+            Err(_e) => println!("restore request send failed"),
+        }
+    });
+    let _ = backup_worker.channel.send(backup::BackupMessage::Refresh);
+
+    app.global::<Outbox>().on_send_now({
+        let email_channel = email_worker.channel.clone();
+        move || match email_channel.send(email::EmailMessage::SendNow) {
+            Ok(_) => println!("send request successfully sent"),
+            Err(_e) => println!("send request send failed"),
+        }
+    });
+    app.global::<Outbox>().on_retry({
+        let email_channel = email_worker.channel.clone();
+        move |id| match email_channel.send(email::EmailMessage::Retry(id as i64)) {
+            Ok(_) => println!("retry request successfully sent"),
+            Err(_e) => println!("retry request send failed"),
+        }
+    });
+    let _ = email_worker.channel.send(email::EmailMessage::Refresh);
+
+    app.global::<StatementRegistry>().on_filter({
+        let statement_channel = statement_worker.channel.clone();
+        move |filter| {
+            let filter = statements::StatementFilter::from_slint(&filter);
+            match statement_channel.send(statements::StatementMessage::Filter(filter)) {
+                Ok(_) => println!("statement filter successfully sent"),
+                Err(_e) => println!("statement filter send failed"),
+            }
+        }
+    });
+    app.global::<StatementRegistry>().on_mark_paid({
+        let statement_channel = statement_worker.channel.clone();
+        move |id| match statement_channel.send(statements::StatementMessage::MarkPaid(id as i64)) {
+            Ok(_) => println!("statement update successfully sent"),
+            Err(_e) => println!("statement update send failed"),
+        }
+    });
+    app.global::<StatementRegistry>().on_void({
+        let statement_channel = statement_worker.channel.clone();
+        move |id, reason| {
+            let message = statements::StatementMessage::Void(id as i64, reason.to_string());
+            match statement_channel.send(message) {
+                Ok(_) => println!("statement void successfully sent"),
+                Err(_e) => println!("statement void send failed"),
+            }
+        }
+    });
+    app.global::<StatementRegistry>().on_reissue({
+        let statement_channel = statement_worker.channel.clone();
+        move |id, reason| {
+            let message = statements::StatementMessage::Reissue(id as i64, reason.to_string());
+            match statement_channel.send(message) {
+                Ok(_) => println!("statement reissue successfully sent"),
+                Err(_e) => println!("statement reissue send failed"),
+            }
+        }
+    });
+    app.global::<StatementRegistry>().on_credit({
+        let statement_channel = statement_worker.channel.clone();
+        let local_app = weak_app.clone();
+        move |id, amount, reason| {
+            let amount = match amount.trim().trim_start_matches('$').parse::<f32>() {
+                Ok(amount) => amount,
+                Err(_) => {
+                    if let Some(app) = local_app.upgrade() {
+                        app.global::<StatementRegistry>()
+                            .set_status(format!("Invalid credit amount: {amount}").into());
+                    }
+                    return;
+                }
+            };
+            let message =
+                statements::StatementMessage::Credit(id as i64, amount, reason.to_string());
+            match statement_channel.send(message) {
+                Ok(_) => println!("credit memo successfully sent"),
+                Err(_e) => println!("credit memo send failed"),
+            }
+        }
+    });
+    app.global::<StatementRegistry>().on_assess_late_fees({
+        let statement_channel = statement_worker.channel.clone();
+        move || match statement_channel.send(statements::StatementMessage::AssessLateFees) {
+            Ok(_) => println!("late fee assessment successfully sent"),
+            Err(_e) => println!("late fee assessment send failed"),
+        }
+    });
+    app.global::<StatementRegistry>().on_reconcile_cam({
+        let statement_channel = statement_worker.channel.clone();
+        let local_app = weak_app.clone();
+        move |leaseholder_id, year| {
+            let Ok(year) = year.trim().parse::<i32>() else {
+                if let Some(app) = local_app.upgrade() {
+                    app.global::<StatementRegistry>()
+                        .set_status(format!("Invalid year: {year}").into());
+                }
+                return;
+            };
+            let message = statements::StatementMessage::ReconcileCam(leaseholder_id as u32, year);
+            match statement_channel.send(message) {
+                Ok(_) => println!("CAM reconciliation successfully sent"),
+                Err(_e) => println!("CAM reconciliation send failed"),
+            }
+        }
+    });
+    app.global::<StatementRegistry>()
+        .on_true_up_percentage_rent({
+            let statement_channel = statement_worker.channel.clone();
+            let local_app = weak_app.clone();
+            move |leaseholder_id, year| {
+                let Ok(year) = year.trim().parse::<i32>() else {
+                    if let Some(app) = local_app.upgrade() {
+                        app.global::<StatementRegistry>()
+                            .set_status(format!("Invalid year: {year}").into());
+                    }
+                    return;
+                };
+                let message =
+                    statements::StatementMessage::TrueUpPercentageRent(leaseholder_id as u32, year);
+                match statement_channel.send(message) {
+                    Ok(_) => println!("percentage rent true-up successfully sent"),
+                    Err(_e) => println!("percentage rent true-up send failed"),
+                }
+            }
+        });
+    app.global::<StatementRegistry>().on_tax_report({
+        let statement_channel = statement_worker.channel.clone();
+        move |input| {
+            let filter = statements::StatementFilter::from_slint(&input);
+            let message =
+                statements::StatementMessage::TaxReport(filter.issued_from, filter.issued_to);
+            match statement_channel.send(message) {
+                Ok(_) => println!("tax report request successfully sent"),
+                Err(_e) => println!("tax report request send failed"),
+            }
+        }
+    });
+    app.global::<StatementRegistry>().on_show_ledger({
+        let statement_channel = statement_worker.channel.clone();
+        move |leaseholder_id| {
+            let message = statements::StatementMessage::Ledger(leaseholder_id as u32);
+            match statement_channel.send(message) {
+                Ok(_) => println!("ledger request successfully sent"),
+                Err(_e) => println!("ledger request send failed"),
+            }
+        }
+    });
+    let _ = statement_worker
+        .channel
+        .send(statements::StatementMessage::Refresh);
+
+    app.global::<PropertyBills>().on_show({
+        let bill_channel = bill_worker.channel.clone();
+        move |property_id| {
+            let message = property_bills::BillMessage::Show(property_id as u32);
+            match bill_channel.send(message) {
+                Ok(_) => println!("bill request successfully sent"),
+                Err(_e) => println!("bill request send failed"),
+            }
+        }
+    });
+    app.global::<PropertyBills>().on_add({
+        let bill_channel = bill_worker.channel.clone();
+        move |property_id, kind, description, amount, coverage_start, coverage_end, due_dates| {
+            let new_bill = property_bills::NewBill {
+                kind: kind.to_string(),
+                description: description.to_string(),
+                amount: amount.to_string(),
+                coverage_start: coverage_start.to_string(),
+                coverage_end: coverage_end.to_string(),
+                due_dates: due_dates.to_string(),
+            };
+            let message = property_bills::BillMessage::Add(property_id as u32, new_bill);
+            match bill_channel.send(message) {
+                Ok(_) => println!("new bill successfully sent"),
+                Err(_e) => println!("new bill send failed"),
+            }
+        }
+    });
+    app.global::<PropertyBills>().on_pay_installment({
+        let bill_channel = bill_worker.channel.clone();
+        move |id| match bill_channel.send(property_bills::BillMessage::PayInstallment(id as i64)) {
+            Ok(_) => println!("installment payment successfully sent"),
+            Err(_e) => println!("installment payment send failed"),
+        }
+    });
+    app.global::<PropertyBills>().on_remove({
+        let bill_channel = bill_worker.channel.clone();
+        move |id| match bill_channel.send(property_bills::BillMessage::Remove(id as i64)) {
+            Ok(_) => println!("bill removal successfully sent"),
+            Err(_e) => println!("bill removal send failed"),
+        }
+    });
+
+    app.global::<SalesReports>().on_show({
+        let sales_channel = sales_worker.channel.clone();
+        move |leaseholder_id| {
+            let message = percentage_rent::SalesMessage::Show(leaseholder_id as u32);
+            match sales_channel.send(message) {
+                Ok(_) => println!("sales report request successfully sent"),
+                Err(_e) => println!("sales report request send failed"),
+            }
+        }
+    });
+    app.global::<SalesReports>().on_add({
+        let sales_channel = sales_worker.channel.clone();
+        move |leaseholder_id, period_start, period_end, gross_sales| {
+            let report = percentage_rent::NewSalesReport {
+                period_start: period_start.to_string(),
+                period_end: period_end.to_string(),
+                gross_sales: gross_sales.to_string(),
+            };
+            let message = percentage_rent::SalesMessage::Add(leaseholder_id as u32, report);
+            match sales_channel.send(message) {
+                Ok(_) => println!("new sales report successfully sent"),
+                Err(_e) => println!("new sales report send failed"),
+            }
+        }
+    });
+    app.global::<SalesReports>().on_remove({
+        let sales_channel = sales_worker.channel.clone();
+        move |id| match sales_channel.send(percentage_rent::SalesMessage::Remove(id as i64)) {
+            Ok(_) => println!("sales report removal successfully sent"),
+            Err(_e) => println!("sales report removal send failed"),
+        }
+    });
+
+    app.global::<MeterReadings>().on_show({
+        let meter_channel = meter_worker.channel.clone();
+        move |leaseholder_id| {
+            let message = utility_billing::MeterMessage::Show(leaseholder_id as u32);
+            match meter_channel.send(message) {
+                Ok(_) => println!("meter reading request successfully sent"),
+                Err(_e) => println!("meter reading request send failed"),
+            }
+        }
+    });
+    app.global::<MeterReadings>().on_add({
+        let meter_channel = meter_worker.channel.clone();
+        move |leaseholder_id, utility, period_start, period_end, start_reading, end_reading| {
+            let reading = utility_billing::NewMeterReading {
+                utility: utility.to_string(),
+                period_start: period_start.to_string(),
+                period_end: period_end.to_string(),
+                start_reading: start_reading.to_string(),
+                end_reading: end_reading.to_string(),
+            };
+            let message = utility_billing::MeterMessage::Add(leaseholder_id as u32, reading);
+            match meter_channel.send(message) {
+                Ok(_) => println!("new meter reading successfully sent"),
+                Err(_e) => println!("new meter reading send failed"),
+            }
+        }
+    });
+    app.global::<MeterReadings>().on_remove({
+        let meter_channel = meter_worker.channel.clone();
+        move |id| match meter_channel.send(utility_billing::MeterMessage::Remove(id as i64)) {
+            Ok(_) => println!("meter reading removal successfully sent"),
+            Err(_e) => println!("meter reading removal send failed"),
+        }
+    });
+
+    app.global::<Documents>().on_show({
+        let document_channel = document_worker.channel.clone();
+        move |owner_type, owner_id| {
+            let Some(owner) =
+                documents::DocumentOwner::parse_string(owner_type.as_str(), owner_id as u32)
+            else {
+                println!("Unknown document owner: {owner_type}");
+                return;
+            };
+            match document_channel.send(documents::DocumentMessage::Show(owner)) {
+                Ok(_) => println!("document request successfully sent"),
+                Err(_e) => println!("document request send failed"),
+            }
+        }
+    });
+    app.global::<Documents>().on_add({
+        let document_channel = document_worker.channel.clone();
+        move |owner_type, owner_id, kind, title, source, effective_date, expiry_date| {
+            let Some(owner) =
+                documents::DocumentOwner::parse_string(owner_type.as_str(), owner_id as u32)
+            else {
+                println!("Unknown document owner: {owner_type}");
+                return;
+            };
+            let document = documents::NewDocument {
+                kind: kind.to_string(),
+                title: title.to_string(),
+                source: source.to_string(),
+                effective_date: effective_date.to_string(),
+                expiry_date: expiry_date.to_string(),
+            };
+            match document_channel.send(documents::DocumentMessage::Add(owner, document)) {
+                Ok(_) => println!("new document successfully sent"),
+                Err(_e) => println!("new document send failed"),
+            }
+        }
+    });
+    app.global::<Documents>().on_remove({
+        let document_channel = document_worker.channel.clone();
+        move |id| match document_channel.send(documents::DocumentMessage::Remove(id as i64)) {
+            Ok(_) => println!("document removal successfully sent"),
+            Err(_e) => println!("document removal send failed"),
+        }
+    });
+
+    app.global::<Insurance>().on_show({
+        let insurance_channel = insurance_worker.channel.clone();
+        move |leaseholder_id| {
+            let message = insurance::InsuranceMessage::Show(leaseholder_id as u32);
+            match insurance_channel.send(message) {
+                Ok(_) => println!("insurance request successfully sent"),
+                Err(_e) => println!("insurance request send failed"),
+            }
+        }
+    });
+    app.global::<Insurance>().on_add({
+        let insurance_channel = insurance_worker.channel.clone();
+        move |leaseholder_id,
+              carrier,
+              policy_number,
+              per_occurrence,
+              aggregate,
+              additional_insured,
+              effective_date,
+              expiry_date| {
+            let certificate = insurance::NewCertificate {
+                carrier: carrier.to_string(),
+                policy_number: policy_number.to_string(),
+                per_occurrence: per_occurrence.to_string(),
+                aggregate: aggregate.to_string(),
+                additional_insured,
+                effective_date: effective_date.to_string(),
+                expiry_date: expiry_date.to_string(),
+            };
+            let message = insurance::InsuranceMessage::Add(leaseholder_id as u32, certificate);
+            match insurance_channel.send(message) {
+                Ok(_) => println!("new certificate successfully sent"),
+                Err(_e) => println!("new certificate send failed"),
+            }
+        }
+    });
+    app.global::<Insurance>().on_remove({
+        let insurance_channel = insurance_worker.channel.clone();
+        move |id| match insurance_channel.send(insurance::InsuranceMessage::Remove(id as i64)) {
+            Ok(_) => println!("certificate removal successfully sent"),
+            Err(_e) => println!("certificate removal send failed"),
+        }
+    });
+
+    app.global::<Letters>().on_show({
+        let letter_channel = letter_worker.channel.clone();
+        move |leaseholder_id| match letter_channel
+            .send(letters::LetterMessage::Show(leaseholder_id as u32))
+        {
+            Ok(_) => println!("letter request successfully sent"),
+            Err(_e) => println!("letter request send failed"),
+        }
+    });
+    app.global::<Letters>().on_generate({
+        let letter_channel = letter_worker.channel.clone();
+        move |leaseholder_id, template, extra_fields| {
+            let message = letters::LetterMessage::Generate(
+                leaseholder_id as u32,
+                template.to_string(),
+                extra_fields.to_string(),
+            );
+            match letter_channel.send(message) {
+                Ok(_) => println!("new letter successfully sent"),
+                Err(_e) => println!("new letter send failed"),
+            }
+        }
+    });
+
     //app.global::<Validation>().on_get_valid_id(move |input| {});
+    app.global::<AuditLog>().on_request_history({
+        let pool = pool.clone();
+        let local_app = weak_app.clone();
+        move |entity_type, id| {
+            let Some(entity_type) = audit::EntityType::parse_string(entity_type.as_str()) else {
+                println!("Unknown audit entity type: {entity_type}");
+                return;
+            };
+            let pool = pool.clone();
+            let local_app = local_app.clone();
+            async_std::task::spawn(async move {
+                let entries: Vec<AuditEntryInput> =
+                    database::get_audit_history(&pool, entity_type, id as u32)
+                        .await
+                        .iter()
+                        .map(audit::AuditEntry::convert_to_slint)
+                        .collect();
+                let upgrade_res = local_app.upgrade_in_event_loop(move |handle| {
+                    handle
+                        .global::<AuditLog>()
+                        .set_entries(ModelRc::new(VecModel::from(entries)));
+                });
+                match upgrade_res {
+                    Ok(_) => (),
+                    Err(e) => println!("Failed to upgrade ui: {e}"),
+                };
+            });
+        }
+    });
+    app.global::<PurgeCheck>().on_request_check({
+        let pool = pool.clone();
+        let local_app = weak_app.clone();
+        move |entity_type, id| {
+            let Some(entity_type) = audit::EntityType::parse_string(entity_type.as_str()) else {
+                println!("Unknown purge entity type: {entity_type}");
+                return;
+            };
+            let pool = pool.clone();
+            let local_app = local_app.clone();
+            async_std::task::spawn(async move {
+                let blocker = match pool.acquire().await {
+                    Ok(mut conn) => {
+                        database::purge_blocker(&mut conn, entity_type, id.into()).await
+                    }
+                    Err(e) => Err(e),
+                };
+                let (allowed, reason) = match blocker {
+                    Ok(None) => (true, String::new()),
+                    Ok(Some(reason)) => (false, reason),
+                    Err(e) => (
+                        false,
+                        format!("Couldn't check whether this can be purged: {e}"),
+                    ),
+                };
+                let upgrade_res = local_app.upgrade_in_event_loop(move |handle| {
+                    let check = handle.global::<PurgeCheck>();
+                    check.set_allowed(allowed);
+                    check.set_reason(reason.into());
+                });
+                match upgrade_res {
+                    Ok(_) => (),
+                    Err(e) => println!("Failed to upgrade ui: {e}"),
+                }
+            });
+        }
+    });
+    app.global::<Validation>().on_get_valid_id({
+        let local_app = weak_app.clone();
+        move |id_type| {
+            let handle = local_app.upgrade().unwrap();
+            match id_type {
+                IdType::Expense => handle.get_potential_expense_id(),
+                IdType::Property => handle.get_potential_prop_id(),
+                IdType::Leaseholder => handle.get_potential_lessee_id(),
+                IdType::Lease => 0,
+            }
+        }
+    });
+
+    let channels = history::WorkerChannels {
+        expense: expense_worker.channel.clone(),
+        property: property_worker.channel.clone(),
+        lessee: lessee_worker.channel.clone(),
+    };
+    let history = Arc::new(Mutex::new(history::CommandHistory::default()));
+
     app.on_new_expense({
-        let expense_channel = expense_worker.channel.clone();
+        let channels = channels.clone();
+        let history = history.clone();
         let local_app = weak_app.clone();
         move |input| {
-            let input_clone = input.clone();
             let upgrade_res = local_app.upgrade_in_event_loop({
-                let internal_channel = expense_channel.clone();
+                let channels = channels.clone();
+                let history = history.clone();
                 move |handle| {
-                    let prev_expense = handle.get_expenses();
-                    let new_expenses = prev_expense
-                        .as_any()
-                        .downcast_ref::<slint::VecModel<ExpenseInput>>()
-                        .expect("Expenses failed to downcast");
-                    let message = match input_clone.message {
-                        MessageType::Create => {
-                            new_expenses.push(input_clone);
-                            expenses::ExpenseMessage::ExpenseCreated(input)
-                        }
-                        MessageType::Update => {
-                            let index = new_expenses
-                                .iter()
-                                .position(|r| {
-                                    println!("r.id: {}. input_clone.id: {}", r.id, input_clone.id);
-                                    r.id == input_clone.id
-                                })
-                                .unwrap();
-                            new_expenses.remove(index);
-                            new_expenses.insert(index, input_clone);
-                            expenses::ExpenseMessage::ExpenseUpdate(input)
-                        }
-                        MessageType::Delete => {
-                            let index = new_expenses
-                                .iter()
-                                .position(|r| {
-                                    println!("r.id: {}. input_clone.id: {}", r.id, input_clone.id);
-                                    r.id == input_clone.id
-                                })
-                                .unwrap();
-                            new_expenses.remove(index);
-                            expenses::ExpenseMessage::ExpenseDelete(input)
+                    let before = handle.get_expenses().iter().find(|r| r.id == input.id);
+                    let command = match input.message {
+                        MessageType::Create => history::Command::Expense {
+                            before: None,
+                            after: Some(input),
+                        },
+                        MessageType::Update => history::Command::Expense {
+                            before,
+                            after: Some(input),
+                        },
+                        MessageType::Delete => history::Command::Expense {
+                            before,
+                            after: None,
+                        },
+                        MessageType::Archive | MessageType::Unarchive => {
+                            println!("Expenses cannot be archived");
+                            return;
                         }
                     };
-                    let res = internal_channel.send(message);
-                    match res {
-                        Ok(_) => println!("expense successfully sent"),
-                        Err(_e) => println!("expense send failed"),
-                    }
+                    history::apply_command(&handle, &command, &channels, false);
+                    history.lock().unwrap().push(command);
                 }
             });
             match upgrade_res {
@@ -159,52 +831,40 @@ fn intialize_slint_callbacks(
     });
 
     app.on_new_property({
-        let property_channel = property_worker.channel.clone();
+        let channels = channels.clone();
+        let history = history.clone();
         let local_app = weak_app.clone();
         move |input| {
-            let input_clone = input.clone();
             let upgrade_res = local_app.upgrade_in_event_loop({
-                let internal_channel = property_channel.clone();
+                let channels = channels.clone();
+                let history = history.clone();
                 move |handle| {
-                    let prev_property = handle.get_properties();
-                    let new_properties = prev_property
-                        .as_any()
-                        .downcast_ref::<slint::VecModel<PropertyInput>>()
-                        .expect("Properties failed to downcast");
-                    let message = match input_clone.message {
-                        crate::MessageType::Create => {
-                            new_properties.push(input_clone);
-                            properties::PropertyMessage::PropertyCreated(input)
-                        }
-                        crate::MessageType::Update => {
-                            let index = new_properties
-                                .iter()
-                                .position(|r| {
-                                    println!("r.id: {}. input_clone.id: {}", r.id, input_clone.id);
-                                    r.id == input_clone.id
-                                })
-                                .unwrap();
-                            new_properties.remove(index);
-                            new_properties.insert(index, input_clone);
-                            properties::PropertyMessage::PropertyUpdate(input)
-                        }
-                        crate::MessageType::Delete => {
-                            let index = new_properties
+                    let before = handle.get_properties().iter().find(|r| r.id == input.id);
+                    let command = match input.message {
+                        crate::MessageType::Create => history::Command::Property {
+                            before: None,
+                            after: Some(input),
+                            cascaded: vec![],
+                        },
+                        crate::MessageType::Update
+                        | crate::MessageType::Archive
+                        | crate::MessageType::Unarchive => history::Command::Property {
+                            before,
+                            after: Some(input),
+                            cascaded: vec![],
+                        },
+                        crate::MessageType::Delete => history::Command::Property {
+                            before,
+                            after: None,
+                            cascaded: handle
+                                .get_lessees()
                                 .iter()
-                                .position(|r| {
-                                    println!("r.id: {}. input_clone.id: {}", r.id, input_clone.id);
-                                    r.id == input_clone.id
-                                })
-                                .unwrap();
-                            new_properties.remove(index);
-                            properties::PropertyMessage::PropertyRemove(input)
-                        }
-                    };
-                    let res = internal_channel.send(message);
-                    match res {
-                        Ok(_) => println!("property successfully sent"),
-                        Err(_e) => println!("property send failed"),
+                                .filter(|r| r.property_id == input.id)
+                                .collect(),
+                        },
                     };
+                    history::apply_command(&handle, &command, &channels, false);
+                    history.lock().unwrap().push(command);
                 }
             });
             match upgrade_res {
@@ -215,52 +875,33 @@ fn intialize_slint_callbacks(
     });
 
     app.on_new_lessee({
-        let lessee_channel = lessee_worker.channel.clone();
+        let channels = channels.clone();
+        let history = history.clone();
         let local_app = weak_app.clone();
         move |input| {
-            let input_clone = input.clone();
             let upgrade_res = local_app.upgrade_in_event_loop({
-                let internal_channel = lessee_channel.clone();
+                let channels = channels.clone();
+                let history = history.clone();
                 move |handle| {
-                    let prev_lessees = handle.get_lessees();
-                    let new_lessees = prev_lessees
-                        .as_any()
-                        .downcast_ref::<slint::VecModel<LeaseholderInput>>()
-                        .expect("Properties failed to downcast");
-                    let message = match input_clone.message {
-                        crate::MessageType::Create => {
-                            new_lessees.push(input_clone);
-                            leaseholders::LeaseholderMessage::LeaseholderCreated(input)
-                        }
-                        crate::MessageType::Update => {
-                            let index = new_lessees
-                                .iter()
-                                .position(|r| {
-                                    println!("r.id: {}. input_clone.id: {}", r.id, input_clone.id);
-                                    r.id == input_clone.id
-                                })
-                                .unwrap();
-                            new_lessees.remove(index);
-                            new_lessees.insert(index, input_clone);
-                            leaseholders::LeaseholderMessage::LeaseholderUpdate(input)
-                        }
-                        crate::MessageType::Delete => {
-                            let index = new_lessees
-                                .iter()
-                                .position(|r| {
-                                    println!("r.id: {}. input_clone.id: {}", r.id, input_clone.id);
-                                    r.id == input_clone.id
-                                })
-                                .unwrap();
-                            new_lessees.remove(index);
-                            leaseholders::LeaseholderMessage::LeaseholderDelete(input)
-                        }
-                    };
-                    let res = internal_channel.send(message);
-                    match res {
-                        Ok(_) => println!("Leaseholder successfully sent"),
-                        Err(_e) => println!("Leaseholder send failed"),
+                    let before = handle.get_lessees().iter().find(|r| r.id == input.id);
+                    let command = match input.message {
+                        crate::MessageType::Create => history::Command::Leaseholder {
+                            before: None,
+                            after: Some(input),
+                        },
+                        crate::MessageType::Update
+                        | crate::MessageType::Archive
+                        | crate::MessageType::Unarchive => history::Command::Leaseholder {
+                            before,
+                            after: Some(input),
+                        },
+                        crate::MessageType::Delete => history::Command::Leaseholder {
+                            before,
+                            after: None,
+                        },
                     };
+                    history::apply_command(&handle, &command, &channels, false);
+                    history.lock().unwrap().push(command);
                 }
             });
             match upgrade_res {
@@ -269,4 +910,34 @@ fn intialize_slint_callbacks(
             };
         }
     });
+
+    app.on_undo({
+        let channels = channels.clone();
+        let history = history.clone();
+        let local_app = weak_app.clone();
+        move || {
+            let command = history.lock().unwrap().undo();
+            match command {
+                Some(c) => {
+                    println!("Undoing {:?}", c);
+                    history::apply_command(&local_app.upgrade().unwrap(), &c, &channels, true);
+                }
+                None => println!("Nothing to undo"),
+            }
+        }
+    });
+
+    app.on_redo({
+        let local_app = weak_app.clone();
+        move || {
+            let command = history.lock().unwrap().redo();
+            match command {
+                Some(c) => {
+                    println!("Redoing {:?}", c);
+                    history::apply_command(&local_app.upgrade().unwrap(), &c, &channels, true);
+                }
+                None => println!("Nothing to redo"),
+            }
+        }
+    });
 }
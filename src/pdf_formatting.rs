@@ -1,189 +1,718 @@
-use std::{fs::File, io::BufWriter};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
 
 use chrono::{Datelike, NaiveDate};
-use printpdf::{BuiltinFont, Line, Mm, PdfDocument, Point, TextRenderingMode};
+use printpdf::{
+    image_crate, BuiltinFont, Image, ImageTransform, IndirectFontRef, Line, Mm,
+    PdfDocumentReference, PdfLayerReference, Point, TextRenderingMode,
+};
 
 use crate::{
-    app_settings::PathSettings, leaseholders::Company, properties::Property, statements::Statement,
+    app_settings::PathSettings,
+    expenses::Expense,
+    lease::StatementLine,
+    letters::Letter,
+    statement_document::StatementDocument,
+    statement_template::{Section, StatementTemplate},
 };
 
-const LEFT_COLUMN: Mm = Mm(20.0);
-const RIGHT_COLUMN: Mm = Mm(115.0);
-const TOP_EDGE: Mm = Mm(297.0);
-const RIGHT_EDGE: Mm = Mm(210.0);
+/// Tracks the pages of a statement and where the next line goes, starting a new page
+/// whenever the content would run into the bottom margin.
+struct PageFlow<'a> {
+    doc: &'a PdfDocumentReference,
+    template: &'a StatementTemplate,
+    font: IndirectFontRef,
+    layers: Vec<PdfLayerReference>,
+    // Printed at the top of every page after the first
+    continued_header: String,
+    y_level: Mm,
+}
+
+impl<'a> PageFlow<'a> {
+    fn new(
+        doc: &'a PdfDocumentReference,
+        first_layer: PdfLayerReference,
+        template: &'a StatementTemplate,
+        font: IndirectFontRef,
+        continued_header: String,
+    ) -> PageFlow<'a> {
+        first_layer.set_text_rendering_mode(TextRenderingMode::Fill);
+        PageFlow {
+            doc,
+            template,
+            font,
+            layers: vec![first_layer],
+            continued_header,
+            y_level: Mm(template.page.top_mm),
+        }
+    }
 
-const HEADER_SIZE: f32 = 16.0;
-const BODY_SIZE: f32 = 13.0;
-const DETAILS_SIZE: f32 = 12.0;
+    fn layer(&self) -> &PdfLayerReference {
+        self.layers.last().unwrap()
+    }
+
+    fn spacing(&self) -> Mm {
+        Mm(self.template.page.line_spacing_mm)
+    }
+
+    fn new_page(&mut self) {
+        let page = &self.template.page;
+        let (page_index, layer_index) = self.doc.add_page(
+            Mm(page.width_mm),
+            Mm(page.height_mm),
+            format!("Page {}", self.layers.len() + 1),
+        );
+        let layer = self.doc.get_page(page_index).get_layer(layer_index);
+        layer.set_text_rendering_mode(TextRenderingMode::Fill);
+        self.layers.push(layer);
+        self.y_level = Mm(page.top_mm);
+
+        let fonts = &self.template.fonts;
+        self.layer().use_text(
+            &self.continued_header,
+            fonts.details_size,
+            Mm(self.template.columns.left_mm),
+            self.y_level,
+            &self.font,
+        );
+        self.y_level -= self.spacing() * 2.0;
+    }
+
+    /// Starts a new page unless `height` still fits above `reserved` on this one.
+    /// Returns whether a page was added.
+    fn ensure_space(&mut self, height: Mm, reserved: Mm) -> bool {
+        if self.y_level - height < Mm(self.template.page.bottom_mm) + reserved {
+            self.new_page();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Writes "Page x of n" on every page, once the page count is known.
+    fn number_pages(&self) {
+        let count = self.layers.len();
+        for (index, layer) in self.layers.iter().enumerate() {
+            layer.use_text(
+                format!("Page {} of {}", index + 1, count),
+                self.template.fonts.details_size,
+                Mm(self.template.page.width_mm / 2.0 - 10.0),
+                Mm(self.template.page.page_number_mm),
+                &self.font,
+            );
+        }
+    }
+}
 
 pub fn write_with_printpdf(
-    statement: Statement,
-    property: Property,
-    company: Company,
-    settings: PathSettings,
-) {
-    // Max dimension values in mm 215.9 x 279.4
-    let (doc, page1, layer1) =
-        PdfDocument::new("Monthly Statement", RIGHT_EDGE, TOP_EDGE, "Layer 1");
-    let current_layer = doc.get_page(page1).get_layer(layer1);
-    let font = doc.add_builtin_font(BuiltinFont::Helvetica).unwrap();
-    let leaseholder = statement.leaseholder;
-    let contact_info = leaseholder.contact_info;
-
-    let mut y_level = Mm(270.0);
-    let mut left_column = LEFT_COLUMN;
-    let right_column = RIGHT_COLUMN;
-    let center = RIGHT_EDGE / 2.0;
-
-    current_layer.set_text_rendering_mode(TextRenderingMode::Fill);
-
-    current_layer.begin_text_section();
-    current_layer.use_text(company.name, HEADER_SIZE, left_column, y_level, &font);
-    y_level -= Mm(10.0);
-    current_layer.use_text(contact_info.email, HEADER_SIZE, left_column, y_level, &font);
-    y_level -= Mm(30.0);
-    current_layer.use_text(&contact_info.name, HEADER_SIZE, left_column, y_level, &font);
-    y_level -= Mm(10.0);
-    current_layer.use_text("ADDRESS TODO", HEADER_SIZE, left_column, y_level, &font);
-    y_level -= Mm(10.0);
-    current_layer.use_text(
-        statement.date.to_string(),
-        HEADER_SIZE,
-        left_column,
+    document: &StatementDocument,
+    template: &StatementTemplate,
+    settings: &PathSettings,
+) -> io::Result<PathBuf> {
+    let page = &template.page;
+    let (doc, page1, layer1) = printpdf::PdfDocument::new(
+        document.title(template),
+        Mm(page.width_mm),
+        Mm(page.height_mm),
+        "Layer 1",
+    );
+    let font = load_font(&doc, settings, &template.fonts.regular);
+    let bold_font = match template.fonts.bold.trim() {
+        "" => font.clone(),
+        path => load_font(&doc, settings, path),
+    };
+    let fonts = &template.fonts;
+    let recipient = &document.recipient;
+    let balances = &document.balances;
+
+    let left_column = Mm(template.columns.left_mm);
+    let right_column = Mm(template.columns.right_mm);
+    let right_edge = Mm(page.width_mm);
+
+    let mut flow = PageFlow::new(
+        &doc,
+        doc.get_page(page1).get_layer(layer1),
+        template,
+        font.clone(),
+        format!(
+            "{} - {} - {} (continued)",
+            document.company, recipient.name, document.date
+        ),
+    );
+    let spacing = flow.spacing();
+    draw_logo(flow.layer(), settings, template);
+
+    let rows: Vec<(String, String)> = document
+        .lines
+        .iter()
+        .map(|line| (format!("{}:", line.label()), line.amount_text()))
+        .collect();
+
+    for section in &template.sections {
+        match section {
+            Section::Company => {
+                flow.ensure_space(spacing * 2.0, Mm(0.0));
+                flow.layer().use_text(
+                    &document.company,
+                    fonts.header_size,
+                    left_column,
+                    flow.y_level,
+                    &bold_font,
+                );
+                flow.y_level -= spacing;
+                flow.layer().use_text(
+                    &recipient.email,
+                    fonts.header_size,
+                    left_column,
+                    flow.y_level,
+                    &font,
+                );
+                flow.y_level -= spacing * 3.0;
+            }
+            Section::Recipient => {
+                flow.ensure_space(spacing * 6.0, Mm(0.0));
+                let invoice_number = document
+                    .invoice_number
+                    .as_ref()
+                    .map(|number| format!("Invoice {number}"))
+                    .unwrap_or_default();
+                let (kind, due_date) = if document.is_credit_memo() {
+                    (document.title(template).to_owned(), String::new())
+                } else {
+                    (String::new(), format!("Due {}", document.due_date))
+                };
+                for (text, details) in [
+                    (recipient.name.clone(), invoice_number),
+                    (recipient.address_line(), kind),
+                    (document.date.to_string(), due_date),
+                ] {
+                    flow.layer().use_text(
+                        text,
+                        fonts.header_size,
+                        left_column,
+                        flow.y_level,
+                        &font,
+                    );
+                    flow.layer().use_text(
+                        details,
+                        fonts.body_size,
+                        right_column + Mm(20.0),
+                        flow.y_level,
+                        &font,
+                    );
+                    flow.y_level -= spacing;
+                }
+                if let Some(note) = &document.note {
+                    flow.layer()
+                        .use_text(note, fonts.body_size, left_column, flow.y_level, &font);
+                    flow.y_level -= spacing;
+                }
+                flow.y_level -= spacing;
+                let line = Line::from_iter(vec![
+                    (Point::new(Mm(0.0), flow.y_level), false),
+                    (Point::new(right_edge, flow.y_level), false),
+                ]);
+                flow.layer().add_line(line);
+                flow.y_level -= spacing;
+            }
+            // A credit memo only states the credit, the ledger carries the balance
+            Section::Balances if document.is_credit_memo() => (),
+            Section::Balances => {
+                flow.ensure_space(spacing * 2.0, Mm(0.0));
+                flow.layer().use_text(
+                    format!("Balance Forward: {:.2}", balances.forward),
+                    fonts.body_size,
+                    left_column,
+                    flow.y_level,
+                    &font,
+                );
+                flow.layer().use_text(
+                    format!("Payment Received {:.2}", balances.payments),
+                    fonts.body_size,
+                    right_column,
+                    flow.y_level,
+                    &font,
+                );
+                flow.y_level -= spacing;
+                flow.layer().use_text(
+                    format!("Outstanding Balance: {:.2}", balances.outstanding),
+                    fonts.body_size,
+                    right_column,
+                    flow.y_level,
+                    &font,
+                );
+                flow.y_level -= spacing;
+            }
+            Section::Charges => {
+                draw_table(&mut flow, &bold_font, &rows);
+                draw_adjustments(&mut flow, &bold_font, &document.adjustments);
+            }
+            // Drawn last, together with the remittance block
+            Section::Total => (),
+        }
+    }
+
+    // Total and remittance block, kept together on the last page. The remittance lines are
+    // built up from the bottom of the page.
+    let remittance_height = spacing * 3.0;
+    if template.sections.contains(&Section::Total) {
+        flow.ensure_space(spacing, remittance_height);
+        flow.y_level -= spacing;
+        flow.layer().use_text(
+            format!("{}: ${:.2}", document.total_label(), document.total_due),
+            fonts.body_size,
+            right_column,
+            flow.y_level,
+            &bold_font,
+        );
+    } else {
+        flow.ensure_space(Mm(0.0), remittance_height);
+    }
+
+    let mut y_level = Mm(page.bottom_mm);
+    let layer = flow.layer();
+    layer.use_text(
+        &template.footer,
+        fonts.body_size,
+        right_column,
         y_level,
         &font,
     );
-    y_level -= Mm(20.0);
-    current_layer.end_text_section();
-
-    let line = Line::from_iter(vec![
-        (Point::new(Mm(0.0), y_level), false),
-        (Point::new(Mm(350.0), y_level), false),
-    ]);
-    current_layer.add_line(line);
-
-    y_level -= Mm(10.0);
-    current_layer.begin_text_section();
-    //current_layer.set_line_height(10.0);
-    //current_layer.set_word_spacing(10.0);
-    //current_layer.set_character_spacing(3.0);
-    current_layer.use_text(
-        format!("Balance Forward: {:.2}", 0),
-        BODY_SIZE,
+    layer.use_text(
+        recipient.city_line(),
+        fonts.body_size,
         left_column,
         y_level,
         &font,
     );
-    current_layer.use_text(
-        format!("Payment Received {:.2}", 0),
-        BODY_SIZE,
-        right_column,
+    y_level += spacing;
+    layer.use_text(
+        &recipient.address.street_address,
+        fonts.body_size,
+        left_column,
         y_level,
         &font,
     );
-    y_level -= Mm(10.0);
-    current_layer.use_text(
-        format!("Outstanding Balance: {:.2}", 0),
-        BODY_SIZE,
-        right_column,
+    // The late fee terms go under the payment terms when the lease has late fees
+    let late_fee_terms = document.late_fee_terms(template);
+    let (upper_terms, lower_terms) = if late_fee_terms.is_empty() {
+        (String::new(), document.payment_terms(template))
+    } else {
+        (document.payment_terms(template), late_fee_terms)
+    };
+    layer.use_text(lower_terms, fonts.body_size, right_column, y_level, &font);
+    y_level += spacing;
+    layer.use_text(
+        &template.remit_heading,
+        fonts.body_size,
+        left_column,
         y_level,
-        &font,
+        &bold_font,
     );
-    y_level -= Mm(10.0);
-    let table_top_level: Mm = y_level;
-    y_level -= Mm(10.0);
-    let mut current_iter = 0;
-    left_column += Mm(15.0);
-    let mut current_x: Mm = left_column;
-    let mut total_due: String = String::new();
-    for line in statement.rates.display_amounts_due(
-        statement.fees,
-        property.property_tax,
-        property.business_insurance,
-    ) {
-        if total_due.is_empty() {
-            total_due.push_str(&line);
-            continue;
-        }
-        current_layer.use_text(line.to_string(), DETAILS_SIZE, current_x, y_level, &font);
-        if current_iter == 1 {
-            y_level -= Mm(10.0);
-            current_x = left_column;
-            current_iter = 0;
-        } else {
-            current_iter += 1;
-            current_x = right_column + Mm(20.0);
-        }
+    layer.use_text(upper_terms, fonts.body_size, right_column, y_level, &font);
+    if template.expense_appendix {
+        draw_expense_appendix(&mut flow, &bold_font, &document.lines);
     }
-    let table_bottom_level: Mm = y_level;
-    y_level -= Mm(20.0);
-    left_column = LEFT_COLUMN;
-    current_layer.use_text(total_due, BODY_SIZE, right_column, y_level, &font);
-    current_layer.end_text_section();
-
-    let table_outline = Line::from_iter(vec![
-        (Point::new(Mm(25.0), table_top_level), false),
-        (Point::new(Mm(25.0), table_bottom_level), false),
-        (Point::new(RIGHT_EDGE - Mm(25.0), table_bottom_level), false),
-        (Point::new(RIGHT_EDGE - Mm(25.0), table_top_level), false),
-        (Point::new(Mm(25.0), table_top_level), false),
-    ]);
-    current_layer.add_line(table_outline);
-    let table_center_line = Line::from_iter(vec![
-        (Point::new(center, table_top_level), false),
-        (Point::new(center, table_bottom_level), false),
-    ]);
-    current_layer.add_line(table_center_line);
-
-    y_level = Mm(15.0);
-    current_layer.use_text(
-        "Thank You".to_owned(),
-        BODY_SIZE,
-        right_column,
-        y_level,
-        &font,
+    flow.number_pages();
+
+    // Save the PDF to a file. Issued statements are never overwritten.
+    let path = document.file_path(settings, "pdf");
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    doc.save(&mut BufWriter::new(file))
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(path)
+}
+
+/// Prints a letter on the statement template's page layout, fonts and logo: the company and
+/// date, the recipient's address, the subject and then the body, wrapped to the page.
+pub fn write_letter_pdf(
+    letter: &Letter,
+    template: &StatementTemplate,
+    settings: &PathSettings,
+    path: &Path,
+) -> io::Result<()> {
+    let page = &template.page;
+    let (doc, page1, layer1) = printpdf::PdfDocument::new(
+        &letter.subject,
+        Mm(page.width_mm),
+        Mm(page.height_mm),
+        "Layer 1",
     );
-    current_layer.use_text(
+    let font = load_font(&doc, settings, &template.fonts.regular);
+    let bold_font = match template.fonts.bold.trim() {
+        "" => font.clone(),
+        path => load_font(&doc, settings, path),
+    };
+    let fonts = &template.fonts;
+    let left_column = Mm(template.columns.left_mm);
+    // Roughly the average width of a character, enough to keep lines inside the margins
+    let line_width = ((page.width_mm - template.columns.left_mm * 2.0) / (fonts.body_size * 0.19))
+        .max(20.0) as usize;
+
+    let mut flow = PageFlow::new(
+        &doc,
+        doc.get_page(page1).get_layer(layer1),
+        template,
+        font.clone(),
         format!(
-            "{}, {} {}",
-            contact_info.remittence_address.city,
-            contact_info.remittence_address.state,
-            contact_info.remittence_address.zip_code
+            "{} - {} - {} (continued)",
+            letter.company, letter.recipient.name, letter.date
         ),
-        BODY_SIZE,
+    );
+    let spacing = flow.spacing();
+    draw_logo(flow.layer(), settings, template);
+
+    flow.layer().use_text(
+        &letter.company,
+        fonts.header_size,
         left_column,
-        y_level,
+        flow.y_level,
+        &bold_font,
+    );
+    flow.y_level -= spacing;
+    flow.layer().use_text(
+        letter.date.format("%B %-d, %Y").to_string(),
+        fonts.body_size,
+        left_column,
+        flow.y_level,
         &font,
     );
-    y_level += Mm(10.0);
-    current_layer.use_text(
-        contact_info.remittence_address.street_address,
-        BODY_SIZE,
+    flow.y_level -= spacing * 2.0;
+    for line in [
+        letter.recipient.name.clone(),
+        letter.recipient.address.street_address.clone(),
+        letter.recipient.city_line(),
+    ] {
+        flow.layer()
+            .use_text(line, fonts.body_size, left_column, flow.y_level, &font);
+        flow.y_level -= spacing;
+    }
+    flow.y_level -= spacing;
+    for line in wrap_text(&letter.subject, line_width) {
+        flow.ensure_space(spacing, Mm(0.0));
+        flow.layer()
+            .use_text(line, fonts.body_size, left_column, flow.y_level, &bold_font);
+        flow.y_level -= spacing;
+    }
+    flow.y_level -= spacing / 2.0;
+
+    let body_spacing = spacing * 0.7;
+    for paragraph in &letter.paragraphs {
+        for line in wrap_text(paragraph, line_width) {
+            flow.ensure_space(body_spacing, Mm(0.0));
+            flow.layer()
+                .use_text(line, fonts.body_size, left_column, flow.y_level, &font);
+            flow.y_level -= body_spacing;
+        }
+        flow.y_level -= body_spacing;
+    }
+    // Keep the closing together with the signature line
+    flow.ensure_space(spacing * 2.0, Mm(0.0));
+    flow.layer().use_text(
+        &letter.closing,
+        fonts.body_size,
         left_column,
-        y_level,
+        flow.y_level,
         &font,
     );
-    current_layer.use_text(
-        "Payment Due 1st of Coming Month".to_owned(),
-        BODY_SIZE,
-        right_column,
-        y_level,
+    flow.y_level -= spacing * 1.5;
+    flow.layer().use_text(
+        &letter.company,
+        fonts.body_size,
+        left_column,
+        flow.y_level,
         &font,
     );
-    y_level += Mm(10.0);
-    current_layer.use_text("Please Remit To:", BODY_SIZE, left_column, y_level, &font);
-
-    // Save the PDF to a file
-    doc.save(&mut BufWriter::new(
-        File::create(format!(
-            "{}{}_Statement_{}.pdf",
-            settings.statements_path,
-            get_word_date(statement.date),
-            &contact_info.name
-        ))
-        .unwrap(),
-    ))
-    .unwrap();
+    flow.number_pages();
+
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    doc.save(&mut BufWriter::new(file))
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Breaks the text into lines of at most `width` characters at spaces. A word longer than
+/// the line gets a line of its own.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draws the charges as a two column table, continuing it on new pages with the column
+/// headings repeated. Each page's part of the table gets its own outline.
+fn draw_table(flow: &mut PageFlow, heading_font: &IndirectFontRef, rows: &[(String, String)]) {
+    let template = flow.template;
+    let spacing = flow.spacing();
+    let details_size = template.fonts.details_size;
+    let table_margin = Mm(template.columns.table_margin_mm);
+    let right_edge = Mm(template.page.width_mm);
+    let label_x = Mm(template.columns.left_mm) + Mm(15.0);
+    let amount_x = Mm(template.columns.right_mm) + Mm(20.0);
+    let center = right_edge / 2.0;
+
+    let outline = |layer: &PdfLayerReference, top: Mm, bottom: Mm| {
+        layer.add_line(Line::from_iter(vec![
+            (Point::new(table_margin, top), false),
+            (Point::new(table_margin, bottom), false),
+            (Point::new(right_edge - table_margin, bottom), false),
+            (Point::new(right_edge - table_margin, top), false),
+            (Point::new(table_margin, top), false),
+        ]));
+        layer.add_line(Line::from_iter(vec![
+            (Point::new(center, top), false),
+            (Point::new(center, bottom), false),
+        ]));
+    };
+    let heading = |flow: &mut PageFlow| {
+        let top = flow.y_level;
+        flow.y_level -= spacing;
+        flow.layer().use_text(
+            &template.charge_heading,
+            details_size,
+            label_x,
+            flow.y_level,
+            heading_font,
+        );
+        flow.layer().use_text(
+            &template.amount_heading,
+            details_size,
+            amount_x,
+            flow.y_level,
+            heading_font,
+        );
+        flow.y_level -= spacing / 2.0;
+        flow.layer().add_line(Line::from_iter(vec![
+            (Point::new(table_margin, flow.y_level), false),
+            (Point::new(right_edge - table_margin, flow.y_level), false),
+        ]));
+        flow.y_level -= spacing;
+        top
+    };
+
+    // Keep the headings together with at least the first row
+    flow.ensure_space(spacing * 3.0, Mm(0.0));
+    let mut table_top = heading(flow);
+    for (label, amount) in rows {
+        if flow.y_level - spacing < Mm(template.page.bottom_mm) {
+            outline(flow.layer(), table_top, flow.y_level + spacing / 2.0);
+            flow.new_page();
+            table_top = heading(flow);
+        }
+        flow.layer()
+            .use_text(label, details_size, label_x, flow.y_level, &flow.font);
+        flow.layer()
+            .use_text(amount, details_size, amount_x, flow.y_level, &flow.font);
+        flow.y_level -= spacing;
+    }
+    outline(flow.layer(), table_top, flow.y_level + spacing / 2.0);
+}
+
+/// Lists the expenses behind each charge on pages of their own, with the building's total
+/// for each expense and the tenant's share of it.
+/// Lists how the lease's CAM clauses changed the charges, under the charge table.
+fn draw_adjustments(flow: &mut PageFlow, heading_font: &IndirectFontRef, adjustments: &[String]) {
+    if adjustments.is_empty() {
+        return;
+    }
+    let template = flow.template;
+    let spacing = flow.spacing();
+    let details_size = template.fonts.details_size;
+    let left_column = Mm(template.columns.left_mm);
+
+    flow.ensure_space(spacing * 2.0, Mm(0.0));
+    flow.layer().use_text(
+        &template.adjustments_heading,
+        details_size,
+        left_column,
+        flow.y_level,
+        heading_font,
+    );
+    flow.y_level -= spacing;
+    for adjustment in adjustments {
+        flow.ensure_space(spacing, Mm(0.0));
+        flow.layer().use_text(
+            adjustment,
+            details_size,
+            left_column,
+            flow.y_level,
+            &flow.font,
+        );
+        flow.y_level -= spacing;
+    }
+}
+
+fn draw_expense_appendix(
+    flow: &mut PageFlow,
+    heading_font: &IndirectFontRef,
+    lines: &[StatementLine],
+) {
+    let template = flow.template;
+    let spacing = flow.spacing();
+    let details_size = template.fonts.details_size;
+    let left_column = Mm(template.columns.left_mm);
+    let right_edge = Mm(template.page.width_mm) - left_column;
+    let date_x = left_column;
+    let category_x = left_column + Mm(25.0);
+    let description_x = left_column + Mm(70.0);
+    let total_x = right_edge - Mm(50.0);
+    let share_x = right_edge - Mm(22.0);
+
+    let column_headings = |flow: &mut PageFlow, title: &str| {
+        flow.layer()
+            .use_text(title, details_size, left_column, flow.y_level, heading_font);
+        flow.y_level -= spacing;
+        for (text, x) in [
+            ("Date", date_x),
+            ("Category", category_x),
+            ("Description", description_x),
+            ("Building", total_x),
+            ("Share", share_x),
+        ] {
+            flow.layer()
+                .use_text(text, details_size, x, flow.y_level, heading_font);
+        }
+        flow.y_level -= spacing / 2.0;
+        flow.layer().add_line(Line::from_iter(vec![
+            (Point::new(left_column, flow.y_level), false),
+            (Point::new(right_edge, flow.y_level), false),
+        ]));
+        flow.y_level -= spacing;
+    };
+    let draw_expense = |flow: &mut PageFlow, line: &StatementLine, expense: &Expense| {
+        let category = expense.expense_type.to_string();
+        let description: String = expense.description.chars().take(28).collect();
+        for (text, x) in [
+            (expense.date.format("%m/%d/%Y").to_string(), date_x),
+            (category, category_x),
+            (description, description_x),
+            (format!("${:.2}", expense.amount), total_x),
+            (format!("${:.2}", line.share_of(expense)), share_x),
+        ] {
+            flow.layer()
+                .use_text(text, details_size, x, flow.y_level, &flow.font);
+        }
+        flow.y_level -= spacing;
+    };
+
+    let itemized: Vec<&StatementLine> = lines
+        .iter()
+        .filter(|line| !line.expenses.is_empty())
+        .collect();
+    if itemized.is_empty() {
+        return;
+    }
+    flow.new_page();
+    flow.layer().use_text(
+        &template.appendix_heading,
+        template.fonts.body_size,
+        left_column,
+        flow.y_level,
+        heading_font,
+    );
+    flow.y_level -= spacing * 1.5;
+
+    for line in itemized {
+        // Keep each heading together with the first expense under it
+        flow.ensure_space(spacing * 4.0, Mm(0.0));
+        let title = line.label();
+        column_headings(flow, &title);
+        for expense in &line.expenses {
+            if flow.ensure_space(spacing, Mm(0.0)) {
+                column_headings(flow, &format!("{title} (continued)"));
+            }
+            draw_expense(flow, line, expense);
+        }
+        flow.ensure_space(Mm(0.0), Mm(0.0));
+        let recoverable = match line.recoverable != line.base {
+            true => format!(" (${:.2} recoverable)", line.recoverable),
+            false => String::new(),
+        };
+        let prorated = match line.factor < 1.0 {
+            true => format!(", prorated {:.2}%", line.factor * 100.0),
+            false => String::new(),
+        };
+        flow.layer().use_text(
+            format!(
+                "Total: ${:.2} of ${:.2}{}{}",
+                line.amount, line.base, recoverable, prorated
+            ),
+            details_size,
+            total_x,
+            flow.y_level,
+            heading_font,
+        );
+        flow.y_level -= spacing * 1.5;
+    }
+}
+
+/// Embeds the TTF font at `path` in the templates directory, or uses Helvetica when the
+/// template doesn't set one or it can't be loaded.
+fn load_font(doc: &PdfDocumentReference, settings: &PathSettings, path: &str) -> IndirectFontRef {
+    if let Some(path) = StatementTemplate::resource_path(settings, path) {
+        match File::open(&path).map(|file| doc.add_external_font(file)) {
+            Ok(Ok(font)) => return font,
+            Ok(Err(e)) => println!("Failed to embed font {}: {}", path.display(), e),
+            Err(e) => println!("Failed to open font {}: {}", path.display(), e),
+        }
+    }
+    doc.add_builtin_font(BuiltinFont::Helvetica).unwrap()
+}
+
+fn draw_logo(layer: &PdfLayerReference, settings: &PathSettings, template: &StatementTemplate) {
+    let Some(path) = StatementTemplate::resource_path(settings, &template.logo.path) else {
+        return;
+    };
+    let decoded = image_crate::io::Reader::open(&path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(image_crate::ImageError::IoError)
+        .and_then(|reader| reader.decode());
+    let logo = match decoded {
+        Ok(logo) => logo,
+        Err(e) => {
+            println!("Failed to load logo {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    // Images are placed at 300 dpi by default, scale that to the requested width
+    let dpi = 300.0;
+    let natural_width = logo.width() as f32 / dpi * 25.4;
+    let natural_height = logo.height() as f32 / dpi * 25.4;
+    let scale = template.logo.width_mm / natural_width;
+    let logo_height = natural_height * scale;
+    Image::from_dynamic_image(&logo).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(template.page.width_mm
+                - template.logo.right_mm
+                - template.logo.width_mm)),
+            translate_y: Some(Mm(template.page.height_mm
+                - template.logo.top_mm
+                - logo_height)),
+            scale_x: Some(scale),
+            scale_y: Some(scale),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
 }
 
 pub fn get_word_date(date: NaiveDate) -> String {
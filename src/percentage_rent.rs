@@ -0,0 +1,462 @@
+use std::fmt;
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    database::{add_sales_report, get_sales_reports, remove_sales_report},
+    lease::{encoded_term, ChargeCategory, Lease, StatementLine},
+    statement_document::BillingPeriod,
+    App, SalesReportInput, SalesReports,
+};
+
+/// How often a retail tenant reports their gross sales. Percentage rent is billed on the
+/// statement after each reporting period ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportingPeriod {
+    Monthly,
+    Quarterly,
+    Annual,
+}
+
+impl fmt::Display for ReportingPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            ReportingPeriod::Monthly => String::from("monthly"),
+            ReportingPeriod::Quarterly => String::from("quarterly"),
+            ReportingPeriod::Annual => String::from("annual"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl ReportingPeriod {
+    pub fn parse_string(period: &str) -> Option<ReportingPeriod> {
+        match period {
+            "monthly" => Some(ReportingPeriod::Monthly),
+            "quarterly" => Some(ReportingPeriod::Quarterly),
+            "annual" => Some(ReportingPeriod::Annual),
+            _ => None,
+        }
+    }
+
+    pub fn months(&self) -> u32 {
+        match self {
+            ReportingPeriod::Monthly => 1,
+            ReportingPeriod::Quarterly => 3,
+            ReportingPeriod::Annual => 12,
+        }
+    }
+
+    /// The reporting period `date` falls in. Quarters and years follow the calendar.
+    pub fn containing(&self, date: NaiveDate) -> BillingPeriod {
+        let months = self.months();
+        let first_month = (date.month0() / months) * months + 1;
+        let start = NaiveDate::from_ymd_opt(date.year(), first_month, 1).unwrap();
+        BillingPeriod {
+            start,
+            end: start + Months::new(months) - Days::new(1),
+        }
+    }
+}
+
+/// The sales over which percentage rent is charged, for a year of sales.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    // The sales at which the percentage equals the base rent, base rent / rate
+    Natural,
+    Fixed(f32),
+}
+
+/// Rent of `rate` times the tenant's gross sales over the breakpoint, on top of base rent.
+#[derive(Debug, Clone, Copy)]
+pub struct PercentageRent {
+    pub rate: f32,
+    pub breakpoint: Breakpoint,
+    pub reporting: ReportingPeriod,
+}
+
+impl PercentageRent {
+    pub fn encode_to_database_string(&self) -> String {
+        let breakpoint = match self.breakpoint {
+            Breakpoint::Natural => String::from("Natural Breakpoint"),
+            Breakpoint::Fixed(amount) => format!("Breakpoint {amount}"),
+        };
+        format!(
+            "Rate {}, {}, Reporting {}",
+            self.rate, breakpoint, self.reporting
+        )
+    }
+
+    pub fn decode_from_database_string(encoded: &str) -> Option<PercentageRent> {
+        let breakpoint = match encoded.contains("Natural Breakpoint") {
+            true => Breakpoint::Natural,
+            false => Breakpoint::Fixed(encoded_term(encoded, "Breakpoint")?),
+        };
+        let (_, reporting) = encoded.split_once("Reporting ")?;
+        Some(PercentageRent {
+            rate: encoded_term(encoded, "Rate")?,
+            breakpoint,
+            reporting: ReportingPeriod::parse_string(reporting.trim())?,
+        })
+    }
+
+    /// The breakpoint for `fraction` of a year of sales, from the monthly base rent for a
+    /// natural breakpoint.
+    pub fn breakpoint(&self, base_rent: f32, fraction: f32) -> f32 {
+        let annual = match self.breakpoint {
+            Breakpoint::Natural if self.rate > 0.0 => base_rent * 12.0 / self.rate,
+            Breakpoint::Natural => 0.0,
+            Breakpoint::Fixed(amount) => amount,
+        };
+        annual * fraction
+    }
+
+    /// What is due on `sales` over `breakpoint`, and the math behind it.
+    pub fn due(&self, sales: f32, breakpoint: f32, period: &BillingPeriod) -> (f32, String) {
+        let excess = (sales - breakpoint).max(0.0);
+        let amount = (self.rate * excess * 100.0).round() / 100.0;
+        let explanation = format!(
+            "Percentage rent on {} sales: {:.1}% of ${:.2} over the ${:.2} breakpoint = ${:.2}",
+            period.range(),
+            self.rate * 100.0,
+            sales,
+            breakpoint,
+            amount
+        );
+        (amount, explanation)
+    }
+}
+
+/// The percentage rent billed on the statement for `period`, when a reporting period ended
+/// the day before it started. Returns the line, if anything is due, and a note explaining
+/// it. Sales not reported yet are left for the year end true-up.
+pub fn statement_charge(
+    leases: &[Lease],
+    reports: &[SalesReport],
+    period: &BillingPeriod,
+) -> Option<(Option<StatementLine>, String)> {
+    let last_day = period.start.pred_opt()?;
+    let lease = leases
+        .iter()
+        .filter(|lease| lease.start_date <= last_day && last_day <= lease.end_date)
+        .max_by_key(|lease| lease.start_date)?;
+    let terms = lease.percentage_rent?;
+    let reporting_period = terms.reporting.containing(last_day);
+    if reporting_period.end != last_day {
+        return None;
+    }
+    let Some(sales) = sales_in(reports, reporting_period.start, reporting_period.end) else {
+        return Some((
+            None,
+            format!(
+                "Percentage rent on {} sales waits on the sales report",
+                reporting_period.range()
+            ),
+        ));
+    };
+    let breakpoint = terms.breakpoint(
+        lease.fee_structure.base_rent(),
+        terms.reporting.months() as f32 / 12.0,
+    );
+    let (amount, explanation) = terms.due(sales, breakpoint, &reporting_period);
+    let line = (amount > 0.0).then(|| {
+        let mut line = StatementLine::share(
+            ChargeCategory::PercentageRent,
+            terms.rate,
+            sales - breakpoint,
+        );
+        line.amount = amount;
+        line
+    });
+    Some((line, explanation))
+}
+
+/// A tenant's gross sales for a period, as they reported them.
+#[derive(Debug, Clone)]
+pub struct SalesReport {
+    pub id: i64,
+    pub leaseholder_id: u32,
+    pub period: BillingPeriod,
+    pub gross_sales: f32,
+    pub reported_on: NaiveDate,
+}
+
+impl SalesReport {
+    pub fn convert_to_slint(&self) -> SalesReportInput {
+        SalesReportInput {
+            id: self.id as i32,
+            period: self.period.range().into(),
+            gross_sales: format!("{:.2}", self.gross_sales).into(),
+            reported_on: self.reported_on.to_string().into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for SalesReport {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let date = |column: &str| -> Result<NaiveDate, sqlx::Error> {
+            let date: String = row.try_get(column)?;
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+
+        Ok(SalesReport {
+            id: row.try_get("sales_report_id")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            period: BillingPeriod {
+                start: date("period_start")?,
+                end: date("period_end")?,
+            },
+            gross_sales: row.try_get::<f64, _>("gross_sales")? as f32,
+            reported_on: date("reported_on")?,
+        })
+    }
+}
+
+/// The sales reported for periods within `from` to `to`, or None when nothing was reported.
+pub fn sales_in(reports: &[SalesReport], from: NaiveDate, to: NaiveDate) -> Option<f32> {
+    let reported: Vec<&SalesReport> = reports
+        .iter()
+        .filter(|report| report.period.start >= from && report.period.end <= to)
+        .collect();
+    match reported.is_empty() {
+        true => None,
+        false => Some(
+            reported
+                .iter()
+                .fold(0.0, |sum, report| sum + report.gross_sales),
+        ),
+    }
+}
+
+/// A sales report as typed into the Leaseholders tab, checked by `parse`.
+#[derive(Debug, Clone)]
+pub struct NewSalesReport {
+    pub period_start: String,
+    pub period_end: String,
+    pub gross_sales: String,
+}
+
+impl NewSalesReport {
+    pub fn parse(&self, leaseholder_id: u32) -> Result<SalesReport, String> {
+        let date = |text: &str| {
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date: {text}, use YYYY-MM-DD"))
+        };
+        let start = date(&self.period_start)?;
+        // The month the sales start in when no end is given
+        let end = match self.period_end.trim() {
+            "" => BillingPeriod::month_of(start).end,
+            end => date(end)?,
+        };
+        if end < start {
+            return Err(String::from("The sales period has to end after it starts"));
+        }
+        let gross_sales = self
+            .gross_sales
+            .trim()
+            .trim_start_matches('$')
+            .replace(',', "")
+            .parse::<f32>()
+            .ok()
+            .filter(|sales| *sales >= 0.0)
+            .ok_or_else(|| format!("Invalid gross sales: {}", self.gross_sales))?;
+        Ok(SalesReport {
+            id: 0,
+            leaseholder_id,
+            period: BillingPeriod { start, end },
+            gross_sales,
+            reported_on: Local::now().date_naive(),
+        })
+    }
+}
+
+pub enum SalesMessage {
+    Show(u32),
+    Add(u32, NewSalesReport),
+    Remove(i64),
+    Quit,
+}
+
+pub struct SalesWorker {
+    pub channel: UnboundedSender<SalesMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl SalesWorker {
+    pub fn new(pool: &sqlx::Pool<sqlx::Sqlite>, app: slint::Weak<App>) -> Self {
+        println!("Create new Sales Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(sales_worker_loop(new_pool, r, app))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(SalesMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn sales_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    mut r: UnboundedReceiver<SalesMessage>,
+    app: slint::Weak<App>,
+) {
+    let mut leaseholder_id: Option<u32> = None;
+    loop {
+        let status = match r.recv().await {
+            Some(SalesMessage::Show(id)) => {
+                leaseholder_id = Some(id);
+                None
+            }
+            Some(SalesMessage::Add(id, new_report)) => {
+                leaseholder_id = Some(id);
+                Some(match new_report.parse(id) {
+                    Ok(mut report) => match add_sales_report(&pool, &mut report).await {
+                        Ok(_) => format!(
+                            "Recorded ${:.2} of sales for {}",
+                            report.gross_sales,
+                            report.period.range()
+                        ),
+                        Err(e) => format!("Failed to record sales: {e}"),
+                    },
+                    Err(e) => e,
+                })
+            }
+            Some(SalesMessage::Remove(id)) => Some(match remove_sales_report(&pool, id).await {
+                Ok(_) => String::from("Removed sales report"),
+                Err(e) => format!("Failed to remove sales report: {e}"),
+            }),
+            Some(SalesMessage::Quit) | None => break,
+        };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+        if let Some(leaseholder_id) = leaseholder_id {
+            show_sales(&pool, &app, leaseholder_id, status).await;
+        }
+    }
+}
+
+async fn show_sales(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    app: &slint::Weak<App>,
+    leaseholder_id: u32,
+    status: Option<String>,
+) {
+    let reports = get_sales_reports(pool, leaseholder_id).await;
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let reports: Vec<SalesReportInput> =
+            reports.iter().map(SalesReport::convert_to_slint).collect();
+        let registry = handle.global::<SalesReports>();
+        registry.set_reports(ModelRc::new(VecModel::from(reports)));
+        registry.set_status(status.unwrap_or_default().into());
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lease::{FeeStructure, Rent};
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    /// A lease at 2000 a month plus 6% of quarterly sales over the natural breakpoint.
+    fn lease() -> Lease {
+        let mut lease = Lease::new(
+            date(1, 1),
+            date(12, 31),
+            FeeStructure::Gross(Rent { base_rent: 2000.0 }),
+            "Check".to_string(),
+        );
+        lease.percentage_rent = Some(PercentageRent {
+            rate: 0.06,
+            breakpoint: Breakpoint::Natural,
+            reporting: ReportingPeriod::Quarterly,
+        });
+        lease
+    }
+
+    fn report(start: NaiveDate, end: NaiveDate, gross_sales: f32) -> SalesReport {
+        SalesReport {
+            id: 0,
+            leaseholder_id: 1,
+            period: BillingPeriod { start, end },
+            gross_sales,
+            reported_on: end,
+        }
+    }
+
+    fn april() -> BillingPeriod {
+        BillingPeriod {
+            start: date(4, 1),
+            end: date(4, 30),
+        }
+    }
+
+    #[test]
+    fn natural_breakpoint_is_base_rent_over_the_rate() {
+        let terms = lease().percentage_rent.unwrap();
+        assert_eq!(terms.breakpoint(2000.0, 1.0), 400_000.0);
+        assert_eq!(terms.breakpoint(2000.0, 0.25), 100_000.0);
+
+        let fixed = PercentageRent {
+            breakpoint: Breakpoint::Fixed(250_000.0),
+            ..terms
+        };
+        assert_eq!(fixed.breakpoint(2000.0, 0.25), 62_500.0);
+    }
+
+    #[test]
+    fn sales_over_the_breakpoint_are_charged_after_the_quarter() {
+        let reports = [
+            report(date(1, 1), date(1, 31), 40_000.0),
+            report(date(2, 1), date(2, 29), 50_000.0),
+            report(date(3, 1), date(3, 31), 40_000.0),
+        ];
+        let (line, explanation) = statement_charge(&[lease()], &reports, &april()).unwrap();
+        assert_eq!(line.unwrap().amount, 1800.0);
+        assert!(
+            explanation.contains("$100000.00 breakpoint"),
+            "{explanation}"
+        );
+
+        // Nothing is billed mid quarter
+        let may = BillingPeriod {
+            start: date(5, 1),
+            end: date(5, 31),
+        };
+        assert!(statement_charge(&[lease()], &reports, &may).is_none());
+    }
+
+    #[test]
+    fn sales_at_the_breakpoint_owe_nothing() {
+        let reports = [report(date(1, 1), date(3, 31), 100_000.0)];
+        let (line, _) = statement_charge(&[lease()], &reports, &april()).unwrap();
+        assert!(line.is_none());
+    }
+
+    #[test]
+    fn unreported_sales_wait_for_the_report() {
+        let (line, explanation) = statement_charge(&[lease()], &[], &april()).unwrap();
+        assert!(line.is_none());
+        assert!(explanation.contains("waits on the sales report"));
+    }
+}
@@ -1,9 +1,14 @@
 use std::fmt;
 
 use crate::{
-    database::{add_property, remove_property, update_property},
-    App, PropertyInput,
+    database::{
+        add_property, remove_property, restore_property, set_property_archived, update_property,
+    },
+    leaseholders::Leaseholder,
+    utility_billing::UtilityBilling,
+    App, LeaseholderInput, PropertyInput,
 };
+use chrono::{Local, NaiveDateTime};
 use sqlx::{sqlite::SqliteRow, Row};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
@@ -15,6 +20,13 @@ pub struct Property {
     pub property_tax: f32,
     pub business_insurance: f32,
     pub num_units: u32,
+    // Name of the statement template in the templates directory, the default when unset
+    pub statement_template: Option<String>,
+    // Name of the tax jurisdiction in the settings file, untaxed when unset
+    pub tax_jurisdiction: Option<String>,
+    // Utilities billed by meter or ratio rather than by each lease's CAM rates
+    pub utility_billing: UtilityBilling,
+    pub archived_at: Option<NaiveDateTime>,
 }
 
 impl Property {
@@ -33,6 +45,10 @@ impl Property {
             property_tax,
             business_insurance,
             num_units,
+            statement_template: None,
+            tax_jurisdiction: None,
+            utility_billing: UtilityBilling::default(),
+            archived_at: None,
         }
     }
 
@@ -49,6 +65,19 @@ impl Property {
             property_tax: input.property_tax,
             business_insurance: input.business_insurance,
             num_units: input.unit_count.to_string().parse::<u32>().unwrap(),
+            statement_template: match input.statement_template.trim() {
+                "" => None,
+                name => Some(name.to_owned()),
+            },
+            tax_jurisdiction: match input.tax_jurisdiction.trim() {
+                "" => None,
+                name => Some(name.to_owned()),
+            },
+            utility_billing: UtilityBilling::decode_from_database_string(&input.utility_billing),
+            archived_at: match input.archived {
+                true => Some(Local::now().naive_local()),
+                false => None,
+            },
         }
     }
 
@@ -69,6 +98,14 @@ impl Property {
             property_tax: self.property_tax,
             business_insurance: self.business_insurance,
             unit_count: self.num_units.to_string().into(),
+            statement_template: self.statement_template.clone().unwrap_or_default().into(),
+            tax_jurisdiction: self.tax_jurisdiction.clone().unwrap_or_default().into(),
+            utility_billing: self
+                .utility_billing
+                .encode_to_database_string()
+                .unwrap_or_default()
+                .into(),
+            archived: self.archived_at.is_some(),
         }
     }
 }
@@ -84,11 +121,22 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for Property {
         let state_string: String = row.try_get("state")?;
         let zip_string: String = row.try_get("zip_code")?;
         let num_units: u32 = row.try_get("num_units")?;
+        let statement_template: Option<String> = row.try_get("statement_template")?;
+        let tax_jurisdiction: Option<String> = row.try_get("tax_jurisdiction")?;
+        let utility_billing: Option<String> = row.try_get("utility_billing")?;
+        let archived_string: Option<String> = row.try_get("archived_at")?;
 
         let property_tax: f32 = tax_string.parse::<f32>().unwrap();
         let business_insurance: f32 = insurance_string.parse::<f32>().unwrap();
 
         let address: Address = Address::new(address_string, city_string, state_string, zip_string);
+        let archived_at = match archived_string {
+            Some(s) => Some(
+                NaiveDateTime::parse_from_str(&s, crate::database::TIMESTAMP_FORMAT)
+                    .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            ),
+            None => None,
+        };
 
         Ok(Property {
             id,
@@ -97,6 +145,12 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for Property {
             property_tax,
             business_insurance,
             num_units,
+            statement_template,
+            tax_jurisdiction,
+            utility_billing: utility_billing
+                .map(|rules| UtilityBilling::decode_from_database_string(&rules))
+                .unwrap_or_default(),
+            archived_at,
         })
     }
 }
@@ -124,6 +178,9 @@ pub enum PropertyMessage {
     PropertyCreated(PropertyInput),
     PropertyUpdate(PropertyInput),
     PropertyRemove(PropertyInput),
+    PropertyRestore(PropertyInput, Vec<LeaseholderInput>),
+    PropertyArchive(PropertyInput),
+    PropertyUnarchive(PropertyInput),
     Quit,
 }
 
@@ -133,6 +190,9 @@ impl fmt::Display for PropertyMessage {
             PropertyMessage::PropertyCreated(_) => String::from("Create"),
             PropertyMessage::PropertyUpdate(_) => String::from("Update"),
             PropertyMessage::PropertyRemove(_) => String::from("Remove"),
+            PropertyMessage::PropertyRestore(_, _) => String::from("Restore"),
+            PropertyMessage::PropertyArchive(_) => String::from("Archive"),
+            PropertyMessage::PropertyUnarchive(_) => String::from("Unarchive"),
             PropertyMessage::Quit => String::from("Quit"),
         };
         write!(f, "{res}")
@@ -198,6 +258,31 @@ async fn property_worker_loop(
                         Err(e) => println!("Failed to remove property via slint: {e}"),
                     }
                 }
+                PropertyMessage::PropertyRestore(restore, lessees) => {
+                    let converted_property = Property::convert_from_slint(restore);
+                    let converted_lessees: Vec<Leaseholder> = lessees
+                        .into_iter()
+                        .map(Leaseholder::convert_from_slint)
+                        .collect();
+                    match restore_property(&pool, &converted_property, &converted_lessees).await {
+                        Ok(_) => println!("Successfully restored property via slint"),
+                        Err(e) => println!("Failed to restore property via slint: {e}"),
+                    }
+                }
+                PropertyMessage::PropertyArchive(archive) => {
+                    let converted_property = Property::convert_from_slint(archive);
+                    match set_property_archived(&pool, &converted_property, true).await {
+                        Ok(_) => println!("Successfully archived property via slint"),
+                        Err(e) => println!("Failed to archive property via slint: {e}"),
+                    }
+                }
+                PropertyMessage::PropertyUnarchive(unarchive) => {
+                    let converted_property = Property::convert_from_slint(unarchive);
+                    match set_property_archived(&pool, &converted_property, false).await {
+                        Ok(_) => println!("Successfully unarchived property via slint"),
+                        Err(e) => println!("Failed to unarchive property via slint: {e}"),
+                    }
+                }
                 PropertyMessage::Quit => {
                     println!("Quitting");
                     continue;
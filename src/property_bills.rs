@@ -0,0 +1,379 @@
+use std::fmt;
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    database::{
+        add_property_bill, get_property_bills, mark_installment_paid, remove_property_bill,
+    },
+    statement_document::BillingPeriod,
+    App, InstallmentInput, PropertyBillInput, PropertyBills,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BillKind {
+    PropertyTax,
+    Insurance,
+}
+
+impl fmt::Display for BillKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            BillKind::PropertyTax => String::from("property_tax"),
+            BillKind::Insurance => String::from("insurance"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl BillKind {
+    pub fn parse_string(kind: &str) -> Option<BillKind> {
+        match kind {
+            "property_tax" | "Property Tax" => Some(BillKind::PropertyTax),
+            "insurance" | "Insurance" => Some(BillKind::Insurance),
+            _ => None,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            BillKind::PropertyTax => "Property Tax",
+            BillKind::Insurance => "Insurance",
+        }
+    }
+}
+
+/// One payment toward a bill, e.g. the spring half of the year's property tax.
+#[derive(Debug, Clone)]
+pub struct Installment {
+    pub id: i64,
+    pub bill_id: i64,
+    pub due_date: NaiveDate,
+    pub amount: f32,
+    pub paid_on: Option<NaiveDate>,
+}
+
+/// A property tax bill or insurance premium. The amount is spread evenly over the months of
+/// its coverage period for billing, whenever the installments toward it are due.
+#[derive(Debug, Clone)]
+pub struct PropertyBill {
+    pub id: i64,
+    pub property_id: u32,
+    pub kind: BillKind,
+    pub description: String,
+    pub amount: f32,
+    pub coverage_start: NaiveDate,
+    pub coverage_end: NaiveDate,
+    pub installments: Vec<Installment>,
+}
+
+impl PropertyBill {
+    /// The part of the bill billed in `period`, its share of the covered months. Partly
+    /// covered months count for the fraction of their days that are covered.
+    pub fn amortized(&self, period: &BillingPeriod) -> f32 {
+        let covered = months_between(self.coverage_start, self.coverage_end);
+        if covered <= 0.0 {
+            return 0.0;
+        }
+        let from = self.coverage_start.max(period.start);
+        let to = self.coverage_end.min(period.end);
+        let billed = (self.amount * months_between(from, to) / covered * 100.0).round() / 100.0;
+        billed.max(0.0)
+    }
+
+    /// What a fully covered month is billed.
+    pub fn monthly_amount(&self) -> f32 {
+        let covered = months_between(self.coverage_start, self.coverage_end);
+        match covered > 0.0 {
+            true => self.amount / covered,
+            false => 0.0,
+        }
+    }
+
+    /// Splits the amount evenly between the due dates, with any rounding left on the last.
+    pub fn schedule_installments(&mut self, due_dates: &[NaiveDate]) {
+        let count = due_dates.len().max(1);
+        let each = (self.amount / count as f32 * 100.0).round() / 100.0;
+        let due_dates = match due_dates.is_empty() {
+            true => vec![self.coverage_start],
+            false => due_dates.to_vec(),
+        };
+        self.installments = due_dates
+            .iter()
+            .enumerate()
+            .map(|(i, due_date)| Installment {
+                id: 0,
+                bill_id: self.id,
+                due_date: *due_date,
+                amount: match i + 1 == count {
+                    true => ((self.amount - each * (count - 1) as f32) * 100.0).round() / 100.0,
+                    false => each,
+                },
+                paid_on: None,
+            })
+            .collect();
+    }
+
+    pub fn convert_to_slint(&self) -> PropertyBillInput {
+        let installments: Vec<InstallmentInput> = self
+            .installments
+            .iter()
+            .map(|installment| InstallmentInput {
+                id: installment.id as i32,
+                due_date: installment.due_date.to_string().into(),
+                amount: format!("{:.2}", installment.amount).into(),
+                paid_on: installment
+                    .paid_on
+                    .map(|date| date.to_string())
+                    .unwrap_or_default()
+                    .into(),
+            })
+            .collect();
+        PropertyBillInput {
+            id: self.id as i32,
+            kind: self.kind.title().into(),
+            description: self.description.clone().into(),
+            amount: format!("{:.2}", self.amount).into(),
+            coverage: format!("{} to {}", self.coverage_start, self.coverage_end).into(),
+            monthly: format!("{:.2}", self.monthly_amount()).into(),
+            installments: ModelRc::new(VecModel::from(installments)),
+        }
+    }
+}
+
+/// The amount of `kind` billed for the period across a property's bills, or None when the
+/// property has no bills of that kind and is still billed from its stored amount.
+pub fn amortized_total(
+    bills: &[PropertyBill],
+    kind: BillKind,
+    period: &BillingPeriod,
+) -> Option<f32> {
+    let bills: Vec<&PropertyBill> = bills.iter().filter(|bill| bill.kind == kind).collect();
+    if bills.is_empty() {
+        return None;
+    }
+    Some(
+        bills
+            .iter()
+            .fold(0.0, |sum, bill| sum + bill.amortized(period)),
+    )
+}
+
+/// Calendar months from `from` to `to`, both included, counting a partial month as the
+/// fraction of its days that are included.
+fn months_between(from: NaiveDate, to: NaiveDate) -> f32 {
+    let mut months = 0.0;
+    let mut month = BillingPeriod::month_of(from);
+    while from <= to && month.start <= to {
+        let start = from.max(month.start);
+        let end = to.min(month.end);
+        let days = (end - start).num_days() + 1;
+        months += days as f32 / month.end.day() as f32;
+        let next = month.start + Months::new(1);
+        month = BillingPeriod::month_of(next);
+    }
+    months
+}
+
+impl<'r> FromRow<'r, SqliteRow> for Installment {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let due_date: String = row.try_get("due_date")?;
+        let paid_on: Option<String> = row.try_get("paid_on")?;
+        Ok(Installment {
+            id: row.try_get("installment_id")?,
+            bill_id: row.try_get("bill_id")?,
+            due_date: NaiveDate::parse_from_str(&due_date, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            amount: row.try_get::<f64, _>("amount")? as f32,
+            paid_on: paid_on.and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+        })
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for PropertyBill {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let kind: String = row.try_get("kind")?;
+        let kind = BillKind::parse_string(&kind)
+            .ok_or_else(|| sqlx::Error::Decode(format!("Unknown bill kind: {kind}").into()))?;
+        let date = |column: &str| -> Result<NaiveDate, sqlx::Error> {
+            let date: String = row.try_get(column)?;
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+
+        Ok(PropertyBill {
+            id: row.try_get("bill_id")?,
+            property_id: row.try_get("property_id")?,
+            kind,
+            description: row.try_get("description")?,
+            amount: row.try_get::<f64, _>("amount")? as f32,
+            coverage_start: date("coverage_start")?,
+            coverage_end: date("coverage_end")?,
+            installments: vec![],
+        })
+    }
+}
+
+/// A bill as typed into the property tab, before it is checked.
+pub struct NewBill {
+    pub kind: String,
+    pub description: String,
+    pub amount: String,
+    pub coverage_start: String,
+    pub coverage_end: String,
+    // Comma separated, e.g. "2024-04-30, 2024-10-31"
+    pub due_dates: String,
+}
+
+impl NewBill {
+    pub fn parse(&self, property_id: u32) -> Result<PropertyBill, String> {
+        let date = |text: &str| {
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date: {text}, use YYYY-MM-DD"))
+        };
+        let kind = BillKind::parse_string(&self.kind)
+            .ok_or_else(|| format!("Choose property tax or insurance, not {}", self.kind))?;
+        let amount = self
+            .amount
+            .trim()
+            .trim_start_matches('$')
+            .parse::<f32>()
+            .ok()
+            .filter(|amount| *amount > 0.0)
+            .ok_or_else(|| format!("Invalid bill amount: {}", self.amount))?;
+        let coverage_start = date(&self.coverage_start)?;
+        // A year of coverage when no end is given
+        let coverage_end = match self.coverage_end.trim() {
+            "" => coverage_start + Months::new(12) - Days::new(1),
+            end => date(end)?,
+        };
+        if coverage_end < coverage_start {
+            return Err(String::from("Coverage has to end after it starts"));
+        }
+        let due_dates = self
+            .due_dates
+            .split(',')
+            .filter(|text| !text.trim().is_empty())
+            .map(date)
+            .collect::<Result<Vec<NaiveDate>, String>>()?;
+
+        let mut bill = PropertyBill {
+            id: 0,
+            property_id,
+            kind,
+            description: self.description.trim().to_owned(),
+            amount,
+            coverage_start,
+            coverage_end,
+            installments: vec![],
+        };
+        bill.schedule_installments(&due_dates);
+        Ok(bill)
+    }
+}
+
+pub enum BillMessage {
+    Show(u32),
+    Add(u32, NewBill),
+    PayInstallment(i64),
+    Remove(i64),
+    Quit,
+}
+
+pub struct BillWorker {
+    pub channel: UnboundedSender<BillMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl BillWorker {
+    pub fn new(pool: &sqlx::Pool<sqlx::Sqlite>, app: slint::Weak<App>) -> Self {
+        println!("Create new Bill Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(bill_worker_loop(new_pool, r, app))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(BillMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn bill_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    mut r: UnboundedReceiver<BillMessage>,
+    app: slint::Weak<App>,
+) {
+    let mut property_id: Option<u32> = None;
+    loop {
+        let status = match r.recv().await {
+            Some(BillMessage::Show(id)) => {
+                property_id = Some(id);
+                None
+            }
+            Some(BillMessage::Add(id, new_bill)) => {
+                property_id = Some(id);
+                Some(match new_bill.parse(id) {
+                    Ok(mut bill) => match add_property_bill(&pool, &mut bill).await {
+                        Ok(_) => format!(
+                            "Added {} bill of ${:.2}, ${:.2} a month",
+                            bill.kind.title(),
+                            bill.amount,
+                            bill.monthly_amount()
+                        ),
+                        Err(e) => format!("Failed to add bill: {e}"),
+                    },
+                    Err(e) => e,
+                })
+            }
+            Some(BillMessage::PayInstallment(id)) => Some(
+                match mark_installment_paid(&pool, id, Local::now().date_naive()).await {
+                    Ok(_) => String::from("Marked installment paid"),
+                    Err(e) => format!("Failed to mark installment paid: {e}"),
+                },
+            ),
+            Some(BillMessage::Remove(id)) => Some(match remove_property_bill(&pool, id).await {
+                Ok(_) => String::from("Removed bill"),
+                Err(e) => format!("Failed to remove bill: {e}"),
+            }),
+            Some(BillMessage::Quit) | None => break,
+        };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+        if let Some(property_id) = property_id {
+            show_bills(&pool, &app, property_id, status).await;
+        }
+    }
+}
+
+async fn show_bills(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    app: &slint::Weak<App>,
+    property_id: u32,
+    status: Option<String>,
+) {
+    let bills = get_property_bills(pool, property_id).await;
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let bills: Vec<PropertyBillInput> =
+            bills.iter().map(PropertyBill::convert_to_slint).collect();
+        let registry = handle.global::<PropertyBills>();
+        registry.set_bills(ModelRc::new(VecModel::from(bills)));
+        registry.set_status(status.unwrap_or_default().into());
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
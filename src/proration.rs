@@ -0,0 +1,416 @@
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{lease::Lease, statement_document::BillingPeriod};
+
+/// How a partial month is turned into a fraction of the monthly charges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProrationMethod {
+    // Days occupied out of the days in that month
+    #[default]
+    ActualDays,
+    // Every month counted as 30 days, with the 31st not counted
+    ThirtyDayMonth,
+    // Twelve months of charges spread over a 365 day year
+    DaysInYear,
+}
+
+/// The part of a billing period one lease covers, and the fraction of its monthly charges
+/// billed for it.
+#[derive(Debug, Clone)]
+pub struct Occupancy {
+    pub lease: Lease,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: u32,
+    pub basis: u32,
+    pub factor: f32,
+    pub method: ProrationMethod,
+}
+
+impl Occupancy {
+    pub fn is_prorated(&self) -> bool {
+        self.factor != 1.0
+    }
+
+    /// The calendar days the lease covers, whatever the proration method counts.
+    pub fn days_occupied(&self) -> u32 {
+        (self.to - self.from).num_days() as u32 + 1
+    }
+
+    /// The proration math, e.g. "03/16/2024 to 03/31/2024: 16/31 days = 51.61%".
+    pub fn describe(&self) -> String {
+        let fraction = match self.method {
+            ProrationMethod::ActualDays => format!("{}/{} days", self.days, self.basis),
+            ProrationMethod::ThirtyDayMonth => {
+                format!("{}/{} days (30-day month)", self.days, self.basis)
+            }
+            ProrationMethod::DaysInYear => format!("{} days x 12/{}", self.days, self.basis),
+        };
+        format!(
+            "{} to {}: {} = {:.2}%",
+            self.from.format("%m/%d/%Y"),
+            self.to.format("%m/%d/%Y"),
+            fraction,
+            self.factor * 100.0
+        )
+    }
+}
+
+impl ProrationMethod {
+    /// Splits the period between the leases in effect during it. Each lease covers the
+    /// days from its start, or move-in, until the day before the next lease starts, its
+    /// end, or move-out. Days outside every lease are not billed.
+    pub fn occupancy(
+        &self,
+        period: &BillingPeriod,
+        leases: &[Lease],
+        move_in: NaiveDate,
+        move_out: Option<NaiveDate>,
+    ) -> Vec<Occupancy> {
+        let mut leases = leases.to_vec();
+        leases.sort_by_key(|lease| lease.start_date);
+
+        let mut occupancy = vec![];
+        for (i, lease) in leases.iter().enumerate() {
+            let from = period.start.max(lease.start_date).max(move_in);
+            let mut to = period.end.min(lease.end_date);
+            if let Some(move_out) = move_out {
+                to = to.min(move_out);
+            }
+            if let Some(next) = leases.get(i + 1) {
+                if let Some(day_before) = next.start_date.checked_sub_days(Days::new(1)) {
+                    to = to.min(day_before);
+                }
+            }
+            if from > to {
+                continue;
+            }
+            let (days, basis, factor) = if from == period.start && to == period.end {
+                let days = (to - from).num_days() as u32 + 1;
+                (days, days, 1.0)
+            } else {
+                self.fraction(period, from, to)
+            };
+            occupancy.push(Occupancy {
+                lease: lease.clone(),
+                from,
+                to,
+                days,
+                basis,
+                factor,
+                method: *self,
+            });
+        }
+        occupancy
+    }
+
+    /// Days billed, the days they are counted against, and the fraction of a month's
+    /// charges that makes.
+    fn fraction(&self, period: &BillingPeriod, from: NaiveDate, to: NaiveDate) -> (u32, u32, f32) {
+        let actual_days = (to - from).num_days() as u32 + 1;
+        match self {
+            ProrationMethod::ActualDays => {
+                let basis = period.end.day();
+                (actual_days, basis, actual_days as f32 / basis as f32)
+            }
+            ProrationMethod::ThirtyDayMonth => {
+                // A lease starting on the 31st has no counted days left that month
+                let first = from.day();
+                let last = if to == period.end {
+                    30
+                } else {
+                    to.day().min(30)
+                };
+                let days = (last + 1).saturating_sub(first);
+                (days, 30, days as f32 / 30.0)
+            }
+            ProrationMethod::DaysInYear => (actual_days, 365, actual_days as f32 * 12.0 / 365.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lease::{FeeStructure, Rent};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn lease(start: NaiveDate, end: NaiveDate) -> Lease {
+        Lease::new(
+            start,
+            end,
+            FeeStructure::Gross(Rent { base_rent: 1500.0 }),
+            "Check".to_string(),
+        )
+    }
+
+    fn year_lease() -> Lease {
+        lease(date(2023, 1, 1), date(2025, 12, 31))
+    }
+
+    /// Days, basis and factor of each part of the period.
+    fn split(
+        method: ProrationMethod,
+        month: NaiveDate,
+        leases: &[Lease],
+        move_in: NaiveDate,
+        move_out: Option<NaiveDate>,
+    ) -> Vec<(u32, u32, f32)> {
+        method
+            .occupancy(&BillingPeriod::month_of(month), leases, move_in, move_out)
+            .iter()
+            .map(|occupancy| (occupancy.days, occupancy.basis, occupancy.factor))
+            .collect()
+    }
+
+    fn assert_factor(factor: f32, expected: f32) {
+        assert!(
+            (factor - expected).abs() < 0.0001,
+            "factor {factor}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn full_month_is_not_prorated() {
+        for method in [
+            ProrationMethod::ActualDays,
+            ProrationMethod::ThirtyDayMonth,
+            ProrationMethod::DaysInYear,
+        ] {
+            let occupancy = method.occupancy(
+                &BillingPeriod::month_of(date(2024, 2, 1)),
+                &[year_lease()],
+                date(2023, 6, 1),
+                None,
+            );
+            assert_eq!(occupancy.len(), 1);
+            assert!(!occupancy[0].is_prorated());
+            assert_eq!(occupancy[0].days_occupied(), 29);
+        }
+    }
+
+    #[test]
+    fn move_in_mid_month() {
+        let leases = [year_lease()];
+        let move_in = date(2024, 3, 16);
+        let parts = split(ProrationMethod::ActualDays, move_in, &leases, move_in, None);
+        assert_eq!(parts.len(), 1);
+        assert_eq!((parts[0].0, parts[0].1), (16, 31));
+        assert_factor(parts[0].2, 16.0 / 31.0);
+
+        let parts = split(
+            ProrationMethod::ThirtyDayMonth,
+            move_in,
+            &leases,
+            move_in,
+            None,
+        );
+        assert_eq!((parts[0].0, parts[0].1), (15, 30));
+        assert_factor(parts[0].2, 0.5);
+
+        let parts = split(ProrationMethod::DaysInYear, move_in, &leases, move_in, None);
+        assert_eq!((parts[0].0, parts[0].1), (16, 365));
+        assert_factor(parts[0].2, 16.0 * 12.0 / 365.0);
+    }
+
+    #[test]
+    fn move_in_on_the_31st_of_a_thirty_day_month() {
+        let move_in = date(2024, 3, 31);
+        let occupancy = ProrationMethod::ThirtyDayMonth.occupancy(
+            &BillingPeriod::month_of(move_in),
+            &[year_lease()],
+            move_in,
+            None,
+        );
+        // The 31st isn't counted, so nothing is billed until April
+        assert_eq!(occupancy.len(), 1);
+        assert_eq!((occupancy[0].days, occupancy[0].basis), (0, 30));
+        assert_factor(occupancy[0].factor, 0.0);
+        assert!(occupancy[0].is_prorated());
+        assert_eq!(occupancy[0].days_occupied(), 1);
+        assert_eq!(
+            occupancy[0].describe(),
+            "03/31/2024 to 03/31/2024: 0/30 days (30-day month) = 0.00%"
+        );
+
+        // Under actual days it's one day of 31
+        let parts = split(
+            ProrationMethod::ActualDays,
+            move_in,
+            &[year_lease()],
+            move_in,
+            None,
+        );
+        assert_eq!((parts[0].0, parts[0].1), (1, 31));
+    }
+
+    #[test]
+    fn move_out_on_the_30th_of_a_thirty_day_month() {
+        let parts = split(
+            ProrationMethod::ThirtyDayMonth,
+            date(2024, 3, 1),
+            &[year_lease()],
+            date(2023, 6, 1),
+            Some(date(2024, 3, 30)),
+        );
+        assert_eq!((parts[0].0, parts[0].1), (30, 30));
+        assert_factor(parts[0].2, 1.0);
+    }
+
+    #[test]
+    fn february() {
+        let leases = [year_lease()];
+        // 2024 is a leap year
+        let move_in = date(2024, 2, 15);
+        let parts = split(ProrationMethod::ActualDays, move_in, &leases, move_in, None);
+        assert_eq!((parts[0].0, parts[0].1), (15, 29));
+        assert_factor(parts[0].2, 15.0 / 29.0);
+
+        let move_in = date(2023, 2, 15);
+        let parts = split(ProrationMethod::ActualDays, move_in, &leases, move_in, None);
+        assert_eq!((parts[0].0, parts[0].1), (14, 28));
+
+        // The end of February counts as the 30th
+        let parts = split(
+            ProrationMethod::ThirtyDayMonth,
+            move_in,
+            &leases,
+            move_in,
+            None,
+        );
+        assert_eq!((parts[0].0, parts[0].1), (16, 30));
+        let parts = split(
+            ProrationMethod::ThirtyDayMonth,
+            move_in,
+            &leases,
+            date(2022, 1, 1),
+            Some(date(2023, 2, 14)),
+        );
+        assert_eq!((parts[0].0, parts[0].1), (14, 30));
+
+        let parts = split(ProrationMethod::DaysInYear, move_in, &leases, move_in, None);
+        assert_eq!((parts[0].0, parts[0].1), (14, 365));
+        assert_factor(parts[0].2, 14.0 * 12.0 / 365.0);
+    }
+
+    #[test]
+    fn lease_change_mid_month_bills_each_lease_once() {
+        let first = lease(date(2023, 3, 16), date(2024, 3, 15));
+        let renewal = lease(date(2024, 3, 16), date(2025, 3, 15));
+        for method in [
+            ProrationMethod::ActualDays,
+            ProrationMethod::ThirtyDayMonth,
+            ProrationMethod::DaysInYear,
+        ] {
+            let occupancy = method.occupancy(
+                &BillingPeriod::month_of(date(2024, 3, 1)),
+                &[renewal.clone(), first.clone()],
+                date(2023, 3, 16),
+                None,
+            );
+            assert_eq!(occupancy.len(), 2);
+            assert_eq!(occupancy[0].lease.start_date, first.start_date);
+            assert_eq!(
+                (occupancy[0].from, occupancy[0].to),
+                (date(2024, 3, 1), date(2024, 3, 15))
+            );
+            assert_eq!(
+                (occupancy[1].from, occupancy[1].to),
+                (date(2024, 3, 16), date(2024, 3, 31))
+            );
+            let days: u32 = occupancy.iter().map(Occupancy::days_occupied).sum();
+            assert_eq!(days, 31);
+        }
+        let parts = split(
+            ProrationMethod::ThirtyDayMonth,
+            date(2024, 3, 1),
+            &[first.clone(), renewal.clone()],
+            date(2023, 3, 16),
+            None,
+        );
+        assert_eq!(parts.iter().map(|part| part.0).sum::<u32>(), 30);
+        assert_factor(parts.iter().map(|part| part.2).sum(), 1.0);
+    }
+
+    #[test]
+    fn lease_change_on_the_first_is_not_prorated() {
+        // The old lease runs past the day the new one starts
+        let first = lease(date(2023, 4, 1), date(2024, 4, 30));
+        let renewal = lease(date(2024, 4, 1), date(2025, 3, 31));
+        let leases = [first, renewal.clone()];
+        for month in [date(2024, 3, 1), date(2024, 4, 1)] {
+            let occupancy = ProrationMethod::ActualDays.occupancy(
+                &BillingPeriod::month_of(month),
+                &leases,
+                date(2023, 4, 1),
+                None,
+            );
+            assert_eq!(occupancy.len(), 1);
+            assert!(!occupancy[0].is_prorated());
+        }
+        let april = ProrationMethod::ThirtyDayMonth.occupancy(
+            &BillingPeriod::month_of(date(2024, 4, 1)),
+            &leases,
+            date(2023, 4, 1),
+            None,
+        );
+        assert_eq!(april[0].lease.start_date, renewal.start_date);
+    }
+
+    #[test]
+    fn lease_change_on_the_31st() {
+        let first = lease(date(2023, 4, 1), date(2024, 3, 30));
+        let renewal = lease(date(2024, 3, 31), date(2025, 3, 30));
+        let parts = split(
+            ProrationMethod::ThirtyDayMonth,
+            date(2024, 3, 1),
+            &[first, renewal],
+            date(2023, 4, 1),
+            None,
+        );
+        assert_eq!(parts, vec![(30, 30, 1.0), (0, 30, 0.0)]);
+    }
+
+    #[test]
+    fn no_occupancy_outside_the_tenancy() {
+        let leases = [year_lease()];
+        let march = date(2024, 3, 1);
+        for method in [
+            ProrationMethod::ActualDays,
+            ProrationMethod::ThirtyDayMonth,
+            ProrationMethod::DaysInYear,
+        ] {
+            // Moved out before the period starts
+            assert!(split(
+                method,
+                march,
+                &leases,
+                date(2023, 6, 1),
+                Some(date(2024, 2, 20))
+            )
+            .is_empty());
+            // Moving in after it ends
+            assert!(split(method, march, &leases, date(2024, 4, 2), None).is_empty());
+            // Moved out before they moved in, e.g. a cancelled move-in
+            assert!(split(
+                method,
+                march,
+                &leases,
+                date(2024, 3, 10),
+                Some(date(2024, 3, 5))
+            )
+            .is_empty());
+            // Between leases
+            let gap = [
+                lease(date(2023, 1, 1), date(2024, 2, 29)),
+                lease(date(2024, 4, 1), date(2025, 3, 31)),
+            ];
+            assert!(split(method, march, &gap, date(2023, 1, 1), None).is_empty());
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{get_statement, get_statements},
+    lease::{ChargeCategory, StatementLine},
+    statements::{StatementFilter, StatementKind, StatementStatus},
+};
+
+/// A sales or rental tax, set up under `[[tax.jurisdictions]]` in the settings file and
+/// chosen by name on each property. Charges are taxed by the name they have on statements,
+/// e.g. "Rent" or "Electricity". Recurring charges are taxed when the lease marks them
+/// taxable instead.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaxJurisdiction {
+    pub name: String,
+    pub rate: f32,
+    pub taxable: Vec<String>,
+}
+
+impl TaxJurisdiction {
+    pub fn taxes(&self, line: &StatementLine) -> bool {
+        match line.category {
+            ChargeCategory::Recurring => line.taxable,
+            ChargeCategory::SalesTax | ChargeCategory::Proration => false,
+            category => self
+                .taxable
+                .iter()
+                .any(|name| name.trim().eq_ignore_ascii_case(&category.to_string())),
+        }
+    }
+}
+
+/// The sales tax on the statements issued over a period under one jurisdiction.
+#[derive(Debug, Clone)]
+pub struct TaxCollected {
+    pub jurisdiction: String,
+    pub statements: usize,
+    pub taxable_sales: f32,
+    pub billed: f32,
+    // The tax on paid statements, less the credit memos against them
+    pub collected: f32,
+}
+
+/// Totals the tax on the statements and credit memos issued from `from` to `to` by
+/// jurisdiction, for remitting it. Voided statements are left out. A credit memo counts
+/// against what was collected once the statement it credits is paid, or straight away
+/// for a year end credit that isn't against one statement.
+pub async fn tax_report(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Vec<TaxCollected> {
+    let filter = StatementFilter {
+        issued_from: from,
+        issued_to: to,
+        ..StatementFilter::default()
+    };
+    let mut report: Vec<TaxCollected> = vec![];
+    for record in get_statements(pool, &filter).await {
+        let Some(jurisdiction) = &record.tax_jurisdiction else {
+            continue;
+        };
+        if matches!(
+            record.status,
+            StatementStatus::Draft | StatementStatus::Void
+        ) {
+            continue;
+        }
+        let index = match report
+            .iter()
+            .position(|totals| &totals.jurisdiction == jurisdiction)
+        {
+            Some(index) => index,
+            None => {
+                report.push(TaxCollected {
+                    jurisdiction: jurisdiction.clone(),
+                    statements: 0,
+                    taxable_sales: 0.0,
+                    billed: 0.0,
+                    collected: 0.0,
+                });
+                report.len() - 1
+            }
+        };
+        let totals = &mut report[index];
+        totals.statements += 1;
+        totals.taxable_sales += record.taxable_sales;
+        totals.billed += record.sales_tax;
+        let paid = match (record.kind, record.related_statement_id) {
+            (StatementKind::Statement, _) => record.status == StatementStatus::Paid,
+            (StatementKind::CreditMemo, Some(credited)) => get_statement(pool, credited)
+                .await
+                .is_some_and(|credited| credited.status == StatementStatus::Paid),
+            (StatementKind::CreditMemo, None) => true,
+        };
+        if paid {
+            totals.collected += record.sales_tax;
+        }
+    }
+    report.sort_by(|a, b| a.jurisdiction.cmp(&b.jurisdiction));
+    report
+}
+
+/// The report as text, one jurisdiction per line.
+pub fn describe_report(
+    report: &[TaxCollected],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> String {
+    let date = |date: Option<NaiveDate>, open: &str| {
+        date.map_or(open.to_owned(), |date| date.format("%m/%d/%Y").to_string())
+    };
+    let mut text = format!(
+        "Sales tax on statements issued from {} to {}",
+        date(from, "the first statement"),
+        date(to, "today")
+    );
+    if report.is_empty() {
+        text.push_str("\nNo sales tax was billed");
+    }
+    for totals in report {
+        text.push_str(&format!(
+            "\n{}: {} statements, {} taxable, {} tax billed, {} collected",
+            totals.jurisdiction,
+            totals.statements,
+            money(totals.taxable_sales),
+            money(totals.billed),
+            money(totals.collected)
+        ));
+    }
+    text
+}
+
+fn money(amount: f32) -> String {
+    match amount < 0.0 {
+        true => format!("-${:.2}", -amount),
+        false => format!("${amount:.2}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::mark_statement_paid,
+        statements::{
+            credit, issue_statement,
+            tests::{billed_on, statement},
+            void,
+        },
+    };
+
+    #[async_std::test]
+    async fn collected_tax_follows_payments_voids_and_credits() {
+        let (statement, mut property) = statement();
+        property.tax_jurisdiction = Some(String::from("Tacoma"));
+        let (pool, mut settings, statement, property, company) =
+            billed_on("tax_report", (statement, property)).await;
+        settings.tax.jurisdictions.push(TaxJurisdiction {
+            name: String::from("Tacoma"),
+            rate: 0.1,
+            taxable: vec![String::from("Rent")],
+        });
+        let issue = || issue_statement(&pool, &settings, &statement, &property, &company);
+        let paid = issue().await.unwrap().record;
+        let voided = issue().await.unwrap().record;
+        let credited = issue().await.unwrap().record;
+        assert_eq!(paid.sales_tax, 120.0);
+
+        mark_statement_paid(&pool, &paid).await.unwrap();
+        void(&pool, voided.id, "Issued twice").await.unwrap();
+        let memo = credit(
+            &pool,
+            &settings,
+            credited.id,
+            credited.amount_due / 2.0,
+            "Half the month",
+        )
+        .await
+        .unwrap()
+        .record;
+        assert_eq!(memo.sales_tax, -60.0);
+
+        // The credit memo only counts against what was collected once its statement is paid
+        let report = tax_report(&pool, None, None).await;
+        assert_eq!(report.len(), 1);
+        let totals = &report[0];
+        assert_eq!(totals.jurisdiction, "Tacoma");
+        assert_eq!(totals.statements, 3);
+        assert_eq!(totals.taxable_sales, 1200.0 + 1200.0 - 600.0);
+        assert_eq!(totals.billed, 120.0 + 120.0 - 60.0);
+        assert_eq!(totals.collected, 120.0);
+
+        mark_statement_paid(&pool, &credited).await.unwrap();
+        let report = tax_report(&pool, None, None).await;
+        assert_eq!(report[0].collected, 120.0 + 120.0 - 60.0);
+    }
+}
@@ -18,7 +18,7 @@ pub async fn initialize_slint_expenses(ui: &App, pool: &sqlx::Pool<Sqlite>, max_
 }
 
 pub async fn initialize_slint_properties(ui: &App, pool: &sqlx::Pool<Sqlite>, max_ids: &ValidIds) {
-    let expenses: Vec<PropertyInput> = crate::database::get_properties(pool)
+    let expenses: Vec<PropertyInput> = crate::database::get_properties(pool, true)
         .await
         .iter()
         .map(Property::convert_to_slint)
@@ -34,7 +34,7 @@ pub async fn initialize_slint_leaseholders(
     pool: &sqlx::Pool<Sqlite>,
     max_ids: &ValidIds,
 ) {
-    let leaseholders: Vec<crate::LeaseholderInput> = crate::database::get_leaseholders(pool)
+    let leaseholders: Vec<crate::LeaseholderInput> = crate::database::get_leaseholders(pool, true)
         .await
         .iter()
         .map(Leaseholder::convert_to_slint)
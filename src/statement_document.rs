@@ -0,0 +1,601 @@
+use std::path::PathBuf;
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+
+use crate::{
+    app_settings::{InvoiceSettings, PathSettings},
+    late_fees::LateFee,
+    lease::{total_due, ChargeCategory, StatementLine},
+    leaseholders::Leaseholder,
+    pdf_formatting::get_word_date,
+    percentage_rent::statement_charge,
+    properties::{Address, Property},
+    property_bills::{amortized_total, BillKind},
+    sales_tax::TaxJurisdiction,
+    statement_template::StatementTemplate,
+    statements::{Statement, StatementRecord},
+};
+
+/// Everything printed on a statement, worked out once by the billing logic. The PDF, HTML
+/// and text renderers only lay this out and never calculate amounts themselves.
+#[derive(Debug, Clone)]
+pub struct StatementDocument {
+    pub kind: DocumentKind,
+    pub company: String,
+    pub recipient: Recipient,
+    pub property: String,
+    // Given when the statement is issued
+    pub invoice_number: Option<String>,
+    pub date: NaiveDate,
+    pub period: BillingPeriod,
+    pub due_date: NaiveDate,
+    // The last day payment is on time, when the lease has late fees
+    pub late_after: Option<NaiveDate>,
+    // Printed under the heading, e.g. which invoice a correction replaces
+    pub note: Option<String>,
+    pub lines: Vec<StatementLine>,
+    // How the lease's CAM clauses changed the charges, one step per entry
+    pub adjustments: Vec<String>,
+    pub balances: Balances,
+    // For a credit memo, the amount credited
+    pub total_due: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentKind {
+    Statement,
+    CreditMemo,
+}
+
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub name: String,
+    pub email: String,
+    pub address: Address,
+}
+
+impl Recipient {
+    pub fn for_leaseholder(leaseholder: &Leaseholder) -> Recipient {
+        let contact_info = &leaseholder.contact_info;
+        Recipient {
+            name: contact_info.name.clone(),
+            email: contact_info.email.clone(),
+            address: contact_info.remittence_address.clone(),
+        }
+    }
+
+    pub fn address_line(&self) -> String {
+        format!(
+            "{} {}, {} {}",
+            self.address.street_address,
+            self.address.city,
+            self.address.state,
+            self.address.zip_code
+        )
+    }
+
+    pub fn city_line(&self) -> String {
+        format!(
+            "{}, {} {}",
+            self.address.city, self.address.state, self.address.zip_code
+        )
+    }
+}
+
+/// The calendar month a statement bills for.
+#[derive(Debug, Clone, Copy)]
+pub struct BillingPeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl BillingPeriod {
+    pub fn month_of(date: NaiveDate) -> BillingPeriod {
+        let start = date.with_day(1).unwrap();
+        let end = start + Months::new(1) - Days::new(1);
+        BillingPeriod { start, end }
+    }
+
+    pub fn range(&self) -> String {
+        format!(
+            "{} to {}",
+            self.start.format("%m/%d/%Y"),
+            self.end.format("%m/%d/%Y")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Balances {
+    pub forward: f32,
+    pub payments: f32,
+    pub outstanding: f32,
+}
+
+impl StatementDocument {
+    pub fn new(
+        statement: &Statement,
+        property: &Property,
+        company: &str,
+        invoice: &InvoiceSettings,
+    ) -> StatementDocument {
+        let period = BillingPeriod::month_of(statement.date);
+        let due_date = period.end + Days::new(invoice.due_days);
+        // Billed from the stored amounts until the property's bills are entered
+        let property_tax = amortized_total(&statement.bills, BillKind::PropertyTax, &period)
+            .unwrap_or(property.property_tax);
+        let insurance = amortized_total(&statement.bills, BillKind::Insurance, &period)
+            .unwrap_or(property.business_insurance);
+        let leaseholder = &statement.leaseholder;
+        let utility_billing = &property.utility_billing;
+        let mut lines = vec![];
+        let mut adjustments = vec![];
+        // Days the leases pass utilities through, for a ratio share of the utility bills
+        let mut utility_days = 0;
+        for occupancy in invoice.proration.occupancy(
+            &period,
+            &statement.leases,
+            leaseholder.move_in_date,
+            leaseholder.move_out_date,
+        ) {
+            let mut charges = occupancy.lease.fee_structure.statement_lines(
+                statement.fees.clone(),
+                property_tax,
+                insurance,
+            );
+            if occupancy.lease.fee_structure.bills_utilities() {
+                utility_days += occupancy.days_occupied();
+            }
+            // Billed below by meter or ratio, for the whole period at once
+            charges.retain(|line| !utility_billing.allocates(line.category));
+            if let Some(clauses) = &occupancy.lease.cam {
+                adjustments.extend(clauses.adjust_lines(
+                    &statement.cam,
+                    period.start,
+                    period.end,
+                    occupancy.lease.start_date.year(),
+                    &mut charges,
+                ));
+            }
+            if occupancy.is_prorated() {
+                lines.push(StatementLine::proration(&occupancy));
+            }
+            lines.extend(
+                charges
+                    .into_iter()
+                    .map(|line| line.prorate(occupancy.factor)),
+            );
+            lines.extend(
+                occupancy
+                    .lease
+                    .recurring_charges
+                    .iter()
+                    .filter_map(|charge| charge.statement_line(&period, &occupancy)),
+            );
+        }
+        if utility_days > 0 {
+            let (utility_lines, notes) = utility_billing.statement_lines(
+                leaseholder,
+                &statement.utilities,
+                &statement.fees,
+                &period,
+                utility_days,
+            );
+            lines.extend(utility_lines);
+            adjustments.extend(notes);
+        }
+        // Sales reported for a period that just ended are billed once, not prorated
+        if let Some((line, explanation)) =
+            statement_charge(&statement.leases, &statement.sales, &period)
+        {
+            lines.extend(line);
+            adjustments.push(explanation);
+        }
+        StatementDocument {
+            kind: DocumentKind::Statement,
+            company: company.to_owned(),
+            recipient: Recipient::for_leaseholder(&statement.leaseholder),
+            property: property.name.clone(),
+            invoice_number: None,
+            date: statement.date,
+            period,
+            due_date,
+            late_after: statement
+                .leaseholder
+                .lease
+                .late_fee
+                .map(|rule| rule.late_after(due_date)),
+            note: None,
+            total_due: total_due(&lines),
+            lines,
+            adjustments,
+            balances: Balances::default(),
+        }
+    }
+
+    /// A credit memo taking `amount` off an issued statement. It has no balances or due
+    /// date, only the credit and the reason for it.
+    pub fn credit_memo(
+        original: &StatementRecord,
+        recipient: Recipient,
+        company: String,
+        amount: f32,
+        reason: &str,
+    ) -> StatementDocument {
+        let date = Local::now().date_naive();
+        let lines = vec![StatementLine::flat(ChargeCategory::Credit, amount)];
+        StatementDocument {
+            kind: DocumentKind::CreditMemo,
+            company,
+            recipient,
+            property: original.property_name.clone(),
+            invoice_number: None,
+            date,
+            period: original
+                .period
+                .unwrap_or_else(|| BillingPeriod::month_of(date)),
+            due_date: date,
+            late_after: None,
+            note: Some(format!(
+                "Credit against invoice {}: {}",
+                original.invoice_number.as_deref().unwrap_or("(unnumbered)"),
+                reason
+            )),
+            total_due: total_due(&lines),
+            lines,
+            adjustments: vec![],
+            balances: Balances::default(),
+        }
+    }
+
+    /// A year end true-up, `difference` being what the lease allows for the year less what
+    /// was billed. Owing more gives a statement, and having paid too much a credit memo.
+    #[allow(clippy::too_many_arguments)]
+    pub fn year_end(
+        leaseholder: &Leaseholder,
+        property: &Property,
+        company: String,
+        year: BillingPeriod,
+        category: ChargeCategory,
+        difference: f32,
+        adjustments: Vec<String>,
+        invoice: &InvoiceSettings,
+    ) -> StatementDocument {
+        let date = Local::now().date_naive();
+        let due_date = date + Days::new(invoice.due_days);
+        let (kind, late_after) = if difference > 0.0 {
+            (
+                DocumentKind::Statement,
+                leaseholder
+                    .lease
+                    .late_fee
+                    .map(|rule| rule.late_after(due_date)),
+            )
+        } else {
+            (DocumentKind::CreditMemo, None)
+        };
+        let mut line = StatementLine::flat(category, difference.abs());
+        line.detail = Some(year.start.year().to_string());
+        let lines = vec![line];
+        StatementDocument {
+            kind,
+            company,
+            recipient: Recipient::for_leaseholder(leaseholder),
+            property: property.name.clone(),
+            invoice_number: None,
+            date,
+            period: year,
+            due_date,
+            late_after,
+            note: Some(format!("{} for {}", category, year.start.year())),
+            total_due: total_due(&lines),
+            lines,
+            adjustments,
+            balances: Balances::default(),
+        }
+    }
+
+    pub fn is_credit_memo(&self) -> bool {
+        self.kind == DocumentKind::CreditMemo
+    }
+
+    pub fn title<'a>(&self, template: &'a StatementTemplate) -> &'a str {
+        match self.kind {
+            DocumentKind::Statement => &template.title,
+            DocumentKind::CreditMemo => &template.credit_memo_title,
+        }
+    }
+
+    /// Adds a line for the tax on the taxable lines, returning the taxable amount and the
+    /// tax, or None when nothing on the document is taxed.
+    pub fn add_sales_tax(&mut self, jurisdiction: &TaxJurisdiction) -> Option<(f32, f32)> {
+        let taxable = self
+            .lines
+            .iter()
+            .filter(|line| jurisdiction.taxes(line))
+            .fold(0.0, |sum, line| sum + line.amount);
+        if taxable.abs() < 0.005 {
+            return None;
+        }
+        let mut line = StatementLine::share(ChargeCategory::SalesTax, jurisdiction.rate, taxable);
+        line.amount = (line.amount * 100.0).round() / 100.0;
+        self.adjustments.push(format!(
+            "{}: {}% on ${:.2} of taxable charges = ${:.2}",
+            jurisdiction.name,
+            jurisdiction.rate * 100.0,
+            taxable,
+            line.amount
+        ));
+        let tax = line.amount;
+        self.lines.push(line);
+        self.total_due = total_due(&self.lines);
+        Some((taxable, tax))
+    }
+
+    /// Adds a line for the late fees on each overdue invoice.
+    pub fn add_late_fees(&mut self, fees: &[LateFee]) {
+        let mut invoices: Vec<(&str, f32)> = vec![];
+        for fee in fees {
+            match invoices
+                .iter_mut()
+                .find(|(invoice, _)| *invoice == fee.invoice_number)
+            {
+                Some((_, amount)) => *amount += fee.amount,
+                None => invoices.push((&fee.invoice_number, fee.amount)),
+            }
+        }
+        for (invoice, amount) in invoices {
+            let mut line = StatementLine::flat(ChargeCategory::LateFee, amount);
+            line.detail = Some(invoice.to_owned());
+            self.lines.push(line);
+        }
+        self.total_due = total_due(&self.lines);
+    }
+
+    /// Nothing is due on a credit memo, so it has no payment terms.
+    pub fn payment_terms(&self, template: &StatementTemplate) -> String {
+        match self.kind {
+            DocumentKind::Statement => template
+                .payment_terms
+                .replace("{due_date}", &self.due_date.format("%m/%d/%Y").to_string()),
+            DocumentKind::CreditMemo => String::new(),
+        }
+    }
+
+    pub fn late_fee_terms(&self, template: &StatementTemplate) -> String {
+        self.late_after
+            .map(|date| {
+                template
+                    .late_fee_terms
+                    .replace("{late_date}", &date.format("%m/%d/%Y").to_string())
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn total_label(&self) -> &'static str {
+        match self.kind {
+            DocumentKind::Statement => "Total Due",
+            DocumentKind::CreditMemo => "Total Credit",
+        }
+    }
+
+    /// Where the statement is saved in the statements directory, for each output format.
+    /// The invoice number keeps corrections from overwriting the statement they replace.
+    pub fn file_path(&self, settings: &PathSettings, extension: &str) -> PathBuf {
+        let kind = match self.kind {
+            DocumentKind::Statement => "Statement",
+            DocumentKind::CreditMemo => "Credit_Memo",
+        };
+        let number = self
+            .invoice_number
+            .as_deref()
+            .map(|number| {
+                let number: String = number
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '-' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                format!("_{number}")
+            })
+            .unwrap_or_default();
+        PathBuf::from(format!(
+            "{}{}_{}_{}{}.{}",
+            settings.statements_path,
+            get_word_date(self.date),
+            kind,
+            self.recipient.name,
+            number,
+            extension
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expenses::{Expense, ExpenseType, MaintenanceType, UtilitiesType},
+        lease::{CAMRates, FeeStructure, InsuranceRate, Lease, PropertyTaxRate, Rent},
+        leaseholders::ContactInformation,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn address() -> Address {
+        Address::new(
+            "3322 S 55th Street".to_string(),
+            "Seattle".to_string(),
+            "WA".to_string(),
+            "98118".to_string(),
+        )
+    }
+
+    fn cents(amount: f32) -> i64 {
+        (amount * 100.0).round() as i64
+    }
+
+    fn assert_whole_cents(line: &StatementLine) {
+        assert!(
+            (line.amount * 100.0 - (line.amount * 100.0).round()).abs() < 0.001,
+            "{} is not rounded to the cent: {}",
+            line.label(),
+            line.amount
+        );
+    }
+
+    /// A triple net tenant moving in mid-month, so every line is shared and prorated.
+    fn statement() -> (Statement, Property) {
+        let property = Property::new(1, "Cedar Court".to_string(), address(), 1234.57, 987.65, 3);
+        let lease = Lease::new(
+            date(2024, 3, 12),
+            date(2025, 3, 11),
+            FeeStructure::TripleNet(
+                Rent { base_rent: 1733.33 },
+                PropertyTaxRate { property_tax: 0.17 },
+                InsuranceRate {
+                    building_insurance: 0.13,
+                },
+                CAMRates::default(),
+            ),
+            "Check".to_string(),
+        );
+        let contact = ContactInformation::new(
+            "Rosa Delgado".to_string(),
+            address(),
+            "rosa@example.com".to_string(),
+            "2065550123".to_string(),
+        );
+        let tenant = Leaseholder::new(1, lease, property.id, contact, date(2024, 3, 12));
+        let expense = |expense_type: ExpenseType, amount: f32| {
+            Expense::new(0, 1, expense_type, amount, date(2024, 3, 5), String::new())
+        };
+        let fees = vec![
+            expense(ExpenseType::Utilities(UtilitiesType::Electricity), 411.37),
+            expense(ExpenseType::Utilities(UtilitiesType::Water), 123.45),
+            expense(ExpenseType::Utilities(UtilitiesType::Water), 67.89),
+            expense(ExpenseType::Utilities(UtilitiesType::Gas), 99.99),
+            expense(
+                ExpenseType::Maintenance(MaintenanceType::Landscaping),
+                250.01,
+            ),
+            expense(ExpenseType::Other, 17.77),
+        ];
+        (Statement::new(date(2024, 3, 1), tenant, fees), property)
+    }
+
+    #[test]
+    fn lines_are_rounded_to_the_cent() {
+        let share = StatementLine::share(ChargeCategory::PropertyTax, 0.2, 1234.57);
+        assert_eq!(cents(share.amount), 24691);
+        assert_whole_cents(&share);
+
+        let electricity = Expense::new(
+            0,
+            1,
+            ExpenseType::Utilities(UtilitiesType::Electricity),
+            100.10,
+            date(2024, 3, 5),
+            String::new(),
+        );
+        let mut water = electricity.clone();
+        water.amount = 33.33;
+        let shared = StatementLine::expense_share(
+            ChargeCategory::Electricity,
+            Some(0.3),
+            vec![electricity, water],
+        );
+        // 30% of 133.43 is 40.029
+        assert_eq!(cents(shared.amount), 4003);
+        assert_whole_cents(&shared);
+
+        let mut recovered = StatementLine::share(ChargeCategory::Landscaping, 0.3, 200.0);
+        recovered.recover(95.5);
+        assert_eq!(cents(recovered.amount), 2865);
+
+        // 20 of 31 days of 1700.00 is 1096.774...
+        let prorated = StatementLine::flat(ChargeCategory::Rent, 1700.0).prorate(20.0 / 31.0);
+        assert_eq!(cents(prorated.amount), 109677);
+        assert_whole_cents(&prorated);
+    }
+
+    #[test]
+    fn total_due_is_rounded_to_the_cent() {
+        let lines: Vec<StatementLine> = (0..10)
+            .map(|_| StatementLine::flat(ChargeCategory::Miscellaneous, 0.1))
+            .collect();
+        assert_eq!(format!("{:.2}", total_due(&lines)), "1.00");
+        assert_eq!(total_due(&[]), 0.0);
+    }
+
+    #[test]
+    fn total_due_adds_up_the_printed_lines() {
+        let (statement, property) = statement();
+        let mut document = StatementDocument::new(
+            &statement,
+            &property,
+            "Hestia Property Management",
+            &InvoiceSettings::default(),
+        );
+        document.add_sales_tax(&TaxJurisdiction {
+            name: "Seattle".to_string(),
+            rate: 0.1035,
+            taxable: vec!["Rent".to_string(), "Electricity".to_string()],
+        });
+        document.add_late_fees(&[
+            LateFee {
+                id: 1,
+                statement_id: 1,
+                invoice_number: "INV-2024-0001".to_string(),
+                leaseholder_id: 1,
+                assessed_on: date(2024, 3, 8),
+                days_late: 3,
+                amount: 33.37,
+            },
+            LateFee {
+                id: 2,
+                statement_id: 1,
+                invoice_number: "INV-2024-0001".to_string(),
+                leaseholder_id: 1,
+                assessed_on: date(2024, 3, 9),
+                days_late: 4,
+                amount: 5.01,
+            },
+        ]);
+
+        let categories: Vec<ChargeCategory> =
+            document.lines.iter().map(|line| line.category).collect();
+        for category in [
+            ChargeCategory::Proration,
+            ChargeCategory::Rent,
+            ChargeCategory::Electricity,
+            ChargeCategory::SalesTax,
+            ChargeCategory::LateFee,
+        ] {
+            assert!(categories.contains(&category), "no {category} line");
+        }
+        document.lines.iter().for_each(assert_whole_cents);
+
+        // What a reader adding up the amount column gets
+        let printed: i64 = document
+            .lines
+            .iter()
+            .map(StatementLine::amount_text)
+            .filter(|text| !text.is_empty())
+            .map(|text| {
+                let (dollars, cents) = text.trim_start_matches('$').split_once('.').unwrap();
+                dollars.parse::<i64>().unwrap() * 100 + cents.parse::<i64>().unwrap()
+            })
+            .sum();
+        assert_eq!(
+            format!("{:.2}", document.total_due),
+            format!("{}.{:02}", printed / 100, printed % 100)
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_settings::PathSettings;
+
+pub const DEFAULT_TEMPLATE: &str = "default";
+const TEMPLATE_EXTENSION: &str = "toml";
+
+/// Layout and branding for statement PDFs, read from `<name>.toml` in the templates
+/// directory. Every field falls back to the built in layout, so a template only needs the
+/// values it changes. Font and logo paths are relative to the templates directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatementTemplate {
+    pub title: String,
+    pub credit_memo_title: String,
+    pub page: PageLayout,
+    pub fonts: FontSettings,
+    pub logo: LogoSettings,
+    pub columns: ColumnLayout,
+    // Blocks printed from the top of the page down, in this order. The total always comes
+    // last, on the same page as the remittance block.
+    pub sections: Vec<Section>,
+    pub charge_heading: String,
+    pub amount_heading: String,
+    // Over the list of how the lease's CAM clauses changed the charges
+    pub adjustments_heading: String,
+    // {due_date} is filled in with the statement's due date
+    pub payment_terms: String,
+    // Added when the lease has late fees. {late_date} is the last day payment is on time
+    pub late_fee_terms: String,
+    pub footer: String,
+    pub remit_heading: String,
+    // Adds pages after the statement listing the building expenses behind each charge
+    pub expense_appendix: bool,
+    pub appendix_heading: String,
+}
+
+impl Default for StatementTemplate {
+    fn default() -> Self {
+        StatementTemplate {
+            title: String::from("Monthly Statement"),
+            credit_memo_title: String::from("Credit Memo"),
+            page: PageLayout::default(),
+            fonts: FontSettings::default(),
+            logo: LogoSettings::default(),
+            columns: ColumnLayout::default(),
+            sections: vec![
+                Section::Company,
+                Section::Recipient,
+                Section::Balances,
+                Section::Charges,
+                Section::Total,
+            ],
+            charge_heading: String::from("Charge"),
+            amount_heading: String::from("Amount"),
+            adjustments_heading: String::from("Lease Adjustments"),
+            payment_terms: String::from("Payment due by {due_date}"),
+            late_fee_terms: String::from("Late fees apply after {late_date}"),
+            footer: String::from("Thank You"),
+            remit_heading: String::from("Please Remit To:"),
+            expense_appendix: false,
+            appendix_heading: String::from("Expense Detail"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    Company,
+    Recipient,
+    Balances,
+    Charges,
+    Total,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageLayout {
+    pub width_mm: f32,
+    pub height_mm: f32,
+    // Where the first section starts and the remittance block ends
+    pub top_mm: f32,
+    pub bottom_mm: f32,
+    pub line_spacing_mm: f32,
+    pub page_number_mm: f32,
+}
+
+impl Default for PageLayout {
+    fn default() -> Self {
+        PageLayout {
+            width_mm: 210.0,
+            height_mm: 297.0,
+            top_mm: 270.0,
+            bottom_mm: 15.0,
+            line_spacing_mm: 10.0,
+            page_number_mm: 7.0,
+        }
+    }
+}
+
+/// TrueType fonts to embed. Left empty, the built in Helvetica is used, and the bold font
+/// falls back to the regular one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontSettings {
+    pub regular: String,
+    pub bold: String,
+    pub header_size: f32,
+    pub body_size: f32,
+    pub details_size: f32,
+}
+
+impl Default for FontSettings {
+    fn default() -> Self {
+        FontSettings {
+            regular: String::new(),
+            bold: String::new(),
+            header_size: 16.0,
+            body_size: 13.0,
+            details_size: 12.0,
+        }
+    }
+}
+
+/// An image drawn in the top right corner. No logo is drawn when the path is empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogoSettings {
+    pub path: String,
+    pub width_mm: f32,
+    pub right_mm: f32,
+    pub top_mm: f32,
+}
+
+impl Default for LogoSettings {
+    fn default() -> Self {
+        LogoSettings {
+            path: String::new(),
+            width_mm: 40.0,
+            right_mm: 20.0,
+            top_mm: 15.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnLayout {
+    pub left_mm: f32,
+    pub right_mm: f32,
+    // Inset of the charges table from each side of the page
+    pub table_margin_mm: f32,
+}
+
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        ColumnLayout {
+            left_mm: 20.0,
+            right_mm: 115.0,
+            table_margin_mm: 25.0,
+        }
+    }
+}
+
+impl StatementTemplate {
+    /// Resolves a font or logo path from the template against the templates directory.
+    pub fn resource_path(settings: &PathSettings, path: &str) -> Option<PathBuf> {
+        match path.trim() {
+            "" => None,
+            path => Some(Path::new(&settings.templates_path).join(path)),
+        }
+    }
+}
+
+fn template_file(settings: &PathSettings, name: &str) -> PathBuf {
+    Path::new(&settings.templates_path).join(format!("{name}.{TEMPLATE_EXTENSION}"))
+}
+
+/// Loads the named template, falling back to the default template and then to the built in
+/// layout if it is missing or invalid.
+pub fn load_template(settings: &PathSettings, name: Option<&str>) -> StatementTemplate {
+    let name = name.unwrap_or(DEFAULT_TEMPLATE);
+    let path = template_file(settings, name);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => match toml::from_str(&contents) {
+            Ok(template) => return template,
+            Err(e) => println!(
+                "Failed to parse statement template {}: {}",
+                path.display(),
+                e
+            ),
+        },
+        Err(e) => println!(
+            "Failed to read statement template {}: {}",
+            path.display(),
+            e
+        ),
+    }
+    if name == DEFAULT_TEMPLATE {
+        StatementTemplate::default()
+    } else {
+        load_template(settings, None)
+    }
+}
+
+/// Writes out the built in layout as the default template the first time, so there is a
+/// starting point to copy and edit.
+pub fn initialize_default_template(settings: &PathSettings) {
+    let path = template_file(settings, DEFAULT_TEMPLATE);
+    if path.exists() {
+        return;
+    }
+    let result = toml::to_string_pretty(&StatementTemplate::default())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        .and_then(|contents| std::fs::write(&path, contents));
+    match result {
+        Ok(_) => println!("Created default statement template"),
+        Err(e) => println!("Failed to create default statement template: {}", e),
+    }
+}
@@ -1,60 +1,98 @@
 use crate::{
-    app_settings::PathSettings,
-    database::add_statement,
+    app_settings::{AppSettings, InvoiceSettings, PathSettings},
+    cam::CamHistory,
+    database::{
+        add_statement, get_cam_billed, get_credited_amount, get_leaseholders, get_leases,
+        get_ledger, get_percentage_rent_billed, get_period_property_expenses, get_properties,
+        get_property_bills, get_property_expenses, get_sales_reports, get_statement,
+        get_statements, get_unbilled_late_fees, mark_statement_issued, mark_statement_paid,
+        void_statement,
+    },
+    email::queue_statement_email,
     expenses::*,
-    lease::FeeStructure,
+    html_formatting::write_html,
+    late_fees::{assess_late_fees, LateFee},
+    lease::{total_due, ChargeCategory, Lease, StatementLine},
     leaseholders::{Company, Leaseholder},
+    ledger::{self, LedgerEntry, LedgerKind},
     pdf_formatting::write_with_printpdf,
+    percentage_rent::{sales_in, SalesReport},
     properties::Property,
+    property_bills::PropertyBill,
+    proration::ProrationMethod,
+    sales_tax::{describe_report, tax_report, TaxJurisdiction},
+    statement_document::{BillingPeriod, DocumentKind, Recipient, StatementDocument},
+    statement_template::{load_template, StatementTemplate},
+    utility_billing::UtilityUsage,
+    App, LedgerEntryInput, StatementFilterInput, StatementInput, StatementRegistry,
+};
+use chrono::{Datelike, Local, NaiveDate};
+use sha2::{Digest, Sha256};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
 };
-use chrono::NaiveDate;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 #[derive(Debug, Clone)]
 pub struct Statement {
     pub date: NaiveDate,
     pub leaseholder: Leaseholder,
-    pub rates: FeeStructure,
     pub fees: Vec<Expense>,
-    pub total: f32,
+    // Every lease the leaseholder has had, so a mid-month change bills each for its days
+    pub leases: Vec<Lease>,
+    // The property's tax bills and insurance premiums, amortized over the period
+    pub bills: Vec<PropertyBill>,
+    // The building's past expenses and occupancy, for leases with CAM clauses
+    pub cam: CamHistory,
+    // The tenant's reported sales, for leases with percentage rent
+    pub sales: Vec<SalesReport>,
+    // The property's meter readings and tenants, for utilities billed by meter or ratio
+    pub utilities: UtilityUsage,
 }
 
 impl Statement {
     pub fn new(date: NaiveDate, tenant: Leaseholder, fees: Vec<Expense>) -> Statement {
-        let tenant_clone = tenant.clone();
         Statement {
             date,
+            leases: vec![tenant.lease.clone()],
             leaseholder: tenant,
-            rates: tenant_clone.clone().lease.fee_structure,
             fees,
-            total: calculate_total(tenant_clone.lease.fee_structure, 1000.0),
+            bills: vec![],
+            cam: CamHistory::default(),
+            sales: vec![],
+            utilities: UtilityUsage::default(),
         }
     }
-}
 
-pub fn calculate_total(fee_structure: FeeStructure, building_fees: f32) -> f32 {
-    match fee_structure {
-        FeeStructure::Gross(rent) => rent.base_rent,
-        FeeStructure::SingleNet(rent, tax) => {
-            rent.base_rent + calculate_share(tax.property_tax, building_fees)
-        }
-        FeeStructure::DoubleNet(rent, tax, insurance) => {
-            rent.base_rent
-                + calculate_share(tax.property_tax, building_fees)
-                + calculate_share(insurance.building_insurance, building_fees)
-        }
-        FeeStructure::TripleNet(rent, tax, insurance, cam) => {
-            rent.base_rent
-                + calculate_share(tax.property_tax, building_fees)
-                + calculate_share(insurance.building_insurance, building_fees)
-                + calculate_share(cam.amenities, building_fees)
-                + calculate_share(cam.electicity, building_fees)
-                + calculate_share(cam.garbage, building_fees)
-                + calculate_share(cam.landscaping, building_fees)
-                + calculate_share(cam.misc, building_fees)
-                + calculate_share(cam.recycling, building_fees)
-                + calculate_share(cam.water, building_fees)
+    /// Bills from the leaseholder's lease history rather than only their current lease.
+    pub fn with_leases(mut self, leases: Vec<Lease>) -> Statement {
+        if !leases.is_empty() {
+            self.leases = leases;
         }
+        self
+    }
+
+    pub fn with_bills(mut self, bills: Vec<PropertyBill>) -> Statement {
+        self.bills = bills;
+        self
+    }
+
+    pub fn with_cam_history(mut self, cam: CamHistory) -> Statement {
+        self.cam = cam;
+        self
+    }
+
+    pub fn with_sales(mut self, sales: Vec<SalesReport>) -> Statement {
+        self.sales = sales;
+        self
+    }
+
+    pub fn with_utilities(mut self, utilities: UtilityUsage) -> Statement {
+        self.utilities = utilities;
+        self
     }
 }
 
@@ -62,19 +100,1023 @@ pub fn calculate_share(rate: f32, total: f32) -> f32 {
     total * rate
 }
 
+/// The files written for one statement.
+pub struct StatementFiles {
+    pub pdf: PathBuf,
+    pub html: Option<PathBuf>,
+}
+
+/// Lays out the statement with the template and writes the PDF, and an HTML copy for
+/// reading on phones.
 pub fn create_statement(
-    statement: Statement,
-    property: Property,
-    company: Company,
-    settings: PathSettings,
-) {
-    write_with_printpdf(statement, property, company, settings);
+    document: &StatementDocument,
+    template: &StatementTemplate,
+    settings: &PathSettings,
+) -> std::io::Result<StatementFiles> {
+    let pdf = write_with_printpdf(document, template, settings)?;
+    let html = match write_html(document, template, settings) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            println!("Failed to write HTML statement: {}", e);
+            None
+        }
+    };
+    Ok(StatementFiles { pdf, html })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatementStatus {
+    Draft,
+    Issued,
+    Paid,
+    Void,
+}
+
+impl fmt::Display for StatementStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            StatementStatus::Draft => String::from("draft"),
+            StatementStatus::Issued => String::from("issued"),
+            StatementStatus::Paid => String::from("paid"),
+            StatementStatus::Void => String::from("void"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl StatementStatus {
+    pub fn parse_string(status: &str) -> Option<StatementStatus> {
+        match status {
+            "draft" => Some(StatementStatus::Draft),
+            "issued" => Some(StatementStatus::Issued),
+            "paid" => Some(StatementStatus::Paid),
+            "void" => Some(StatementStatus::Void),
+            _ => None,
+        }
+    }
+}
+
+/// What a row in the registry is. Credit memos are numbered with their own prefix and
+/// point at the statement they credit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatementKind {
+    Statement,
+    CreditMemo,
+}
+
+impl fmt::Display for StatementKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            StatementKind::Statement => String::from("statement"),
+            StatementKind::CreditMemo => String::from("credit_memo"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl StatementKind {
+    pub fn parse_string(kind: &str) -> Option<StatementKind> {
+        match kind {
+            "statement" => Some(StatementKind::Statement),
+            "credit_memo" => Some(StatementKind::CreditMemo),
+            _ => None,
+        }
+    }
+}
+
+/// A statement in the `statements` registry. Rows from before invoice numbering was added
+/// have no number, period or dates.
+#[derive(Debug, Clone)]
+pub struct StatementRecord {
+    pub id: i64,
+    pub kind: StatementKind,
+    pub invoice_number: Option<String>,
+    pub leaseholder_id: u32,
+    pub leaseholder_name: String,
+    pub property_id: Option<u32>,
+    pub property_name: String,
+    pub period: Option<BillingPeriod>,
+    pub issue_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub amount_due: f32,
+    pub amount_paid: f32,
+    pub status: StatementStatus,
+    pub pdf_path: Option<String>,
+    pub pdf_hash: Option<String>,
+    // The statement a credit memo credits, or the voided statement a reissue replaces
+    pub related_statement_id: Option<i64>,
+    pub related_invoice: Option<String>,
+    // Why a credit memo or reissue was made
+    pub note: Option<String>,
+    pub void_reason: Option<String>,
+    // The company the statement was issued under, for reissuing it
+    pub company_name: Option<String>,
+    // The part of amount_due from late fees, which are on the ledger already
+    pub late_fees: f32,
+    // The part of amount_due from CAM charges, negative for a CAM reconciliation credit
+    pub cam_billed: f32,
+    // The part of amount_due from percentage rent, negative for a true-up credit
+    pub percentage_rent: f32,
+    // The jurisdiction the sales tax was charged under, None when untaxed
+    pub tax_jurisdiction: Option<String>,
+    // What the tax was charged on and the tax itself, negative on a credit memo
+    pub taxable_sales: f32,
+    pub sales_tax: f32,
+}
+
+impl StatementRecord {
+    /// A draft for the document, numbered when it is added to the database. A credit memo
+    /// is registered with a negative amount due.
+    pub fn draft(
+        document: &StatementDocument,
+        leaseholder_id: u32,
+        property_id: Option<u32>,
+    ) -> Self {
+        let (kind, amount_due) = match document.kind {
+            DocumentKind::Statement => (StatementKind::Statement, document.total_due),
+            DocumentKind::CreditMemo => (StatementKind::CreditMemo, -document.total_due),
+        };
+        StatementRecord {
+            id: 0,
+            kind,
+            invoice_number: None,
+            leaseholder_id,
+            leaseholder_name: document.recipient.name.clone(),
+            property_id,
+            property_name: document.property.clone(),
+            period: Some(document.period),
+            issue_date: Some(Local::now().date_naive()),
+            due_date: Some(document.due_date),
+            amount_due,
+            amount_paid: 0.0,
+            status: StatementStatus::Draft,
+            pdf_path: None,
+            pdf_hash: None,
+            related_statement_id: None,
+            related_invoice: None,
+            note: None,
+            void_reason: None,
+            company_name: Some(document.company.clone()),
+            late_fees: 0.0,
+            cam_billed: document
+                .lines
+                .iter()
+                .filter(|line| line.category.is_shared_expense() && !line.utility_billed)
+                .fold(0.0, |sum, line| sum + line.amount),
+            percentage_rent: document
+                .lines
+                .iter()
+                .filter(|line| line.category == ChargeCategory::PercentageRent)
+                .fold(0.0, |sum, line| sum + line.amount),
+            tax_jurisdiction: None,
+            taxable_sales: 0.0,
+            sales_tax: 0.0,
+        }
+    }
+
+    fn number(&self) -> &str {
+        self.invoice_number.as_deref().unwrap_or("(unnumbered)")
+    }
+
+    /// The ledger entry posted when the statement is issued.
+    pub fn issue_entry(&self) -> LedgerEntry {
+        let (kind, description) = match self.kind {
+            StatementKind::Statement => (
+                LedgerKind::Charge,
+                format!(
+                    "Invoice {} for {}",
+                    self.number(),
+                    self.period.map(|period| period.range()).unwrap_or_default()
+                ),
+            ),
+            StatementKind::CreditMemo => (
+                LedgerKind::Credit,
+                match &self.related_invoice {
+                    Some(invoice) => {
+                        format!("Credit memo {} against invoice {}", self.number(), invoice)
+                    }
+                    // A CAM reconciliation credit stands alone
+                    None => format!(
+                        "Credit memo {}: {}",
+                        self.number(),
+                        self.note.as_deref().unwrap_or_default()
+                    ),
+                },
+            ),
+        };
+        LedgerEntry::new(
+            self.leaseholder_id,
+            Some(self.id),
+            kind,
+            description,
+            self.amount_due - self.late_fees,
+        )
+    }
+
+    /// Pays off the balance left after `credited`, what credit memos against it took off.
+    pub fn payment_entry(&self, credited: f32) -> LedgerEntry {
+        LedgerEntry::new(
+            self.leaseholder_id,
+            Some(self.id),
+            LedgerKind::Payment,
+            format!("Payment for invoice {}", self.number()),
+            -(self.amount_due - self.amount_paid - credited),
+        )
+    }
+
+    /// Reverses what issuing the statement posted. Late fees it carried stay owed.
+    pub fn void_entry(&self, reason: &str) -> LedgerEntry {
+        LedgerEntry::new(
+            self.leaseholder_id,
+            Some(self.id),
+            LedgerKind::Void,
+            format!("Void {}: {}", self.number(), reason),
+            -(self.amount_due - self.late_fees),
+        )
+    }
+
+    /// Takes off the late fees charged for paying the statement late, when it is voided.
+    pub fn waive_entry(&self, amount: f32) -> LedgerEntry {
+        LedgerEntry::new(
+            self.leaseholder_id,
+            Some(self.id),
+            LedgerKind::Void,
+            format!("Waive late fees on {}", self.number()),
+            -amount,
+        )
+    }
+
+    pub fn convert_to_slint(&self) -> StatementInput {
+        let date = |date: Option<NaiveDate>| date.map(|d| d.to_string()).unwrap_or_default();
+        let reference = match (self.kind, &self.related_invoice) {
+            (StatementKind::CreditMemo, Some(invoice)) => format!("Credits {invoice}"),
+            (StatementKind::Statement, Some(invoice)) => format!("Replaces {invoice}"),
+            (_, None) => String::new(),
+        };
+        StatementInput {
+            id: self.id as i32,
+            kind: self.kind.to_string().into(),
+            invoice_number: self.invoice_number.clone().unwrap_or_default().into(),
+            leaseholder_id: self.leaseholder_id as i32,
+            leaseholder: self.leaseholder_name.clone().into(),
+            property: self.property_name.clone().into(),
+            period: self
+                .period
+                .map(|period| period.range())
+                .unwrap_or_default()
+                .into(),
+            issue_date: date(self.issue_date).into(),
+            due_date: date(self.due_date).into(),
+            amount_due: format!("${:.2}", self.amount_due).into(),
+            status: self.status.to_string().into(),
+            pdf_path: self.pdf_path.clone().unwrap_or_default().into(),
+            reference: reference.into(),
+            note: self.note.clone().unwrap_or_default().into(),
+            void_reason: self.void_reason.clone().unwrap_or_default().into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for StatementRecord {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let status: String = row.try_get("status")?;
+        let status = StatementStatus::parse_string(&status).ok_or_else(|| {
+            sqlx::Error::Decode(format!("Unknown statement status: {status}").into())
+        })?;
+        let kind: String = row.try_get("kind")?;
+        let kind = StatementKind::parse_string(&kind)
+            .ok_or_else(|| sqlx::Error::Decode(format!("Unknown statement kind: {kind}").into()))?;
+        let date = |column: &str| -> Result<Option<NaiveDate>, sqlx::Error> {
+            let value: Option<String> = row.try_get(column)?;
+            value
+                .map(|value| NaiveDate::parse_from_str(&value, "%Y-%m-%d"))
+                .transpose()
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+        let period = match (date("period_start")?, date("period_end")?) {
+            (Some(start), Some(end)) => Some(BillingPeriod { start, end }),
+            _ => None,
+        };
+
+        Ok(StatementRecord {
+            id: row.try_get("statement_id")?,
+            kind,
+            invoice_number: row.try_get("invoice_number")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            leaseholder_name: row
+                .try_get::<Option<String>, _>("leaseholder_name")?
+                .unwrap_or_default(),
+            property_id: row.try_get("property_id")?,
+            property_name: row
+                .try_get::<Option<String>, _>("property_name")?
+                .unwrap_or_default(),
+            period,
+            issue_date: date("issue_date")?,
+            due_date: date("due_date")?,
+            amount_due: row.try_get::<f64, _>("amount_due")? as f32,
+            amount_paid: row.try_get::<f64, _>("amount_paid")? as f32,
+            status,
+            pdf_path: row.try_get("statement_path")?,
+            pdf_hash: row.try_get("pdf_hash")?,
+            related_statement_id: row.try_get("related_statement_id")?,
+            related_invoice: row.try_get("related_invoice")?,
+            note: row.try_get("note")?,
+            void_reason: row.try_get("void_reason")?,
+            company_name: row.try_get("company_name")?,
+            late_fees: row.try_get::<f64, _>("late_fees")? as f32,
+            cam_billed: row.try_get::<f64, _>("cam_billed")? as f32,
+            percentage_rent: row.try_get::<f64, _>("percentage_rent")? as f32,
+            tax_jurisdiction: row.try_get("tax_jurisdiction")?,
+            taxable_sales: row.try_get::<f64, _>("taxable_sales")? as f32,
+            sales_tax: row.try_get::<f64, _>("sales_tax")? as f32,
+        })
+    }
+}
+
+impl InvoiceSettings {
+    /// The invoice number format with everything but the sequence filled in. Statements
+    /// with the same key share a sequence.
+    pub fn sequence_key(&self, kind: StatementKind, property_id: u32, date: NaiveDate) -> String {
+        let prefix = match kind {
+            StatementKind::Statement => &self.prefix,
+            StatementKind::CreditMemo => &self.credit_memo_prefix,
+        };
+        self.number_format
+            .replace("{prefix}", prefix)
+            .replace("{property}", &property_id.to_string())
+            .replace("{year}", &date.format("%Y").to_string())
+    }
+
+    pub fn invoice_number(&self, sequence_key: &str, sequence: i64) -> String {
+        sequence_key.replace(
+            "{seq}",
+            &format!("{:0width$}", sequence, width = self.sequence_digits),
+        )
+    }
+}
+
+/// A statement that has been numbered and written out.
+pub struct IssuedStatement {
+    pub record: StatementRecord,
+    pub document: StatementDocument,
+    pub template: StatementTemplate,
+    pub files: StatementFiles,
+}
+
+/// Registers the statement under the next invoice number, writes its files with that number
+/// on them, and marks it issued with the hash of the PDF. The number is only kept once the
+/// files are written, so numbers are never skipped.
+pub async fn issue_statement(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    statement: &Statement,
+    property: &Property,
+    company: &Company,
+) -> Result<IssuedStatement, sqlx::Error> {
+    let document = StatementDocument::new(statement, property, &company.name, &settings.invoice);
+    let record = StatementRecord::draft(&document, statement.leaseholder.id, Some(property.id));
+    let template = load_template(&settings.paths, property.statement_template.as_deref());
+    issue_document(pool, settings, record, document, template).await
+}
+
+async fn issue_document(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    mut record: StatementRecord,
+    mut document: StatementDocument,
+    template: StatementTemplate,
+) -> Result<IssuedStatement, sqlx::Error> {
+    let late_fees = match document.kind {
+        DocumentKind::Statement => {
+            get_unbilled_late_fees(pool, record.leaseholder_id, record.related_statement_id).await
+        }
+        DocumentKind::CreditMemo => vec![],
+    };
+    if !late_fees.is_empty() {
+        document.add_late_fees(&late_fees);
+        record.amount_due = document.total_due;
+        record.late_fees = late_fees.iter().fold(0.0, |sum, fee| sum + fee.amount);
+    }
+    if let Some(jurisdiction) = property_jurisdiction(pool, settings, record.property_id).await? {
+        if let Some((taxable, tax)) = document.add_sales_tax(&jurisdiction) {
+            let sign = match document.kind {
+                DocumentKind::Statement => 1.0,
+                DocumentKind::CreditMemo => -1.0,
+            };
+            record.amount_due = sign * document.total_due;
+            record.tax_jurisdiction = Some(jurisdiction.name);
+            record.taxable_sales = sign * taxable;
+            record.sales_tax = sign * tax;
+        }
+    }
+
+    // The number is taken, the files written with it and the statement recorded in one
+    // transaction. The files are written to a staging directory and only moved into the
+    // statements directory just before it commits, so a failure anywhere gives the number back.
+    let staging = staging_paths(&settings.paths)?;
+    let mut tx = pool.begin().await?;
+    add_statement(&mut tx, &mut record, &settings.invoice).await?;
+    document.invoice_number = record.invoice_number.clone();
+    let staged = create_statement(&document, &template, &staging)?;
+    let files =
+        match publish_statement(tx, &mut record, &document, &staged, &late_fees, settings).await {
+            Ok(files) => files,
+            Err(e) => {
+                discard_files(&staged);
+                return Err(e);
+            }
+        };
+    Ok(IssuedStatement {
+        record,
+        document,
+        template,
+        files,
+    })
+}
+
+/// Records the staged files against the numbered statement, marks it issued and moves the
+/// files into the statements directory, committing only once they're in place.
+async fn publish_statement(
+    mut tx: sqlx::Transaction<'_, sqlx::Sqlite>,
+    record: &mut StatementRecord,
+    document: &StatementDocument,
+    staged: &StatementFiles,
+    late_fees: &[LateFee],
+    settings: &AppSettings,
+) -> Result<StatementFiles, sqlx::Error> {
+    let pdf = document.file_path(&settings.paths, "pdf");
+    record.pdf_path = Some(pdf.to_string_lossy().to_string());
+    record.pdf_hash = Some(hash_file(&staged.pdf)?);
+    record.status = StatementStatus::Issued;
+    mark_statement_issued(&mut tx, record, late_fees).await?;
+
+    // Issued statements are never overwritten
+    if pdf.try_exists()? {
+        return Err(refused(format!("{} already exists", pdf.display())));
+    }
+    std::fs::rename(&staged.pdf, &pdf)?;
+    let html = staged.html.as_ref().and_then(|staged_html| {
+        let html = document.file_path(&settings.paths, "html");
+        let moved = match html.try_exists() {
+            Ok(false) => std::fs::rename(staged_html, &html),
+            Ok(true) => Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists)),
+            Err(e) => Err(e),
+        };
+        match moved {
+            Ok(_) => Some(html),
+            Err(e) => {
+                println!("Failed to move HTML statement: {}", e);
+                None
+            }
+        }
+    });
+    let files = StatementFiles { pdf, html };
+    if let Err(e) = tx.commit().await {
+        discard_files(&files);
+        return Err(e);
+    }
+    Ok(files)
+}
+
+/// The paths with statements written to a staging directory inside the statements directory,
+/// on the same drive so they can be moved into place.
+fn staging_paths(settings: &PathSettings) -> std::io::Result<PathSettings> {
+    let staging = Path::new(&settings.statements_path).join("staging");
+    std::fs::create_dir_all(&staging)?;
+    Ok(PathSettings {
+        statements_path: format!("{}/", staging.to_string_lossy()),
+        ..settings.clone()
+    })
+}
+
+fn discard_files(files: &StatementFiles) {
+    for path in std::iter::once(&files.pdf).chain(&files.html) {
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                println!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// The tax jurisdiction the property is set to. Refuses rather than leave the tax off when
+/// the jurisdiction isn't in the settings.
+async fn property_jurisdiction(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    property_id: Option<u32>,
+) -> Result<Option<TaxJurisdiction>, sqlx::Error> {
+    let Some(property_id) = property_id else {
+        return Ok(None);
+    };
+    let Some(name) = get_properties(pool, true)
+        .await
+        .into_iter()
+        .find(|property| property.id == property_id)
+        .and_then(|property| property.tax_jurisdiction)
+    else {
+        return Ok(None);
+    };
+    match settings.tax.jurisdiction(&name) {
+        Some(jurisdiction) => Ok(Some(jurisdiction.clone())),
+        None => Err(refused(format!(
+            "Tax jurisdiction {name} is not in the settings file"
+        ))),
+    }
+}
+
+fn refused(message: String) -> sqlx::Error {
+    sqlx::Error::Protocol(message)
+}
+
+/// Refusals are shown as they are, without sqlx's wording around them.
+fn describe(error: &sqlx::Error) -> String {
+    match error {
+        sqlx::Error::Protocol(message) => message.clone(),
+        e => e.to_string(),
+    }
+}
+
+async fn issued_statement(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    statement_id: i64,
+) -> Result<StatementRecord, sqlx::Error> {
+    get_statement(pool, statement_id)
+        .await
+        .ok_or_else(|| refused(format!("No statement with id {statement_id}")))
+}
+
+/// Voids an issued statement or credit memo and reverses it on the ledger. A statement
+/// with credit memos against it has to have them voided first, and a paid one can only
+/// be credited.
+pub async fn void(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    statement_id: i64,
+    reason: &str,
+) -> Result<StatementRecord, sqlx::Error> {
+    let record = issued_statement(pool, statement_id).await?;
+    if reason.trim().is_empty() {
+        return Err(refused(String::from(
+            "A reason is needed to void a statement",
+        )));
+    }
+    if record.status != StatementStatus::Issued {
+        return Err(refused(format!(
+            "Only issued statements can be voided, {} is {}",
+            record.number(),
+            record.status
+        )));
+    }
+    if record.kind == StatementKind::Statement && get_credited_amount(pool, record.id).await > 0.0 {
+        return Err(refused(format!(
+            "Void the credit memos against {} first",
+            record.number()
+        )));
+    }
+    void_statement(pool, &record, reason.trim()).await?;
+    Ok(record)
+}
+
+/// Issues a corrected copy of a statement under a new number, billed from the property's
+/// current rates and the expenses in the original period, then voids the original.
+pub async fn reissue(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    statement_id: i64,
+    reason: &str,
+) -> Result<IssuedStatement, sqlx::Error> {
+    let original = issued_statement(pool, statement_id).await?;
+    if reason.trim().is_empty() {
+        return Err(refused(String::from(
+            "A reason is needed to reissue a statement",
+        )));
+    }
+    if original.kind == StatementKind::CreditMemo {
+        return Err(refused(String::from("Credit memos can not be reissued")));
+    }
+    if original.status != StatementStatus::Issued {
+        return Err(refused(format!(
+            "Only issued statements can be reissued, {} is {}",
+            original.number(),
+            original.status
+        )));
+    }
+    if get_credited_amount(pool, original.id).await > 0.0 {
+        return Err(refused(format!(
+            "Void the credit memos against {} first",
+            original.number()
+        )));
+    }
+    let (Some(property_id), Some(period)) = (original.property_id, original.period) else {
+        return Err(refused(format!(
+            "{} was issued before statements recorded their property and period",
+            original.number()
+        )));
+    };
+    // A year end true-up is worked out from the whole year, not billed like a month
+    if period.end != BillingPeriod::month_of(period.start).end {
+        return Err(refused(format!(
+            "{} is a year end true-up, void it and run the true-up again",
+            original.number()
+        )));
+    }
+    let property = get_properties(pool, true)
+        .await
+        .into_iter()
+        .find(|property| property.id == property_id)
+        .ok_or_else(|| refused(format!("No property with id {property_id}")))?;
+    let leaseholder = get_leaseholders(pool, true)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.id == original.leaseholder_id)
+        .ok_or_else(|| {
+            refused(format!(
+                "No leaseholder with id {}",
+                original.leaseholder_id
+            ))
+        })?;
+    let expenses = get_period_property_expenses(pool, property.id, &period).await;
+    let leases = get_leases(pool, leaseholder.id).await;
+    let bills = get_property_bills(pool, property.id).await;
+    let tenants: Vec<Leaseholder> = get_leaseholders(pool, true)
+        .await
+        .into_iter()
+        .filter(|tenant| tenant.property_id == property.id)
+        .collect();
+    let history = CamHistory::new(
+        get_property_expenses(pool, property.id).await,
+        property.num_units,
+        &tenants,
+    );
+    let sales = get_sales_reports(pool, leaseholder.id).await;
+    let utilities = UtilityUsage::for_property(pool, property.id).await;
+
+    let statement = Statement::new(period.start, leaseholder, expenses)
+        .with_leases(leases)
+        .with_bills(bills)
+        .with_cam_history(history)
+        .with_sales(sales)
+        .with_utilities(utilities);
+    let mut document = StatementDocument::new(
+        &statement,
+        &property,
+        original.company_name.as_deref().unwrap_or_default(),
+        &settings.invoice,
+    );
+    document.note = Some(format!(
+        "Replaces invoice {}: {}",
+        original.number(),
+        reason.trim()
+    ));
+    let mut record = StatementRecord::draft(&document, statement.leaseholder.id, Some(property.id));
+    record.related_statement_id = Some(original.id);
+    record.related_invoice = original.invoice_number.clone();
+    record.note = Some(reason.trim().to_owned());
+    let template = load_template(&settings.paths, property.statement_template.as_deref());
+    let issued = issue_document(pool, settings, record, document, template).await?;
+
+    let void_reason = format!("Reissued as {}: {}", issued.record.number(), reason.trim());
+    void_statement(pool, &original, &void_reason).await?;
+    Ok(issued)
+}
+
+/// Issues a credit memo taking `amount` off an issued or paid statement. The credits
+/// against a statement can not add up to more than it charged.
+pub async fn credit(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    statement_id: i64,
+    amount: f32,
+    reason: &str,
+) -> Result<IssuedStatement, sqlx::Error> {
+    let original = issued_statement(pool, statement_id).await?;
+    if reason.trim().is_empty() {
+        return Err(refused(String::from(
+            "A reason is needed for a credit memo",
+        )));
+    }
+    if original.kind == StatementKind::CreditMemo {
+        return Err(refused(String::from("Credit memos can not be credited")));
+    }
+    if !matches!(
+        original.status,
+        StatementStatus::Issued | StatementStatus::Paid
+    ) {
+        return Err(refused(format!(
+            "Only issued or paid statements can be credited, {} is {}",
+            original.number(),
+            original.status
+        )));
+    }
+    let remaining = original.amount_due - get_credited_amount(pool, original.id).await;
+    if !(amount > 0.0 && amount <= remaining + 0.005) {
+        return Err(refused(format!(
+            "Credit must be more than $0.00 and at most ${:.2}, what is left on {}",
+            remaining,
+            original.number()
+        )));
+    }
+    let leaseholder = get_leaseholders(pool, true)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.id == original.leaseholder_id)
+        .ok_or_else(|| {
+            refused(format!(
+                "No leaseholder with id {}",
+                original.leaseholder_id
+            ))
+        })?;
+    let statement_template = match original.property_id {
+        Some(property_id) => get_properties(pool, true)
+            .await
+            .into_iter()
+            .find(|property| property.id == property_id)
+            .and_then(|property| property.statement_template),
+        None => None,
+    };
+
+    let mut document = StatementDocument::credit_memo(
+        &original,
+        Recipient::for_leaseholder(&leaseholder),
+        original.company_name.clone().unwrap_or_default(),
+        amount,
+        reason.trim(),
+    );
+    let mut record = StatementRecord::draft(&document, leaseholder.id, original.property_id);
+    record.related_statement_id = Some(original.id);
+    record.related_invoice = original.invoice_number.clone();
+    record.note = Some(reason.trim().to_owned());
+    // The credit takes back its share of the tax charged on the statement
+    if let Some(jurisdiction) = &original.tax_jurisdiction {
+        let share = amount / original.amount_due;
+        record.tax_jurisdiction = Some(jurisdiction.clone());
+        record.taxable_sales = -(original.taxable_sales * share * 100.0).round() / 100.0;
+        record.sales_tax = -(original.sales_tax * share * 100.0).round() / 100.0;
+        document.adjustments.push(format!(
+            "Includes ${:.2} of {}",
+            -record.sales_tax, jurisdiction
+        ));
+    }
+    let template = load_template(&settings.paths, statement_template.as_deref());
+    issue_document(pool, settings, record, document, template).await
+}
+
+/// Trues up a leaseholder's CAM charges for `year`. What they owe is worked out from the
+/// year's building expenses under each of their leases for the part of the year it covered,
+/// with its CAM clauses applied to the whole year, then what their statements for the year
+/// already charged is taken off. Owing more issues a statement and owing less a credit memo.
+/// Utilities the property bills by meter or ratio are left out.
+pub async fn reconcile_cam(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    leaseholder_id: u32,
+    year: i32,
+) -> Result<IssuedStatement, sqlx::Error> {
+    let leaseholders = get_leaseholders(pool, true).await;
+    let leaseholder = leaseholders
+        .iter()
+        .find(|leaseholder| leaseholder.id == leaseholder_id)
+        .cloned()
+        .ok_or_else(|| refused(format!("No leaseholder with id {leaseholder_id}")))?;
+    let name = &leaseholder.contact_info.name;
+    let leases = match get_leases(pool, leaseholder.id).await {
+        leases if leases.is_empty() => vec![leaseholder.lease.clone()],
+        leases => leases,
+    };
+    if leases.iter().all(|lease| lease.cam.is_none()) {
+        return Err(refused(format!("{name} has no lease with CAM clauses")));
+    }
+    let (Some(start), Some(end)) = (
+        NaiveDate::from_ymd_opt(year, 1, 1),
+        NaiveDate::from_ymd_opt(year, 12, 31),
+    ) else {
+        return Err(refused(format!("{year} is not a year to reconcile")));
+    };
+    let period = BillingPeriod { start, end };
+    let property = get_properties(pool, true)
+        .await
+        .into_iter()
+        .find(|property| property.id == leaseholder.property_id)
+        .ok_or_else(|| refused(format!("No property with id {}", leaseholder.property_id)))?;
+    let tenants: Vec<Leaseholder> = leaseholders
+        .into_iter()
+        .filter(|tenant| tenant.property_id == property.id)
+        .collect();
+    let history = CamHistory::new(
+        get_property_expenses(pool, property.id).await,
+        property.num_units,
+        &tenants,
+    );
+    let expenses = get_period_property_expenses(pool, property.id, &period).await;
+
+    let year_days = (end - start).num_days() as f32 + 1.0;
+    let mut owed = 0.0;
+    let mut adjustments = vec![];
+    for occupancy in ProrationMethod::ActualDays.occupancy(
+        &period,
+        &leases,
+        leaseholder.move_in_date,
+        leaseholder.move_out_date,
+    ) {
+        let lease = &occupancy.lease;
+        let mut lines: Vec<StatementLine> = lease
+            .fee_structure
+            .statement_lines(expenses.clone(), 0.0, 0.0)
+            .into_iter()
+            .filter(|line| {
+                line.category.is_shared_expense()
+                    && !property.utility_billing.allocates(line.category)
+            })
+            .collect();
+        if let Some(clauses) = &lease.cam {
+            adjustments.extend(clauses.adjust_lines(
+                &history,
+                start,
+                end,
+                lease.start_date.year(),
+                &mut lines,
+            ));
+        }
+        let covered = ((occupancy.to - occupancy.from).num_days() as f32 + 1.0) / year_days;
+        if covered < 1.0 {
+            adjustments.push(format!(
+                "Lease covers {} to {}, {:.2}% of the year",
+                occupancy.from.format("%m/%d/%Y"),
+                occupancy.to.format("%m/%d/%Y"),
+                covered * 100.0
+            ));
+        }
+        owed += total_due(&lines) * covered;
+    }
+    let billed = get_cam_billed(pool, leaseholder.id, year).await;
+    let difference = ((owed - billed) * 100.0).round() / 100.0;
+    if difference.abs() < 0.01 {
+        return Err(refused(format!(
+            "CAM for {year} is already reconciled for {name}"
+        )));
+    }
+    adjustments.push(format!("CAM owed for {year}: ${owed:.2}"));
+    adjustments.push(format!("CAM billed for {year}: ${billed:.2}"));
+
+    let document = StatementDocument::year_end(
+        &leaseholder,
+        &property,
+        latest_company(pool, leaseholder.id).await,
+        period,
+        ChargeCategory::CamReconciliation,
+        difference,
+        adjustments,
+        &settings.invoice,
+    );
+    let mut record = StatementRecord::draft(&document, leaseholder.id, Some(property.id));
+    record.note = document.note.clone();
+    record.cam_billed = difference;
+    let template = load_template(&settings.paths, property.statement_template.as_deref());
+    issue_document(pool, settings, record, document, template).await
+}
+
+/// Trues up a retail tenant's percentage rent for `year`. What they owe is the rate times
+/// the year's reported sales over the annual breakpoint, for each lease over the part of
+/// the year it covered. What their statements already charged for the year's sales is
+/// taken off. Owing more issues a statement and owing less a credit memo.
+pub async fn true_up_percentage_rent(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    leaseholder_id: u32,
+    year: i32,
+) -> Result<IssuedStatement, sqlx::Error> {
+    let leaseholder = get_leaseholders(pool, true)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.id == leaseholder_id)
+        .ok_or_else(|| refused(format!("No leaseholder with id {leaseholder_id}")))?;
+    let name = &leaseholder.contact_info.name;
+    let leases = match get_leases(pool, leaseholder.id).await {
+        leases if leases.is_empty() => vec![leaseholder.lease.clone()],
+        leases => leases,
+    };
+    if leases.iter().all(|lease| lease.percentage_rent.is_none()) {
+        return Err(refused(format!("{name} has no lease with percentage rent")));
+    }
+    let (Some(start), Some(end)) = (
+        NaiveDate::from_ymd_opt(year, 1, 1),
+        NaiveDate::from_ymd_opt(year, 12, 31),
+    ) else {
+        return Err(refused(format!("{year} is not a year to true up")));
+    };
+    let period = BillingPeriod { start, end };
+    let property = get_properties(pool, true)
+        .await
+        .into_iter()
+        .find(|property| property.id == leaseholder.property_id)
+        .ok_or_else(|| refused(format!("No property with id {}", leaseholder.property_id)))?;
+    let reports = get_sales_reports(pool, leaseholder.id).await;
+    if sales_in(&reports, start, end).is_none() {
+        return Err(refused(format!("{name} has reported no sales for {year}")));
+    }
+
+    let year_days = (end - start).num_days() as f32 + 1.0;
+    let mut owed = 0.0;
+    let mut adjustments = vec![];
+    for occupancy in ProrationMethod::ActualDays.occupancy(
+        &period,
+        &leases,
+        leaseholder.move_in_date,
+        leaseholder.move_out_date,
+    ) {
+        let Some(terms) = occupancy.lease.percentage_rent else {
+            continue;
+        };
+        let covered = ((occupancy.to - occupancy.from).num_days() as f32 + 1.0) / year_days;
+        let breakpoint = terms.breakpoint(occupancy.lease.fee_structure.base_rent(), covered);
+        let sales = sales_in(&reports, occupancy.from, occupancy.to).unwrap_or_default();
+        let covered_period = BillingPeriod {
+            start: occupancy.from,
+            end: occupancy.to,
+        };
+        let (due, explanation) = terms.due(sales, breakpoint, &covered_period);
+        adjustments.push(explanation);
+        owed += due;
+    }
+    let billed = get_percentage_rent_billed(pool, leaseholder.id, year).await;
+    let difference = ((owed - billed) * 100.0).round() / 100.0;
+    if difference.abs() < 0.01 {
+        return Err(refused(format!(
+            "Percentage rent for {year} is already trued up for {name}"
+        )));
+    }
+    adjustments.push(format!("Percentage rent owed for {year}: ${owed:.2}"));
+    adjustments.push(format!("Percentage rent billed for {year}: ${billed:.2}"));
+
+    let document = StatementDocument::year_end(
+        &leaseholder,
+        &property,
+        latest_company(pool, leaseholder.id).await,
+        period,
+        ChargeCategory::PercentageRentTrueUp,
+        difference,
+        adjustments,
+        &settings.invoice,
+    );
+    let mut record = StatementRecord::draft(&document, leaseholder.id, Some(property.id));
+    record.note = document.note.clone();
+    record.percentage_rent = difference;
+    let template = load_template(&settings.paths, property.statement_template.as_deref());
+    issue_document(pool, settings, record, document, template).await
+}
+
+/// Year end true-ups are issued under the company on the leaseholder's latest statement.
+pub async fn latest_company(pool: &sqlx::Pool<sqlx::Sqlite>, leaseholder_id: u32) -> String {
+    get_statements(pool, &StatementFilter::default())
+        .await
+        .into_iter()
+        .filter(|statement| statement.leaseholder_id == leaseholder_id)
+        .find_map(|statement| statement.company_name)
+        .unwrap_or_default()
+}
+
+/// SHA-256 of the file as hex, so an issued PDF can be checked against the registry.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let contents = std::fs::read(path)?;
+    Ok(format!("{:x}", Sha256::digest(contents)))
+}
+
+/// What the Statements tab is showing. Empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct StatementFilter {
+    pub status: Option<StatementStatus>,
+    // Matched against the invoice number, leaseholder and property names
+    pub search: String,
+    pub issued_from: Option<NaiveDate>,
+    pub issued_to: Option<NaiveDate>,
+}
+
+impl StatementFilter {
+    pub fn from_slint(input: &StatementFilterInput) -> StatementFilter {
+        let date = |text: &str| NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok();
+        StatementFilter {
+            status: StatementStatus::parse_string(&input.status.to_lowercase()),
+            search: input.search.trim().to_owned(),
+            issued_from: date(&input.issued_from),
+            issued_to: date(&input.issued_to),
+        }
+    }
 }
 
 pub enum StatementMessage {
-    StatementCreated(Statement),
-    StatementUpdate(Statement),
-    StatementDelete(Statement),
+    Refresh,
+    Filter(StatementFilter),
+    MarkPaid(i64),
+    Void(i64, String),
+    Reissue(i64, String),
+    Credit(i64, f32, String),
+    Ledger(u32),
+    AssessLateFees,
+    // Leaseholder id and year
+    ReconcileCam(u32, i32),
+    TrueUpPercentageRent(u32, i32),
+    // Issued from and to
+    TaxReport(Option<NaiveDate>, Option<NaiveDate>),
     Quit,
 }
 
@@ -84,15 +1126,20 @@ pub struct StatementWorker {
 }
 
 impl StatementWorker {
-    pub fn new(pool: &sqlx::Pool<sqlx::Sqlite>) -> Self {
+    pub fn new(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        settings: &AppSettings,
+        app: slint::Weak<App>,
+    ) -> Self {
         println!("Create new Statement Worker");
         let (sender, r) = tokio::sync::mpsc::unbounded_channel();
         let worker_thread = std::thread::spawn({
             let new_pool = pool.clone();
+            let settings = settings.clone();
             move || {
                 tokio::runtime::Runtime::new()
                     .unwrap()
-                    .block_on(statement_worker_loop(new_pool, r))
+                    .block_on(statement_worker_loop(new_pool, settings, r, app))
             }
         });
         Self {
@@ -108,22 +1155,327 @@ impl StatementWorker {
 
 async fn statement_worker_loop(
     pool: sqlx::Pool<sqlx::Sqlite>,
+    settings: AppSettings,
     mut r: UnboundedReceiver<StatementMessage>,
+    app: slint::Weak<App>,
 ) {
+    let mut filter = StatementFilter::default();
+    let mut ledger_leaseholder: Option<u32> = None;
+    let mut schedule = tokio::time::interval(std::time::Duration::from_secs(
+        settings.late_fees.check_interval_minutes.max(1) * 60,
+    ));
     loop {
-        let m = r.recv().await;
-
-        match m {
-            Some(s) => match s {
-                StatementMessage::StatementCreated(create) => {}
-                StatementMessage::StatementUpdate(update) => {}
-                StatementMessage::StatementDelete(remove) => {}
-                StatementMessage::Quit => {
-                    println!("Quitting");
+        let message = tokio::select! {
+            _ = schedule.tick() => {
+                if !settings.late_fees.scheduled {
                     continue;
                 }
-            },
-            None => continue,
+                Some(StatementMessage::AssessLateFees)
+            }
+            m = r.recv() => m,
+        };
+        let status = match message {
+            Some(StatementMessage::Refresh) => None,
+            Some(StatementMessage::Filter(new_filter)) => {
+                filter = new_filter;
+                None
+            }
+            Some(StatementMessage::MarkPaid(id)) => Some(mark_paid(&pool, id).await),
+            Some(StatementMessage::Void(id, reason)) => {
+                Some(match void(&pool, id, &reason).await {
+                    Ok(record) => format!("Voided {}", record.number()),
+                    Err(e) => format!("Failed to void statement: {}", describe(&e)),
+                })
+            }
+            Some(StatementMessage::Reissue(id, reason)) => {
+                Some(match reissue(&pool, &settings, id, &reason).await {
+                    Ok(issued) => {
+                        queue_email(&pool, &settings, &issued).await;
+                        format!("Reissued as {}", issued.record.number())
+                    }
+                    Err(e) => format!("Failed to reissue statement: {}", describe(&e)),
+                })
+            }
+            Some(StatementMessage::Credit(id, amount, reason)) => {
+                Some(match credit(&pool, &settings, id, amount, &reason).await {
+                    Ok(issued) => {
+                        queue_email(&pool, &settings, &issued).await;
+                        format!("Issued credit memo {}", issued.record.number())
+                    }
+                    Err(e) => format!("Failed to issue credit memo: {}", describe(&e)),
+                })
+            }
+            Some(StatementMessage::Ledger(leaseholder_id)) => {
+                ledger_leaseholder = Some(leaseholder_id);
+                None
+            }
+            Some(StatementMessage::AssessLateFees) => {
+                match assess_late_fees(&pool, Local::now().date_naive()).await {
+                    Ok(fees) if fees.is_empty() => None,
+                    Ok(fees) => Some(format!(
+                        "Assessed {} late fees totalling ${:.2}",
+                        fees.len(),
+                        fees.iter().fold(0.0, |sum, fee| sum + fee.amount)
+                    )),
+                    Err(e) => Some(format!("Failed to assess late fees: {}", describe(&e))),
+                }
+            }
+            Some(StatementMessage::ReconcileCam(leaseholder_id, year)) => Some(
+                match reconcile_cam(&pool, &settings, leaseholder_id, year).await {
+                    Ok(issued) => {
+                        queue_email(&pool, &settings, &issued).await;
+                        format!(
+                            "Issued CAM reconciliation {} for ${:.2}",
+                            issued.record.number(),
+                            issued.record.cam_billed
+                        )
+                    }
+                    Err(e) => format!("Failed to reconcile CAM: {}", describe(&e)),
+                },
+            ),
+            Some(StatementMessage::TrueUpPercentageRent(leaseholder_id, year)) => Some(
+                match true_up_percentage_rent(&pool, &settings, leaseholder_id, year).await {
+                    Ok(issued) => {
+                        queue_email(&pool, &settings, &issued).await;
+                        format!(
+                            "Issued percentage rent true-up {} for ${:.2}",
+                            issued.record.number(),
+                            issued.record.percentage_rent
+                        )
+                    }
+                    Err(e) => format!("Failed to true up percentage rent: {}", describe(&e)),
+                },
+            ),
+            Some(StatementMessage::TaxReport(from, to)) => Some(describe_report(
+                &tax_report(&pool, from, to).await,
+                from,
+                to,
+            )),
+            Some(StatementMessage::Quit) | None => break,
         };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+        show_statements(&pool, &app, &filter, status).await;
+        if let Some(leaseholder_id) = ledger_leaseholder {
+            show_ledger(&pool, &app, leaseholder_id).await;
+        }
+    }
+}
+
+async fn mark_paid(pool: &sqlx::Pool<sqlx::Sqlite>, statement_id: i64) -> String {
+    let record = match issued_statement(pool, statement_id).await {
+        Ok(record) => record,
+        Err(e) => return format!("Failed to mark statement paid: {}", describe(&e)),
+    };
+    if record.kind != StatementKind::Statement || record.status != StatementStatus::Issued {
+        return format!(
+            "Only issued statements can be paid, {} is {}",
+            record.number(),
+            record.status
+        );
+    }
+    match mark_statement_paid(pool, &record).await {
+        Ok(_) => format!("Marked {} paid", record.number()),
+        Err(e) => format!("Failed to mark {} paid: {e}", record.number()),
+    }
+}
+
+async fn queue_email(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    settings: &AppSettings,
+    issued: &IssuedStatement,
+) {
+    match queue_statement_email(pool, &settings.email, issued).await {
+        Ok(Some(id)) => println!("Queued email {id} for {}", issued.record.number()),
+        Ok(None) => (),
+        Err(e) => println!("Failed to queue email for {}: {e}", issued.record.number()),
+    }
+}
+
+async fn show_statements(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    app: &slint::Weak<App>,
+    filter: &StatementFilter,
+    status: Option<String>,
+) {
+    let statements: Vec<StatementInput> = get_statements(pool, filter)
+        .await
+        .iter()
+        .map(StatementRecord::convert_to_slint)
+        .collect();
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let registry = handle.global::<StatementRegistry>();
+        registry.set_statements(ModelRc::new(VecModel::from(statements)));
+        if let Some(status) = status {
+            registry.set_status(status.into());
+        }
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+async fn show_ledger(pool: &sqlx::Pool<sqlx::Sqlite>, app: &slint::Weak<App>, leaseholder_id: u32) {
+    let entries = ledger::with_balances(&get_ledger(pool, leaseholder_id).await);
+    let balance = entries.last().map_or(0.0, |(_, balance)| *balance);
+    // Newest first, like the statement list
+    let entries: Vec<LedgerEntryInput> = entries
+        .iter()
+        .rev()
+        .map(|(entry, balance)| entry.convert_to_slint(*balance))
+        .collect();
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let registry = handle.global::<StatementRegistry>();
+        registry.set_ledger(ModelRc::new(VecModel::from(entries)));
+        registry.set_ledger_balance(format!("${:.2}", balance).into());
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        database::{add_leaseholders, add_property, scratch_database},
+        lease::{FeeStructure, Rent},
+        leaseholders::ContactInformation,
+        properties::Address,
+    };
+
+    /// A March 2024 statement for a tenant on a gross lease at 1200 a month.
+    pub(crate) fn statement() -> (Statement, Property) {
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        statement_on(Lease::new(
+            date(1, 1),
+            date(12, 31),
+            FeeStructure::Gross(Rent { base_rent: 1200.0 }),
+            "Check".to_string(),
+        ))
+    }
+
+    /// A March 2024 statement for a tenant on `lease`.
+    pub(crate) fn statement_on(lease: Lease) -> (Statement, Property) {
+        let address = || {
+            Address::new(
+                "12 Birch Way".to_string(),
+                "Tacoma".to_string(),
+                "WA".to_string(),
+                "98402".to_string(),
+            )
+        };
+        let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        let property = Property::new(1, "Birch Court".to_string(), address(), 0.0, 0.0, 1);
+        let contact = ContactInformation::new(
+            "Noor Haddad".to_string(),
+            address(),
+            "noor@example.com".to_string(),
+            "2535550188".to_string(),
+        );
+        let tenant = Leaseholder::new(1, lease, property.id, contact, date(1, 1));
+        (Statement::new(date(3, 1), tenant, vec![]), property)
+    }
+
+    /// A scratch database with the statement's property and tenant in it.
+    pub(crate) async fn billed(
+        name: &str,
+    ) -> (
+        sqlx::Pool<sqlx::Sqlite>,
+        AppSettings,
+        Statement,
+        Property,
+        Company,
+    ) {
+        billed_on(name, statement()).await
+    }
+
+    /// A scratch database with the property and tenant of `statement` in it.
+    pub(crate) async fn billed_on(
+        name: &str,
+        (statement, property): (Statement, Property),
+    ) -> (
+        sqlx::Pool<sqlx::Sqlite>,
+        AppSettings,
+        Statement,
+        Property,
+        Company,
+    ) {
+        let (pool, settings) = scratch_database(name).await;
+        add_property(&pool, &property).await.unwrap();
+        add_leaseholders(&pool, &statement.leaseholder, property.id)
+            .await
+            .unwrap();
+        let company = Company::new("Birch Holdings".to_string(), 0);
+        (pool, settings, statement, property, company)
+    }
+
+    #[async_std::test]
+    async fn failing_to_write_a_statement_gives_its_number_back() {
+        let (pool, settings, statement, property, company) = billed("issue_rollback").await;
+
+        // A file already where the first statement would go stops it being issued
+        let mut document =
+            StatementDocument::new(&statement, &property, &company.name, &settings.invoice);
+        let key = settings.invoice.sequence_key(
+            StatementKind::Statement,
+            property.id,
+            Local::now().date_naive(),
+        );
+        let first_number = settings.invoice.invoice_number(&key, 1);
+        document.invoice_number = Some(first_number.clone());
+        let taken = document.file_path(&settings.paths, "pdf");
+        std::fs::write(&taken, "not a statement").unwrap();
+
+        let err = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .err()
+            .unwrap();
+        assert!(describe(&err).contains("already exists"), "{err}");
+        assert!(get_statements(&pool, &StatementFilter::default())
+            .await
+            .is_empty());
+        let staging = Path::new(&settings.paths.statements_path).join("staging");
+        assert_eq!(std::fs::read_dir(&staging).unwrap().count(), 0);
+
+        std::fs::remove_file(&taken).unwrap();
+        let issued = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .unwrap();
+        assert_eq!(issued.record.invoice_number, Some(first_number));
+        assert_eq!(issued.files.pdf, taken);
+        assert_eq!(
+            issued.record.pdf_hash,
+            Some(hash_file(&issued.files.pdf).unwrap())
+        );
+        assert_eq!(std::fs::read_dir(&staging).unwrap().count(), 0);
+    }
+
+    #[async_std::test]
+    async fn paying_a_credited_statement_settles_the_ledger() {
+        let (pool, settings, statement, property, company) = billed("credit_then_pay").await;
+        let issued = issue_statement(&pool, &settings, &statement, &property, &company)
+            .await
+            .unwrap();
+        credit(
+            &pool,
+            &settings,
+            issued.record.id,
+            200.0,
+            "Parking lot closed",
+        )
+        .await
+        .unwrap();
+
+        mark_paid(&pool, issued.record.id).await;
+        let paid = get_statement(&pool, issued.record.id).await.unwrap();
+        assert_eq!(paid.status, StatementStatus::Paid);
+        assert_eq!(paid.amount_paid, paid.amount_due - 200.0);
+        let ledger = ledger::with_balances(&get_ledger(&pool, 1).await);
+        let (payment, balance) = ledger.last().unwrap();
+        assert_eq!(payment.kind, LedgerKind::Payment);
+        assert_eq!(payment.amount, -(paid.amount_due - 200.0));
+        assert!(balance.abs() < 0.005, "balance left: {balance}");
     }
 }
@@ -1,29 +1,76 @@
 use crate::{
-    app_settings::PathSettings,
+    app_settings::{scratch_settings, AppSettings},
+    cam::{CamCap, CamClauses, CamHistory},
     database::{
-        add_expense, add_leaseholders, add_property, add_statement, get_current_property_expenses,
-        update_property,
+        self, add_expense, add_insurance_certificate, add_lease, add_leaseholders,
+        add_meter_reading, add_property, add_property_bill, add_sales_report,
+        get_current_property_expenses, get_documents, get_leaseholders, get_leases, get_ledger,
+        get_letters, get_period_property_expenses, get_property_bills, get_property_expenses,
+        get_sales_reports, mark_installment_paid, mark_statement_paid, update_lease,
     },
+    documents::{attach_document, reminders, DocumentOwner, NewDocument},
     expenses::*,
+    insurance::{compliance_report, ComplianceEntry, InsuranceRequirement, NewCertificate},
+    late_fees::assess_late_fees,
     lease::{self, *},
     leaseholders::*,
+    ledger,
+    letters::{self, generate_letter},
+    percentage_rent::{Breakpoint, PercentageRent, ReportingPeriod, SalesReport},
     properties::*,
-    statements::{create_statement, Statement},
+    property_bills::NewBill,
+    sales_tax::{describe_report, tax_report, TaxJurisdiction},
+    statement_document::BillingPeriod,
+    statement_template,
+    statements::{
+        credit, issue_statement, reconcile_cam, reissue, true_up_percentage_rent, Statement,
+    },
+    text_formatting::render_text,
+    utility_billing::{MeterReading, Utility, UtilityBilling, UtilityUsage},
 };
-use chrono::NaiveDate;
+use chrono::{Days, Local, NaiveDate};
 use sqlx::Sqlite;
 
 pub async fn activate_test_mode(activate: bool, instances: &sqlx::Pool<Sqlite>) {
     if activate {
-        let settings = test_settings().await;
-        let (company, leaseholder, mut property) = test_database(instances).await;
+        let (_, _, property) = test_database(instances).await;
         test_expenses(instances, &property).await;
-        test_statements(instances, &mut property, leaseholder, company, settings).await;
+        test_bills(instances, &property).await;
     }
 }
 
-async fn test_settings() -> PathSettings {
-    PathSettings::default()
+/// Runs the billing scenarios end to end for `hestia scenarios`. They issue statements,
+/// which use up invoice numbers and go on the append-only ledger and audit log for good,
+/// so they run against a fresh database in the temp directory rather than the user's.
+pub async fn run_scenarios() {
+    let app_settings = scratch_settings("scenarios");
+    statement_template::initialize_default_template(&app_settings.paths);
+    letters::initialize_letter_templates(&app_settings.paths);
+    let instances = database::initialize_database(&app_settings).await;
+
+    let (company, leaseholder, property) = test_database(&instances).await;
+    test_expenses(&instances, &property).await;
+    test_bills(&instances, &property).await;
+    test_statements(
+        &instances,
+        &property,
+        leaseholder,
+        company.clone(),
+        &app_settings,
+    )
+    .await;
+    test_cam(&instances, &property, company.clone(), &app_settings).await;
+    test_percentage_rent(&instances, &property, company.clone(), &app_settings).await;
+    test_sales_tax(&instances, company.clone(), &app_settings).await;
+    test_utility_billing(&instances, company, &app_settings).await;
+    test_documents(&instances, &app_settings).await;
+    test_insurance(&instances, &app_settings).await;
+    test_letters(&instances, &app_settings).await;
+    instances.close().await;
+    println!(
+        "Scenario statements are in {}",
+        app_settings.paths.statements_path
+    );
 }
 
 async fn test_database(instances: &sqlx::Pool<Sqlite>) -> (Company, Leaseholder, Property) {
@@ -64,7 +111,7 @@ async fn test_database(instances: &sqlx::Pool<Sqlite>) -> (Company, Leaseholder,
         "2064445555".to_string(),
     );
 
-    let lease = Lease::new(
+    let mut lease = Lease::new(
         NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
         NaiveDate::from_ymd_opt(2025, 2, 28).unwrap(),
         lease::FeeStructure::TripleNet(
@@ -75,6 +122,7 @@ async fn test_database(instances: &sqlx::Pool<Sqlite>) -> (Company, Leaseholder,
             },
             CAMRates {
                 electicity: 0.3,
+                gas: 0.3,
                 recycling: 0.3,
                 garbage: 0.3,
                 water: 0.3,
@@ -85,6 +133,31 @@ async fn test_database(instances: &sqlx::Pool<Sqlite>) -> (Company, Leaseholder,
         ),
         "Check".to_string(),
     );
+    lease.late_fee = Some(LateFeeRule {
+        grace_days: 5,
+        flat_fee: 50.0,
+        percent: 0.01,
+        daily_fee: 5.0,
+        cap: Some(250.0),
+    });
+    lease.recurring_charges = vec![
+        RecurringCharge {
+            name: "Parking Stall 12".to_string(),
+            amount: 75.0,
+            frequency: ChargeFrequency::Monthly,
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            end_date: None,
+            taxable: true,
+        },
+        RecurringCharge {
+            name: "Pet Rent".to_string(),
+            amount: 35.0,
+            frequency: ChargeFrequency::Monthly,
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            end_date: None,
+            taxable: false,
+        },
+    ];
     let mut leaseholder = Leaseholder::new(
         0,
         lease.clone(),
@@ -156,37 +229,886 @@ pub async fn test_expenses(instances: &sqlx::Pool<Sqlite>, property: &Property)
         Ok(_) => println!("Successfully added EXPENSE"),
         Err(e) => println!("Error when adding EXPENSE: {}", e),
     }
+
+    let expense = Expense::new(
+        0,
+        property.id,
+        ExpenseType::Maintenance(MaintenanceType::Amenities),
+        240.0,
+        dt.unwrap(),
+        "Pool Service".to_string(),
+    );
+    match add_expense(instances, &expense).await {
+        Ok(_) => println!("Successfully added EXPENSE"),
+        Err(e) => println!("Error when adding EXPENSE: {}", e),
+    }
+
+    let expense = Expense::new(
+        0,
+        property.id,
+        ExpenseType::Utilities(UtilitiesType::Gas),
+        310.0,
+        dt.unwrap(),
+        "Gas Bill".to_string(),
+    );
+    match add_expense(instances, &expense).await {
+        Ok(_) => println!("Successfully added EXPENSE"),
+        Err(e) => println!("Error when adding EXPENSE: {}", e),
+    }
+}
+
+pub async fn test_bills(instances: &sqlx::Pool<Sqlite>, property: &Property) {
+    println!("- - - Testing Bills - - -");
+    // The year's property tax, paid in spring and fall halves
+    let mut tax = NewBill {
+        kind: String::from("property_tax"),
+        description: String::from("2024 property tax"),
+        amount: String::from("12000"),
+        coverage_start: String::from("2024-01-01"),
+        coverage_end: String::from("2024-12-31"),
+        due_dates: String::from("2024-04-30, 2024-10-31"),
+    }
+    .parse(property.id)
+    .unwrap();
+    match add_property_bill(instances, &mut tax).await {
+        Ok(_) => println!("Successfully added PROPERTY TAX. ID: {}", tax.id),
+        Err(e) => println!("Error when adding PROPERTY TAX: {}", e),
+    }
+    if let Some(installment) = tax.installments.first() {
+        match mark_installment_paid(instances, installment.id, installment.due_date).await {
+            Ok(_) => println!("Successfully paid INSTALLMENT. ID: {}", installment.id),
+            Err(e) => println!("Error when paying INSTALLMENT: {}", e),
+        }
+    }
 }
 
 pub async fn test_statements(
     instances: &sqlx::Pool<Sqlite>,
-    property: &mut Property,
+    property: &Property,
     leaseholder: Leaseholder,
     company: Company,
-    settings: PathSettings,
+    app_settings: &AppSettings,
 ) {
     println!("- - - Testing Statements - - -");
     let statement = Statement::new(
         NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
-        leaseholder,
+        leaseholder.clone(),
         get_current_property_expenses(
             instances,
             property.id,
             NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
         )
         .await,
+    )
+    .with_bills(get_property_bills(instances, property.id).await);
+    let issued =
+        match issue_statement(instances, app_settings, &statement, property, &company).await {
+            Ok(issued) => {
+                println!(
+                    "Successfully issued STATEMENT {}",
+                    issued.record.invoice_number.clone().unwrap_or_default()
+                );
+                issued
+            }
+            Err(e) => {
+                println!("Error when issuing STATEMENT: {}", e);
+                return;
+            }
+        };
+    println!("{}", render_text(&issued.document, &issued.template));
+    if let Some(html) = &issued.files.html {
+        println!("Wrote HTML statement {}", html.display());
+    }
+
+    match assess_late_fees(instances, Local::now().date_naive()).await {
+        Ok(fees) => {
+            for fee in fees {
+                println!(
+                    "Assessed LATE FEE ${:.2} on {}",
+                    fee.amount, fee.invoice_number
+                );
+            }
+        }
+        Err(e) => println!("Error when assessing LATE FEES: {}", e),
+    }
+
+    let mut premium = NewBill {
+        kind: String::from("insurance"),
+        description: String::from("Building policy renewal"),
+        amount: String::from("12600"),
+        coverage_start: String::from("2024-03-01"),
+        coverage_end: String::new(),
+        due_dates: String::from("2024-03-01"),
+    }
+    .parse(property.id)
+    .unwrap();
+    match add_property_bill(instances, &mut premium).await {
+        Ok(_) => println!("Successfully added INSURANCE PREMIUM. ID: {}", premium.id),
+        Err(e) => println!("Error when adding INSURANCE PREMIUM: {}", e),
+    }
+
+    // Correct the statement for the new insurance, then credit part of the correction back
+    let reissued = match reissue(
+        instances,
+        app_settings,
+        issued.record.id,
+        "Insurance premium updated",
+    )
+    .await
+    {
+        Ok(reissued) => {
+            println!(
+                "Successfully reissued STATEMENT as {}",
+                reissued.record.invoice_number.clone().unwrap_or_default()
+            );
+            reissued
+        }
+        Err(e) => {
+            println!("Error when reissuing STATEMENT: {}", e);
+            return;
+        }
+    };
+    match credit(
+        instances,
+        app_settings,
+        reissued.record.id,
+        25.0,
+        "Landscaping visit missed",
+    )
+    .await
+    {
+        Ok(memo) => println!("{}", render_text(&memo.document, &memo.template)),
+        Err(e) => println!("Error when issuing CREDIT MEMO: {}", e),
+    }
+    for (entry, balance) in
+        ledger::with_balances(&get_ledger(instances, reissued.record.leaseholder_id).await)
+    {
+        println!(
+            "{} {:<8} {:>10.2} {:>10.2}  {}",
+            entry.date, entry.kind, entry.amount, balance, entry.description
+        );
+    }
+
+    // Renew mid-month at a higher rent, so April is billed half from each lease
+    let mut renewal = leaseholder.lease.clone();
+    renewal.start_date = NaiveDate::from_ymd_opt(2024, 4, 16).unwrap();
+    renewal.end_date = NaiveDate::from_ymd_opt(2025, 4, 15).unwrap();
+    if let lease::FeeStructure::TripleNet(rent, ..) = &mut renewal.fee_structure {
+        rent.base_rent = 1800.0;
+    }
+    match add_lease(instances, leaseholder.id, &renewal).await {
+        Ok(id) => println!("Successfully renewed LEASE. ID: {}", id),
+        Err(e) => println!("Error when renewing LEASE: {}", e),
+    }
+    let statement = Statement::new(
+        NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+        leaseholder.clone(),
+        vec![],
+    )
+    .with_leases(get_leases(instances, leaseholder.id).await)
+    .with_bills(get_property_bills(instances, property.id).await);
+    match issue_statement(instances, app_settings, &statement, property, &company).await {
+        Ok(issued) => println!("{}", render_text(&issued.document, &issued.template)),
+        Err(e) => println!("Error when issuing prorated STATEMENT: {}", e),
+    }
+}
+
+pub async fn test_cam(
+    instances: &sqlx::Pool<Sqlite>,
+    property: &Property,
+    company: Company,
+    app_settings: &AppSettings,
+) {
+    println!("- - - Testing CAM Clauses - - -");
+    // The base year the commercial tenant's caps count from
+    for (expense_type, amount, description) in [
+        (
+            ExpenseType::Utilities(UtilitiesType::Electricity),
+            1500.0,
+            "2023 Electricity Bill",
+        ),
+        (
+            ExpenseType::Maintenance(MaintenanceType::Landscaping),
+            200.0,
+            "2023 Landscaping",
+        ),
+    ] {
+        let expense = Expense::new(
+            0,
+            property.id,
+            expense_type,
+            amount,
+            NaiveDate::from_ymd_opt(2023, 6, 10).unwrap(),
+            description.to_string(),
+        );
+        match add_expense(instances, &expense).await {
+            Ok(_) => println!("Successfully added EXPENSE"),
+            Err(e) => println!("Error when adding EXPENSE: {}", e),
+        }
+    }
+
+    let mut lease = Lease::new(
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2026, 12, 31).unwrap(),
+        lease::FeeStructure::TripleNet(
+            Rent { base_rent: 2500.0 },
+            PropertyTaxRate { property_tax: 0.1 },
+            InsuranceRate {
+                building_insurance: 0.1,
+            },
+            CAMRates::default(),
+        ),
+        "Check".to_string(),
     );
-    match add_statement(instances, &statement).await {
-        Ok(_) => println!("Successfully added STATEMENT"),
-        Err(e) => println!("Error when adding STATEMENT: {}", e),
+    lease.cam = Some(CamClauses {
+        base_year: Some(2023),
+        expense_stop: Some(600.0),
+        caps: vec![CamCap {
+            category: Some(ChargeCategory::Electricity),
+            percent: 0.05,
+            cumulative: true,
+        }],
+        excluded: vec![ChargeCategory::Landscaping],
+        gross_up: Some(0.25),
+    });
+    // Billed in March, June, September and December
+    lease.recurring_charges = vec![RecurringCharge {
+        name: "Storage Locker B".to_string(),
+        amount: 150.0,
+        frequency: ChargeFrequency::Quarterly,
+        start_date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+        end_date: Some(NaiveDate::from_ymd_opt(2025, 2, 28).unwrap()),
+        taxable: true,
+    }];
+    let contact = ContactInformation::new(
+        "Harbor Books".to_owned(),
+        Address::new(
+            "1200 Harbor Ave".to_string(),
+            "Seattle".to_string(),
+            "WA".to_string(),
+            "98126".to_string(),
+        ),
+        "books@harbor.example".to_string(),
+        "2065550100".to_string(),
+    );
+    let mut tenant = Leaseholder::new(
+        0,
+        lease,
+        property.id,
+        contact,
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+    );
+    match add_leaseholders(instances, &tenant, property.id).await {
+        Ok(t) => {
+            tenant.id = t.last_insert_rowid() as u32;
+            println!("Successfully added COMMERCIAL LEASEHOLDER")
+        }
+        Err(e) => println!("Error when adding COMMERCIAL LEASEHOLDER: {}", e),
+    };
+
+    let period = BillingPeriod::month_of(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+    let history = CamHistory::new(
+        get_property_expenses(instances, property.id).await,
+        property.num_units,
+        &get_leaseholders(instances, true).await,
+    );
+    let statement = Statement::new(
+        period.start,
+        tenant.clone(),
+        get_period_property_expenses(instances, property.id, &period).await,
+    )
+    .with_bills(get_property_bills(instances, property.id).await)
+    .with_cam_history(history);
+    match issue_statement(instances, app_settings, &statement, property, &company).await {
+        Ok(issued) => println!("{}", render_text(&issued.document, &issued.template)),
+        Err(e) => println!("Error when issuing CAM STATEMENT: {}", e),
     }
-    //println!("New Statement: {:#?}", statement);
 
-    create_statement(statement, property.clone(), company, settings);
+    // Only March was billed, so the year end true-up bills the rest of the year's CAM
+    match reconcile_cam(instances, app_settings, tenant.id, 2024).await {
+        Ok(issued) => println!("{}", render_text(&issued.document, &issued.template)),
+        Err(e) => println!("Error when reconciling CAM: {}", e),
+    }
+    match reconcile_cam(instances, app_settings, tenant.id, 2024).await {
+        Ok(issued) => println!(
+            "Unexpected second CAM reconciliation {}",
+            issued.record.invoice_number.clone().unwrap_or_default()
+        ),
+        Err(e) => println!("Second CAM reconciliation refused: {}", e),
+    }
+}
 
-    property.business_insurance += 100.0;
-    match update_property(instances, property).await {
-        Ok(_) => println!("Successfully updated PROPERTY. ID: {}", property.id),
-        Err(e) => println!("Error when adding STATEMENT: {}", e),
+pub async fn test_percentage_rent(
+    instances: &sqlx::Pool<Sqlite>,
+    property: &Property,
+    company: Company,
+    app_settings: &AppSettings,
+) {
+    println!("- - - Testing Percentage Rent - - -");
+    let mut lease = Lease::new(
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2026, 12, 31).unwrap(),
+        lease::FeeStructure::Gross(Rent { base_rent: 3000.0 }),
+        "Check".to_string(),
+    );
+    // A natural breakpoint of $600,000 a year, $150,000 a quarter
+    lease.percentage_rent = Some(PercentageRent {
+        rate: 0.06,
+        breakpoint: Breakpoint::Natural,
+        reporting: ReportingPeriod::Quarterly,
+    });
+    let contact = ContactInformation::new(
+        "Pike Street Deli".to_owned(),
+        Address::new(
+            "1200 Harbor Ave".to_string(),
+            "Seattle".to_string(),
+            "WA".to_string(),
+            "98126".to_string(),
+        ),
+        "deli@pike.example".to_string(),
+        "2065550111".to_string(),
+    );
+    let mut tenant = Leaseholder::new(
+        0,
+        lease,
+        property.id,
+        contact,
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+    );
+    match add_leaseholders(instances, &tenant, property.id).await {
+        Ok(t) => {
+            tenant.id = t.last_insert_rowid() as u32;
+            println!("Successfully added RETAIL LEASEHOLDER")
+        }
+        Err(e) => println!("Error when adding RETAIL LEASEHOLDER: {}", e),
+    };
+
+    // The first quarter reported month by month, $180,000 in all
+    for (month, gross_sales) in [(1, 50000.0), (2, 60000.0), (3, 70000.0)] {
+        let mut report = SalesReport {
+            id: 0,
+            leaseholder_id: tenant.id,
+            period: BillingPeriod::month_of(NaiveDate::from_ymd_opt(2024, month, 1).unwrap()),
+            gross_sales,
+            reported_on: NaiveDate::from_ymd_opt(2024, 4, 5).unwrap(),
+        };
+        match add_sales_report(instances, &mut report).await {
+            Ok(_) => println!("Successfully added SALES REPORT"),
+            Err(e) => println!("Error when adding SALES REPORT: {}", e),
+        }
+    }
+
+    // April's statement bills 6% of the first quarter's sales over $150,000
+    let period = BillingPeriod::month_of(NaiveDate::from_ymd_opt(2024, 4, 1).unwrap());
+    let statement = Statement::new(
+        period.start,
+        tenant.clone(),
+        get_period_property_expenses(instances, property.id, &period).await,
+    )
+    .with_sales(get_sales_reports(instances, tenant.id).await);
+    match issue_statement(instances, app_settings, &statement, property, &company).await {
+        Ok(issued) => println!("{}", render_text(&issued.document, &issued.template)),
+        Err(e) => println!("Error when issuing PERCENTAGE RENT STATEMENT: {}", e),
+    }
+
+    // The rest of the year puts sales $70,000 over the annual breakpoint
+    for (start, end, gross_sales) in [
+        ((2024, 4, 1), (2024, 6, 30), 140000.0),
+        ((2024, 7, 1), (2024, 9, 30), 130000.0),
+        ((2024, 10, 1), (2024, 12, 31), 220000.0),
+    ] {
+        let mut report = SalesReport {
+            id: 0,
+            leaseholder_id: tenant.id,
+            period: BillingPeriod {
+                start: NaiveDate::from_ymd_opt(start.0, start.1, start.2).unwrap(),
+                end: NaiveDate::from_ymd_opt(end.0, end.1, end.2).unwrap(),
+            },
+            gross_sales,
+            reported_on: NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(),
+        };
+        match add_sales_report(instances, &mut report).await {
+            Ok(_) => println!("Successfully added SALES REPORT"),
+            Err(e) => println!("Error when adding SALES REPORT: {}", e),
+        }
+    }
+
+    match true_up_percentage_rent(instances, app_settings, tenant.id, 2024).await {
+        Ok(issued) => println!("{}", render_text(&issued.document, &issued.template)),
+        Err(e) => println!("Error when truing up PERCENTAGE RENT: {}", e),
+    }
+    match true_up_percentage_rent(instances, app_settings, tenant.id, 2024).await {
+        Ok(issued) => println!(
+            "Unexpected second percentage rent true-up {}",
+            issued.record.invoice_number.clone().unwrap_or_default()
+        ),
+        Err(e) => println!("Second percentage rent true-up refused: {}", e),
+    }
+}
+
+pub async fn test_sales_tax(
+    instances: &sqlx::Pool<Sqlite>,
+    company: Company,
+    app_settings: &AppSettings,
+) {
+    println!("- - - Testing Sales Tax - - -");
+    let mut settings = app_settings.clone();
+    settings.tax.jurisdictions.push(TaxJurisdiction {
+        name: "Tempe Rental Tax".to_string(),
+        rate: 0.025,
+        taxable: vec!["Rent".to_string(), "Property Tax".to_string()],
+    });
+    let mut property = Property::new(
+        0,
+        "Mill Avenue Shops".to_string(),
+        Address::new(
+            "410 S Mill Ave".to_string(),
+            "Tempe".to_string(),
+            "AZ".to_string(),
+            "85281".to_string(),
+        ),
+        800.0,
+        400.0,
+        4,
+    );
+    property.tax_jurisdiction = Some("Tempe Rental Tax".to_string());
+    match add_property(instances, &property).await {
+        Ok(r) => {
+            property.id = r.last_insert_rowid() as u32;
+            println!("Successfully added TAXED PROPERTY");
+        }
+        Err(e) => println!("Error when adding TAXED PROPERTY: {}", e),
+    };
+
+    let mut lease = Lease::new(
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2025, 12, 31).unwrap(),
+        lease::FeeStructure::SingleNet(
+            Rent { base_rent: 2000.0 },
+            PropertyTaxRate { property_tax: 0.25 },
+        ),
+        "Check".to_string(),
+    );
+    // Signage is taxed by its flag, the storage room isn't
+    lease.recurring_charges = vec![
+        RecurringCharge {
+            name: "Pylon Sign Panel".to_string(),
+            amount: 60.0,
+            frequency: ChargeFrequency::Monthly,
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end_date: None,
+            taxable: true,
+        },
+        RecurringCharge {
+            name: "Storage Room".to_string(),
+            amount: 40.0,
+            frequency: ChargeFrequency::Monthly,
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end_date: None,
+            taxable: false,
+        },
+    ];
+    let contact = ContactInformation::new(
+        "Desert Cycle Works".to_owned(),
+        Address::new(
+            "410 S Mill Ave".to_string(),
+            "Tempe".to_string(),
+            "AZ".to_string(),
+            "85281".to_string(),
+        ),
+        "shop@desertcycle.example".to_string(),
+        "4805550123".to_string(),
+    );
+    let mut tenant = Leaseholder::new(
+        0,
+        lease,
+        property.id,
+        contact,
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+    );
+    match add_leaseholders(instances, &tenant, property.id).await {
+        Ok(t) => {
+            tenant.id = t.last_insert_rowid() as u32;
+            println!("Successfully added TAXED LEASEHOLDER")
+        }
+        Err(e) => println!("Error when adding TAXED LEASEHOLDER: {}", e),
+    };
+
+    let statement = Statement::new(
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+        tenant.clone(),
+        vec![],
+    );
+    let issued = match issue_statement(instances, &settings, &statement, &property, &company).await
+    {
+        Ok(issued) => {
+            println!("{}", render_text(&issued.document, &issued.template));
+            issued
+        }
+        Err(e) => {
+            println!("Error when issuing TAXED STATEMENT: {}", e);
+            return;
+        }
+    };
+    // The credit takes its share of the tax back off the report
+    match credit(
+        instances,
+        &settings,
+        issued.record.id,
+        229.5,
+        "Sign panel out of service",
+    )
+    .await
+    {
+        Ok(memo) => println!("{}", render_text(&memo.document, &memo.template)),
+        Err(e) => println!("Error when issuing TAXED CREDIT MEMO: {}", e),
+    }
+    match mark_statement_paid(instances, &issued.record).await {
+        Ok(_) => println!("Successfully paid TAXED STATEMENT"),
+        Err(e) => println!("Error when paying TAXED STATEMENT: {}", e),
+    }
+    let report = tax_report(instances, None, None).await;
+    println!("{}", describe_report(&report, None, None));
+}
+
+pub async fn test_utility_billing(
+    instances: &sqlx::Pool<Sqlite>,
+    company: Company,
+    app_settings: &AppSettings,
+) {
+    println!("- - - Testing Utility Billing - - -");
+    let mut property = Property::new(
+        0,
+        "Cedar Court Flats".to_string(),
+        Address::new(
+            "1200 Cedar Ct".to_string(),
+            "Boise".to_string(),
+            "ID".to_string(),
+            "83702".to_string(),
+        ),
+        900.0,
+        300.0,
+        3,
+    );
+    property.utility_billing = UtilityBilling::decode_from_database_string(
+        "Water Metered; Electricity Metered 0.14; Gas RUBS Occupants",
+    );
+    match add_property(instances, &property).await {
+        Ok(r) => {
+            property.id = r.last_insert_rowid() as u32;
+            println!("Successfully added METERED PROPERTY");
+        }
+        Err(e) => println!("Error when adding METERED PROPERTY: {}", e),
+    };
+
+    // The second unit moved in halfway through March
+    let mut tenants: Vec<Leaseholder> = vec![];
+    for (name, occupants, move_in, water, electricity) in [
+        ("Rosa Delgado", 2, (2024, 3, 1), 3000.0, 850.0),
+        ("Ben Ito", 1, (2024, 3, 16), 1000.0, 300.0),
+        ("Priya Shah", 3, (2024, 3, 1), 2000.0, 620.0),
+    ] {
+        let move_in = NaiveDate::from_ymd_opt(move_in.0, move_in.1, move_in.2).unwrap();
+        let lease = Lease::new(
+            move_in,
+            NaiveDate::from_ymd_opt(2025, 2, 28).unwrap(),
+            lease::FeeStructure::TripleNet(
+                Rent { base_rent: 1400.0 },
+                PropertyTaxRate { property_tax: 0.3 },
+                InsuranceRate {
+                    building_insurance: 0.3,
+                },
+                CAMRates::default(),
+            ),
+            "Check".to_string(),
+        );
+        let contact = ContactInformation::new(
+            name.to_owned(),
+            Address::new(
+                "1200 Cedar Ct".to_string(),
+                "Boise".to_string(),
+                "ID".to_string(),
+                "83702".to_string(),
+            ),
+            "tenant@cedarcourt.example".to_string(),
+            "2085550188".to_string(),
+        );
+        let mut tenant = Leaseholder::new(0, lease, property.id, contact, move_in);
+        tenant.occupants = occupants;
+        match add_leaseholders(instances, &tenant, property.id).await {
+            Ok(t) => {
+                tenant.id = t.last_insert_rowid() as u32;
+                println!("Successfully added METERED LEASEHOLDER")
+            }
+            Err(e) => println!("Error when adding METERED LEASEHOLDER: {}", e),
+        };
+        for (utility, usage) in [(Utility::Water, water), (Utility::Electricity, electricity)] {
+            let mut reading = MeterReading {
+                id: 0,
+                leaseholder_id: tenant.id,
+                utility,
+                period: BillingPeriod::month_of(move_in),
+                start_reading: 10000.0,
+                end_reading: 10000.0 + usage,
+                recorded_on: NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+            };
+            match add_meter_reading(instances, &mut reading).await {
+                Ok(_) => println!("Successfully added METER READING"),
+                Err(e) => println!("Error when adding METER READING: {}", e),
+            }
+        }
+        tenants.push(tenant);
+    }
+
+    let dt = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+    for (utilities_type, amount, description) in [
+        (UtilitiesType::Water, 480.0, "Water and Sewer Bill"),
+        (UtilitiesType::Gas, 300.0, "Gas Bill"),
+    ] {
+        let expense = Expense::new(
+            0,
+            property.id,
+            ExpenseType::Utilities(utilities_type),
+            amount,
+            dt,
+            description.to_string(),
+        );
+        match add_expense(instances, &expense).await {
+            Ok(_) => println!("Successfully added EXPENSE"),
+            Err(e) => println!("Error when adding EXPENSE: {}", e),
+        }
+    }
+
+    // Water by metered share of the bill, electricity at the rate, gas by occupants
+    let period = BillingPeriod::month_of(dt);
+    let statement = Statement::new(
+        period.start,
+        tenants[0].clone(),
+        get_period_property_expenses(instances, property.id, &period).await,
+    )
+    .with_utilities(UtilityUsage::for_property(instances, property.id).await);
+    match issue_statement(instances, app_settings, &statement, &property, &company).await {
+        Ok(issued) => println!("{}", render_text(&issued.document, &issued.template)),
+        Err(e) => println!("Error when issuing METERED STATEMENT: {}", e),
+    }
+}
+
+pub async fn test_documents(instances: &sqlx::Pool<Sqlite>, app_settings: &AppSettings) {
+    println!("- - - Testing Documents - - -");
+    let Some(tenant) = get_leaseholders(instances, false)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.contact_info.name == "Rosa Delgado")
+    else {
+        println!("Error when finding DOCUMENT LEASEHOLDER");
+        return;
+    };
+    let today = Local::now().date_naive();
+    let source_folder = std::env::temp_dir().join("hestia_test_documents");
+    let _ = std::fs::create_dir_all(&source_folder);
+
+    // Last year's certificate is superseded by the renewal, which is about to run out
+    let renewal = (today + Days::new(20)).to_string();
+    let lapsed = (today - Days::new(345)).to_string();
+    for (owner, kind, file_name, contents, effective_date, expiry_date) in [
+        (
+            DocumentOwner::Lease(tenant.lease.id),
+            "Signed Lease",
+            "lease_signed.pdf",
+            "Signed lease for 1200 Cedar Ct, unit 1",
+            tenant.lease.start_date.to_string(),
+            tenant.lease.end_date.to_string(),
+        ),
+        (
+            DocumentOwner::Leaseholder(tenant.id),
+            "Insurance Certificate",
+            "coi_2024.pdf",
+            "Certificate of liability insurance 2024",
+            (today - Days::new(710)).to_string(),
+            lapsed.clone(),
+        ),
+        (
+            DocumentOwner::Leaseholder(tenant.id),
+            "Insurance Certificate",
+            "coi_2025.pdf",
+            "Certificate of liability insurance 2025",
+            lapsed,
+            renewal,
+        ),
+        (
+            DocumentOwner::Leaseholder(tenant.id),
+            "W-9",
+            "w9.pdf",
+            "Request for taxpayer identification number",
+            String::new(),
+            String::new(),
+        ),
+    ] {
+        let source = source_folder.join(file_name);
+        if let Err(e) = std::fs::write(&source, contents) {
+            println!("Error when writing DOCUMENT SOURCE: {}", e);
+            continue;
+        }
+        let document = NewDocument {
+            kind: kind.to_owned(),
+            title: String::new(),
+            source: source.to_string_lossy().into_owned(),
+            effective_date,
+            expiry_date,
+        };
+        match attach_document(instances, &app_settings.paths, owner, &document).await {
+            Ok(document) => println!("Successfully attached DOCUMENT: {}", document.file_path),
+            Err(e) => println!("Error when attaching DOCUMENT: {}", e),
+        }
+        // The same file twice is refused
+        if file_name == "w9.pdf" {
+            match attach_document(instances, &app_settings.paths, owner, &document).await {
+                Ok(_) => println!("Error: attached the same DOCUMENT twice"),
+                Err(e) => println!("Refused duplicate DOCUMENT: {}", e),
+            }
+        }
+    }
+
+    for document in get_documents(instances, DocumentOwner::Leaseholder(tenant.id)).await {
+        println!(
+            "{} {} ({}): {}",
+            document.owner,
+            document.kind,
+            document.title,
+            document.check()
+        );
+    }
+    for reminder in reminders(instances, &app_settings.documents, today).await {
+        println!("Reminder: {}", reminder.describe());
+    }
+}
+
+pub async fn test_insurance(instances: &sqlx::Pool<Sqlite>, app_settings: &AppSettings) {
+    println!("- - - Testing Insurance Compliance - - -");
+    let today = Local::now().date_naive();
+    let requirement = InsuranceRequirement {
+        per_occurrence: 1000000.0,
+        aggregate: 2000000.0,
+        additional_insured: true,
+    };
+    let expiring = (today + Days::new(20)).to_string();
+    // Rosa's policy is about to run out, Ben's is too small and Priya's has lapsed
+    for leaseholder in get_leaseholders(instances, false).await {
+        let (per_occurrence, additional_insured, effective_date, expiry_date) =
+            match leaseholder.contact_info.name.as_str() {
+                "Rosa Delgado" => ("1000000", true, today - Days::new(345), expiring.clone()),
+                "Ben Ito" => (
+                    "500000",
+                    false,
+                    today - Days::new(100),
+                    (today + Days::new(265)).to_string(),
+                ),
+                "Priya Shah" => (
+                    "1000000",
+                    true,
+                    today - Days::new(400),
+                    (today - Days::new(35)).to_string(),
+                ),
+                _ => continue,
+            };
+        let mut lease = leaseholder.lease.clone();
+        lease.insurance_requirement = Some(requirement);
+        match update_lease(instances, &lease).await {
+            Ok(_) => println!("Successfully added INSURANCE REQUIREMENT"),
+            Err(e) => println!("Error when adding INSURANCE REQUIREMENT: {}", e),
+        }
+        let certificate = NewCertificate {
+            carrier: "Gem State Mutual".to_string(),
+            policy_number: format!("GL-{}", 4400 + leaseholder.id),
+            per_occurrence: per_occurrence.to_string(),
+            aggregate: "2,000,000".to_string(),
+            additional_insured,
+            effective_date: effective_date.to_string(),
+            expiry_date,
+        };
+        let mut certificate = match certificate.parse(leaseholder.id) {
+            Ok(certificate) => certificate,
+            Err(e) => {
+                println!("Error when parsing CERTIFICATE: {}", e);
+                continue;
+            }
+        };
+        match add_insurance_certificate(instances, &mut certificate).await {
+            Ok(_) => println!("Successfully added CERTIFICATE"),
+            Err(e) => println!("Error when adding CERTIFICATE: {}", e),
+        }
+    }
+    let print_report = |report: Vec<ComplianceEntry>| {
+        for entry in report {
+            println!(
+                "{}: {}, {}",
+                entry.name,
+                entry.compliance.label(),
+                entry.compliance.describe()
+            );
+        }
+    };
+    print_report(compliance_report(instances, &app_settings.documents, today).await);
+
+    // Once Rosa's renewal comes in she is compliant again
+    let Some(rosa) = get_leaseholders(instances, false)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.contact_info.name == "Rosa Delgado")
+    else {
+        return;
+    };
+    let renewal = NewCertificate {
+        carrier: "Gem State Mutual".to_string(),
+        policy_number: format!("GL-{}-R", 4400 + rosa.id),
+        per_occurrence: "1000000".to_string(),
+        aggregate: "2000000".to_string(),
+        additional_insured: true,
+        effective_date: (today + Days::new(21)).to_string(),
+        expiry_date: (today + Days::new(386)).to_string(),
+    };
+    match renewal.parse(rosa.id) {
+        Ok(mut renewal) => match add_insurance_certificate(instances, &mut renewal).await {
+            Ok(_) => println!("Successfully added RENEWAL CERTIFICATE"),
+            Err(e) => println!("Error when adding RENEWAL CERTIFICATE: {}", e),
+        },
+        Err(e) => println!("Error when parsing RENEWAL CERTIFICATE: {}", e),
+    }
+    print_report(compliance_report(instances, &app_settings.documents, today).await);
+}
+
+pub async fn test_letters(instances: &sqlx::Pool<Sqlite>, app_settings: &AppSettings) {
+    println!("- - - Testing Letters - - -");
+    let Some(tenant) = get_leaseholders(instances, false)
+        .await
+        .into_iter()
+        .find(|leaseholder| leaseholder.contact_info.name == "Rosa Delgado")
+    else {
+        println!("Error when finding LETTER LEASEHOLDER");
+        return;
+    };
+    // The rent increase needs the new rent, and Rosa hasn't given notice to move out
+    for (template, extra_fields) in [
+        ("rent_increase", ""),
+        (
+            "rent_increase",
+            "new_rent=1475.00; effective_date=March 1, 2025",
+        ),
+        ("late_notice", ""),
+        ("move_out", ""),
+    ] {
+        match generate_letter(instances, app_settings, tenant.id, template, extra_fields).await {
+            Ok(letter) => println!(
+                "Successfully generated LETTER: {} at {}",
+                letter.subject, letter.file_path
+            ),
+            Err(e) => println!("Refused LETTER {}: {}", template, e),
+        }
+    }
+    for letter in get_letters(instances, tenant.id).await {
+        println!(
+            "Sent {} on {}: {}",
+            letter.template, letter.sent_on, letter.subject
+        );
     }
 }
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+
+use crate::{statement_document::StatementDocument, statement_template::StatementTemplate};
+
+const WIDTH: usize = 60;
+
+/// Renders the statement as plain text, for terminals and plain text email.
+pub fn render_text(document: &StatementDocument, template: &StatementTemplate) -> String {
+    let recipient = &document.recipient;
+    let balances = &document.balances;
+    let rule = "-".repeat(WIDTH);
+    let row =
+        |label: &str, amount: String| format!("{label:<width$}{amount:>12}\n", width = WIDTH - 12);
+
+    let mut text = String::new();
+    let _ = writeln!(text, "{}", document.company);
+    let _ = writeln!(
+        text,
+        "{} for {}",
+        document.title(template),
+        document.period.start.format("%B %Y")
+    );
+    if let Some(invoice_number) = &document.invoice_number {
+        let _ = writeln!(text, "Invoice: {invoice_number}");
+    }
+    let _ = writeln!(text, "Date: {}", document.date);
+    if !document.is_credit_memo() {
+        let _ = writeln!(text, "Due: {}", document.due_date);
+    }
+    if let Some(note) = &document.note {
+        let _ = writeln!(text, "{note}");
+    }
+    text.push('\n');
+    let _ = writeln!(text, "{}", recipient.name);
+    let _ = writeln!(text, "{}", recipient.address.street_address);
+    let _ = writeln!(text, "{}", recipient.city_line());
+    let _ = writeln!(text, "Property: {}", document.property);
+    let _ = writeln!(text, "Period: {}\n", document.period.range());
+
+    if !document.is_credit_memo() {
+        text.push_str(&row("Balance Forward:", format!("{:.2}", balances.forward)));
+        text.push_str(&row(
+            "Payment Received:",
+            format!("{:.2}", balances.payments),
+        ));
+        text.push_str(&row(
+            "Outstanding Balance:",
+            format!("{:.2}", balances.outstanding),
+        ));
+        text.push('\n');
+    }
+
+    text.push_str(&row(
+        &template.charge_heading,
+        template.amount_heading.clone(),
+    ));
+    let _ = writeln!(text, "{rule}");
+    for line in &document.lines {
+        text.push_str(&row(&line.label(), line.amount_text()));
+    }
+    let _ = writeln!(text, "{rule}");
+    text.push_str(&row(
+        document.total_label(),
+        format!("${:.2}", document.total_due),
+    ));
+    if !document.adjustments.is_empty() {
+        let _ = writeln!(text, "\n{}", template.adjustments_heading);
+        for adjustment in &document.adjustments {
+            let _ = writeln!(text, "  {adjustment}");
+        }
+    }
+
+    let _ = writeln!(text, "\n{}", document.payment_terms(template));
+    let late_fee_terms = document.late_fee_terms(template);
+    if !late_fee_terms.is_empty() {
+        let _ = writeln!(text, "{late_fee_terms}");
+    }
+    let _ = writeln!(text, "{}", template.remit_heading);
+    let _ = writeln!(text, "{}", recipient.address.street_address);
+    let _ = writeln!(text, "{}\n", recipient.city_line());
+    let _ = writeln!(text, "{}", template.footer);
+    text
+}
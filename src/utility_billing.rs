@@ -0,0 +1,734 @@
+use std::fmt;
+
+use chrono::{Local, NaiveDate};
+use slint::{ComponentHandle, ModelRc, VecModel};
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    database::{
+        add_meter_reading, get_leaseholders, get_meter_readings, get_property_meter_readings,
+        remove_meter_reading,
+    },
+    expenses::Expense,
+    lease::{ChargeCategory, StatementLine},
+    leaseholders::Leaseholder,
+    statement_document::BillingPeriod,
+    App, MeterReadingInput, MeterReadings,
+};
+
+/// A utility that can be billed by meter or by ratio instead of the lease's CAM rates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Utility {
+    Water,
+    Electricity,
+    Gas,
+}
+
+impl fmt::Display for Utility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            Utility::Water => String::from("Water"),
+            Utility::Electricity => String::from("Electricity"),
+            Utility::Gas => String::from("Gas"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl Utility {
+    pub fn parse_string(utility: &str) -> Option<Utility> {
+        match utility.trim().to_ascii_lowercase().as_str() {
+            "water" => Some(Utility::Water),
+            "electricity" | "electric" => Some(Utility::Electricity),
+            "gas" => Some(Utility::Gas),
+            _ => None,
+        }
+    }
+
+    /// The statement line the utility is billed under.
+    pub fn category(&self) -> ChargeCategory {
+        match self {
+            Utility::Water => ChargeCategory::WaterSewer,
+            Utility::Electricity => ChargeCategory::Electricity,
+            Utility::Gas => ChargeCategory::Gas,
+        }
+    }
+
+    /// What the meter counts.
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Utility::Water => "gallons",
+            Utility::Electricity => "kWh",
+            Utility::Gas => "therms",
+        }
+    }
+}
+
+/// What a ratio utility bill (RUBS) is divided by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RubsBasis {
+    Occupants,
+    SquareFootage,
+    UnitCount,
+}
+
+impl fmt::Display for RubsBasis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let res = match self {
+            RubsBasis::Occupants => String::from("Occupants"),
+            RubsBasis::SquareFootage => String::from("Square Footage"),
+            RubsBasis::UnitCount => String::from("Unit Count"),
+        };
+        write!(f, "{res}")
+    }
+}
+
+impl RubsBasis {
+    pub fn parse_string(basis: &str) -> Option<RubsBasis> {
+        match basis.trim().to_ascii_lowercase().as_str() {
+            "occupants" => Some(RubsBasis::Occupants),
+            "square footage" | "square feet" => Some(RubsBasis::SquareFootage),
+            "unit count" | "units" => Some(RubsBasis::UnitCount),
+            _ => None,
+        }
+    }
+
+    /// The tenant's measure under the basis, before it is weighted by days occupied.
+    pub fn measure(&self, leaseholder: &Leaseholder) -> f32 {
+        match self {
+            RubsBasis::Occupants => leaseholder.occupants as f32,
+            RubsBasis::SquareFootage => leaseholder.square_feet,
+            RubsBasis::UnitCount => 1.0,
+        }
+    }
+}
+
+/// How the property divides one utility between its tenants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Allocation {
+    // By each tenant's metered usage, of the utility bill, or at a rate per unit when given
+    Metered(Option<f32>),
+    // By each tenant's share of the basis, weighted by the days they occupied
+    Rubs(RubsBasis),
+}
+
+/// The utilities a property bills by meter or ratio, chosen per utility. Utilities left
+/// out are billed by the lease's CAM rates as before.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UtilityBilling {
+    pub rules: Vec<(Utility, Allocation)>,
+}
+
+impl UtilityBilling {
+    /// Written as e.g. "Water Metered; Electricity Metered 0.14; Gas RUBS Occupants", or None
+    /// when every utility is billed by the lease.
+    pub fn encode_to_database_string(&self) -> Option<String> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let rules: Vec<String> = self
+            .rules
+            .iter()
+            .map(|(utility, allocation)| match allocation {
+                Allocation::Metered(None) => format!("{utility} Metered"),
+                Allocation::Metered(Some(rate)) => format!("{utility} Metered {rate}"),
+                Allocation::Rubs(basis) => format!("{utility} RUBS {basis}"),
+            })
+            .collect();
+        Some(rules.join("; "))
+    }
+
+    /// Reads back the rules written by `encode_to_database_string`, or typed the same way
+    /// on the Properties tab. Rules that can't be read are left out.
+    pub fn decode_from_database_string(encoded: &str) -> UtilityBilling {
+        let mut billing = UtilityBilling::default();
+        for rule in encoded.split(';') {
+            let Some((utility, allocation)) = rule.trim().split_once(' ') else {
+                continue;
+            };
+            let Some(utility) = Utility::parse_string(utility) else {
+                continue;
+            };
+            let allocation = allocation.trim();
+            let allocation = if let Some(rate) = allocation.strip_prefix("Metered") {
+                match rate.trim() {
+                    "" => Some(Allocation::Metered(None)),
+                    rate => rate
+                        .trim_start_matches('$')
+                        .parse::<f32>()
+                        .ok()
+                        .map(|rate| Allocation::Metered(Some(rate))),
+                }
+            } else if let Some(basis) = allocation.strip_prefix("RUBS") {
+                RubsBasis::parse_string(basis).map(Allocation::Rubs)
+            } else {
+                None
+            };
+            if let Some(allocation) = allocation {
+                billing.rules.retain(|(billed, _)| *billed != utility);
+                billing.rules.push((utility, allocation));
+            }
+        }
+        billing
+    }
+
+    /// Whether the statement line is billed by these rules rather than the lease.
+    pub fn allocates(&self, category: ChargeCategory) -> bool {
+        self.rules
+            .iter()
+            .any(|(utility, _)| utility.category() == category)
+    }
+
+    /// The tenant's utility lines for the period, with a note on how each was worked out.
+    /// `days` is how many days of the period the tenant's leases pass utilities through.
+    pub fn statement_lines(
+        &self,
+        leaseholder: &Leaseholder,
+        usage: &UtilityUsage,
+        expenses: &[Expense],
+        period: &BillingPeriod,
+        days: u32,
+    ) -> (Vec<StatementLine>, Vec<String>) {
+        let mut lines = vec![];
+        let mut notes = vec![];
+        for (utility, allocation) in &self.rules {
+            let category = utility.category();
+            let bills: Vec<Expense> = expenses
+                .iter()
+                .filter(|expense| ChargeCategory::for_expense(&expense.expense_type) == category)
+                .cloned()
+                .collect();
+            match allocation {
+                Allocation::Metered(rate) => {
+                    let Some(used) = usage.used(leaseholder.id, *utility, period) else {
+                        notes.push(format!(
+                            "{} waits on a meter reading for {}",
+                            category,
+                            period.range()
+                        ));
+                        continue;
+                    };
+                    // Prorated readings rarely come to whole units
+                    let used = (used * 100.0).round() / 100.0;
+                    match rate {
+                        Some(rate) => {
+                            let amount = (used * rate * 100.0).round() / 100.0;
+                            let mut line = StatementLine::flat(category, amount);
+                            line.detail = Some(format!("{} {} at ${}", used, utility.unit(), rate));
+                            line.utility_billed = true;
+                            lines.push(line);
+                        }
+                        None => {
+                            let total =
+                                (usage.total_used(*utility, period) * 100.0).round() / 100.0;
+                            if bills.is_empty() || total <= 0.0 {
+                                continue;
+                            }
+                            let share = used / total;
+                            let mut line =
+                                StatementLine::expense_share(category, Some(share), bills);
+                            line.utility_billed = true;
+                            notes.push(format!(
+                                "{}: {} of {} {} metered at the property = {:.2}%",
+                                category,
+                                used,
+                                total,
+                                utility.unit(),
+                                share * 100.0
+                            ));
+                            lines.push(line);
+                        }
+                    }
+                }
+                Allocation::Rubs(basis) => {
+                    if bills.is_empty() {
+                        continue;
+                    }
+                    let period_days = (period.end - period.start).num_days() as f32 + 1.0;
+                    let weight = basis.measure(leaseholder) * days as f32 / period_days;
+                    let total = usage
+                        .tenants
+                        .iter()
+                        .filter(|tenant| tenant.id != leaseholder.id)
+                        .fold(weight, |sum, tenant| {
+                            sum + basis.measure(tenant) * days_occupied(tenant, period) as f32
+                                / period_days
+                        });
+                    if weight <= 0.0 || total <= 0.0 {
+                        continue;
+                    }
+                    let share = weight / total;
+                    let mut line = StatementLine::expense_share(category, Some(share), bills);
+                    line.utility_billed = true;
+                    notes.push(format!(
+                        "{} by {}: {} x {}/{} days of {:.2} at the property = {:.2}%",
+                        category,
+                        basis.to_string().to_lowercase(),
+                        basis.measure(leaseholder),
+                        days,
+                        period_days,
+                        total,
+                        share * 100.0
+                    ));
+                    lines.push(line);
+                }
+            }
+        }
+        (lines, notes)
+    }
+}
+
+/// The days of the period between the tenant's move-in and move-out.
+fn days_occupied(leaseholder: &Leaseholder, period: &BillingPeriod) -> u32 {
+    let from = period.start.max(leaseholder.move_in_date);
+    let to = leaseholder
+        .move_out_date
+        .map_or(period.end, |move_out| period.end.min(move_out));
+    match from > to {
+        true => 0,
+        false => (to - from).num_days() as u32 + 1,
+    }
+}
+
+/// The property's meter readings and tenants, for billing utilities by meter or ratio.
+#[derive(Debug, Clone, Default)]
+pub struct UtilityUsage {
+    pub readings: Vec<MeterReading>,
+    pub tenants: Vec<Leaseholder>,
+}
+
+impl UtilityUsage {
+    pub async fn for_property(pool: &sqlx::Pool<sqlx::Sqlite>, property_id: u32) -> UtilityUsage {
+        UtilityUsage {
+            readings: get_property_meter_readings(pool, property_id).await,
+            tenants: get_leaseholders(pool, true)
+                .await
+                .into_iter()
+                .filter(|tenant| tenant.property_id == property_id)
+                .collect(),
+        }
+    }
+
+    /// Readings of the utility that cover any day of the period. Meters are often read on
+    /// their own cycle, e.g. the 15th to the 14th, rather than by calendar month.
+    fn in_period<'a>(
+        &'a self,
+        utility: Utility,
+        period: &'a BillingPeriod,
+    ) -> impl Iterator<Item = &'a MeterReading> {
+        self.readings.iter().filter(move |reading| {
+            reading.utility == utility
+                && reading.period.start <= period.end
+                && reading.period.end >= period.start
+        })
+    }
+
+    /// What the tenant's meter read for the period, or None when it hasn't been read.
+    /// Readings running past either end of the period count for the days inside it.
+    pub fn used(
+        &self,
+        leaseholder_id: u32,
+        utility: Utility,
+        period: &BillingPeriod,
+    ) -> Option<f32> {
+        let readings: Vec<&MeterReading> = self
+            .in_period(utility, period)
+            .filter(|reading| reading.leaseholder_id == leaseholder_id)
+            .collect();
+        match readings.is_empty() {
+            true => None,
+            false => Some(
+                readings
+                    .iter()
+                    .fold(0.0, |sum, reading| sum + reading.usage_during(period)),
+            ),
+        }
+    }
+
+    /// What every tenant's meter read for the period.
+    pub fn total_used(&self, utility: Utility, period: &BillingPeriod) -> f32 {
+        self.in_period(utility, period)
+            .fold(0.0, |sum, reading| sum + reading.usage_during(period))
+    }
+}
+
+/// A tenant's meter read at the start and end of a period.
+#[derive(Debug, Clone)]
+pub struct MeterReading {
+    pub id: i64,
+    pub leaseholder_id: u32,
+    pub utility: Utility,
+    pub period: BillingPeriod,
+    pub start_reading: f32,
+    pub end_reading: f32,
+    pub recorded_on: NaiveDate,
+}
+
+impl MeterReading {
+    pub fn usage(&self) -> f32 {
+        self.end_reading - self.start_reading
+    }
+
+    /// The part of the usage from the days the reading shares with the period, taking the
+    /// usage to be spread evenly over the days read.
+    pub fn usage_during(&self, period: &BillingPeriod) -> f32 {
+        let from = self.period.start.max(period.start);
+        let to = self.period.end.min(period.end);
+        if from > to {
+            return 0.0;
+        }
+        let overlap = (to - from).num_days() + 1;
+        let days = (self.period.end - self.period.start).num_days() + 1;
+        self.usage() * overlap as f32 / days as f32
+    }
+
+    pub fn convert_to_slint(&self) -> MeterReadingInput {
+        MeterReadingInput {
+            id: self.id as i32,
+            utility: self.utility.to_string().into(),
+            period: self.period.range().into(),
+            usage: format!("{} {}", self.usage(), self.utility.unit()).into(),
+            recorded_on: self.recorded_on.to_string().into(),
+        }
+    }
+}
+
+impl<'r> FromRow<'r, SqliteRow> for MeterReading {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let date = |column: &str| -> Result<NaiveDate, sqlx::Error> {
+            let date: String = row.try_get(column)?;
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+        };
+        let utility: String = row.try_get("utility")?;
+
+        Ok(MeterReading {
+            id: row.try_get("meter_reading_id")?,
+            leaseholder_id: row.try_get("leaseholder_id")?,
+            utility: Utility::parse_string(&utility)
+                .ok_or_else(|| sqlx::Error::Decode(format!("Unknown utility: {utility}").into()))?,
+            period: BillingPeriod {
+                start: date("period_start")?,
+                end: date("period_end")?,
+            },
+            start_reading: row.try_get::<f64, _>("start_reading")? as f32,
+            end_reading: row.try_get::<f64, _>("end_reading")? as f32,
+            recorded_on: date("recorded_on")?,
+        })
+    }
+}
+
+/// A meter reading as typed into the Leaseholders tab, checked by `parse`.
+#[derive(Debug, Clone)]
+pub struct NewMeterReading {
+    pub utility: String,
+    pub period_start: String,
+    pub period_end: String,
+    pub start_reading: String,
+    pub end_reading: String,
+}
+
+impl NewMeterReading {
+    pub fn parse(&self, leaseholder_id: u32) -> Result<MeterReading, String> {
+        let date = |text: &str| {
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date: {text}, use YYYY-MM-DD"))
+        };
+        let reading = |text: &str| {
+            text.trim()
+                .replace(',', "")
+                .parse::<f32>()
+                .ok()
+                .filter(|reading| *reading >= 0.0)
+                .ok_or_else(|| format!("Invalid meter reading: {text}"))
+        };
+        let utility = Utility::parse_string(&self.utility)
+            .ok_or_else(|| format!("Unknown utility: {}", self.utility))?;
+        let start = date(&self.period_start)?;
+        // The month the reading starts in when no end is given
+        let end = match self.period_end.trim() {
+            "" => BillingPeriod::month_of(start).end,
+            end => date(end)?,
+        };
+        if end < start {
+            return Err(String::from("The reading has to end after it starts"));
+        }
+        let start_reading = reading(&self.start_reading)?;
+        let end_reading = reading(&self.end_reading)?;
+        if end_reading < start_reading {
+            return Err(String::from(
+                "The meter can't read less at the end than at the start",
+            ));
+        }
+        Ok(MeterReading {
+            id: 0,
+            leaseholder_id,
+            utility,
+            period: BillingPeriod { start, end },
+            start_reading,
+            end_reading,
+            recorded_on: Local::now().date_naive(),
+        })
+    }
+}
+
+pub enum MeterMessage {
+    Show(u32),
+    Add(u32, NewMeterReading),
+    Remove(i64),
+    Quit,
+}
+
+pub struct MeterWorker {
+    pub channel: UnboundedSender<MeterMessage>,
+    pub worker_thread: std::thread::JoinHandle<()>,
+}
+
+impl MeterWorker {
+    pub fn new(pool: &sqlx::Pool<sqlx::Sqlite>, app: slint::Weak<App>) -> Self {
+        println!("Create new Meter Worker");
+        let (sender, r) = tokio::sync::mpsc::unbounded_channel();
+        let worker_thread = std::thread::spawn({
+            let new_pool = pool.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(meter_worker_loop(new_pool, r, app))
+            }
+        });
+        Self {
+            channel: sender,
+            worker_thread,
+        }
+    }
+    pub fn join(self) -> std::thread::Result<()> {
+        let _ = self.channel.send(MeterMessage::Quit);
+        self.worker_thread.join()
+    }
+}
+
+async fn meter_worker_loop(
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    mut r: UnboundedReceiver<MeterMessage>,
+    app: slint::Weak<App>,
+) {
+    let mut leaseholder_id: Option<u32> = None;
+    loop {
+        let status = match r.recv().await {
+            Some(MeterMessage::Show(id)) => {
+                leaseholder_id = Some(id);
+                None
+            }
+            Some(MeterMessage::Add(id, new_reading)) => {
+                leaseholder_id = Some(id);
+                Some(match new_reading.parse(id) {
+                    Ok(mut reading) => match add_meter_reading(&pool, &mut reading).await {
+                        Ok(_) => format!(
+                            "Recorded {} {} of {} for {}",
+                            reading.usage(),
+                            reading.utility.unit(),
+                            reading.utility.to_string().to_lowercase(),
+                            reading.period.range()
+                        ),
+                        Err(e) => format!("Failed to record meter reading: {e}"),
+                    },
+                    Err(e) => e,
+                })
+            }
+            Some(MeterMessage::Remove(id)) => Some(match remove_meter_reading(&pool, id).await {
+                Ok(_) => String::from("Removed meter reading"),
+                Err(e) => format!("Failed to remove meter reading: {e}"),
+            }),
+            Some(MeterMessage::Quit) | None => break,
+        };
+        if let Some(status) = &status {
+            println!("{status}");
+        }
+        if let Some(leaseholder_id) = leaseholder_id {
+            show_readings(&pool, &app, leaseholder_id, status).await;
+        }
+    }
+}
+
+async fn show_readings(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    app: &slint::Weak<App>,
+    leaseholder_id: u32,
+    status: Option<String>,
+) {
+    let readings = get_meter_readings(pool, leaseholder_id).await;
+    let upgrade_res = app.upgrade_in_event_loop(move |handle| {
+        let readings: Vec<MeterReadingInput> = readings
+            .iter()
+            .map(MeterReading::convert_to_slint)
+            .collect();
+        let registry = handle.global::<MeterReadings>();
+        registry.set_readings(ModelRc::new(VecModel::from(readings)));
+        registry.set_status(status.unwrap_or_default().into());
+    });
+    if let Err(e) = upgrade_res {
+        println!("Failed to upgrade ui: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expenses::{ExpenseType, UtilitiesType},
+        lease::{CAMRates, FeeStructure, InsuranceRate, Lease, PropertyTaxRate, Rent},
+        leaseholders::ContactInformation,
+        properties::Address,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn tenant(id: u32) -> Leaseholder {
+        let lease = Lease::new(
+            date(2023, 1, 1),
+            date(2025, 12, 31),
+            FeeStructure::TripleNet(
+                Rent { base_rent: 1500.0 },
+                PropertyTaxRate { property_tax: 0.1 },
+                InsuranceRate {
+                    building_insurance: 0.1,
+                },
+                CAMRates::default(),
+            ),
+            "Check".to_string(),
+        );
+        let address = Address::new(
+            "3322 S 55th Street".to_string(),
+            "Seattle".to_string(),
+            "WA".to_string(),
+            "98118".to_string(),
+        );
+        let contact = ContactInformation::new(
+            format!("Tenant {id}"),
+            address,
+            String::new(),
+            String::new(),
+        );
+        Leaseholder::new(id, lease, 1, contact, date(2023, 1, 1))
+    }
+
+    fn reading(
+        leaseholder_id: u32,
+        start: NaiveDate,
+        end: NaiveDate,
+        start_reading: f32,
+        end_reading: f32,
+    ) -> MeterReading {
+        MeterReading {
+            id: 0,
+            leaseholder_id,
+            utility: Utility::Electricity,
+            period: BillingPeriod { start, end },
+            start_reading,
+            end_reading,
+            recorded_on: end,
+        }
+    }
+
+    /// Tenant 1's meter is read from the 15th to the 14th, tenant 2's by calendar month.
+    fn usage() -> UtilityUsage {
+        UtilityUsage {
+            readings: vec![
+                // 29 days, 10 kWh a day
+                reading(1, date(2024, 2, 15), date(2024, 3, 14), 0.0, 290.0),
+                // 31 days, 10 kWh a day
+                reading(1, date(2024, 3, 15), date(2024, 4, 14), 290.0, 600.0),
+                reading(2, date(2024, 3, 1), date(2024, 3, 31), 1000.0, 1300.0),
+            ],
+            tenants: vec![tenant(1), tenant(2)],
+        }
+    }
+
+    fn assert_usage(actual: Option<f32>, expected: f32) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{actual} used, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn readings_crossing_a_month_boundary_are_prorated() {
+        let usage = usage();
+        let march = BillingPeriod::month_of(date(2024, 3, 1));
+        // 14 days of the first reading and 17 of the second
+        assert_usage(usage.used(1, Utility::Electricity, &march), 310.0);
+        assert_usage(Some(usage.total_used(Utility::Electricity, &march)), 610.0);
+
+        let february = BillingPeriod::month_of(date(2024, 2, 1));
+        assert_usage(usage.used(1, Utility::Electricity, &february), 150.0);
+        assert!(usage.used(2, Utility::Electricity, &february).is_none());
+
+        let april = BillingPeriod::month_of(date(2024, 4, 1));
+        assert_usage(usage.used(1, Utility::Electricity, &april), 140.0);
+
+        // Every day read is counted once across the months
+        let billed: f32 = [february, march, april]
+            .iter()
+            .map(|month| usage.used(1, Utility::Electricity, month).unwrap())
+            .sum();
+        assert!((billed - 600.0).abs() < 0.01);
+
+        let may = BillingPeriod::month_of(date(2024, 5, 1));
+        assert!(usage.used(1, Utility::Electricity, &may).is_none());
+        assert!(usage.used(1, Utility::Gas, &march).is_none());
+    }
+
+    #[test]
+    fn metered_at_a_rate_bills_the_prorated_usage() {
+        let billing = UtilityBilling::decode_from_database_string("Electricity Metered 0.14");
+        let march = BillingPeriod::month_of(date(2024, 3, 1));
+        let (lines, notes) = billing.statement_lines(&tenant(1), &usage(), &[], &march, 31);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(format!("{:.2}", lines[0].amount), "43.40");
+        assert_eq!(lines[0].detail.as_deref(), Some("310 kWh at $0.14"));
+        assert!(lines[0].utility_billed);
+        assert!(notes.is_empty());
+
+        let may = BillingPeriod::month_of(date(2024, 5, 1));
+        let (lines, notes) = billing.statement_lines(&tenant(1), &usage(), &[], &may, 31);
+        assert!(lines.is_empty());
+        assert_eq!(
+            notes,
+            vec!["Electricity waits on a meter reading for 05/01/2024 to 05/31/2024"]
+        );
+    }
+
+    #[test]
+    fn metered_bills_are_split_by_prorated_usage() {
+        let billing = UtilityBilling::decode_from_database_string("Electricity Metered");
+        let march = BillingPeriod::month_of(date(2024, 3, 1));
+        let bill = Expense::new(
+            0,
+            1,
+            ExpenseType::Utilities(UtilitiesType::Electricity),
+            1220.0,
+            date(2024, 3, 31),
+            String::new(),
+        );
+        let (lines, notes) = billing.statement_lines(
+            &tenant(1),
+            &usage(),
+            std::slice::from_ref(&bill),
+            &march,
+            31,
+        );
+        assert_eq!(lines.len(), 1);
+        assert_eq!(format!("{:.2}", lines[0].amount), "620.00");
+        assert_eq!(
+            notes,
+            vec!["Electricity: 310 of 610 kWh metered at the property = 50.82%"]
+        );
+
+        let (lines, _) = billing.statement_lines(&tenant(2), &usage(), &[bill], &march, 31);
+        assert_eq!(format!("{:.2}", lines[0].amount), "600.00");
+    }
+}